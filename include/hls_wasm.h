@@ -0,0 +1,240 @@
+/*
+ * C ABI of hls_wasm (see `src/ffi.rs`).
+ *
+ * Typical driving loop:
+ *
+ *   HlsFfiPlayer *p = hls_ffi_player_new();
+ *   hls_ffi_player_play(p, url, url_len, m3u8, m3u8_len);
+ *   for (;;) {
+ *       HlsAction a;
+ *       hls_ffi_player_poll_action(p, &a);
+ *       // HLS_ACTION_FETCH_DATA: fetch `a.url` (only the `range` of `a.json` if present)
+ *       //                        honoring the `credentials` and `mode` hints of `a.json`,
+ *       //                        then call hls_ffi_player_handle_data()
+ *       //                        (or hls_ffi_player_handle_error() if the fetch failed)
+ *       // HLS_ACTION_SET_TIMEOUT: wait `a.duration_ms`, then call hls_ffi_player_handle_timeout()
+ *       // HLS_ACTION_ABORT: cancel the fetch or timer identified by `a.action_id`
+ *       // HLS_ACTION_PRECONNECT: optionally open a connection to the origin `a.url`
+ *
+ *       HlsBuffer event;
+ *       hls_ffi_player_poll_event(p, &event); // JSON notifications (e.g., fatal errors)
+ *
+ *       HlsBuffer segment;
+ *       hls_ffi_player_poll_segment(p, &segment); // append non-empty segments to the decoder
+ *   }
+ *   hls_ffi_player_free(p);
+ *
+ * Buffers returned by the player stay valid until the next call on the same handle.
+ */
+#ifndef HLS_WASM_H
+#define HLS_WASM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define HLS_OK 0
+#define HLS_ERROR_INVALID_INPUT 1
+#define HLS_ERROR_OTHER 2
+#define HLS_ERROR_NULL_POINTER 3
+
+#define HLS_ACTION_NONE 0
+#define HLS_ACTION_FETCH_DATA 1
+#define HLS_ACTION_SET_TIMEOUT 2
+#define HLS_ACTION_ABORT 3
+#define HLS_ACTION_PRECONNECT 4
+
+#define HLS_RESOURCE_PLAYLIST 0
+#define HLS_RESOURCE_SEGMENT 1
+#define HLS_RESOURCE_INIT_SEGMENT 2
+#define HLS_RESOURCE_STEERING_MANIFEST 3
+#define HLS_RESOURCE_SUBTITLES 4
+#define HLS_RESOURCE_KEY 5
+
+#define HLS_LOG_ERROR 1
+#define HLS_LOG_WARN 2
+#define HLS_LOG_INFO 3
+#define HLS_LOG_DEBUG 4
+#define HLS_LOG_TRACE 5
+
+#define HLS_MAX_URL_LEN 8192
+
+typedef struct HlsFfiPlayer HlsFfiPlayer;
+
+/*
+ * Writes the rewritten `url` to `out` and returns its length.
+ * Returning 0 (or more than `out_capacity`) keeps the original URL.
+ */
+typedef size_t (*HlsUrlRewriterFn)(void *user_data, uint32_t kind,
+                                   const uint8_t *url, size_t url_len,
+                                   uint8_t *out, size_t out_capacity);
+
+/*
+ * Called when a fetch of `url` is rejected with 401 or 403. Writes the query parameters
+ * carrying a fresh token (e.g., `token=abc&expires=123`) to `out` and returns their length.
+ * Returning 0 (or more than `out_capacity`) fails the fetch as before.
+ */
+typedef size_t (*HlsTokenRefresherFn)(void *user_data, uint32_t kind,
+                                      const uint8_t *url, size_t url_len,
+                                      uint8_t *out, size_t out_capacity);
+
+/* Returns the current wall-clock time in milliseconds since the UNIX epoch. */
+typedef double (*HlsClockFn)(void *user_data);
+
+/* Receives a log record; `message` is UTF-8 and not NUL-terminated. */
+typedef void (*HlsLogFn)(void *user_data, uint32_t level, const uint8_t *message,
+                         size_t message_len);
+
+/* Text buffers (URLs and JSON) are NUL-terminated; `len` excludes the terminator. */
+typedef struct HlsBuffer {
+    const uint8_t *data;
+    size_t len;
+} HlsBuffer;
+
+typedef struct HlsAction {
+    uint32_t kind;
+    uint32_t action_id;
+    HlsBuffer url;        /* HLS_ACTION_FETCH_DATA, HLS_ACTION_PRECONNECT (origin) */
+    uint32_t duration_ms; /* HLS_ACTION_SET_TIMEOUT */
+    HlsBuffer json;       /* full JSON form of the action */
+} HlsAction;
+
+HlsFfiPlayer *hls_ffi_player_new(void);
+void hls_ffi_player_free(HlsFfiPlayer *player);
+
+int32_t hls_ffi_player_configure(HlsFfiPlayer *player, const uint8_t *json, size_t json_len);
+/* Pass NULL as `callback` to unregister. */
+int32_t hls_ffi_player_set_url_rewriter(HlsFfiPlayer *player, HlsUrlRewriterFn callback,
+                                        void *user_data);
+/* Retries fetches rejected as unauthorized with a fresh token; pass NULL to unregister. */
+int32_t hls_ffi_player_set_token_refresher(HlsFfiPlayer *player, HlsTokenRefresherFn callback,
+                                           void *user_data);
+/* Enables timing measurements and live latency; pass NULL to unregister. */
+int32_t hls_ffi_player_set_clock(HlsFfiPlayer *player, HlsClockFn callback, void *user_data);
+/* Records up to `log_level` of the configuration are passed; pass NULL to unregister. */
+int32_t hls_ffi_player_set_logger(HlsFfiPlayer *player, HlsLogFn callback, void *user_data);
+int32_t hls_ffi_player_play(HlsFfiPlayer *player,
+                            const uint8_t *url, size_t url_len,
+                            const uint8_t *m3u8, size_t m3u8_len);
+/* Stops the current playback and plays the playlist at `url`, fetched by the player. */
+int32_t hls_ffi_player_load_source(HlsFfiPlayer *player, const uint8_t *url, size_t url_len);
+/* Downloads a whole VOD media playlist as a single fMP4 stream taken by poll_segment. */
+int32_t hls_ffi_player_download(HlsFfiPlayer *player,
+                                const uint8_t *url, size_t url_len,
+                                const uint8_t *m3u8, size_t m3u8_len);
+/* Fetches the keyframes of an I-frame playlist; poll_segment takes raw H.264 access units,
+ * each preceded by a ThumbnailLoaded event. */
+int32_t hls_ffi_player_generate_thumbnails(HlsFfiPlayer *player,
+                                           const uint8_t *url, size_t url_len,
+                                           const uint8_t *m3u8, size_t m3u8_len);
+/* Queues an ad pod (VOD media playlist) replacing the main content at the next ad break. */
+int32_t hls_ffi_player_insert_ad_pod(HlsFfiPlayer *player,
+                                     const uint8_t *url, size_t url_len,
+                                     const uint8_t *m3u8, size_t m3u8_len);
+/* Queues a master or media playlist to follow the current VOD playlist without a gap. */
+int32_t hls_ffi_player_queue_next(HlsFfiPlayer *player,
+                                  const uint8_t *url, size_t url_len,
+                                  const uint8_t *m3u8, size_t m3u8_len);
+/* Trick play from the I-frame playlist at `rate` times the speed (negative for rewinding). */
+int32_t hls_ffi_player_start_trick_play(HlsFfiPlayer *player, double rate,
+                                        uint32_t position_ms);
+/* Resumes the normal playback from `position_ms`. */
+int32_t hls_ffi_player_stop_trick_play(HlsFfiPlayer *player, uint32_t position_ms);
+/* Defers steering switches until unlocked or the end of the next ad break. */
+int32_t hls_ffi_player_set_variant_locked(HlsFfiPlayer *player, int32_t locked);
+/* Slows down reloads, caps the buffer and plays the lowest variant while enabled. */
+int32_t hls_ffi_player_set_low_power(HlsFfiPlayer *player, int32_t enabled);
+/* Comma-separated RFC 5646 tags (e.g., "de,en-US"); the audio rendition is selected again. */
+int32_t hls_ffi_player_set_audio_languages(HlsFfiPlayer *player,
+                                           const uint8_t *languages, size_t languages_len);
+/* Halts fetching until resumed; live playbacks then catch up or jump to the live point. */
+int32_t hls_ffi_player_suspend(HlsFfiPlayer *player);
+int32_t hls_ffi_player_resume(HlsFfiPlayer *player);
+int32_t hls_ffi_player_stop(HlsFfiPlayer *player);
+/* Reported to servers as CMCD `bl` when enabled by the configuration. */
+int32_t hls_ffi_player_set_buffer_length(HlsFfiPlayer *player, uint32_t buffer_length_ms);
+/* Position on the media timeline of the appended segments, which starts at zero. */
+int32_t hls_ffi_player_set_playback_position(HlsFfiPlayer *player, uint32_t position_ms);
+/* `ranges_len` pairs of buffered start and end positions in milliseconds. */
+int32_t hls_ffi_player_set_buffered_ranges(HlsFfiPlayer *player, const uint32_t *ranges,
+                                           size_t ranges_len);
+
+int32_t hls_ffi_player_handle_data(HlsFfiPlayer *player, uint32_t action_id,
+                                   const uint8_t *data, size_t data_len,
+                                   uint32_t fetch_duration_ms);
+/* Also passes the post-redirect URL of the response, the base of relative URIs. */
+int32_t hls_ffi_player_handle_data_with_url(HlsFfiPlayer *player, uint32_t action_id,
+                                            const uint8_t *data, size_t data_len,
+                                            uint32_t fetch_duration_ms,
+                                            const uint8_t *final_url, size_t final_url_len);
+/* Response headers as a JSON object (Server-Timing, CMSD-Dynamic, Date, Age), before the body. */
+int32_t hls_ffi_player_handle_response_headers(HlsFfiPlayer *player, uint32_t action_id,
+                                               const uint8_t *headers_json,
+                                               size_t headers_json_len);
+/* Progressive alternative to hls_ffi_player_handle_data(); `final_url` may be NULL. */
+int32_t hls_ffi_player_handle_data_chunk(HlsFfiPlayer *player, uint32_t action_id,
+                                         const uint8_t *chunk, size_t chunk_len);
+int32_t hls_ffi_player_handle_data_end(HlsFfiPlayer *player, uint32_t action_id,
+                                       uint32_t fetch_duration_ms,
+                                       const uint8_t *final_url, size_t final_url_len);
+int32_t hls_ffi_player_handle_timeout(HlsFfiPlayer *player, uint32_t action_id);
+/* Reports a fetch exceeding the `timeout` (milliseconds) of its JSON form. */
+int32_t hls_ffi_player_handle_fetch_timeout(HlsFfiPlayer *player, uint32_t action_id);
+/* `status` is the HTTP status code, or 0 if no response was received. */
+int32_t hls_ffi_player_handle_error(HlsFfiPlayer *player, uint32_t action_id, uint16_t status);
+
+int32_t hls_ffi_player_poll_action(HlsFfiPlayer *player, HlsAction *out);
+/* JSON array of all the pending actions (`[]` if none), taken at once. */
+int32_t hls_ffi_player_drain_actions(HlsFfiPlayer *player, HlsBuffer *out);
+/* `out->len` is zero if there are no pending events; otherwise `out` holds a JSON object. */
+int32_t hls_ffi_player_poll_event(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object of bitrate, throughput, buffer length, download totals and live latency. */
+int32_t hls_ffi_player_stats(HlsFfiPlayer *player, HlsBuffer *out);
+/* Same as the stats with `timestamp_ms`, for analytics beacons. */
+int32_t hls_ffi_player_metrics(HlsFfiPlayer *player, HlsBuffer *out);
+/* HAR (HTTP Archive) like JSON object of the requested fetches. */
+int32_t hls_ffi_player_request_log(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON array of the trace records; empty unless `trace.enabled` is configured. */
+int32_t hls_ffi_player_trace(HlsFfiPlayer *player, HlsBuffer *out);
+int32_t hls_ffi_player_clear_trace(HlsFfiPlayer *player);
+/* JSON array of the inputs for replaying; empty unless `input_log.enabled` is configured. */
+int32_t hls_ffi_player_input_log(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object of the segment offsets of the download; empty if none has been started. */
+int32_t hls_ffi_player_download_manifest(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object for aligning the playback to `date_time_ms` (e.g., the `program_date_time_ms`
+ * stats of another player); empty until a segment with EXT-X-PROGRAM-DATE-TIME is produced. */
+int32_t hls_ffi_player_sync_to(HlsFfiPlayer *player, double date_time_ms, HlsBuffer *out);
+/* JSON array of the keyframe offsets of the produced segments. */
+int32_t hls_ffi_player_keyframe_index(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object of the keyframe (segment and offset) to seek to for `position_ms`;
+ * empty if none has been found yet. */
+int32_t hls_ffi_player_nearest_keyframe(HlsFfiPlayer *player, double position_ms,
+                                        HlsBuffer *out);
+/* JSON object of the total duration and segment start times of the VOD playlist;
+ * empty until it is loaded, and for live playlists. */
+int32_t hls_ffi_player_timeline(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object of the image URL and crop region of the tile previewing `position_ms`
+ * (with `thumbnails.image_streams`); empty if none. */
+int32_t hls_ffi_player_image_tile_at(HlsFfiPlayer *player, double position_ms, HlsBuffer *out);
+/* JSON array of the EXT-X-DATERANGEs which do not mark ad breaks (e.g., chapters). */
+int32_t hls_ffi_player_date_ranges(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON array of the spec violations and compatibility problems of `m3u8`. */
+int32_t hls_ffi_player_validate_playlist(HlsFfiPlayer *player,
+                                         const uint8_t *m3u8, size_t m3u8_len,
+                                         HlsBuffer *out);
+int32_t hls_ffi_player_poll_segment(HlsFfiPlayer *player, HlsBuffer *out);
+/* JSON object of the sequence number, duration, variant, discontinuity sequence number,
+ * program date time and init segment size of the last polled segment; empty until one is taken. */
+int32_t hls_ffi_player_segment_info(HlsFfiPlayer *player, HlsBuffer *out);
+/* Bytes recorded since the last call when `recording.enabled` is configured. */
+int32_t hls_ffi_player_take_recording(HlsFfiPlayer *player, HlsBuffer *out);
+int32_t hls_ffi_player_last_error(HlsFfiPlayer *player, HlsBuffer *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HLS_WASM_H */
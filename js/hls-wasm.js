@@ -10,21 +10,70 @@ class HlsPlayer {
         this.hls_wasm = hls_wasm;
         this.api = hls_wasm.exports;
         this.player = this.api.hls_player_new();
+        this.configure({reload: {random_seed: Math.floor(Math.random() * 4294967296)}});
+        this.fetches = new Map();
+        this.timers = new Map();
 
         let media_source = new MediaSource();
         media_source.addEventListener('sourceopen', () => {
             console.log("[DEBUG] Event.sourceopen");
             const mimeCodec = 'video/mp4; codecs="avc1.4dc00d,mp4a.40.2"'; // TODO
             this.sb = media_source.addSourceBuffer(mimeCodec);
+            this.mime_type = mimeCodec;
             this.sb.mode = 'sequence';
         }, false);
         this.media_source = media_source;
 
         this.video = document.getElementsByTagName('video')[0];
         this.video.src = URL.createObjectURL(media_source);
+        this.video.addEventListener('timeupdate', () => this.report_buffer_length());
+        this.video.addEventListener('waiting', () => this.report_buffer_length());
+        document.addEventListener('visibilitychange', () => this.update_low_power());
+        this.video.addEventListener('leavepictureinpicture', () => this.update_low_power());
         this.video.play();
     }
 
+    update_low_power() {
+        const background = document.hidden && !document.pictureInPictureElement;
+        this.api.hls_player_set_low_power(this.player, background ? 1 : 0);
+        this.poll();
+    }
+
+    // Selects the audio rendition of the first matching language, e.g., `['de', 'en']`.
+    set_audio_languages(languages) {
+        let error = this.with_wasm_str((new TextEncoder).encode(JSON.stringify(languages)), json => {
+            return this.api.hls_player_set_audio_languages(this.player, json);
+        });
+        if (error != 0) {
+            console.log(this.wasm_str_into_json(error));
+        }
+        this.poll();
+    }
+
+    configure(config) {
+        let error = this.with_wasm_str((new TextEncoder).encode(JSON.stringify(config)), json => {
+            return this.api.hls_player_set_config(this.player, json);
+        });
+        if (error != 0) {
+            console.log(this.wasm_str_into_json(error));
+        }
+    }
+
+    // Returns the timestamped statistics, e.g., for analytics beacons.
+    metrics() {
+        return this.wasm_str_into_json(this.api.hls_player_metrics(this.player));
+    }
+
+    // Returns the requested fetches as a HAR-like object, e.g., for support tooling.
+    request_log() {
+        return this.wasm_str_into_json(this.api.hls_player_request_log(this.player));
+    }
+
+    // Returns the diagnostic trace; it is recorded after `configure({trace: {enabled: true}})`.
+    trace() {
+        return this.wasm_str_into_json(this.api.hls_player_trace(this.player));
+    }
+
     play(m3u8_url) {
         fetch(m3u8_url)
             .then(response => response.arrayBuffer())
@@ -47,16 +96,67 @@ class HlsPlayer {
             .catch(error => alert(`Cannot fetch ${m3u8_url}\n\n[Reason]\n${error}`))
     }
 
-    fetch_url(action_id, url) {
+    handle_response_headers(action_id, response_headers) {
+        const headers = {};
+        for (const name of ["Server-Timing", "CMSD-Dynamic", "Date", "Age"]) {
+            const value = response_headers.get(name);
+            if (value !== null) {
+                headers[name] = value;
+            }
+        }
+        if (Object.keys(headers).length == 0) {
+            return;
+        }
+        let error = this.with_wasm_str((new TextEncoder).encode(JSON.stringify(headers)), json => {
+            return this.api.hls_player_handle_response_headers(this.player, action_id, json);
+        });
+        if (error != 0) {
+            console.log(this.wasm_str_into_json(error));
+        }
+    }
+
+    fetch_url(action_id, url, timeout, headers, range, priority, credentials, mode) {
         console.log(`[DEBUG] Starts fetching url: [${action_id}] ${url}`);
         const start_time = new Date();
-        fetch(url)
-            .then(response => response.arrayBuffer())
-            .then(body => {
+        const controller = new AbortController();
+        this.fetches.set(action_id, controller);
+        let timed_out = false;
+        const timer = setTimeout(() => {
+            timed_out = true;
+            controller.abort();
+        }, timeout);
+        headers = Object.assign({}, headers);
+        if (range) {
+            headers["Range"] = `bytes=${range.offset}-${range.offset + range.length - 1}`;
+        }
+        const init = {signal: controller.signal, headers: headers, priority: priority || "auto"};
+        if (credentials) {
+            init.credentials = credentials;
+        }
+        if (mode) {
+            init.mode = mode;
+        }
+        fetch(url, init)
+            .then(response => {
+                if (!response.ok) {
+                    return Promise.reject({status: response.status});
+                }
+                this.handle_response_headers(action_id, response.headers);
+                return response.arrayBuffer().then(body => ({body: body, final_url: response.url}));
+            })
+            .then(({body, final_url}) => {
+                clearTimeout(timer);
+                this.fetches.delete(action_id);
                 const end_time = new Date();
                 const fetch_duration_ms = end_time - start_time;
                 let error = this.with_wasm_bytes(new Uint8Array(body), bytes => {
-                    return this.api.hls_player_handle_data(this.player, action_id, bytes, fetch_duration_ms);
+                    if (!final_url || final_url == url) {
+                        return this.api.hls_player_handle_data(this.player, action_id, bytes, fetch_duration_ms);
+                    }
+                    return this.with_wasm_str((new TextEncoder).encode(final_url), final_url => {
+                        return this.api.hls_player_handle_data_with_url(
+                            this.player, action_id, bytes, fetch_duration_ms, final_url);
+                    });
                 });
                 if (error != 0) {
                     let json = this.wasm_str_into_json(error);
@@ -65,6 +165,20 @@ class HlsPlayer {
                 }
                 console.log(`[DEBUG] Handled: [${action_id}] ${url} (delay:${fetch_duration_ms})`);
                 this.poll();
+            }, error => {
+                clearTimeout(timer);
+                if (!this.fetches.delete(action_id)) {
+                    return; // Aborted by the player
+                }
+                const status = (error && error.status) || 0;
+                console.log(`[WARN] Cannot fetch url: [${action_id}] ${url} (status:${status}, timed_out:${timed_out})`);
+                let result = timed_out
+                    ? this.api.hls_player_handle_fetch_timeout(this.player, action_id)
+                    : this.api.hls_player_handle_error(this.player, action_id, status);
+                if (result != 0) {
+                    return Promise.reject(JSON.stringify(this.wasm_str_into_json(result)));
+                }
+                this.poll();
             })
             .catch(error => alert(`Cannot fetch url: ${url}: ${error}`))
     }
@@ -89,6 +203,17 @@ class HlsPlayer {
         return result;
     }
 
+    // Writes the produced segments into `ring` (see `hls_output_ring_new`),
+    // and wakes up the consumer waiting in `readOutputRing`.
+    fill_output_ring(ring) {
+        let error = this.api.hls_player_fill_output_ring(this.player, ring);
+        if (error != 0) {
+            console.log(this.wasm_str_into_json(error));
+        }
+        const header = new Int32Array(this.api.memory.buffer, this.api.hls_output_ring_header_ptr(ring), 4);
+        Atomics.notify(header, 3);
+    }
+
     wasm_str_into_json(s) {
         let buf = new Uint8Array(this.api.memory.buffer, this.api.wasm_str_ptr(s), this.api.wasm_str_len(s));
         let json = JSON.parse(new TextDecoder("utf-8").decode(buf));
@@ -114,45 +239,171 @@ class HlsPlayer {
             new Uint8Array(this.api.memory.buffer,
                            this.api.wasm_bytes_ptr(wasm_bytes),
                            this.api.wasm_bytes_len(wasm_bytes));
-        console.log(`[DEBUG] segment: ${segment.length} bytes (ptr:${this.api.wasm_bytes_ptr(wasm_bytes)})`);
-        this.sb.appendBuffer(segment);
-        this.api.wasm_bytes_free(wasm_bytes);
+        let info = this.wasm_str_into_json(this.api.hls_player_segment_info(this.player));
+        const media_type = info["media_type"];
+        if (media_type && media_type["mime_type"] != this.mime_type && this.sb.changeType) {
+            this.sb.changeType(media_type["mime_type"]); // Codecs of the following segments
+            this.mime_type = media_type["mime_type"];
+        }
+        console.log(`[DEBUG] segment: ${segment.length} bytes (ptr:${this.api.wasm_bytes_ptr(wasm_bytes)}): ${JSON.stringify(info)}`);
+        this.sb.appendBuffer(segment); // Copies `segment`
+        this.api.hls_player_recycle_segment(this.player, wasm_bytes);
 
         this.sb.addEventListener('updateend', () => {
-            this.poll_segment();
+            this.report_buffer_length();
+            this.poll(); // Taking segments may resume suspended fetches
+        });
+    }
+    report_buffer_length() {
+        if (!this.sb) {
+            return;
+        }
+        const buffered = this.sb.buffered;
+        const ahead = buffered.length > 0
+              ? buffered.end(buffered.length - 1) - this.video.currentTime
+              : 0;
+        this.api.hls_player_set_buffer_length(this.player, Math.max(0, Math.round(ahead * 1000)));
+        const ranges = [];
+        for (let i = 0; i < buffered.length; i++) {
+            ranges.push([Math.round(buffered.start(i) * 1000), Math.round(buffered.end(i) * 1000)]);
+        }
+        this.with_wasm_str((new TextEncoder).encode(JSON.stringify(ranges)), json => {
+            return this.api.hls_player_set_buffered_ranges(this.player, json);
         });
+        this.api.hls_player_set_playback_position(this.player, Math.round(this.video.currentTime * 1000));
+        this.poll_event();
+    }
+    poll_event() {
+        while (true) {
+            let json = this.api.hls_player_next_event(this.player);
+            if (json == 0) {
+                break;
+            }
+
+            let event = this.wasm_str_into_json(json);
+            console.log(`[DEBUG] Event: ${JSON.stringify(event)}`);
+            if (event["type"] == "GapNudge") {
+                this.video.currentTime = event["jump_to_ms"] / 1000; // Jumps over the gap
+            } else if (event["type"] == "Error") {
+                if (event["fatal"]) {
+                    console.log(`[ERROR] History: ${JSON.stringify(event["history"] || [])}`);
+                    alert(`Playback failed: ${event["reason"]}`);
+                } else {
+                    console.log(`[WARN] ${event["reason"]}`);
+                }
+            }
+        }
     }
     poll() {
         this.poll_segment();
+        this.poll_event();
 
         while (true) {
-            let json = this.api.hls_player_next_action(this.player);
+            let json = this.api.hls_player_drain_actions(this.player);
             if (json == 0) {
                 break;
             }
 
-            let action = this.wasm_str_into_json(json);
-            console.log(`[DEBUG] Next Action: ${JSON.stringify(action)}`);
-            if (action["type"] == "FetchData") {
-                this.fetch_url(action["action_id"], action["url"]);
-            } else if (action["type"] == "SetTimeout") {
-                setTimeout(() => {
-                    let error = this.api.hls_player_handle_timeout(this.player, action["action_id"]);
-                    if (error != 0) {
-                        let json = this.wasm_str_into_json(error);
-                        console.log(json);
-                    };
-                    this.poll();
-                }, action["duration"]);
-            } else {
-                console.log("[WARN] Unknown action");
+            for (const action of this.wasm_str_into_json(json)) {
+                console.log(`[DEBUG] Next Action: ${JSON.stringify(action)}`);
+                if (action["type"] == "FetchData") {
+                    this.fetch_url(action["action_id"], action["url"], action["timeout"],
+                                   action["headers"], action["range"], action["priority"],
+                                   action["credentials"], action["mode"]);
+                } else if (action["type"] == "SetTimeout") {
+                    const timer = setTimeout(() => {
+                        this.timers.delete(action["action_id"]);
+                        let error = this.api.hls_player_handle_timeout(this.player, action["action_id"]);
+                        if (error != 0) {
+                            let json = this.wasm_str_into_json(error);
+                            console.log(json);
+                        };
+                        this.poll();
+                    }, action["duration"]);
+                    this.timers.set(action["action_id"], timer);
+                } else if (action["type"] == "Abort") {
+                    let controller = this.fetches.get(action["action_id"]);
+                    if (controller) {
+                        this.fetches.delete(action["action_id"]);
+                        controller.abort();
+                    }
+                    let timer = this.timers.get(action["action_id"]);
+                    if (timer !== undefined) {
+                        this.timers.delete(action["action_id"]);
+                        clearTimeout(timer);
+                    }
+                } else if (action["type"] == "Preconnect") {
+                    let link = document.createElement('link');
+                    link.rel = 'preconnect';
+                    link.href = action["origin"];
+                    link.crossOrigin = 'anonymous';
+                    document.head.appendChild(link);
+                } else {
+                    console.log("[WARN] Unknown action");
+                }
             }
         }
     }
 }
 
 var hls_wasm;
-fetchAndInstantiate("../target/wasm32-unknown-unknown/release/hls_wasm.wasm", {})
+const LOG_LEVELS = ["", "ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+
+function hostLog(level, ptr, len) {
+    const message = new TextDecoder().decode(new Uint8Array(hls_wasm.exports.memory.buffer, ptr, len));
+    const line = `[${LOG_LEVELS[level]}] ${message}`;
+    if (level <= 1) {
+        console.error(line);
+    } else if (level == 2) {
+        console.warn(line);
+    } else {
+        console.log(line);
+    }
+}
+
+// Reads the records of an output ring from `memory` (the shared memory of the player, given as
+// `env.memory` to the module built as "Shared memory" in README.md),
+// e.g., in another thread after `Atomics.wait(new Int32Array(memory, header_ptr, 4), 3, count)`.
+// `onSegment` is given a view into the ring, which is reused once it returns.
+function readOutputRing(memory, header_ptr, data_ptr, onSegment) {
+    const header = new Int32Array(memory, header_ptr, 4);
+    const capacity = Atomics.load(header, 2);
+    const view = new DataView(memory, data_ptr, capacity);
+    let read = Atomics.load(header, 1);
+    while (read != Atomics.load(header, 0)) {
+        let len = view.getUint32(read, true);
+        if (len == 0) { // Wraps around
+            read = 0;
+            len = view.getUint32(0, true);
+        }
+        const info_len = view.getUint32(read + 4, true);
+        const info_bytes = new Uint8Array(memory, data_ptr + read + 8, info_len).slice();
+        const info = JSON.parse(new TextDecoder().decode(info_bytes));
+        onSegment(new Uint8Array(memory, data_ptr + read + 8 + info_len, len - 8 - info_len), info);
+        read = (read + Math.ceil(len / 4) * 4) % capacity;
+        Atomics.store(header, 1, read);
+    }
+}
+
+// Receiver of the segments taken by `hls_player_transfer_segment`, e.g., in a Web Worker:
+// `onTransferredSegment = (data, info) => postMessage({data, info}, [data]);`
+var onTransferredSegment = null;
+
+function hostTransferSegment(data_ptr, data_len, info_ptr, info_len) {
+    const memory = hls_wasm.exports.memory.buffer;
+    const data = memory.slice(data_ptr, data_ptr + data_len); // Owned copy, which may be transferred
+    const info = JSON.parse(new TextDecoder().decode(new Uint8Array(memory, info_ptr, info_len)));
+    if (onTransferredSegment) {
+        onTransferredSegment(data, info);
+    }
+}
+
+fetchAndInstantiate("../target/wasm32-unknown-unknown/release/hls_wasm.wasm",
+                    {env: {
+                        hls_host_now_ms: () => performance.timeOrigin + performance.now(),
+                        hls_host_log: hostLog,
+                        hls_host_transfer_segment: hostTransferSegment,
+                    }})
     .then(instance => {
         hls_wasm = instance;
     });
@@ -1,20 +1,21 @@
 use std;
 use hls_m3u8;
+use mpeg2ts;
 use mse_fmp4;
+use serde_json;
 use trackable::Trackable;
 use trackable::error::{ErrorKind as TrackableErrorKind, ErrorKindExt, TrackableError};
 use url;
 
 /// This crate specific error type.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, TrackableError)]
 pub struct Error(TrackableError<ErrorKind>);
-derive_traits_for_trackable_error_newtype!(Error, ErrorKind);
 impl Error {
     pub fn to_json_string(&self) -> String {
         use std::error::Error as StdError;
 
         let kind = format!("{:?}", self.kind());
-        let reason = self.cause()
+        let reason = self.source()
             .as_ref()
             .map(|c| c.to_string())
             .unwrap_or_else(|| "".to_owned());
@@ -58,6 +59,20 @@ impl From<std::str::Utf8Error> for Error {
         ErrorKind::InvalidInput.cause(f).into()
     }
 }
+impl From<serde_json::Error> for Error {
+    fn from(f: serde_json::Error) -> Self {
+        ErrorKind::InvalidInput.cause(f).into()
+    }
+}
+impl From<mpeg2ts::Error> for Error {
+    fn from(f: mpeg2ts::Error) -> Self {
+        let kind = match *f.kind() {
+            mpeg2ts::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            mpeg2ts::ErrorKind::Unsupported | mpeg2ts::ErrorKind::Other => ErrorKind::Other,
+        };
+        kind.takes_over(f).into()
+    }
+}
 impl From<mse_fmp4::Error> for Error {
     fn from(f: mse_fmp4::Error) -> Self {
         let kind = match *f.kind() {
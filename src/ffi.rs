@@ -0,0 +1,1439 @@
+//! Stable C ABI.
+//!
+//! The functions in `wasm_api` exchange Rust-owned handles and are meant to be driven by
+//! `js/hls-wasm.js`. The functions in this module instead use the C calling convention and
+//! `#[repr(C)]` structures, so that the same player core can be embedded from non-JS hosts
+//! (e.g., smart TV middleware or Node N-API addons). See `include/hls_wasm.h`.
+//!
+//! All functions return a status code (`HLS_OK` on success).
+//! Buffers handed out by the player are owned by it and remain valid
+//! until the next call on the same player handle.
+use std::collections::BTreeMap;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+use std::str;
+use std::time::Duration;
+use url::Url;
+use url::form_urlencoded;
+
+use {Error, ErrorKind, HlsPlayer, Result};
+use player::{validate, Action, ActionId, Clock, LogLevel, Logger, ResourceKind, TokenRefresher,
+             UrlRewriter};
+
+pub const HLS_OK: i32 = 0;
+pub const HLS_ERROR_INVALID_INPUT: i32 = 1;
+pub const HLS_ERROR_OTHER: i32 = 2;
+pub const HLS_ERROR_NULL_POINTER: i32 = 3;
+
+pub const HLS_ACTION_NONE: u32 = 0;
+pub const HLS_ACTION_FETCH_DATA: u32 = 1;
+pub const HLS_ACTION_SET_TIMEOUT: u32 = 2;
+pub const HLS_ACTION_ABORT: u32 = 3;
+pub const HLS_ACTION_PRECONNECT: u32 = 4;
+
+pub const HLS_RESOURCE_PLAYLIST: u32 = 0;
+pub const HLS_RESOURCE_SEGMENT: u32 = 1;
+pub const HLS_RESOURCE_INIT_SEGMENT: u32 = 2;
+pub const HLS_RESOURCE_STEERING_MANIFEST: u32 = 3;
+pub const HLS_RESOURCE_SUBTITLES: u32 = 4;
+pub const HLS_RESOURCE_KEY: u32 = 5;
+
+pub const HLS_LOG_ERROR: u32 = 1;
+pub const HLS_LOG_WARN: u32 = 2;
+pub const HLS_LOG_INFO: u32 = 3;
+pub const HLS_LOG_DEBUG: u32 = 4;
+pub const HLS_LOG_TRACE: u32 = 5;
+
+/// Maximum length of the URLs returned by `HlsUrlRewriterFn`.
+pub const HLS_MAX_URL_LEN: usize = 8192;
+
+/// URL rewriting callback.
+///
+/// It writes the rewritten form of `url` to `out` (which can hold `out_capacity` bytes),
+/// and returns the written length. Returning zero (or a value larger than `out_capacity`)
+/// keeps the original URL.
+pub type HlsUrlRewriterFn = extern "C" fn(
+    user_data: *mut c_void,
+    kind: u32,
+    url: *const u8,
+    url_len: usize,
+    out: *mut u8,
+    out_capacity: usize,
+) -> usize;
+
+/// Token refresh callback, called when a fetch of `url` is rejected with 401 or 403.
+///
+/// It writes the query parameters carrying the fresh token (e.g., `token=abc&expires=123`)
+/// to `out` (which can hold `out_capacity` bytes), and returns the written length.
+/// Returning zero (or a value larger than `out_capacity`) fails the fetch as before.
+pub type HlsTokenRefresherFn = extern "C" fn(
+    user_data: *mut c_void,
+    kind: u32,
+    url: *const u8,
+    url_len: usize,
+    out: *mut u8,
+    out_capacity: usize,
+) -> usize;
+
+/// Read-only byte buffer owned by the player.
+///
+/// Text buffers (URLs and JSON) are followed by a NUL byte that is not counted in `len`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HlsBuffer {
+    pub data: *const u8,
+    pub len: usize,
+}
+impl HlsBuffer {
+    fn null() -> Self {
+        HlsBuffer {
+            data: ptr::null(),
+            len: 0,
+        }
+    }
+
+    fn from_text(buf: &[u8]) -> Self {
+        HlsBuffer {
+            data: buf.as_ptr(),
+            len: buf.len() - 1,
+        }
+    }
+}
+
+/// Action requested from the host.
+///
+/// `url` is set for `HLS_ACTION_FETCH_DATA` and `duration_ms` for `HLS_ACTION_SET_TIMEOUT`.
+/// `json` always holds the JSON form of the action, which may carry additional fields
+/// that are not part of this fixed layout.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HlsAction {
+    pub kind: u32,
+    pub action_id: u32,
+    pub url: HlsBuffer,
+    pub duration_ms: u32,
+    pub json: HlsBuffer,
+}
+impl HlsAction {
+    fn none() -> Self {
+        HlsAction {
+            kind: HLS_ACTION_NONE,
+            action_id: 0,
+            url: HlsBuffer::null(),
+            duration_ms: 0,
+            json: HlsBuffer::null(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FfiUrlRewriter {
+    callback: HlsUrlRewriterFn,
+    user_data: *mut c_void,
+}
+impl UrlRewriter for FfiUrlRewriter {
+    fn rewrite_url(&self, kind: ResourceKind, url: Url) -> Url {
+        let kind = resource_kind_code(kind);
+        let mut out = vec![0; HLS_MAX_URL_LEN];
+        let len = {
+            let url = url.as_str();
+            (self.callback)(
+                self.user_data,
+                kind,
+                url.as_ptr(),
+                url.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        if len == 0 || len > out.len() {
+            return url;
+        }
+        str::from_utf8(&out[..len])
+            .ok()
+            .and_then(|s| Url::parse(s).ok())
+            .unwrap_or(url)
+    }
+}
+
+#[derive(Debug)]
+struct FfiTokenRefresher {
+    callback: HlsTokenRefresherFn,
+    user_data: *mut c_void,
+}
+impl TokenRefresher for FfiTokenRefresher {
+    fn refresh_token(&self, kind: ResourceKind, url: &Url) -> Option<BTreeMap<String, String>> {
+        let mut out = vec![0; HLS_MAX_URL_LEN];
+        let len = {
+            let url = url.as_str();
+            (self.callback)(
+                self.user_data,
+                resource_kind_code(kind),
+                url.as_ptr(),
+                url.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        if len == 0 || len > out.len() {
+            return None;
+        }
+        let params = form_urlencoded::parse(&out[..len]).into_owned().collect();
+        Some(params)
+    }
+}
+
+fn resource_kind_code(kind: ResourceKind) -> u32 {
+    match kind {
+        ResourceKind::Playlist => HLS_RESOURCE_PLAYLIST,
+        ResourceKind::Segment => HLS_RESOURCE_SEGMENT,
+        ResourceKind::InitSegment => HLS_RESOURCE_INIT_SEGMENT,
+        ResourceKind::SteeringManifest => HLS_RESOURCE_STEERING_MANIFEST,
+        ResourceKind::Subtitles => HLS_RESOURCE_SUBTITLES,
+        ResourceKind::Key => HLS_RESOURCE_KEY,
+    }
+}
+
+/// Player handle used by the C ABI.
+#[derive(Debug)]
+pub struct HlsFfiPlayer {
+    player: HlsPlayer,
+    action_url: Vec<u8>,
+    action_json: Vec<u8>,
+    actions_json: Vec<u8>,
+    event_json: Vec<u8>,
+    stats_json: Vec<u8>,
+    metrics_json: Vec<u8>,
+    request_log_json: Vec<u8>,
+    trace_json: Vec<u8>,
+    input_log_json: Vec<u8>,
+    download_manifest_json: Vec<u8>,
+    validation_json: Vec<u8>,
+    sync_json: Vec<u8>,
+    keyframes_json: Vec<u8>,
+    timeline_json: Vec<u8>,
+    image_tile_json: Vec<u8>,
+    date_ranges_json: Vec<u8>,
+    recording: Vec<u8>,
+    segment: Vec<u8>,
+    segment_info_json: Vec<u8>,
+    last_error: Vec<u8>,
+}
+impl HlsFfiPlayer {
+    fn new() -> Self {
+        HlsFfiPlayer {
+            player: HlsPlayer::new(),
+            action_url: Vec::new(),
+            action_json: Vec::new(),
+            actions_json: Vec::new(),
+            event_json: Vec::new(),
+            stats_json: Vec::new(),
+            metrics_json: Vec::new(),
+            request_log_json: Vec::new(),
+            trace_json: Vec::new(),
+            input_log_json: Vec::new(),
+            download_manifest_json: Vec::new(),
+            validation_json: Vec::new(),
+            sync_json: Vec::new(),
+            keyframes_json: Vec::new(),
+            timeline_json: Vec::new(),
+            image_tile_json: Vec::new(),
+            date_ranges_json: Vec::new(),
+            recording: Vec::new(),
+            segment: Vec::new(),
+            segment_info_json: Vec::new(),
+            last_error: vec![0],
+        }
+    }
+
+    fn status(&mut self, result: Result<()>) -> i32 {
+        match result {
+            Ok(()) => HLS_OK,
+            Err(e) => {
+                let status = match *e.kind() {
+                    ErrorKind::InvalidInput => HLS_ERROR_INVALID_INPUT,
+                    ErrorKind::Other => HLS_ERROR_OTHER,
+                };
+                self.last_error = nul_terminated(e.to_json_string());
+                status
+            }
+        }
+    }
+}
+
+fn nul_terminated(s: String) -> Vec<u8> {
+    let mut buf = s.into_bytes();
+    buf.push(0);
+    buf
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn text<'a>(data: *const u8, len: usize) -> Result<&'a str> {
+    track!(str::from_utf8(bytes(data, len)).map_err(Error::from))
+}
+
+macro_rules! player_mut {
+    ($player:expr) => {
+        match $player.as_mut() {
+            None => return HLS_ERROR_NULL_POINTER,
+            Some(p) => p,
+        }
+    };
+}
+
+/// Creates a new player. The handle must be released by `hls_ffi_player_free`.
+#[no_mangle]
+pub extern "C" fn hls_ffi_player_new() -> *mut HlsFfiPlayer {
+    Box::into_raw(Box::new(HlsFfiPlayer::new()))
+}
+
+/// Releases a player created by `hls_ffi_player_new`.
+///
+/// # Safety
+///
+/// `player` must be null or a handle returned by `hls_ffi_player_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_free(player: *mut HlsFfiPlayer) {
+    if !player.is_null() {
+        let _ = Box::from_raw(player);
+    }
+}
+
+/// Sets the player configuration from a JSON object (see `PlayerConfig`).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `json` must point to `json_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_configure(
+    player: *mut HlsFfiPlayer,
+    json: *const u8,
+    json_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = text(json, json_len).and_then(|json| track!(p.player.set_config_json(json)));
+    p.status(result)
+}
+
+/// Registers (or unregisters, if `callback` is null) the URL rewriting callback.
+///
+/// It is applied to every URL requested by `HLS_ACTION_FETCH_DATA` actions
+/// issued by the playback started after this call.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `user_data` must remain valid while the callback is
+/// registered.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_url_rewriter(
+    player: *mut HlsFfiPlayer,
+    callback: Option<HlsUrlRewriterFn>,
+    user_data: *mut c_void,
+) -> i32 {
+    let p = player_mut!(player);
+    let mut hooks = p.player.config().hooks.clone();
+    hooks.url_rewriter = callback.map(|callback| {
+        Rc::new(FfiUrlRewriter {
+            callback,
+            user_data,
+        }) as Rc<dyn UrlRewriter>
+    });
+    p.player.set_hooks(hooks);
+    HLS_OK
+}
+
+/// Registers (or unregisters, if `callback` is null) the token refresh callback.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `user_data` must remain valid while the callback is
+/// registered.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_token_refresher(
+    player: *mut HlsFfiPlayer,
+    callback: Option<HlsTokenRefresherFn>,
+    user_data: *mut c_void,
+) -> i32 {
+    let p = player_mut!(player);
+    let mut hooks = p.player.config().hooks.clone();
+    hooks.token_refresher = callback.map(|callback| {
+        Rc::new(FfiTokenRefresher {
+            callback,
+            user_data,
+        }) as Rc<dyn TokenRefresher>
+    });
+    p.player.set_hooks(hooks);
+    HLS_OK
+}
+
+/// Returns the current wall-clock time in milliseconds since the UNIX epoch.
+pub type HlsClockFn = extern "C" fn(user_data: *mut c_void) -> f64;
+
+#[derive(Debug)]
+struct FfiClock {
+    callback: HlsClockFn,
+    user_data: *mut c_void,
+}
+impl Clock for FfiClock {
+    fn now(&self) -> Duration {
+        let now_ms = (self.callback)(self.user_data);
+        Duration::from_secs_f64(now_ms.max(0.0) / 1000.0)
+    }
+}
+
+/// Registers (or, if `callback` is `None`, unregisters) the clock used for timing measurements
+/// and live latency.
+///
+/// It takes effect at the next play call.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `user_data` must remain valid while the callback is
+/// registered.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_clock(
+    player: *mut HlsFfiPlayer,
+    callback: Option<HlsClockFn>,
+    user_data: *mut c_void,
+) -> i32 {
+    let p = player_mut!(player);
+    let mut hooks = p.player.config().hooks.clone();
+    hooks.clock = callback.map(|callback| {
+        Rc::new(FfiClock {
+            callback,
+            user_data,
+        }) as Rc<dyn Clock>
+    });
+    p.player.set_hooks(hooks);
+    HLS_OK
+}
+
+/// Receives a log record; `message` is UTF-8 and not NUL-terminated.
+pub type HlsLogFn =
+    extern "C" fn(user_data: *mut c_void, level: u32, message: *const u8, message_len: usize);
+
+#[derive(Debug)]
+struct FfiLogger {
+    callback: HlsLogFn,
+    user_data: *mut c_void,
+}
+impl Logger for FfiLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        let level = match level {
+            LogLevel::Error => HLS_LOG_ERROR,
+            LogLevel::Warn => HLS_LOG_WARN,
+            LogLevel::Info => HLS_LOG_INFO,
+            LogLevel::Debug => HLS_LOG_DEBUG,
+            LogLevel::Trace => HLS_LOG_TRACE,
+        };
+        (self.callback)(self.user_data, level, message.as_ptr(), message.len());
+    }
+}
+
+/// Registers (or, if `callback` is `None`, unregisters) the receiver of log records.
+///
+/// The most verbose level is given by `log_level` of the configuration.
+/// It takes effect at the next play call.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `user_data` must remain valid while the callback is
+/// registered.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_logger(
+    player: *mut HlsFfiPlayer,
+    callback: Option<HlsLogFn>,
+    user_data: *mut c_void,
+) -> i32 {
+    let p = player_mut!(player);
+    let mut hooks = p.player.config().hooks.clone();
+    hooks.logger = callback.map(|callback| {
+        Rc::new(FfiLogger {
+            callback,
+            user_data,
+        }) as Rc<dyn Logger>
+    });
+    p.player.set_hooks(hooks);
+    HLS_OK
+}
+
+/// Starts playing a master or media playlist fetched from `url`.
+///
+/// Invalid UTF-8 sequences in `m3u8` are replaced with `U+FFFD`.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url`/`m3u8` must point to
+/// `url_len`/`m3u8_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_play(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+    m3u8: *const u8,
+    m3u8_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+        track!(p.player.play(url, &m3u8))
+    })();
+    p.status(result)
+}
+
+/// Switches to the master or media playlist at `url`, which is fetched by the player.
+///
+/// The current playback is stopped, and its fetches in flight are cancelled.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url` must point to `url_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_load_source(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        track!(p.player.load_source(url))
+    })();
+    p.status(result)
+}
+
+/// Starts downloading all the segments of the VOD media playlist `m3u8` fetched from `url`,
+/// which are taken by `hls_ffi_player_poll_segment` in order as a single fMP4 stream.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url`/`m3u8` must point to
+/// `url_len`/`m3u8_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_download(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+    m3u8: *const u8,
+    m3u8_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+        track!(p.player.download(url, &m3u8))
+    })();
+    p.status(result)
+}
+
+/// Starts fetching the keyframes of the I-frame playlist `m3u8` fetched from `url`.
+///
+/// Each segment taken by `hls_ffi_player_poll_segment` is a raw H.264 access unit,
+/// described by the `ThumbnailLoaded` event polled before it.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url`/`m3u8` must point to
+/// `url_len`/`m3u8_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_generate_thumbnails(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+    m3u8: *const u8,
+    m3u8_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+        track!(p.player.generate_thumbnails(url, &m3u8))
+    })();
+    p.status(result)
+}
+
+/// Queues the ad pod `m3u8` (a VOD media playlist fetched from `url`) for the next ad break
+/// of the current playback.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url`/`m3u8` must point to
+/// `url_len`/`m3u8_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_insert_ad_pod(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+    m3u8: *const u8,
+    m3u8_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+        track!(p.player.insert_ad_pod(url, &m3u8))
+    })();
+    p.status(result)
+}
+
+/// Queues the master or media playlist `m3u8` fetched from `url` to be played
+/// once the current VOD playlist has ended.
+///
+/// # Safety
+///
+/// `player` must be a live handle, and `url`/`m3u8` must point to
+/// `url_len`/`m3u8_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_queue_next(
+    player: *mut HlsFfiPlayer,
+    url: *const u8,
+    url_len: usize,
+    m3u8: *const u8,
+    m3u8_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let url = track!(text(url, url_len))?;
+        let url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+        track!(p.player.queue_next(url, &m3u8))
+    })();
+    p.status(result)
+}
+
+/// Switches the master playlist being played to trick play at `rate` times the normal speed
+/// (negative for rewinding) from `position_ms`; keyframes are taken as video-only segments.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_start_trick_play(
+    player: *mut HlsFfiPlayer,
+    rate: f64,
+    position_ms: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    let position = Duration::from_millis(u64::from(position_ms));
+    let result = p.player.start_trick_play(rate, position);
+    p.status(result)
+}
+
+/// Leaves trick play, and resumes the normal playback from `position_ms`.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_stop_trick_play(
+    player: *mut HlsFfiPlayer,
+    position_ms: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    let position = Duration::from_millis(u64::from(position_ms));
+    let result = p.player.stop_trick_play(position);
+    p.status(result)
+}
+
+/// Locks (non-zero `locked`) or unlocks the played variant of the master playlist;
+/// the lock is released at the end of the next ad break.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_variant_locked(
+    player: *mut HlsFfiPlayer,
+    locked: i32,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.set_variant_locked(locked != 0);
+    p.status(result)
+}
+
+/// Enters or leaves the low-power mode (e.g., when the page is hidden or shown).
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_low_power(
+    player: *mut HlsFfiPlayer,
+    enabled: i32,
+) -> i32 {
+    let p = player_mut!(player);
+    p.player.set_low_power(enabled != 0);
+    HLS_OK
+}
+
+/// Replaces the preferred audio languages by the comma-separated RFC 5646 tags `languages`
+/// (e.g., `de,en-US`), selecting the audio rendition of the played variant again.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `languages` must point to `languages_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_audio_languages(
+    player: *mut HlsFfiPlayer,
+    languages: *const u8,
+    languages_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = text(languages, languages_len).map(|languages| {
+        let languages = languages
+            .split(',')
+            .map(|l| l.trim().to_owned())
+            .filter(|l| !l.is_empty())
+            .collect();
+        p.player.set_audio_languages(languages);
+    });
+    p.status(result)
+}
+
+/// Halts fetching until `hls_ffi_player_resume`, remembering the position in the playlist.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_suspend(player: *mut HlsFfiPlayer) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.suspend();
+    p.status(result)
+}
+
+/// Resumes the playback halted by `hls_ffi_player_suspend`, catching up or jumping to live.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_resume(player: *mut HlsFfiPlayer) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.resume();
+    p.status(result)
+}
+
+/// Stops the current playback; in-flight fetches and pending timers are cancelled
+/// by `HLS_ACTION_ABORT` actions.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_stop(player: *mut HlsFfiPlayer) -> i32 {
+    let p = player_mut!(player);
+    p.player.stop();
+    HLS_OK
+}
+
+/// Notifies the length of the media buffered ahead of the playback position (reported as CMCD).
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_buffer_length(
+    player: *mut HlsFfiPlayer,
+    buffer_length_ms: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    p.player
+        .set_buffer_length(Duration::from_millis(u64::from(buffer_length_ms)));
+    HLS_OK
+}
+
+/// Notifies the playback position on the media timeline of the appended segments,
+/// which starts at zero.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_playback_position(
+    player: *mut HlsFfiPlayer,
+    position_ms: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    p.player
+        .set_playback_position(Duration::from_millis(u64::from(position_ms)));
+    HLS_OK
+}
+
+/// Notifies the buffered time ranges as `ranges_len` pairs of start and end positions
+/// in milliseconds, which may emit gap nudge events.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `ranges` must point to `2 * ranges_len` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_set_buffered_ranges(
+    player: *mut HlsFfiPlayer,
+    ranges: *const u32,
+    ranges_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let values = if ranges.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(ranges, ranges_len * 2)
+    };
+    let ranges = values
+        .chunks(2)
+        .map(|r| {
+            (
+                Duration::from_millis(u64::from(r[0])),
+                Duration::from_millis(u64::from(r[1])),
+            )
+        })
+        .collect::<Vec<_>>();
+    p.player.set_buffered_ranges(&ranges);
+    HLS_OK
+}
+
+/// Passes the response body of a `HLS_ACTION_FETCH_DATA` action to the player.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `data` must point to `data_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_data(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    data: *const u8,
+    data_len: usize,
+    fetch_duration_ms: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    let data = bytes(data, data_len);
+    let result = p.player
+        .handle_data(ActionId::from(action_id), data, fetch_duration_ms);
+    p.status(result)
+}
+
+/// Same as `hls_ffi_player_handle_data`, but also passes the URL the response was served from
+/// after redirects, against which relative URIs are resolved.
+///
+/// # Safety
+///
+/// `player` must be a live handle, `data` must point to `data_len` readable bytes
+/// and `final_url` to `final_url_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_data_with_url(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    data: *const u8,
+    data_len: usize,
+    fetch_duration_ms: u32,
+    final_url: *const u8,
+    final_url_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let data = bytes(data, data_len);
+        let final_url = track!(text(final_url, final_url_len))?;
+        let final_url = track!(Url::parse(final_url).map_err(Error::from))?;
+        track!(p.player.handle_data_with_url(
+            ActionId::from(action_id),
+            data,
+            fetch_duration_ms,
+            Some(&final_url)
+        ))
+    })();
+    p.status(result)
+}
+
+/// Passes the response headers of a `HLS_ACTION_FETCH_DATA` action to the player,
+/// as a JSON object of their names and values (see `HlsPlayer::handle_response_headers`).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `headers_json` must point to `headers_json_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_response_headers(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    headers_json: *const u8,
+    headers_json_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let action_id = ActionId::from(action_id);
+    let result = text(headers_json, headers_json_len)
+        .and_then(|json| track!(p.player.handle_response_headers_json(action_id, json)));
+    p.status(result)
+}
+
+/// Passes a chunk of the response body of a `HLS_ACTION_FETCH_DATA` action to the player.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `chunk` must point to `chunk_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_data_chunk(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    chunk: *const u8,
+    chunk_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let chunk = bytes(chunk, chunk_len);
+    let result = p.player.handle_data_chunk(ActionId::from(action_id), chunk);
+    p.status(result)
+}
+
+/// Notifies the end of the response body passed by `hls_ffi_player_handle_data_chunk`.
+///
+/// `final_url` may be NULL; otherwise it is the same as `hls_ffi_player_handle_data_with_url`.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `final_url` (if non-NULL) must point to
+/// `final_url_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_data_end(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    fetch_duration_ms: u32,
+    final_url: *const u8,
+    final_url_len: usize,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = (|| {
+        let final_url = if final_url.is_null() {
+            None
+        } else {
+            let url = track!(text(final_url, final_url_len))?;
+            Some(track!(Url::parse(url).map_err(Error::from))?)
+        };
+        track!(p.player.handle_data_end(
+            ActionId::from(action_id),
+            fetch_duration_ms,
+            final_url.as_ref()
+        ))
+    })();
+    p.status(result)
+}
+
+/// Notifies the player that the timer of a `HLS_ACTION_SET_TIMEOUT` action has expired.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_timeout(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.handle_timeout(ActionId::from(action_id));
+    p.status(result)
+}
+
+/// Notifies the player that the fetch of a `HLS_ACTION_FETCH_DATA` action did not complete
+/// within the `timeout` given in its JSON form.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_fetch_timeout(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.handle_fetch_timeout(ActionId::from(action_id));
+    p.status(result)
+}
+
+/// Notifies the player that the fetch of a `HLS_ACTION_FETCH_DATA` action has failed.
+///
+/// `status` is the HTTP status code of the response, or `0` if no response was received.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_handle_error(
+    player: *mut HlsFfiPlayer,
+    action_id: u32,
+    status: u16,
+) -> i32 {
+    let p = player_mut!(player);
+    let result = p.player.handle_error(ActionId::from(action_id), status);
+    p.status(result)
+}
+
+/// Takes the next action to execute.
+///
+/// `out.kind` is set to `HLS_ACTION_NONE` if there are no pending actions.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_poll_action(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsAction,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsAction::none();
+    if let Some(action) = p.player.next_action() {
+        let json = json!(action).to_string();
+        p.action_json = nul_terminated(json);
+        out.action_id = action.id().into();
+        out.json = HlsBuffer::from_text(&p.action_json);
+
+        match action {
+            Action::FetchData { url, .. } => {
+                p.action_url = nul_terminated(url.into_string());
+                out.kind = HLS_ACTION_FETCH_DATA;
+                out.url = HlsBuffer::from_text(&p.action_url);
+            }
+            Action::SetTimeout { duration, .. } => {
+                out.kind = HLS_ACTION_SET_TIMEOUT;
+                out.duration_ms = duration;
+            }
+            Action::Abort { .. } => {
+                out.kind = HLS_ACTION_ABORT;
+            }
+            Action::Preconnect { origin, .. } => {
+                p.action_url = nul_terminated(origin);
+                out.kind = HLS_ACTION_PRECONNECT;
+                out.url = HlsBuffer::from_text(&p.action_url);
+            }
+        }
+    }
+    HLS_OK
+}
+
+/// Takes all the queued actions as a JSON array of their JSON forms (see `Action`),
+/// which is empty if there are no pending actions.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_drain_actions(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.actions_json = nul_terminated(json!(p.player.drain_actions()).to_string());
+    *out = HlsBuffer::from_text(&p.actions_json);
+    HLS_OK
+}
+
+/// Takes the next event as a JSON object (see `Event`).
+///
+/// `out.len` is set to zero if there are no pending events.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_poll_event(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    if let Some(event) = p.player.next_event() {
+        p.event_json = nul_terminated(json!(event).to_string());
+        *out = HlsBuffer::from_text(&p.event_json);
+    }
+    HLS_OK
+}
+
+/// Returns the statistics of the current playback as a JSON object (see `Stats`).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_stats(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.stats_json = nul_terminated(json!(p.player.stats()).to_string());
+    *out = HlsBuffer::from_text(&p.stats_json);
+    HLS_OK
+}
+
+/// Returns the timestamped statistics as a JSON object (see `Metrics`), for analytics beacons.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_metrics(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    let result = p.player.metrics_json().map(|json| {
+        p.metrics_json = nul_terminated(json);
+        *out = HlsBuffer::from_text(&p.metrics_json);
+    });
+    p.status(result)
+}
+
+/// Returns the log of the requested fetches as a HAR (HTTP Archive) like JSON object.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_request_log(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.request_log_json = nul_terminated(p.player.request_log_har().to_string());
+    *out = HlsBuffer::from_text(&p.request_log_json);
+    HLS_OK
+}
+
+/// Returns the diagnostic trace as a JSON array (see `TraceRecord`).
+///
+/// It is empty unless `trace.enabled` is set in the configuration.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_trace(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.trace_json = nul_terminated(json!(p.player.trace_records()).to_string());
+    *out = HlsBuffer::from_text(&p.trace_json);
+    HLS_OK
+}
+
+/// Returns the inputs of the current (or last) playback as a JSON array (see `InputRecord`),
+/// which can be replayed by `player::replay::replay_json`.
+///
+/// It is empty unless `input_log.enabled` is set in the configuration.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_input_log(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.input_log_json = nul_terminated(json!(p.player.input_log()).to_string());
+    *out = HlsBuffer::from_text(&p.input_log_json);
+    HLS_OK
+}
+
+/// Returns the byte layout of the stream produced by the current (or last) download
+/// as a JSON object (see `DownloadManifest`).
+///
+/// `out.len` is set to zero if no download has been started.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_download_manifest(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    if let Some(manifest) = p.player.download_manifest() {
+        p.download_manifest_json = nul_terminated(json!(manifest).to_string());
+        *out = HlsBuffer::from_text(&p.download_manifest_json);
+    }
+    HLS_OK
+}
+
+/// Returns the hint for aligning the playback to the date-time `date_time_ms`
+/// (milliseconds since the UNIX epoch) as a JSON object (see `SyncHint`).
+///
+/// `out.len` is set to zero until a segment with `EXT-X-PROGRAM-DATE-TIME` has been produced.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_sync_to(
+    player: *mut HlsFfiPlayer,
+    date_time_ms: f64,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    let date_time = Duration::from_secs_f64(date_time_ms.max(0.0) / 1000.0);
+    if let Some(hint) = p.player.sync_to(date_time) {
+        p.sync_json = nul_terminated(json!(hint).to_string());
+        *out = HlsBuffer::from_text(&p.sync_json);
+    }
+    HLS_OK
+}
+
+/// Returns the keyframes of the produced segments as a JSON array (see `IndexedSegment`).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_keyframe_index(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.keyframes_json = nul_terminated(json!(p.player.keyframe_index()).to_string());
+    *out = HlsBuffer::from_text(&p.keyframes_json);
+    HLS_OK
+}
+
+/// Returns the keyframe to seek to for `position_ms` as a JSON object
+/// (see `KeyframePosition`).
+///
+/// `out.len` is set to zero if no keyframe has been found yet.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_nearest_keyframe(
+    player: *mut HlsFfiPlayer,
+    position_ms: f64,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    let position = Duration::from_secs_f64(position_ms.max(0.0) / 1000.0);
+    if let Some(keyframe) = p.player.nearest_keyframe(position) {
+        p.keyframes_json = nul_terminated(json!(keyframe).to_string());
+        *out = HlsBuffer::from_text(&p.keyframes_json);
+    }
+    HLS_OK
+}
+
+/// Returns the timeline of the VOD media playlist as a JSON object (see `Timeline`).
+///
+/// `out.len` is set to zero until the playlist is loaded, and for live playlists.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_timeline(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    if let Some(timeline) = p.player.timeline() {
+        p.timeline_json = nul_terminated(json!(timeline).to_string());
+        *out = HlsBuffer::from_text(&p.timeline_json);
+    }
+    HLS_OK
+}
+
+/// Returns the tile of the image stream previewing `position_ms` as a JSON object
+/// (see `ImageTile`).
+///
+/// `out.len` is set to zero until the image playlist is loaded, and outside of its images.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_image_tile_at(
+    player: *mut HlsFfiPlayer,
+    position_ms: f64,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    let position = Duration::from_secs_f64(position_ms.max(0.0) / 1000.0);
+    if let Some(tile) = p.player.image_tile_at(position) {
+        p.image_tile_json = nul_terminated(json!(tile).to_string());
+        *out = HlsBuffer::from_text(&p.image_tile_json);
+    }
+    HLS_OK
+}
+
+/// Returns the `EXT-X-DATERANGE`s which do not mark ad breaks (e.g., chapters)
+/// as a JSON array (see `DateRange`).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_date_ranges(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.date_ranges_json = nul_terminated(json!(p.player.date_ranges()).to_string());
+    *out = HlsBuffer::from_text(&p.date_ranges_json);
+    HLS_OK
+}
+
+/// Returns the spec violations and compatibility problems of the playlist `m3u8`
+/// as a JSON array (see `ValidationIssue`), which is empty for conforming playlists.
+///
+/// Invalid UTF-8 sequences in `m3u8` are replaced with `U+FFFD`.
+///
+/// # Safety
+///
+/// `player` must be a live handle, `m3u8` must point to `m3u8_len` readable bytes,
+/// and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_validate_playlist(
+    player: *mut HlsFfiPlayer,
+    m3u8: *const u8,
+    m3u8_len: usize,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    let m3u8 = String::from_utf8_lossy(bytes(m3u8, m3u8_len));
+    p.validation_json = nul_terminated(json!(validate(&m3u8)).to_string());
+    *out = HlsBuffer::from_text(&p.validation_json);
+    HLS_OK
+}
+
+/// Discards the recorded diagnostic trace.
+///
+/// # Safety
+///
+/// `player` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_clear_trace(player: *mut HlsFfiPlayer) -> i32 {
+    let p = player_mut!(player);
+    p.player.clear_trace();
+    HLS_OK
+}
+
+/// Takes the next fMP4 segment to append to the media buffer.
+///
+/// `out.len` is set to zero if there are no produced segments.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_poll_segment(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    if let Some(segment) = p.player.next_segment() {
+        // The previous segment is no longer referred to by the host.
+        let previous = mem::replace(&mut p.segment, segment.data);
+        p.player.recycle_segment(previous);
+        *out = HlsBuffer {
+            data: p.segment.as_ptr(),
+            len: p.segment.len(),
+        };
+    }
+    HLS_OK
+}
+
+/// Returns the sequence number, duration, variant, discontinuity sequence number
+/// and program date time of the segment last taken by `hls_ffi_player_poll_segment`
+/// as a JSON object (see `SegmentInfo`).
+///
+/// `out.len` is set to zero until a segment has been taken.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_segment_info(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::null();
+    if let Some(info) = p.player.last_segment_info() {
+        p.segment_info_json = nul_terminated(json!(info).to_string());
+        *out = HlsBuffer::from_text(&p.segment_info_json);
+    }
+    HLS_OK
+}
+
+/// Takes the bytes recorded since the last call, to be appended to the recorded fMP4 file.
+///
+/// `out.len` is set to zero if nothing has been recorded (e.g., `recording.enabled`
+/// is not configured).
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_take_recording(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    p.recording = p.player.take_recording();
+    *out = HlsBuffer {
+        data: p.recording.as_ptr(),
+        len: p.recording.len(),
+    };
+    HLS_OK
+}
+
+/// Returns the JSON description of the error reported by the last failing call.
+///
+/// # Safety
+///
+/// `player` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hls_ffi_player_last_error(
+    player: *mut HlsFfiPlayer,
+    out: *mut HlsBuffer,
+) -> i32 {
+    let p = player_mut!(player);
+    let out = player_mut!(out);
+    *out = HlsBuffer::from_text(&p.last_error);
+    HLS_OK
+}
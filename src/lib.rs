@@ -12,7 +12,7 @@ extern crate url;
 extern crate url_serde;
 
 pub use error::{Error, ErrorKind};
-pub use player::{Action, HlsPlayer};
+pub use player::{Action, HlsPlayer, PlayerConfig};
 
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
@@ -29,6 +29,7 @@ macro_rules! ok {
     () => { WasmStr(Ptr::null()) }
 }
 
+pub mod ffi;
 pub mod player;
 pub mod wasm_api;
 
@@ -40,6 +41,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub struct MaybeJson<T> {
+    #[allow(dead_code)] // Read by the wasm host through the returned pointer.
     json_str: WasmStr,
     _phantom: PhantomData<T>,
 }
@@ -81,11 +83,27 @@ impl<T> Ptr<T> {
             _phantom: PhantomData,
         }
     }
+    /// # Safety
+    ///
+    /// The pointer must have been created by `Ptr::new` and must not be used after this call.
     pub unsafe fn free(&mut self) {
         if self.ptr != 0 {
             let _ = Box::from_raw(self.ptr as *mut T);
         }
     }
+
+    /// Takes the pointee out of the box, or returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// See `Ptr::free`.
+    pub unsafe fn into_inner(self) -> Option<T> {
+        if self.ptr == 0 {
+            None
+        } else {
+            Some(*Box::from_raw(self.ptr as *mut T))
+        }
+    }
 }
 impl<T> Deref for Ptr<T> {
     type Target = T;
@@ -111,6 +129,9 @@ impl WasmStr {
         self.deref().as_ptr() as i32
     }
 
+    /// # Safety
+    ///
+    /// See `Ptr::free`.
     pub unsafe fn free(&mut self) {
         self.0.free();
     }
@@ -141,10 +162,23 @@ impl WasmBytes {
         self.deref().as_ptr() as i32
     }
 
+    /// # Safety
+    ///
+    /// See `Ptr::free`.
     pub unsafe fn free(&mut self) {
         self.0.free();
     }
 }
+impl WasmBytes {
+    /// Takes the ownership of the bytes.
+    ///
+    /// # Safety
+    ///
+    /// See `Ptr::free`.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        self.0.into_inner().unwrap_or_default()
+    }
+}
 impl Deref for WasmBytes {
     type Target = [u8];
     fn deref(&self) -> &Self::Target {
@@ -1,28 +1,123 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 use url::Url;
 use url_serde;
 
-use super::StreamId;
+use super::{CdnSelector, CmcdState, Event, PlayerConfig, StreamId, ThroughputEstimator};
+use super::cmcd;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum Action {
     FetchData {
         action_id: ActionId,
         #[serde(with = "url_serde")] url: Url,
+        kind: ResourceKind,
+        timeout: u32, //Duration
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        range: Option<ByteRange>,
+        priority: FetchPriority,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        credentials: Option<CredentialsMode>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mode: Option<CorsMode>,
+
+        /// Static metadata of the client (see `PlayerConfig::request_metadata`).
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        metadata: BTreeMap<String, String>,
     },
     SetTimeout {
         action_id: ActionId,
         duration: u32, //Duration
     },
+
+    /// Requests the host to cancel the in-flight `FetchData` action or the pending `SetTimeout`
+    /// action identified by `action_id`.
+    Abort { action_id: ActionId },
+
+    /// Hints the host to open a connection to `origin` (e.g., `"https://cdn.example.com"`)
+    /// ahead of the requests to it.
+    ///
+    /// No response is expected; hosts may ignore it.
+    Preconnect { action_id: ActionId, origin: String },
 }
 impl Action {
     pub fn id(&self) -> ActionId {
         match *self {
             Action::FetchData { action_id, .. } => action_id,
             Action::SetTimeout { action_id, .. } => action_id,
+            Action::Abort { action_id } => action_id,
+            Action::Preconnect { action_id, .. } => action_id,
         }
     }
+
+    pub fn is_fetch_data(&self) -> bool {
+        matches!(*self, Action::FetchData { .. })
+    }
+}
+
+/// Kind of the resource requested by `Action::FetchData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResourceKind {
+    Playlist,
+    Segment,
+
+    /// Media initialization section specified by `EXT-X-MAP`.
+    InitSegment,
+
+    /// AES-128 key specified by `EXT-X-KEY`.
+    Key,
+
+    /// Content steering manifest specified by `EXT-X-CONTENT-STEERING`.
+    SteeringManifest,
+
+    /// WebVTT segment of a subtitle rendition.
+    Subtitles,
+}
+
+/// Priority hint of `Action::FetchData`, corresponding to the `priority` option of `fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchPriority {
+    High,
+    Low,
+    Auto,
+}
+
+/// Credentials mode hint of `Action::FetchData`, corresponding to the `credentials` option
+/// of `fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialsMode {
+    Omit,
+    SameOrigin,
+    Include,
+}
+
+/// CORS mode hint of `Action::FetchData`, corresponding to the `mode` option of `fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CorsMode {
+    Cors,
+    NoCors,
+    SameOrigin,
+}
+
+/// Byte range of the resource requested by `Action::FetchData`.
+///
+/// The host should fetch it by the `Range: bytes=<offset>-<offset + length - 1>` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+impl ByteRange {
+    /// Returns the offset just after the end of the range.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -33,7 +128,7 @@ impl ActionId {
     }
 
     fn next(&mut self) -> ActionId {
-        let id = self.clone();
+        let id = *self;
         self.0 += 1;
         id
     }
@@ -43,33 +138,183 @@ impl From<u32> for ActionId {
         ActionId(f)
     }
 }
+impl From<ActionId> for u32 {
+    fn from(f: ActionId) -> Self {
+        f.0
+    }
+}
 
+/// Factory of actions.
+///
+/// Request attributes (timeouts, headers, etc) of `Action::FetchData` are decided here
+/// according to the player configuration.
 #[derive(Debug)]
 pub struct ActionFactory {
     next_action_id: ActionId,
+    config: PlayerConfig,
+    target_duration: Option<Duration>,
+    cdn: CdnSelector,
+    cmcd_state: CmcdState,
+    throughput: ThroughputEstimator,
+    next_object_url: Option<Url>,
+    known_origins: BTreeSet<String>,
 }
 impl ActionFactory {
-    pub fn new(stream_id: StreamId) -> Self {
+    pub fn new(stream_id: StreamId, config: &PlayerConfig) -> Self {
         ActionFactory {
-            next_action_id: ActionId(u32::from(stream_id) << 8),
+            next_action_id: ActionId(u32::from(stream_id) << 24),
+            config: config.clone(),
+            target_duration: None,
+            cdn: CdnSelector::new(&config.cdn_failover),
+            cmcd_state: CmcdState::default(),
+            throughput: ThroughputEstimator::new(),
+            next_object_url: None,
+            known_origins: BTreeSet::new(),
         }
     }
 
+    /// Sets the target duration of the media playlist, which scales the fetch timeouts.
+    pub fn set_target_duration(&mut self, target_duration: Duration) {
+        self.target_duration = Some(target_duration);
+    }
+
+    /// Replaces `PlayerConfig::query_params` (e.g., after a token refresh).
+    pub fn set_query_params(&mut self, params: &BTreeMap<String, String>) {
+        self.config.query_params = params.clone();
+    }
+
+    /// Records the successful completion of a fetch.
+    pub fn record_fetch_success(&mut self) {
+        self.cdn.record_success();
+    }
+
+    /// Records a failed fetch, and returns `Event::CdnFailover` if it caused failover.
+    pub fn record_fetch_failure(&mut self) -> Option<Event> {
+        self.cdn
+            .record_failure()
+            .map(|(from, to)| Event::CdnFailover { from, to })
+    }
+
+    /// Sets the buffer length reported by the host (CMCD `bl`).
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        self.cmcd_state.buffer_length_ms = Some(to_millis(buffer_length));
+    }
+
+    pub fn buffer_length_ms(&self) -> Option<u32> {
+        self.cmcd_state.buffer_length_ms
+    }
+
+    /// Records that `bytes` bytes of a segment were fetched in `fetch_duration_ms` (CMCD `mtp`).
+    pub fn record_throughput(&mut self, bytes: usize, fetch_duration_ms: u32) {
+        self.throughput.record(bytes, fetch_duration_ms);
+        self.cmcd_state.throughput_kbps = self.throughput.estimate_kbps();
+    }
+
+    /// Records the throughput `kbps` estimated by the servers (CMSD `etp`).
+    pub fn record_throughput_hint(&mut self, kbps: u32) {
+        self.throughput.record_hint(kbps);
+        self.cmcd_state.throughput_kbps = self.throughput.estimate_kbps();
+    }
+
+    pub fn throughput_kbps(&self) -> Option<u32> {
+        self.throughput.estimate_kbps()
+    }
+
+    /// Sets the URL of the object requested after the next fetch (CMCD `nor`).
+    pub fn set_next_object_url(&mut self, url: Option<Url>) {
+        self.next_object_url = url;
+    }
+
     pub fn media_playlist_id(&self) -> StreamId {
         self.next_action_id.media_playlist_id()
     }
 
-    pub fn fetch_data(&mut self, url: Url) -> Action {
+    /// Makes an action to fetch `url`, or only its `range` if specified.
+    pub fn fetch_data(&mut self, url: Url, kind: ResourceKind, range: Option<ByteRange>) -> Action {
         let action_id = self.next_action_id.next();
-        Action::FetchData { action_id, url }
+        let mut url = self.cdn.rewrite_url(url);
+        self.known_origins.insert(url.origin().ascii_serialization());
+        let mut headers = self.config.request_headers.headers(kind);
+        let next_object_url = self.next_object_url.take();
+        cmcd::apply(
+            &self.config.cmcd,
+            &self.cmcd_state,
+            kind,
+            next_object_url.as_ref(),
+            &mut url,
+            &mut headers,
+        );
+        let url = self.config.rewrite_url(kind, url);
+        let priority = self.priority(kind);
+        let modes = self.config.request_modes.modes(kind);
+        let timeout = self.config
+            .fetch_timeout
+            .timeout(kind, self.target_duration);
+        Action::FetchData {
+            action_id,
+            url,
+            kind,
+            timeout: to_millis(timeout),
+            headers,
+            range,
+            priority,
+            credentials: modes.credentials,
+            mode: modes.mode,
+            metadata: self.config.request_metadata.metadata(),
+        }
+    }
+
+    /// Playlists, init sections and keys block the playback, so they take precedence over segments
+    /// unless the buffer is about to run dry.
+    fn priority(&self, kind: ResourceKind) -> FetchPriority {
+        match kind {
+            ResourceKind::Playlist | ResourceKind::InitSegment | ResourceKind::Key => {
+                FetchPriority::High
+            }
+            ResourceKind::SteeringManifest => FetchPriority::Auto,
+            ResourceKind::Subtitles => FetchPriority::Low,
+            ResourceKind::Segment => {
+                let buffer_length_ms = self.cmcd_state.buffer_length_ms;
+                match (buffer_length_ms, self.target_duration) {
+                    (Some(b), Some(d)) if u64::from(b) < d.as_secs() * 1000 => FetchPriority::High,
+                    (Some(_), Some(_)) => FetchPriority::Low,
+                    _ => FetchPriority::Auto,
+                }
+            }
+        }
+    }
+
+    /// Makes a `Preconnect` action for the origin of `url`.
+    ///
+    /// Returns `None` if the origin has already been hinted or fetched from.
+    pub fn preconnect(&mut self, url: &Url) -> Option<Action> {
+        let origin = self.cdn.rewrite_url(url.clone()).origin();
+        if !origin.is_tuple() {
+            return None;
+        }
+        let origin = origin.ascii_serialization();
+        if !self.known_origins.insert(origin.clone()) {
+            return None;
+        }
+        let action_id = self.next_action_id.next();
+        Some(Action::Preconnect { action_id, origin })
+    }
+
+    /// Marks the origin of `url` as connected (e.g., the one of the master playlist).
+    pub fn mark_connected(&mut self, url: &Url) {
+        self.known_origins.insert(url.origin().ascii_serialization());
     }
 
     pub fn set_timeout(&mut self, duration: Duration) -> Action {
         let action_id = self.next_action_id.next();
-        let duration = (duration.as_secs() * 1000) as u32 + duration.subsec_nanos() / 1_000_000;
+        let duration = to_millis(duration);
         Action::SetTimeout {
             action_id,
             duration,
         }
     }
 }
+
+fn to_millis(duration: Duration) -> u32 {
+    (duration.as_secs() * 1000) as u32 + duration.subsec_millis()
+}
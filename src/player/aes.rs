@@ -0,0 +1,699 @@
+//! AES-128-CBC decryption of the resources protected by `EXT-X-KEY:METHOD=AES-128`
+//! (RFC 8216, section 4.3.2.4): media segments and their `EXT-X-MAP` init sections.
+//!
+//! The cipher is bitsliced: it has no table lookups nor branches depending on the key or
+//! the data. Segments are decrypted eight blocks at a time in eight 128-bit planes, which are
+//! SIMD registers on `wasm32` builds with the `simd128` target feature (see `Wide`), and pairs
+//! of 64-bit integers elsewhere. The tests cover the build they run on, e.g.,
+//! `RUSTFLAGS="-C target-feature=+simd128" cargo test --target wasm32-wasip1` for the former.
+use std::convert::TryInto;
+use std::ops::{BitAnd, BitXor, Not};
+
+use {ErrorKind, Result};
+
+/// Size of AES-128 keys and initialization vectors.
+pub const KEY_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 16;
+const ROUNDS: usize = 10;
+
+/// Number of blocks in 64-bit planes.
+const PARALLEL_BLOCKS: usize = 4;
+
+/// Number of blocks in `Wide` planes, decrypted at once.
+const WIDE_BLOCKS: usize = 2 * PARALLEL_BLOCKS;
+
+const RCON: [u8; ROUNDS] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// Returns the IV of a resource without the `IV` attribute, i.e., its media sequence number
+/// as a 128-bit big-endian integer.
+pub fn sequence_iv(seq: u64) -> [u8; KEY_LEN] {
+    let mut iv = [0; KEY_LEN];
+    iv[8..].copy_from_slice(&seq.to_be_bytes());
+    iv
+}
+
+/// Decrypts `data` by AES-128 in the CBC mode, and removes its PKCS#7 padding.
+pub fn decrypt(key: &[u8], iv: &[u8; KEY_LEN], data: &[u8]) -> Result<Vec<u8>> {
+    track_assert!(
+        key.len() == KEY_LEN,
+        ErrorKind::InvalidInput,
+        "AES-128 keys must be 16 bytes: {} bytes",
+        key.len()
+    );
+    track_assert!(
+        !data.is_empty() && data.len().is_multiple_of(BLOCK_LEN),
+        ErrorKind::InvalidInput,
+        "Not a multiple of the AES block size: {} bytes",
+        data.len()
+    );
+    let mut output = decrypt_cbc(&expand_key(key), iv, data);
+    track_assert!(
+        is_valid_padding(&output[output.len() - BLOCK_LEN..]),
+        ErrorKind::InvalidInput,
+        "Invalid PKCS#7 padding (wrong key?)"
+    );
+    let padding = usize::from(output[output.len() - 1]);
+    output.truncate(output.len() - padding);
+    Ok(output)
+}
+
+/// Decrypts the whole blocks of `data` in the CBC mode, `WIDE_BLOCKS` at a time.
+fn decrypt_cbc(round_keys: &RoundKeys<u64>, iv: &[u8; KEY_LEN], data: &[u8]) -> Vec<u8> {
+    let round_keys = round_keys.map(|k| k.map(Wide::splat));
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = *iv;
+    for chunk in data.chunks(WIDE_BLOCKS * BLOCK_LEN) {
+        let mut blocks = [0; WIDE_BLOCKS * BLOCK_LEN];
+        blocks[..chunk.len()].copy_from_slice(chunk);
+        decrypt_wide_blocks(&round_keys, &mut blocks);
+        for (i, block) in blocks[..chunk.len()].chunks_mut(BLOCK_LEN).enumerate() {
+            let chained = if i == 0 {
+                &previous[..]
+            } else {
+                &chunk[BLOCK_LEN * (i - 1)..BLOCK_LEN * i]
+            };
+            for (b, c) in block.iter_mut().zip(chained) {
+                *b ^= c;
+            }
+        }
+        output.extend_from_slice(&blocks[..chunk.len()]);
+        previous.copy_from_slice(&chunk[chunk.len() - BLOCK_LEN..]);
+    }
+    output
+}
+
+/// Checks the PKCS#7 padding of the last block without branching on its bytes.
+fn is_valid_padding(last_block: &[u8]) -> bool {
+    let padding = u32::from(last_block[BLOCK_LEN - 1]);
+
+    // The high bits are set by the wrapping subtractions iff `padding` is out of `1..=16`.
+    let mut invalid = (padding.wrapping_sub(1) | (BLOCK_LEN as u32).wrapping_sub(padding)) >> 8;
+    for (i, &b) in last_block.iter().rev().enumerate() {
+        // All ones iff the `i`-th byte from the end belongs to the padding.
+        let mask = ((i as u32).wrapping_sub(padding) >> 8) as u8;
+        invalid |= u32::from(mask & (b ^ padding as u8));
+    }
+    invalid == 0
+}
+
+/// Plane of a bitsliced state, whose blocks take 16 bits each.
+trait Plane: Copy + BitAnd<Output = Self> + BitXor<Output = Self> + Not<Output = Self> {
+    /// Repeats the 64-bit `plane` over all the blocks.
+    fn splat(plane: u64) -> Self;
+
+    /// Rotates the 16 bits of each block left by `n`.
+    fn rotate_blocks(self, n: u32) -> Self;
+
+    /// Moves the row `r + n` of each column to the row `r`.
+    fn rotate_columns(self, n: u32) -> Self;
+}
+impl Plane for u64 {
+    fn splat(plane: u64) -> Self {
+        plane
+    }
+
+    fn rotate_blocks(self, n: u32) -> Self {
+        let low = 0x0001_0001_0001_0001 * ((1 << n) - 1);
+        ((self << n) & !low) | ((self >> (16 - n)) & low)
+    }
+
+    fn rotate_columns(self, n: u32) -> Self {
+        let low = 0x1111_1111_1111_1111 * ((1 << (4 - n)) - 1);
+        ((self >> n) & low) | ((self << (4 - n)) & !low)
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+use self::simd128::Wide;
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+use self::portable::Wide;
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod simd128 {
+    use std::arch::wasm32::{u16x8_shl, u16x8_shr, u64x2, u64x2_extract_lane, u64x2_shl,
+                            u64x2_shr, u64x2_splat, v128, v128_and, v128_bitselect, v128_not,
+                            v128_or, v128_xor};
+    use std::ops::{BitAnd, BitXor, Not};
+
+    use super::Plane;
+
+    /// Plane of `WIDE_BLOCKS` blocks in a SIMD register, whose 64-bit lanes are the planes
+    /// of the first and the last `PARALLEL_BLOCKS` blocks.
+    #[derive(Clone, Copy)]
+    pub struct Wide(v128);
+    impl Wide {
+        pub fn new(low: u64, high: u64) -> Self {
+            Wide(u64x2(low, high))
+        }
+
+        pub fn halves(self) -> (u64, u64) {
+            (u64x2_extract_lane::<0>(self.0), u64x2_extract_lane::<1>(self.0))
+        }
+    }
+    impl BitAnd for Wide {
+        type Output = Self;
+        fn bitand(self, other: Self) -> Self {
+            Wide(v128_and(self.0, other.0))
+        }
+    }
+    impl BitXor for Wide {
+        type Output = Self;
+        fn bitxor(self, other: Self) -> Self {
+            Wide(v128_xor(self.0, other.0))
+        }
+    }
+    impl Not for Wide {
+        type Output = Self;
+        fn not(self) -> Self {
+            Wide(v128_not(self.0))
+        }
+    }
+    impl Plane for Wide {
+        fn splat(plane: u64) -> Self {
+            Wide(u64x2_splat(plane))
+        }
+
+        fn rotate_blocks(self, n: u32) -> Self {
+            // The blocks are the 16-bit lanes.
+            Wide(v128_or(u16x8_shl(self.0, n), u16x8_shr(self.0, 16 - n)))
+        }
+
+        fn rotate_columns(self, n: u32) -> Self {
+            let low = u64x2_splat(0x1111_1111_1111_1111 * ((1 << (4 - n)) - 1));
+            Wide(v128_bitselect(u64x2_shr(self.0, n), u64x2_shl(self.0, 4 - n), low))
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+mod portable {
+    use std::ops::{BitAnd, BitXor, Not};
+
+    use super::Plane;
+
+    /// Plane of `WIDE_BLOCKS` blocks, as the planes of the first and the last
+    /// `PARALLEL_BLOCKS` blocks.
+    #[derive(Clone, Copy)]
+    pub struct Wide([u64; 2]);
+    impl Wide {
+        pub fn new(low: u64, high: u64) -> Self {
+            Wide([low, high])
+        }
+
+        pub fn halves(self) -> (u64, u64) {
+            (self.0[0], self.0[1])
+        }
+
+        fn map<F: Fn(u64) -> u64>(self, f: F) -> Self {
+            Wide([f(self.0[0]), f(self.0[1])])
+        }
+    }
+    impl BitAnd for Wide {
+        type Output = Self;
+        fn bitand(self, other: Self) -> Self {
+            Wide([self.0[0] & other.0[0], self.0[1] & other.0[1]])
+        }
+    }
+    impl BitXor for Wide {
+        type Output = Self;
+        fn bitxor(self, other: Self) -> Self {
+            Wide([self.0[0] ^ other.0[0], self.0[1] ^ other.0[1]])
+        }
+    }
+    impl Not for Wide {
+        type Output = Self;
+        fn not(self) -> Self {
+            self.map(|x| !x)
+        }
+    }
+    impl Plane for Wide {
+        fn splat(plane: u64) -> Self {
+            Wide([plane; 2])
+        }
+
+        fn rotate_blocks(self, n: u32) -> Self {
+            self.map(|x| x.rotate_blocks(n))
+        }
+
+        fn rotate_columns(self, n: u32) -> Self {
+            self.map(|x| x.rotate_columns(n))
+        }
+    }
+}
+
+/// Bitsliced state: the bit `j` of the plane `i` is the bit `i` of the byte `j`
+/// (i.e., the byte `r + 4c` of the block `j / 16`).
+type State<P> = [P; 8];
+
+type RoundKeys<P> = [State<P>; ROUNDS + 1];
+
+fn expand_key(key: &[u8]) -> RoundKeys<u64> {
+    let mut words = [[0; 4]; 4 * (ROUNDS + 1)];
+    for (i, word) in words.iter_mut().take(4).enumerate() {
+        word.copy_from_slice(&key[4 * i..4 * i + 4]);
+    }
+    for i in 4..words.len() {
+        let mut t = words[i - 1];
+        if i % 4 == 0 {
+            let s = sub_word(t);
+            t = [s[1] ^ RCON[i / 4 - 1], s[2], s[3], s[0]];
+        }
+        for j in 0..4 {
+            words[i][j] = words[i - 4][j] ^ t[j];
+        }
+    }
+
+    // Each round key is repeated in all the blocks of the state.
+    let mut round_keys = [[0; 8]; ROUNDS + 1];
+    for (r, round_key) in round_keys.iter_mut().enumerate() {
+        let mut blocks = [0; PARALLEL_BLOCKS * BLOCK_LEN];
+        for block in blocks.chunks_mut(BLOCK_LEN) {
+            for c in 0..4 {
+                block[4 * c..4 * c + 4].copy_from_slice(&words[4 * r + c]);
+            }
+        }
+        *round_key = pack(&blocks);
+    }
+    round_keys
+}
+
+fn sub_word(word: [u8; 4]) -> [u8; 4] {
+    let mut bytes = [0; PARALLEL_BLOCKS * BLOCK_LEN];
+    bytes[..4].copy_from_slice(&word);
+    let mut q = pack(&bytes);
+    sub_bytes(&mut q);
+    unpack(&q, &mut bytes);
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Inverse cipher of `WIDE_BLOCKS` consecutive blocks.
+fn decrypt_wide_blocks(round_keys: &RoundKeys<Wide>, blocks: &mut [u8; WIDE_BLOCKS * BLOCK_LEN]) {
+    let (low, high) = blocks.split_at_mut(PARALLEL_BLOCKS * BLOCK_LEN);
+    let low: &mut [u8; PARALLEL_BLOCKS * BLOCK_LEN] = low.try_into().expect("Never fails");
+    let high: &mut [u8; PARALLEL_BLOCKS * BLOCK_LEN] = high.try_into().expect("Never fails");
+    let (low_planes, high_planes) = (pack(low), pack(high));
+    let mut q = [Wide::splat(0); 8];
+    for (i, plane) in q.iter_mut().enumerate() {
+        *plane = Wide::new(low_planes[i], high_planes[i]);
+    }
+    decrypt_state(round_keys, &mut q);
+    let (mut low_planes, mut high_planes) = ([0; 8], [0; 8]);
+    for (i, plane) in q.iter().enumerate() {
+        let (l, h) = plane.halves();
+        low_planes[i] = l;
+        high_planes[i] = h;
+    }
+    unpack(&low_planes, low);
+    unpack(&high_planes, high);
+}
+
+/// Inverse cipher of `PARALLEL_BLOCKS` consecutive blocks.
+#[cfg(test)]
+fn decrypt_blocks(round_keys: &RoundKeys<u64>, blocks: &mut [u8; PARALLEL_BLOCKS * BLOCK_LEN]) {
+    let mut q = pack(blocks);
+    decrypt_state(round_keys, &mut q);
+    unpack(&q, blocks);
+}
+
+/// Inverse cipher (FIPS 197, section 5.3).
+fn decrypt_state<P: Plane>(round_keys: &RoundKeys<P>, q: &mut State<P>) {
+    add_round_key(q, &round_keys[ROUNDS]);
+    for round in (1..ROUNDS).rev() {
+        inv_shift_rows(q);
+        inv_sub_bytes(q);
+        add_round_key(q, &round_keys[round]);
+        inv_mix_columns(q);
+    }
+    inv_shift_rows(q);
+    inv_sub_bytes(q);
+    add_round_key(q, &round_keys[0]);
+}
+
+fn pack(bytes: &[u8; PARALLEL_BLOCKS * BLOCK_LEN]) -> State<u64> {
+    let mut q = [0; 8];
+    for (k, group) in bytes.chunks(8).enumerate() {
+        let mut row = [0; 8];
+        row.copy_from_slice(group);
+        let x = transpose(u64::from_le_bytes(row));
+        for (i, plane) in q.iter_mut().enumerate() {
+            *plane |= ((x >> (8 * i)) & 0xff) << (8 * k);
+        }
+    }
+    q
+}
+
+fn unpack(q: &State<u64>, bytes: &mut [u8; PARALLEL_BLOCKS * BLOCK_LEN]) {
+    for (k, group) in bytes.chunks_mut(8).enumerate() {
+        let mut x = 0;
+        for (i, plane) in q.iter().enumerate() {
+            x |= ((plane >> (8 * k)) & 0xff) << (8 * i);
+        }
+        group.copy_from_slice(&transpose(x).to_le_bytes());
+    }
+}
+
+/// Transposes the 8x8 bit matrix whose row `m` is the byte `m` of `x`.
+fn transpose(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^ t ^ (t << 28)
+}
+
+fn add_round_key<P: Plane>(q: &mut State<P>, round_key: &State<P>) {
+    for (p, &k) in q.iter_mut().zip(round_key) {
+        *p = *p ^ k;
+    }
+}
+
+/// Rotates the row `r` of each block right by `r` columns.
+fn inv_shift_rows<P: Plane>(q: &mut State<P>) {
+    const ROW: u64 = 0x1111_1111_1111_1111;
+    let rows = [P::splat(ROW), P::splat(ROW << 1), P::splat(ROW << 2), P::splat(ROW << 3)];
+    for plane in q.iter_mut() {
+        let x = *plane;
+
+        // The rows are disjoint, so that XOR combines them as OR would.
+        *plane = (x & rows[0]) ^ (x & rows[1]).rotate_blocks(4) ^ (x & rows[2]).rotate_blocks(8)
+            ^ (x & rows[3]).rotate_blocks(12);
+    }
+}
+
+fn inv_mix_columns<P: Plane>(q: &mut State<P>) {
+    let a = *q;
+    let a2 = xtime(&a);
+    let a4 = xtime(&a2);
+    let a8 = xtime(&a4);
+    for (i, plane) in q.iter_mut().enumerate() {
+        *plane = (a8[i] ^ a4[i] ^ a2[i]) ^ (a8[i] ^ a2[i] ^ a[i]).rotate_columns(1)
+            ^ (a8[i] ^ a4[i] ^ a[i]).rotate_columns(2) ^ (a8[i] ^ a[i]).rotate_columns(3);
+    }
+}
+
+/// Multiplies each byte by 2 in GF(2^8) with the AES polynomial.
+fn xtime<P: Plane>(p: &State<P>) -> State<P> {
+    [p[7], p[0] ^ p[7], p[1], p[2] ^ p[7], p[3] ^ p[7], p[4], p[5], p[6]]
+}
+
+/// InvSubBytes, as the inverse affine transformation around SubBytes:
+/// `S^-1(x) = A^-1(S(A^-1(x)))` since `S(x) = A(x^-1)`.
+fn inv_sub_bytes<P: Plane>(q: &mut State<P>) {
+    inv_affine(q);
+    sub_bytes(q);
+    inv_affine(q);
+}
+
+fn inv_affine<P: Plane>(q: &mut State<P>) {
+    let x = *q;
+    for (i, plane) in q.iter_mut().enumerate() {
+        *plane = x[(i + 2) % 8] ^ x[(i + 5) % 8] ^ x[(i + 7) % 8];
+    }
+    q[0] = !q[0];
+    q[2] = !q[2];
+}
+
+/// SubBytes by the circuit of Boyar and Peralta ("A new combinational logic minimization
+/// technique with applications to cryptology", 2009), as in BearSSL.
+///
+/// The variables `x*` (input) and `s*` (output) are numbered from the high bit.
+fn sub_bytes<P: Plane>(q: &mut State<P>) {
+    let (x0, x1, x2, x3, x4, x5, x6, x7) = (q[7], q[6], q[5], q[4], q[3], q[2], q[1], q[0]);
+
+    // Top linear transformation.
+    let y14 = x3 ^ x5;
+    let y13 = x0 ^ x6;
+    let y9 = x0 ^ x3;
+    let y8 = x0 ^ x5;
+    let t0 = x1 ^ x2;
+    let y1 = t0 ^ x7;
+    let y4 = y1 ^ x3;
+    let y12 = y13 ^ y14;
+    let y2 = y1 ^ x0;
+    let y5 = y1 ^ x6;
+    let y3 = y5 ^ y8;
+    let t1 = x4 ^ y12;
+    let y15 = t1 ^ x5;
+    let y20 = t1 ^ x1;
+    let y6 = y15 ^ x7;
+    let y10 = y15 ^ t0;
+    let y11 = y20 ^ y9;
+    let y7 = x7 ^ y11;
+    let y17 = y10 ^ y11;
+    let y19 = y10 ^ y8;
+    let y16 = t0 ^ y11;
+    let y21 = y13 ^ y16;
+    let y18 = x0 ^ y16;
+
+    // Non-linear section.
+    let t2 = y12 & y15;
+    let t3 = y3 & y6;
+    let t4 = t3 ^ t2;
+    let t5 = y4 & x7;
+    let t6 = t5 ^ t2;
+    let t7 = y13 & y16;
+    let t8 = y5 & y1;
+    let t9 = t8 ^ t7;
+    let t10 = y2 & y7;
+    let t11 = t10 ^ t7;
+    let t12 = y9 & y11;
+    let t13 = y14 & y17;
+    let t14 = t13 ^ t12;
+    let t15 = y8 & y10;
+    let t16 = t15 ^ t12;
+    let t17 = t4 ^ t14;
+    let t18 = t6 ^ t16;
+    let t19 = t9 ^ t14;
+    let t20 = t11 ^ t16;
+    let t21 = t17 ^ y20;
+    let t22 = t18 ^ y19;
+    let t23 = t19 ^ y21;
+    let t24 = t20 ^ y18;
+
+    let t25 = t21 ^ t22;
+    let t26 = t21 & t23;
+    let t27 = t24 ^ t26;
+    let t28 = t25 & t27;
+    let t29 = t28 ^ t22;
+    let t30 = t23 ^ t24;
+    let t31 = t22 ^ t26;
+    let t32 = t31 & t30;
+    let t33 = t32 ^ t24;
+    let t34 = t23 ^ t33;
+    let t35 = t27 ^ t33;
+    let t36 = t24 & t35;
+    let t37 = t36 ^ t34;
+    let t38 = t27 ^ t36;
+    let t39 = t29 & t38;
+    let t40 = t25 ^ t39;
+
+    let t41 = t40 ^ t37;
+    let t42 = t29 ^ t33;
+    let t43 = t29 ^ t40;
+    let t44 = t33 ^ t37;
+    let t45 = t42 ^ t41;
+    let z0 = t44 & y15;
+    let z1 = t37 & y6;
+    let z2 = t33 & x7;
+    let z3 = t43 & y16;
+    let z4 = t40 & y1;
+    let z5 = t29 & y7;
+    let z6 = t42 & y11;
+    let z7 = t45 & y17;
+    let z8 = t41 & y10;
+    let z9 = t44 & y12;
+    let z10 = t37 & y3;
+    let z11 = t33 & y4;
+    let z12 = t43 & y13;
+    let z13 = t40 & y5;
+    let z14 = t29 & y2;
+    let z15 = t42 & y9;
+    let z16 = t45 & y14;
+    let z17 = t41 & y8;
+
+    // Bottom linear transformation.
+    let t46 = z15 ^ z16;
+    let t47 = z10 ^ z11;
+    let t48 = z5 ^ z13;
+    let t49 = z9 ^ z10;
+    let t50 = z2 ^ z12;
+    let t51 = z2 ^ z5;
+    let t52 = z7 ^ z8;
+    let t53 = z0 ^ z3;
+    let t54 = z6 ^ z7;
+    let t55 = z16 ^ z17;
+    let t56 = z12 ^ t48;
+    let t57 = t50 ^ t53;
+    let t58 = z4 ^ t46;
+    let t59 = z3 ^ t54;
+    let t60 = t46 ^ t57;
+    let t61 = z14 ^ t57;
+    let t62 = t52 ^ t58;
+    let t63 = t49 ^ t58;
+    let t64 = z4 ^ t59;
+    let t65 = t61 ^ t62;
+    let t66 = z1 ^ t63;
+    let s0 = t59 ^ t63;
+    let s6 = t56 ^ !t62;
+    let s7 = t48 ^ !t60;
+    let t67 = t64 ^ t65;
+    let s3 = t53 ^ t66;
+    let s4 = t51 ^ t66;
+    let s5 = t47 ^ t65;
+    let s1 = t64 ^ !s3;
+    let s2 = t55 ^ !t67;
+
+    *q = [s7, s6, s5, s4, s3, s2, s1, s0];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn block(s: &str) -> [u8; BLOCK_LEN] {
+        let mut block = [0; BLOCK_LEN];
+        block.copy_from_slice(&hex(s));
+        block
+    }
+
+    /// SubBytes of the specification: the multiplicative inverse followed by the affine
+    /// transformation.
+    fn reference_sbox(x: u8) -> u8 {
+        let mul = |mut a: u8, mut b: u8| {
+            let mut product = 0;
+            while b != 0 {
+                if b & 1 != 0 {
+                    product ^= a;
+                }
+                a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+                b >>= 1;
+            }
+            product
+        };
+        let inverse = (1..=255).find(|&y| mul(x, y) == 1).unwrap_or(0);
+        inverse ^ inverse.rotate_left(1) ^ inverse.rotate_left(2) ^ inverse.rotate_left(3)
+            ^ inverse.rotate_left(4) ^ 0x63
+    }
+
+    #[test]
+    fn bitsliced_sbox_works() {
+        for base in (0..256).step_by(PARALLEL_BLOCKS * BLOCK_LEN) {
+            let mut bytes = [0; PARALLEL_BLOCKS * BLOCK_LEN];
+            for (i, b) in bytes.iter_mut().enumerate() {
+                *b = (base + i) as u8;
+            }
+            let mut q = pack(&bytes);
+            sub_bytes(&mut q);
+            let mut substituted = [0; PARALLEL_BLOCKS * BLOCK_LEN];
+            unpack(&q, &mut substituted);
+            for (&x, &s) in bytes.iter().zip(substituted.iter()) {
+                assert_eq!(s, reference_sbox(x), "S({:#04x})", x);
+            }
+
+            inv_sub_bytes(&mut q);
+            unpack(&q, &mut substituted);
+            assert_eq!(&substituted[..], &bytes[..]);
+        }
+    }
+
+    #[test]
+    fn wide_planes_match_the_64_bit_ones() {
+        let (low, high) = (0x0123_4567_89ab_cdef_u64, 0xfedc_ba98_7654_3210_u64);
+        let wide = Wide::new(low, high);
+        assert_eq!(wide.halves(), (low, high));
+        assert_eq!((wide & Wide::splat(low)).halves(), (low, low & high));
+        assert_eq!((wide ^ Wide::splat(low)).halves(), (0, low ^ high));
+        assert_eq!((!wide).halves(), (!low, !high));
+        for n in [4, 8, 12] {
+            let halves = (low.rotate_blocks(n), high.rotate_blocks(n));
+            assert_eq!(wide.rotate_blocks(n).halves(), halves, "n={}", n);
+        }
+        for n in 1..4 {
+            let halves = (low.rotate_columns(n), high.rotate_columns(n));
+            assert_eq!(wide.rotate_columns(n).halves(), halves, "n={}", n);
+        }
+    }
+
+    #[test]
+    fn fips197_known_answer_works() {
+        // FIPS 197, appendix C.1.
+        let round_keys = expand_key(&hex("000102030405060708090a0b0c0d0e0f"));
+        let mut blocks = [0; PARALLEL_BLOCKS * BLOCK_LEN];
+        for b in blocks.chunks_mut(BLOCK_LEN) {
+            b.copy_from_slice(&hex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+        }
+        decrypt_blocks(&round_keys, &mut blocks);
+        for b in blocks.chunks(BLOCK_LEN) {
+            assert_eq!(b, &hex("00112233445566778899aabbccddeeff")[..]);
+        }
+
+        let round_keys = round_keys.map(|k| k.map(Wide::splat));
+        let mut blocks = [0; WIDE_BLOCKS * BLOCK_LEN];
+        for b in blocks.chunks_mut(BLOCK_LEN) {
+            b.copy_from_slice(&hex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+        }
+        decrypt_wide_blocks(&round_keys, &mut blocks);
+        for b in blocks.chunks(BLOCK_LEN) {
+            assert_eq!(b, &hex("00112233445566778899aabbccddeeff")[..]);
+        }
+    }
+
+    #[test]
+    fn cbc_known_answer_works() {
+        // NIST SP 800-38A, F.2.2 (CBC-AES128.Decrypt) twice, and the first ciphertext block
+        // once more to cross the blocks processed at once.
+        let round_keys = expand_key(&hex("2b7e151628aed2a6abf7158809cf4f3c"));
+        let iv = block("000102030405060708090a0b0c0d0e0f");
+        let vector = hex(
+            "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2\
+             73bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7",
+        );
+        let plaintext = hex(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        );
+        let ciphertext = [&vector[..], &vector[..], &vector[..BLOCK_LEN]].concat();
+
+        // The first block repeated after the last one is chained to it instead of the IV.
+        let mut repeated_first = plaintext[..BLOCK_LEN].to_vec();
+        for (i, b) in repeated_first.iter_mut().enumerate() {
+            *b ^= iv[i] ^ vector[vector.len() - BLOCK_LEN + i];
+        }
+        let expected = [
+            &plaintext[..],
+            &repeated_first[..],
+            &plaintext[BLOCK_LEN..],
+            &repeated_first[..],
+        ].concat();
+        assert_eq!(decrypt_cbc(&round_keys, &iv, &ciphertext), expected);
+    }
+
+    #[test]
+    fn padding_check_works() {
+        let mut last_block = [0; BLOCK_LEN];
+        for padding in 0..=255 {
+            for (i, b) in last_block.iter_mut().rev().enumerate() {
+                *b = if i < padding as usize { padding } else { 0xaa };
+            }
+            let expected = padding >= 1 && padding as usize <= BLOCK_LEN;
+            assert_eq!(is_valid_padding(&last_block), expected, "padding={}", padding);
+        }
+
+        last_block[BLOCK_LEN - 1] = 3;
+        last_block[BLOCK_LEN - 2] = 3;
+        last_block[BLOCK_LEN - 3] = 2;
+        assert!(!is_valid_padding(&last_block));
+        last_block[BLOCK_LEN - 3] = 3;
+        assert!(is_valid_padding(&last_block));
+        assert!(decrypt(&[0; KEY_LEN], &[0; KEY_LEN], &[0; 17]).is_err());
+    }
+}
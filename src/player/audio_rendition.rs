@@ -0,0 +1,652 @@
+//! Playback of the media playlist of the selected audio rendition along the video
+//! (see `RenditionConfig::audio_playlists`).
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+use Result;
+use super::{Action, ActionFactory, ActionId, ByteRange, Event, FailureClass, PlayerConfig,
+            Rendition, ResourceKind, StreamId};
+use super::date_time;
+use super::discontinuity;
+use super::media_playlist_handler::{self, SegmentSpan, SwitchPoint};
+use super::segment_resources::{self, Encryption};
+use super::target_duration;
+use super::text;
+use super::ts_sync;
+
+/// Stream ID of the actions of `AudioRenditionLoader`, distinct from those of media playlists.
+pub const AUDIO_STREAM_ID: StreamId = 0xFA;
+
+/// Loader of the media playlist and the TS segments of an audio rendition.
+///
+/// Live playlists are reloaded every target duration. Once loaded, the playlist is aligned
+/// with the video by `align`: its cursor is the segment presented at the same time as
+/// the next segment of the video, matched by `EXT-X-PROGRAM-DATE-TIME`, by the position
+/// in VOD playlists, or else by the media sequence number.
+///
+/// The audio of each video segment (see `request_audio`) is then fetched while the video is,
+/// and taken by `take_audio` to be merged into it. Failed segments are skipped after
+/// `PlayerConfig::segment_retry`, so that the video goes on.
+///
+/// Only MPEG-2 TS segments can be merged into the video (see `ts_merge`). Renditions of
+/// packed audio (e.g., ADTS/AAC) or fMP4 segments are rejected by a non-fatal `Event::Error`
+/// once their first segment is fetched, and the video is played alone (see `is_rejected`).
+#[derive(Debug)]
+pub struct AudioRenditionLoader {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    rendition: Rendition,
+    url: Url,
+    fetch_playlist_action_id: Option<ActionId>,
+    playlist_failures: u32,
+    reload_action_id: Option<ActionId>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+    playlist: Option<AudioPlaylist>,
+
+    /// Sequence number of the next segment to be played, once aligned.
+    next_sequence: Option<u64>,
+
+    /// Difference of the sequence numbers of the audio and the video at the alignment,
+    /// which matches their segments if they cannot be matched in time.
+    sequence_offset: i64,
+
+    request: Option<AudioRequest>,
+    keys: HashMap<Url, Vec<u8>>,
+    retry_action_id: Option<ActionId>,
+    is_rejected: bool,
+}
+impl AudioRenditionLoader {
+    pub fn new(config: &PlayerConfig, rendition: Rendition, url: Url) -> Self {
+        let mut loader = AudioRenditionLoader {
+            config: config.clone(),
+            action_factory: ActionFactory::new(AUDIO_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            rendition,
+            url,
+            fetch_playlist_action_id: None,
+            playlist_failures: 0,
+            reload_action_id: None,
+            partial_bodies: HashMap::new(),
+            playlist: None,
+            next_sequence: None,
+            sequence_offset: 0,
+            request: None,
+            keys: HashMap::new(),
+            retry_action_id: None,
+            is_rejected: false,
+        };
+        loader.request_playlist();
+        loader
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == AUDIO_STREAM_ID
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn rendition(&self) -> &Rendition {
+        &self.rendition
+    }
+
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.next_sequence
+    }
+
+    /// Returns `true` if the playlist is loaded but not aligned with the video yet.
+    pub fn is_unaligned(&self) -> bool {
+        self.playlist.is_some() && self.next_sequence.is_none()
+    }
+
+    /// Moves the cursor to the segment presented at `point` of the video,
+    /// and emits `Event::AudioRenditionSwitched`.
+    pub fn align(&mut self, point: &SwitchPoint) {
+        let playlist = match self.playlist {
+            None => return,
+            Some(ref playlist) => playlist,
+        };
+        let sequence = media_playlist_handler::aligned_sequence(
+            &playlist.playlist,
+            playlist.first_seq,
+            point,
+            playlist.is_live,
+        ).unwrap_or_else(|| cmp::max(point.sequence, playlist.first_seq));
+        log!(
+            self.config,
+            Info,
+            "Aligned the audio rendition {:?} to the segment #{} (video #{})",
+            self.rendition.name,
+            sequence,
+            point.sequence
+        );
+        self.next_sequence = Some(sequence);
+        self.sequence_offset = sequence as i64 - point.sequence as i64;
+        self.event_queue.push_back(Event::AudioRenditionSwitched {
+            name: self.rendition.name.clone(),
+            language: self.rendition.language.clone(),
+            sequence,
+        });
+    }
+
+    /// Returns `true` if the segments of the rendition are not in MPEG-2 TS,
+    /// so that it is no longer loaded.
+    pub fn is_rejected(&self) -> bool {
+        self.is_rejected
+    }
+
+    /// Starts fetching the audio segments presented during the video segment `span`,
+    /// cancelling those of another one.
+    pub fn request_audio(&mut self, span: &SegmentSpan) {
+        if self.is_rejected || self.request.as_ref().is_some_and(|r| r.span == *span) {
+            return;
+        }
+        if let Some(action_id) = self.request.take().and_then(|r| r.fetch).map(|f| f.0) {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+        self.retry_action_id = None;
+        self.request = Some(AudioRequest {
+            span: *span,
+            segments: None,
+            fetch: None,
+            attempts: 0,
+            first_sequence: None,
+            data: Vec::new(),
+        });
+        self.resolve_request();
+    }
+
+    /// Takes the TS packets of the audio of `span` once fetched, with the sequence number
+    /// of their first segment (or `None` if there is no audio for the span).
+    pub fn take_audio(&mut self, span: &SegmentSpan) -> Option<(Option<u64>, Vec<u8>)> {
+        let is_ready = self.request.as_ref().is_some_and(|r| {
+            r.span == *span
+                && r.segments.as_ref().is_some_and(|s| s.is_empty())
+                && r.fetch.is_none()
+        });
+        if !is_ready {
+            return None;
+        }
+        let request = self.request.take().expect("Never fails");
+        Some((request.first_sequence, request.data))
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8]) {
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let result = self.handle_playlist(&text::decode(data));
+            if let Err(e) = result {
+                self.handle_playlist_failure(FailureClass::Permanent, e.to_string());
+            }
+            self.resolve_request();
+            return;
+        }
+        let fetch = match self.request.as_mut() {
+            Some(request) if request.fetch.as_ref().is_some_and(|f| f.0 == action_id) => {
+                request.attempts = 0;
+                request.fetch.take().expect("Never fails").1
+            }
+            _ => return,
+        };
+        match fetch {
+            AudioFetch::Key(url) => {
+                self.keys.insert(url, data.to_vec());
+            }
+            AudioFetch::Segment(segment) => {
+                let decrypted = segment
+                    .key
+                    .as_ref()
+                    .map(|key| {
+                        let key_data = self.keys.get(&key.key.url).map_or(&[][..], |k| &k[..]);
+                        track!(key.decrypt(key_data, segment.seq, data))
+                    })
+                    .transpose();
+                let payload = decrypted.as_ref().ok().and_then(|d| d.as_ref()).map_or(data, |d| d);
+                if let Some(container) = container_of(payload) {
+                    self.reject(container);
+                    return;
+                }
+                let request = self.request.as_mut().expect("Never fails");
+                request.segments.as_mut().map(|s| s.pop_front());
+                match decrypted {
+                    Ok(_) => {
+                        let (packets, _) = ts_sync::resync(payload);
+                        request.data.extend_from_slice(&packets);
+                        request.first_sequence = request.first_sequence.or(Some(segment.seq));
+                        self.next_sequence = Some(segment.seq + 1);
+                    }
+                    Err(e) => {
+                        let reason = format!("Cannot decrypt the audio segment #{}", segment.seq);
+                        self.skip_segment(&reason, &e.to_string());
+                    }
+                }
+            }
+        }
+        self.fetch_next();
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        self.partial_bodies
+            .entry(action_id)
+            .or_default()
+            .extend_from_slice(chunk);
+    }
+
+    pub fn handle_data_end(&mut self, action_id: ActionId) {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        self.handle_data(action_id, &body);
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) {
+        if self.reload_action_id == Some(action_id) {
+            self.reload_action_id = None;
+            self.request_playlist();
+        } else if self.retry_action_id == Some(action_id) {
+            self.retry_action_id = None;
+            self.fetch_next();
+        }
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    pub fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        self.partial_bodies.remove(&action_id);
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let reason = format!(
+                "Cannot fetch the audio playlist {}: {}",
+                self.url, description
+            );
+            self.handle_playlist_failure(class, reason);
+            return;
+        }
+        let request = match self.request.as_mut() {
+            Some(request) if request.fetch.as_ref().is_some_and(|f| f.0 == action_id) => request,
+            _ => return,
+        };
+        let fetch = request.fetch.take().expect("Never fails").1;
+        request.attempts += 1;
+        let retry = &self.config.segment_retry;
+        if !class.is_permanent() && request.attempts < retry.max_attempts {
+            let action = self.action_factory
+                .set_timeout(retry.delay(request.attempts));
+            self.retry_action_id = Some(action.id());
+            self.action_queue.push_back(action);
+            return;
+        }
+        request.attempts = 0;
+        request.segments.as_mut().map(|s| s.pop_front());
+        let reason = match fetch {
+            AudioFetch::Key(url) => format!("Cannot fetch the key {} of the audio segment", url),
+            AudioFetch::Segment(segment) => {
+                format!("Cannot fetch the audio segment {}", segment.url)
+            }
+        };
+        self.skip_segment(&reason, description);
+        self.fetch_next();
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.reload_action_id = None;
+        self.retry_action_id = None;
+        self.partial_bodies.clear();
+        let action_ids = self.fetch_playlist_action_id
+            .take()
+            .into_iter()
+            .chain(self.request.take().and_then(|r| r.fetch).map(|f| f.0))
+            .collect::<Vec<_>>();
+        for action_id in action_ids {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn request_playlist(&mut self) {
+        let action = self.action_factory
+            .fetch_data(self.url.clone(), ResourceKind::Playlist, None);
+        self.fetch_playlist_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+
+    fn handle_playlist(&mut self, m3u8: &str) -> Result<()> {
+        let (m3u8, _) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        self.playlist_failures = 0;
+        let target_duration = playlist.target_duration_tag().duration();
+        self.action_factory.set_target_duration(target_duration);
+
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        let segments = track!(self.parse_segments(&playlist, first_seq))?;
+        let is_live = playlist.end_list_tag().is_none();
+        self.playlist = Some(AudioPlaylist {
+            playlist,
+            first_seq,
+            is_live,
+            segments,
+        });
+        if is_live {
+            self.schedule_reload(target_duration);
+        }
+        Ok(())
+    }
+
+    fn parse_segments(
+        &self,
+        playlist: &MediaPlaylist,
+        first_seq: u64,
+    ) -> Result<Vec<AudioSegment>> {
+        let mut segments = Vec::new();
+        let mut key = None;
+        let mut program_date_time = None;
+        let mut position = Duration::from_secs(0);
+        let mut byte_range_end = 0;
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            let seq = first_seq + i as u64;
+            let duration = segment.inf_tag().duration();
+            program_date_time = segment
+                .program_date_time_tag()
+                .and_then(|t| date_time::parse(t.date_time()))
+                .or(program_date_time);
+
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+
+            if !segment.key_tags().is_empty() {
+                let tags = segment.key_tags();
+                key = track!(segment_resources::parse_key(&self.config, &self.url, tags))?;
+            }
+            segments.push(AudioSegment {
+                seq,
+                url: track!(segment_resources::resolve_url(&self.url, segment.uri()))?,
+                range,
+                key: key.clone(),
+                duration,
+                program_date_time,
+                position,
+            });
+            program_date_time = program_date_time.map(|t| t + duration);
+            position += duration;
+        }
+        Ok(segments)
+    }
+
+    /// Lists the segments of the request once the playlist does, and starts fetching them.
+    fn resolve_request(&mut self) {
+        let segments = match (self.request.as_ref(), self.playlist.as_ref()) {
+            (Some(request), Some(playlist)) if request.segments.is_none() => {
+                playlist.segments_during(&request.span, self.sequence_offset)
+            }
+            _ => return,
+        };
+        let segments = match segments {
+            None => return,
+            Some(segments) => segments,
+        };
+        if segments.is_empty() {
+            let span = &self.request.as_ref().expect("Never fails").span;
+            log!(
+                self.config,
+                Warn,
+                "No audio segments of {} for the video segment #{}",
+                self.url,
+                span.start.sequence
+            );
+        }
+        self.request.as_mut().expect("Never fails").segments = Some(segments.into());
+        self.fetch_next();
+    }
+
+    /// Fetches the next segment of the request (or its key).
+    fn fetch_next(&mut self) {
+        if self.retry_action_id.is_some() {
+            return;
+        }
+        let segment = match self.request.as_ref() {
+            Some(request) if request.fetch.is_none() => {
+                match request.segments.as_ref().and_then(|s| s.front()) {
+                    None => return,
+                    Some(segment) => segment.clone(),
+                }
+            }
+            _ => return,
+        };
+        let key_url = segment
+            .key
+            .as_ref()
+            .map(|k| k.key.url.clone())
+            .filter(|url| !self.keys.contains_key(url));
+        let (action, fetch) = match key_url {
+            Some(url) => (
+                self.action_factory
+                    .fetch_data(url.clone(), ResourceKind::Key, None),
+                AudioFetch::Key(url),
+            ),
+            None => (
+                self.action_factory
+                    .fetch_data(segment.url.clone(), ResourceKind::Segment, segment.range),
+                AudioFetch::Segment(Box::new(segment)),
+            ),
+        };
+        self.request.as_mut().expect("Never fails").fetch = Some((action.id(), fetch));
+        self.action_queue.push_back(action);
+    }
+
+    /// Stops loading the rendition, whose segments are in `container` instead of MPEG-2 TS.
+    fn reject(&mut self, container: &str) {
+        let reason = format!(
+            "Cannot merge the audio rendition {} in {} into the video, which is played alone \
+             (only MPEG-2 TS renditions are supported)",
+            self.url, container
+        );
+        log!(self.config, Error, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+        self.stop();
+        self.is_rejected = true;
+    }
+
+    /// Reports the segment of the request which is skipped by a non-fatal `Event::Error`.
+    fn skip_segment(&mut self, reason: &str, description: &str) {
+        let reason = format!("{}: {}", reason, description);
+        log!(self.config, Warn, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    fn handle_playlist_failure(&mut self, class: FailureClass, reason: String) {
+        self.playlist_failures += 1;
+        if !class.is_permanent() && self.playlist_failures < self.config.playlist_retry.max_attempts
+        {
+            let delay = self.config.playlist_retry.delay(self.playlist_failures);
+            self.schedule_reload(delay);
+            return;
+        }
+        log!(self.config, Warn, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    fn schedule_reload(&mut self, delay: Duration) {
+        let action = self.action_factory.set_timeout(delay);
+        self.reload_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
+
+/// Last loaded media playlist of the rendition.
+#[derive(Debug)]
+struct AudioPlaylist {
+    playlist: MediaPlaylist,
+    first_seq: u64,
+    is_live: bool,
+    segments: Vec<AudioSegment>,
+}
+impl AudioPlaylist {
+    /// Returns the segments whose middle is in the video segment `span`, or `None` if
+    /// the live playlist may list more of them later.
+    ///
+    /// Without program date-times (or VOD positions) on both sides, the segment numbered
+    /// as the video one plus `sequence_offset` is taken.
+    fn segments_during(
+        &self,
+        span: &SegmentSpan,
+        sequence_offset: i64,
+    ) -> Option<Vec<AudioSegment>> {
+        let start = &span.start;
+        let has_date_times = self.segments.iter().all(|s| s.program_date_time.is_some());
+        let bounds = match start.program_date_time {
+            Some(t) if has_date_times && !self.segments.is_empty() => Some((t, true)),
+            _ if self.is_live => None,
+            _ => start.position.map(|p| (p, false)),
+        };
+        let (from, by_date_time) = match bounds {
+            None => {
+                let seq = start.sequence as i64 + sequence_offset;
+                let last = self.first_seq as i64 + self.segments.len() as i64 - 1;
+                if seq > last && self.is_live {
+                    return None;
+                }
+                let matched = self.segments.iter().find(|s| s.seq as i64 == seq);
+                return Some(matched.cloned().into_iter().collect());
+            }
+            Some(bounds) => bounds,
+        };
+        let until = from + span.duration;
+        let middle = |s: &AudioSegment| {
+            let start = if by_date_time {
+                s.program_date_time.expect("Never fails")
+            } else {
+                s.position
+            };
+            start + s.duration / 2
+        };
+        if self.is_live && self.segments.last().is_none_or(|s| middle(s) < until) {
+            return None;
+        }
+        Some(
+            self.segments
+                .iter()
+                .filter(|s| middle(s) >= from && middle(s) < until)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AudioSegment {
+    seq: u64,
+    url: Url,
+    range: Option<ByteRange>,
+    key: Option<Encryption>,
+    duration: Duration,
+
+    /// Time since the UNIX epoch, if the playlist has `EXT-X-PROGRAM-DATE-TIME`.
+    program_date_time: Option<Duration>,
+
+    /// Time from the start of the playlist.
+    position: Duration,
+}
+
+/// Returns the name of the container of `segment` if it is known not to be MPEG-2 TS.
+fn container_of(segment: &[u8]) -> Option<&'static str> {
+    const FMP4_BOXES: [&[u8]; 4] = [b"ftyp", b"styp", b"sidx", b"moof"];
+    let is_adts = segment.len() >= 2 && segment[0] == 0xFF && segment[1] & 0xF6 == 0xF0;
+    if segment.get(4..8).is_some_and(|t| FMP4_BOXES.contains(&t)) {
+        Some("fMP4")
+    } else if segment.starts_with(b"ID3") || is_adts {
+        // Packed audio starts with an ID3 tag of its timestamp (RFC 8216, 3.4).
+        Some("packed audio (ADTS/AAC)")
+    } else {
+        None
+    }
+}
+
+/// Audio of a video segment.
+#[derive(Debug)]
+struct AudioRequest {
+    span: SegmentSpan,
+
+    /// Segments to be fetched, or `None` until the playlist lists them.
+    segments: Option<VecDeque<AudioSegment>>,
+
+    fetch: Option<(ActionId, AudioFetch)>,
+    attempts: u32,
+    first_sequence: Option<u64>,
+
+    /// TS packets of the fetched segments.
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum AudioFetch {
+    Key(Url),
+    Segment(Box<AudioSegment>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::simulation::{Response, Simulation};
+
+    const MASTER: &str = "#EXTM3U\n\
+                          #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",NAME=\"en\",DEFAULT=YES,\
+                          URI=\"audio.m3u8\"\n\
+                          #EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"a\"\nvideo.m3u8\n";
+
+    fn playlist(uri: &str) -> String {
+        format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\n{}\n#EXT-X-ENDLIST\n",
+            uri
+        )
+    }
+
+    #[test]
+    fn packed_audio_is_rejected() {
+        let mut config = PlayerConfig::default();
+        config.renditions.audio_playlists = true;
+        let mut sim = Simulation::new(config);
+        sim.respond("http://x/master.m3u8", Response::data(MASTER))
+            .respond("http://x/video.m3u8", Response::data(playlist("0.ts")))
+            .respond("http://x/audio.m3u8", Response::data(playlist("0.aac")))
+            .respond("http://x/0.ts", Response::data(vec![0x47; 188]))
+            .respond("http://x/0.aac", Response::data(&b"ID3\x04\x00"[..]));
+        sim.play("http://x/master.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(10)).unwrap();
+
+        let errors = sim.events()
+            .into_iter()
+            .filter_map(|e| match *e {
+                Event::Error { ref reason, .. } => Some(reason.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(errors[0].contains("audio rendition http://x/audio.m3u8 in packed audio"));
+
+        // The video segment is remuxed alone instead of awaiting its audio.
+        assert!(errors[1..].iter().any(|e| e.contains("Cannot remux the segment http://x/0.ts")));
+    }
+}
@@ -0,0 +1,139 @@
+//! Synchronization of the audio of remuxed segments with their video.
+//!
+//! The remuxer lays out the AAC frames of a segment back to back from the start of its video,
+//! discarding their timestamps. Audio starting earlier or later than the video, or with gaps
+//! between its PES packets, therefore drifts from the video by the difference. The drift is
+//! measured from the PES timestamps, and can be cancelled out by dropping or duplicating frames.
+use std::cmp;
+use mse_fmp4::fmp4::{InitializationSegment, MediaSegment, Mp4Box, SampleEntry};
+
+use Result;
+use super::timestamps::{self, StreamTimestamps, PTS_TIMESCALE, SAMPLES_PER_FRAME};
+
+/// Index of the audio `traf` box and `mdat` box in the segments made by the remuxer.
+const AUDIO_INDEX: usize = 1;
+
+/// Returns the offsets (in 90 kHz ticks) from the places of the audio frames in the output
+/// to their timestamps relative to the video, or `None` if they do not match the `frames`
+/// of the output.
+fn deviations(timestamps: &StreamTimestamps, frames: usize, sample_rate: u32) -> Option<Vec<i64>> {
+    let video_start = timestamps.video_start()?;
+    let audio = timestamps.audio();
+    let total = audio.iter().map(|&(_, n)| n).sum::<usize>();
+    if total != frames || sample_rate == 0 {
+        return None;
+    }
+    let mut deviations = Vec::with_capacity(frames);
+    for &(pts, n) in audio {
+        let start = timestamps::elapsed_signed(video_start, pts);
+        for j in 0..n {
+            let placed = frame_ticks(deviations.len() as i64, sample_rate);
+            deviations.push(start + frame_ticks(j as i64, sample_rate) - placed);
+        }
+    }
+    Some(deviations)
+}
+
+/// Returns the drift (in milliseconds) of the audio frames of `media_segment` with
+/// the largest magnitude, which is positive if they are output earlier than their timestamps.
+pub fn measure(
+    timestamps: &StreamTimestamps,
+    media_segment: &MediaSegment,
+    sample_rate: u32,
+) -> Option<f64> {
+    let frames = media_segment.moof_box.traf_boxes.get(AUDIO_INDEX)?.trun_box.samples.len();
+    let deviations = deviations(timestamps, frames, sample_rate)?;
+    let drift = deviations.into_iter().max_by_key(|d| d.abs())?;
+    Some(drift as f64 * 1000.0 / PTS_TIMESCALE as f64)
+}
+
+/// Drops the audio frames of `media_segment` output a frame or more later than their
+/// timestamps, and repeats those output a frame or more earlier until they are in place.
+///
+/// Returns the numbers of the duplicated and dropped frames.
+pub fn correct(
+    timestamps: &StreamTimestamps,
+    media_segment: &mut MediaSegment,
+    sample_rate: u32,
+) -> Result<(usize, usize)> {
+    let (samples, data) = match (
+        media_segment.moof_box.traf_boxes.get(AUDIO_INDEX),
+        media_segment.mdat_boxes.get(AUDIO_INDEX),
+    ) {
+        (Some(traf), Some(mdat)) => (&traf.trun_box.samples, &mdat.data),
+        _ => return Ok((0, 0)),
+    };
+    let deviations = match deviations(timestamps, samples.len(), sample_rate) {
+        None => return Ok((0, 0)),
+        Some(x) => x,
+    };
+
+    let mut corrected_samples = Vec::with_capacity(samples.len());
+    let mut corrected_data = Vec::with_capacity(data.len());
+    let (mut duplicated, mut dropped) = (0, 0);
+    let mut offset = 0;
+    for (i, (sample, deviation)) in samples.iter().zip(deviations).enumerate() {
+        let size = sample.size.unwrap_or(0) as usize;
+        let frame = &data[offset..cmp::min(offset + size, data.len())];
+        offset += size;
+
+        // Deviation of the frame at its new place.
+        let shift = frame_ticks((duplicated as i64) - (dropped as i64), sample_rate);
+        let mut deviation = deviation - shift;
+        let frame_duration = frame_ticks(1, sample_rate);
+        let is_only_left = i + 1 == samples.len() && corrected_samples.is_empty();
+        if deviation <= -frame_duration && !is_only_left {
+            dropped += 1;
+            continue;
+        }
+        while deviation >= frame_duration {
+            corrected_samples.push(sample.clone());
+            corrected_data.extend_from_slice(frame);
+            duplicated += 1;
+            deviation -= frame_duration;
+        }
+        corrected_samples.push(sample.clone());
+        corrected_data.extend_from_slice(frame);
+    }
+    if duplicated + dropped == 0 {
+        return Ok((0, 0));
+    }
+    media_segment.moof_box.traf_boxes[AUDIO_INDEX].trun_box.samples = corrected_samples;
+    media_segment.mdat_boxes[AUDIO_INDEX].data = corrected_data;
+
+    // The `moof` box has grown or shrunk by the `trun` entries, so the data offsets
+    // (from the start of the `moof` box to the payload of each `mdat` box) are updated.
+    let mut data_offset = track!(media_segment.moof_box.box_size())? as i32 + 8;
+    for (traf, mdat) in media_segment
+        .moof_box
+        .traf_boxes
+        .iter_mut()
+        .zip(&media_segment.mdat_boxes)
+    {
+        traf.trun_box.data_offset = Some(data_offset);
+        data_offset += track!(mdat.box_size())? as i32;
+    }
+    Ok((duplicated, dropped))
+}
+
+/// Returns the sampling frequency of the audio track of `init`.
+pub fn sample_rate(init: &InitializationSegment) -> Option<u32> {
+    init.moov_box
+        .trak_boxes
+        .iter()
+        .find(|t| {
+            t.mdia_box
+                .minf_box
+                .stbl_box
+                .stsd_box
+                .sample_entries
+                .iter()
+                .any(|e| matches!(*e, SampleEntry::Aac(_)))
+        })
+        .map(|t| t.mdia_box.mdhd_box.timescale)
+}
+
+/// Returns the duration of `frames` AAC frames in 90 kHz ticks.
+fn frame_ticks(frames: i64, sample_rate: u32) -> i64 {
+    frames * SAMPLES_PER_FRAME as i64 * PTS_TIMESCALE as i64 / i64::from(sample_rate)
+}
@@ -0,0 +1,41 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use url::Url;
+
+use super::ByteRange;
+
+/// Resolved location of a fetched resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResourceKey {
+    pub url: Url,
+    pub range: Option<ByteRange>,
+}
+
+/// Cache of immutable resources (e.g., `EXT-X-MAP` init sections) shared among media playlists.
+///
+/// Clones of a cache refer to the same entries, so the resources fetched for one variant
+/// are reused after switching to another one.
+///
+/// Entries are reference-counted, so that readers share them without copying.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceCache {
+    entries: Rc<RefCell<HashMap<ResourceKey, Rc<[u8]>>>>,
+}
+impl ResourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &ResourceKey) -> Option<Rc<[u8]>> {
+        self.entries.borrow().get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &ResourceKey) -> bool {
+        self.entries.borrow().contains_key(key)
+    }
+
+    pub fn insert(&self, key: ResourceKey, data: &[u8]) {
+        self.entries.borrow_mut().insert(key, Rc::from(data));
+    }
+}
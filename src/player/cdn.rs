@@ -0,0 +1,59 @@
+use url::Url;
+
+use super::CdnFailoverConfig;
+
+/// Selector of the base URL (CDN) used for requests.
+#[derive(Debug)]
+pub struct CdnSelector {
+    config: CdnFailoverConfig,
+    current: usize,
+    consecutive_failures: u32,
+}
+impl CdnSelector {
+    pub fn new(config: &CdnFailoverConfig) -> Self {
+        CdnSelector {
+            config: config.clone(),
+            current: 0,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Replaces the base URL of `url` with the currently selected one.
+    ///
+    /// URLs that do not start with any of the configured base URLs are returned unchanged.
+    pub fn rewrite_url(&self, url: Url) -> Url {
+        let current = match self.config.base_urls.get(self.current) {
+            None => return url,
+            Some(x) => x,
+        };
+        let rewritten = {
+            let s = url.as_str();
+            self.config
+                .base_urls
+                .iter()
+                .find(|base| s.starts_with(base.as_str()))
+                .filter(|base| *base != current)
+                .and_then(|base| Url::parse(&format!("{}{}", current, &s[base.len()..])).ok())
+        };
+        rewritten.unwrap_or(url)
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+    }
+
+    /// Records a failed request, and returns the `(from, to)` base URLs if it caused failover.
+    pub fn record_failure(&mut self) -> Option<(String, String)> {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures < self.config.max_consecutive_failures
+            || self.current + 1 >= self.config.base_urls.len()
+        {
+            return None;
+        }
+        self.consecutive_failures = 0;
+        self.current += 1;
+        let from = self.config.base_urls[self.current - 1].clone();
+        let to = self.config.base_urls[self.current].clone();
+        Some((from, to))
+    }
+}
@@ -0,0 +1,214 @@
+//! [CMCD] (Common Media Client Data, CTA-5004) attached to fetch actions.
+//!
+//! [CMCD]: https://cdn.cta.tech/cta/media/media/resources/standards/pdfs/cta-5004-final.pdf
+use std::collections::BTreeMap;
+use url::Url;
+
+use super::{CmcdConfig, CmcdTransmission, ResourceKind};
+
+/// Client state reported by CMCD.
+#[derive(Debug, Default, Clone)]
+pub struct CmcdState {
+    /// Buffer length in milliseconds, as reported by the host.
+    pub buffer_length_ms: Option<u32>,
+
+    /// Estimated throughput of segment fetches in kbps.
+    pub throughput_kbps: Option<u32>,
+}
+
+/// Adds the CMCD keys for the request of `url` to either its query or `headers`.
+///
+/// `next_url` is the URL of the object expected to be requested next, if known.
+pub fn apply(
+    config: &CmcdConfig,
+    state: &CmcdState,
+    kind: ResourceKind,
+    next_url: Option<&Url>,
+    url: &mut Url,
+    headers: &mut BTreeMap<String, String>,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    // (header, key, value)
+    let mut keys = Vec::new();
+    if let Some(bl) = state.buffer_length_ms {
+        keys.push(("CMCD-Request", "bl", round_to(bl, 100).to_string()));
+    }
+    if let Some(ref cid) = config.content_id {
+        keys.push(("CMCD-Session", "cid", quote(cid)));
+    }
+    if let Some(mtp) = state.throughput_kbps {
+        keys.push(("CMCD-Request", "mtp", round_to(mtp, 100).to_string()));
+    }
+    if let Some(next_url) = next_url {
+        keys.push(("CMCD-Request", "nor", quote(&relative_path(url, next_url))));
+    }
+    let object_type = match kind {
+        ResourceKind::Playlist => "m",
+        ResourceKind::Segment => "av",
+        ResourceKind::InitSegment => "i",
+        ResourceKind::Key => "k",
+        ResourceKind::SteeringManifest => "o",
+        ResourceKind::Subtitles => "c",
+    };
+    keys.push(("CMCD-Object", "ot", object_type.to_owned()));
+    if let Some(ref sid) = config.session_id {
+        keys.push(("CMCD-Session", "sid", quote(sid)));
+    }
+
+    match config.transmission {
+        CmcdTransmission::Query => {
+            let value = join(keys.iter().map(|&(_, k, ref v)| (k, v)));
+            url.query_pairs_mut().append_pair("CMCD", &value);
+        }
+        CmcdTransmission::Headers => {
+            for header in &["CMCD-Object", "CMCD-Request", "CMCD-Session"] {
+                let value = join(
+                    keys.iter()
+                        .filter(|&&(h, _, _)| h == *header)
+                        .map(|&(_, k, ref v)| (k, v)),
+                );
+                if !value.is_empty() {
+                    headers.insert((*header).to_owned(), value);
+                }
+            }
+        }
+    }
+}
+
+fn join<'a, I>(pairs: I) -> String
+where
+    I: Iterator<Item = (&'a str, &'a String)>,
+{
+    pairs
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn round_to(n: u32, unit: u32) -> u32 {
+    (n + unit / 2) / unit * unit
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns `next_url` as a path relative to the origin of `base`, if they share it.
+fn relative_path(base: &Url, next_url: &Url) -> String {
+    if base.origin() != next_url.origin() {
+        return next_url.as_str().to_owned();
+    }
+    match next_url.query() {
+        None => next_url.path().to_owned(),
+        Some(query) => format!("{}?{}", next_url.path(), query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(transmission: CmcdTransmission) -> CmcdConfig {
+        CmcdConfig {
+            enabled: true,
+            transmission,
+            session_id: Some("6e2fb550-c457-11e9-bb97-0800200c9a66".to_owned()),
+            content_id: Some("say \"hi\" \\o/".to_owned()),
+        }
+    }
+
+    fn state() -> CmcdState {
+        CmcdState {
+            buffer_length_ms: Some(21_349),
+            throughput_kbps: Some(25_449),
+        }
+    }
+
+    /// Applies CMCD to the request of `url`, returning the modified URL and the headers.
+    fn apply_to(
+        config: &CmcdConfig,
+        kind: ResourceKind,
+        url: &str,
+        next_url: Option<&str>,
+    ) -> (Url, BTreeMap<String, String>) {
+        let mut url = Url::parse(url).unwrap();
+        let next_url = next_url.map(|u| Url::parse(u).unwrap());
+        let mut headers = BTreeMap::new();
+        apply(config, &state(), kind, next_url.as_ref(), &mut url, &mut headers);
+        (url, headers)
+    }
+
+    #[test]
+    fn query_keys_are_sorted_and_escaped() {
+        let config = config(CmcdTransmission::Query);
+        let (url, headers) = apply_to(
+            &config,
+            ResourceKind::Segment,
+            "http://x/v/1.ts?token=a",
+            Some("http://x/v/2.ts?token=a"),
+        );
+        assert!(headers.is_empty());
+        let pairs = url.query_pairs().collect::<Vec<_>>();
+        assert_eq!(pairs[0].0, "token");
+        assert_eq!(pairs[1].0, "CMCD");
+        assert_eq!(
+            pairs[1].1,
+            "bl=21300,cid=\"say \\\"hi\\\" \\\\o/\",mtp=25400,nor=\"/v/2.ts?token=a\",ot=av,\
+             sid=\"6e2fb550-c457-11e9-bb97-0800200c9a66\""
+        );
+        // The value itself is percent-encoded in the query.
+        assert!(!url.query().unwrap().contains('"'));
+    }
+
+    #[test]
+    fn header_keys_are_grouped_by_header() {
+        let config = config(CmcdTransmission::Headers);
+        let (url, headers) = apply_to(
+            &config,
+            ResourceKind::Playlist,
+            "http://x/v.m3u8",
+            Some("http://y/w.m3u8"),
+        );
+        assert_eq!(url.query(), None);
+        let headers = headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            [
+                ("CMCD-Object", "ot=m"),
+                ("CMCD-Request", "bl=21300,mtp=25400,nor=\"http://y/w.m3u8\""),
+                (
+                    "CMCD-Session",
+                    "cid=\"say \\\"hi\\\" \\\\o/\",sid=\"6e2fb550-c457-11e9-bb97-0800200c9a66\""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_headers_are_omitted() {
+        let config = CmcdConfig {
+            session_id: None,
+            content_id: None,
+            ..config(CmcdTransmission::Headers)
+        };
+        let mut url = Url::parse("http://x/k").unwrap();
+        let mut headers = BTreeMap::new();
+        let state = CmcdState::default();
+        apply(&config, &state, ResourceKind::Key, None, &mut url, &mut headers);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["CMCD-Object"], "ot=k");
+
+        let config = CmcdConfig {
+            enabled: false,
+            ..config
+        };
+        let (url, headers) = apply_to(&config, ResourceKind::Segment, "http://x/1.ts", None);
+        assert_eq!((url.query(), headers.len()), (None, 0));
+    }
+}
@@ -0,0 +1,68 @@
+//! `CODECS` and `SUPPLEMENTAL-CODECS` of variants.
+//!
+//! `SUPPLEMENTAL-CODECS` (e.g., `dvh1.08.07/db4h` for Dolby Vision) describes an enhancement
+//! layer of the video whose base layer is decodable by the codec listed in `CODECS`,
+//! so devices without support for it just play the base layer.
+
+/// Sample entry types of audio codecs, which are not replaced by supplemental codecs.
+const AUDIO_CODECS: &[&str] = &["mp4a", "ac-3", "ec-3", "ac-4", "opus", "flac", "alac"];
+
+/// Supplemental codec of a variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupplementalCodec {
+    /// RFC 6381 codec string (e.g., `dvh1.08.07`).
+    pub codec: String,
+
+    /// Compatibility brands following the codec string (e.g., `db4h`).
+    pub brands: Vec<String>,
+}
+
+/// Parses the value of `SUPPLEMENTAL-CODECS`.
+pub fn parse_supplemental(value: &str) -> Vec<SupplementalCodec> {
+    value
+        .split(',')
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            let mut fields = c.split('/');
+            let codec = fields.next().unwrap_or("").to_owned();
+            let brands = fields.map(|b| b.to_owned()).collect();
+            SupplementalCodec { codec, brands }
+        })
+        .collect()
+}
+
+/// Returns the first of `supplemental` whose sample entry type (e.g., `dvh1`) or
+/// compatibility brand is listed in `supported`.
+pub fn find_supported<'a>(
+    supplemental: &'a [SupplementalCodec],
+    supported: &[String],
+) -> Option<&'a SupplementalCodec> {
+    supplemental.iter().find(|s| {
+        supported
+            .iter()
+            .any(|x| *x == sample_entry(&s.codec) || s.brands.contains(x))
+    })
+}
+
+/// Returns `codecs` with its video codec replaced by `supplemental`.
+pub fn enhance(codecs: &str, supplemental: &SupplementalCodec) -> String {
+    let mut is_replaced = false;
+    let codecs = codecs
+        .split(',')
+        .map(|c| c.trim())
+        .map(|c| {
+            if !is_replaced && !AUDIO_CODECS.contains(&sample_entry(c)) {
+                is_replaced = true;
+                supplemental.codec.as_str()
+            } else {
+                c
+            }
+        })
+        .collect::<Vec<_>>();
+    codecs.join(",")
+}
+
+fn sample_entry(codec: &str) -> &str {
+    codec.split('.').next().unwrap_or(codec)
+}
@@ -0,0 +1,1082 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde_json;
+use url::Url;
+
+use {Error, Result};
+use super::{CorsMode, CredentialsMode, Hooks, LogLevel, ResourceKind};
+
+/// Player configuration.
+///
+/// Hosts pass it as a JSON object; omitted fields take their default values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    /// Index of the `EXT-X-STREAM-INF` entry played when starting from a master playlist.
+    ///
+    /// A variant of a similar bandwidth with a supported `SUPPLEMENTAL-CODECS` or a higher
+    /// `SCORE` is played instead.
+    pub variant_index: usize,
+
+    /// Retry policy for failed media segment fetches.
+    pub segment_retry: RetryConfig,
+
+    /// Retry policy for failed (or unparsable) media playlist reloads.
+    ///
+    /// When the attempts are exhausted, a fatal `Event::Error` is emitted.
+    pub playlist_retry: RetryConfig,
+
+    /// Retry policy for failed init section (`EXT-X-MAP`) and key (`EXT-X-KEY`) fetches;
+    /// `segment_retry` if `None`.
+    pub init_retry: Option<RetryConfig>,
+
+    /// Fails the playback once the fetches of the media playlist and its segments have kept
+    /// failing for this long without progress, even if retries remain.
+    ///
+    /// Fetched segments are progress, and so are playlist reloads unless a segment is being
+    /// retried. The time is measured by `Hooks::clock` if set, and otherwise approximated by
+    /// the retry delays. Disabled if `None`.
+    pub no_progress_timeout_ms: Option<u32>,
+
+    /// Media playlist reload (polling) intervals.
+    pub reload: ReloadConfig,
+
+    /// Timeouts attached to `Action::FetchData`.
+    pub fetch_timeout: TimeoutConfig,
+
+    /// HTTP headers attached to `Action::FetchData`.
+    pub request_headers: HeadersConfig,
+
+    /// Credentials and CORS mode hints attached to `Action::FetchData`.
+    pub request_modes: RequestModeConfig,
+
+    /// Client metadata attached to `Action::FetchData` (e.g., for correlating CDN logs).
+    pub request_metadata: RequestMetadataConfig,
+
+    /// Alternative base URLs used on repeated request failures.
+    pub cdn_failover: CdnFailoverConfig,
+
+    /// CMCD keys attached to `Action::FetchData`.
+    pub cmcd: CmcdConfig,
+
+    /// Throughput hints of the response headers (see `HlsPlayer::handle_response_headers`).
+    pub throughput_hints: ThroughputHintConfig,
+
+    /// Minimum change of the live latency that emits `Event::LatencyChanged`.
+    ///
+    /// The event is disabled if `None`.
+    pub latency_event_threshold_ms: Option<u32>,
+
+    /// Query parameters appended to every requested URL (e.g., signed tokens),
+    /// replacing the same-named ones of the URL.
+    pub query_params: BTreeMap<String, String>,
+
+    /// Most verbose level of the records passed to `Hooks::logger`.
+    pub log_level: LogLevel,
+
+    /// Limits of the produced segments waiting to be taken by the host.
+    pub output_queue: OutputQueueConfig,
+
+    /// Log of the requested fetches (see `HlsPlayer::request_log_har`).
+    pub request_log: RequestLogConfig,
+
+    /// Recording of the played stream into a single fMP4 (see `HlsPlayer::take_recording`).
+    pub recording: RecordingConfig,
+
+    /// Offline downloads of VOD playlists (see `HlsPlayer::download`).
+    pub download: DownloadConfig,
+
+    /// Thumbnail generation from I-frame playlists (see `HlsPlayer::generate_thumbnails`).
+    pub thumbnails: ThumbnailConfig,
+
+    /// Trick play from I-frame playlists (see `HlsPlayer::start_trick_play`).
+    pub trick_play: TrickPlayConfig,
+
+    /// Log of the inputs given by the host for replaying (see `HlsPlayer::input_log`).
+    pub input_log: InputLogConfig,
+
+    /// Content steering of master playlists with `EXT-X-CONTENT-STEERING`.
+    pub content_steering: ContentSteeringConfig,
+
+    /// Distance from the live edge (`EXT-X-SERVER-CONTROL`).
+    pub live_edge: LiveEdgeConfig,
+
+    /// Selection of the audio and subtitle renditions (`EXT-X-MEDIA`) of master playlists.
+    pub renditions: RenditionConfig,
+
+    /// Capabilities of the device, beyond which variants of master playlists are not played.
+    pub device: DeviceConfig,
+
+    /// Temporary exclusion of the variants of master playlists which keep failing.
+    pub variant_blacklist: VariantBlacklistConfig,
+
+    /// Background reloads of the media playlists of the variants which the next switch may take.
+    pub playlist_prefetch: PlaylistPrefetchConfig,
+
+    /// Elementary streams remuxed from TS segments with several audio or video streams.
+    pub tracks: TrackConfig,
+
+    /// Monitoring of the drift between the audio and video of the remuxed segments.
+    pub av_sync: AvSyncConfig,
+
+    /// Validation of the `EXTINF` durations against those measured from the remuxed segments.
+    pub duration_check: DurationCheckConfig,
+
+    /// Hints for jumping over small gaps in the buffered media.
+    pub gap_jump: GapJumpConfig,
+
+    /// In-band event messages (`emsg`) of the timed metadata of the produced segments.
+    pub emsg: EmsgConfig,
+
+    /// Low-power mode for background playback (see `HlsPlayer::set_low_power`).
+    pub low_power: LowPowerConfig,
+
+    /// Retention of the live segments which have left the playlist window before being fetched.
+    pub time_shift: TimeShiftConfig,
+
+    /// Segments fetched at once while the buffer is low (e.g., after seeks).
+    pub catch_up: CatchUpConfig,
+
+    /// Diagnostic trace of state transitions (see `HlsPlayer::trace_records`)
+    /// and the history attached to fatal errors.
+    pub trace: TraceConfig,
+
+    #[serde(skip)]
+    pub hooks: Hooks,
+}
+impl PlayerConfig {
+    pub fn from_json(json: &str) -> Result<Self> {
+        track!(serde_json::from_str(json).map_err(Error::from))
+    }
+
+    /// Returns the retry policy for the fetches of `kind`.
+    pub fn retry(&self, kind: ResourceKind) -> &RetryConfig {
+        match kind {
+            ResourceKind::InitSegment | ResourceKind::Key => {
+                self.init_retry.as_ref().unwrap_or(&self.segment_retry)
+            }
+            ResourceKind::Segment | ResourceKind::Subtitles => &self.segment_retry,
+            ResourceKind::Playlist | ResourceKind::SteeringManifest => &self.playlist_retry,
+        }
+    }
+
+    /// Applies `query_params` and the `UrlRewriter` hook to `url`.
+    pub fn rewrite_url(&self, kind: ResourceKind, mut url: Url) -> Url {
+        let is_replacing = url.query_pairs()
+            .any(|(k, _)| self.query_params.contains_key(k.as_ref()));
+        if is_replacing {
+            // Rebuilds the query without the replaced ones, which re-encodes the others.
+            let pairs = url.query_pairs()
+                .filter(|(k, _)| !self.query_params.contains_key(k.as_ref()))
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect::<Vec<_>>();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        if !self.query_params.is_empty() {
+            url.query_pairs_mut().extend_pairs(self.query_params.iter());
+        }
+        if let Some(ref rewriter) = self.hooks.url_rewriter {
+            url = rewriter.rewrite_url(kind, url);
+        }
+        url
+    }
+}
+
+/// Retry policy with exponential backoff.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Maximum number of fetch attempts (including the first one) before giving up.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry in milliseconds; it doubles on each subsequent retry.
+    pub initial_delay_ms: u32,
+
+    /// Upper bound of the retry delay in milliseconds.
+    pub max_delay_ms: u32,
+}
+impl RetryConfig {
+    /// Returns the delay before the retry following the `attempts`-th failed attempt.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let exponent = cmp::min(attempts.saturating_sub(1), 31);
+        let delay_ms = u64::from(self.initial_delay_ms) << exponent;
+        Duration::from_millis(cmp::min(delay_ms, u64::from(self.max_delay_ms)))
+    }
+}
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            initial_delay_ms: 500,
+            max_delay_ms: 8000,
+        }
+    }
+}
+
+/// Media playlist reload settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// Maximum random deviation of reload intervals as a ratio of them (e.g., `0.1` for ±10%).
+    pub jitter_ratio: f64,
+
+    /// Factor by which the interval grows on each consecutive reload without new segments.
+    pub unchanged_backoff_factor: f64,
+
+    /// Upper bound of the backed off interval as a multiple of the target duration.
+    pub max_backoff_ratio: f64,
+
+    /// Retries reloads answered by `404 Not Found` (with `playlist_retry`) instead of giving up.
+    ///
+    /// It only applies after the playlist has been loaded once.
+    pub retry_not_found: bool,
+
+    /// Time without new segments, as a multiple of the target duration,
+    /// after which a live playlist is regarded as stalled (see `Event::LiveStreamStalled`).
+    ///
+    /// The detection is disabled if it is not positive.
+    pub stall_threshold_ratio: f64,
+
+    /// Number of the segments that may drop out of the live playlist between reloads
+    /// (e.g., while the host was suspended) before the media sequence is regarded as jumped.
+    ///
+    /// After a jump, the playback rejoins the new window at `HOLD-BACK` behind the end,
+    /// even if `LiveEdgeConfig::hold_back` is disabled, instead of fetching all of it.
+    pub max_sequence_gap: u64,
+
+    /// Seed of the jitter.
+    ///
+    /// Hosts should give a random value, so that clients do not reload in lockstep.
+    pub random_seed: u64,
+}
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig {
+            jitter_ratio: 0.1,
+            unchanged_backoff_factor: 1.5,
+            max_backoff_ratio: 1.5,
+            retry_not_found: true,
+            stall_threshold_ratio: 3.0,
+            max_sequence_gap: 10,
+            random_seed: 0,
+        }
+    }
+}
+
+/// Fetch timeouts, scaled by the target duration of the media playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Timeout in milliseconds used until the target duration is known.
+    pub default_ms: u32,
+
+    /// Lower bound of the scaled timeouts in milliseconds.
+    pub min_ms: u32,
+
+    /// Playlist fetch timeout as a multiple of the target duration.
+    pub playlist_ratio: f64,
+
+    /// Segment (and init section) fetch timeout as a multiple of the target duration.
+    pub segment_ratio: f64,
+}
+impl TimeoutConfig {
+    pub fn timeout(&self, kind: ResourceKind, target_duration: Option<Duration>) -> Duration {
+        let target_duration = match target_duration {
+            None => return Duration::from_millis(u64::from(self.default_ms)),
+            Some(d) => d,
+        };
+        let ratio = match kind {
+            ResourceKind::Playlist | ResourceKind::SteeringManifest => self.playlist_ratio,
+            ResourceKind::Segment
+            | ResourceKind::InitSegment
+            | ResourceKind::Key
+            | ResourceKind::Subtitles => {
+                self.segment_ratio
+            }
+        };
+        let timeout_ms = (target_duration.as_secs() as f64 * 1000.0
+            + f64::from(target_duration.subsec_millis())) * ratio;
+        Duration::from_millis(cmp::max(timeout_ms as u64, u64::from(self.min_ms)))
+    }
+}
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            default_ms: 10_000,
+            min_ms: 2000,
+            playlist_ratio: 1.0,
+            segment_ratio: 2.0,
+        }
+    }
+}
+
+/// HTTP request headers (e.g., `Authorization`) per resource kind.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeadersConfig {
+    /// Headers attached to every request.
+    pub all: BTreeMap<String, String>,
+
+    /// Headers attached to playlist and steering manifest requests (in addition to `all`).
+    pub playlist: BTreeMap<String, String>,
+
+    /// Headers attached to segment, init section and key requests (in addition to `all`).
+    pub segment: BTreeMap<String, String>,
+}
+impl HeadersConfig {
+    /// Returns the headers for `kind`; kind specific values take precedence over `all`.
+    pub fn headers(&self, kind: ResourceKind) -> BTreeMap<String, String> {
+        let specific = match kind {
+            ResourceKind::Playlist | ResourceKind::SteeringManifest => &self.playlist,
+            ResourceKind::Segment
+            | ResourceKind::InitSegment
+            | ResourceKind::Key
+            | ResourceKind::Subtitles => {
+                &self.segment
+            }
+        };
+        let mut headers = self.all.clone();
+        headers.extend(specific.iter().map(|(k, v)| (k.clone(), v.clone())));
+        headers
+    }
+}
+
+/// Credentials and CORS mode hints per resource kind (e.g., cookies only for playlists).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestModeConfig {
+    /// Hints attached to every request.
+    pub all: RequestModes,
+
+    /// Hints for playlist and steering manifest requests, overriding `all`.
+    pub playlist: RequestModes,
+
+    /// Hints for segment, init section, key and subtitle requests, overriding `all`.
+    pub segment: RequestModes,
+}
+impl RequestModeConfig {
+    /// Returns the hints for `kind`; kind specific values take precedence over `all`.
+    pub fn modes(&self, kind: ResourceKind) -> RequestModes {
+        let specific = match kind {
+            ResourceKind::Playlist | ResourceKind::SteeringManifest => &self.playlist,
+            ResourceKind::Segment
+            | ResourceKind::InitSegment
+            | ResourceKind::Key
+            | ResourceKind::Subtitles => {
+                &self.segment
+            }
+        };
+        RequestModes {
+            credentials: specific.credentials.or(self.all.credentials),
+            mode: specific.mode.or(self.all.mode),
+        }
+    }
+}
+
+/// Hints of `Action::FetchData`; the host's defaults apply to the omitted ones.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestModes {
+    /// `"omit"`, `"same-origin"` or `"include"`.
+    pub credentials: Option<CredentialsMode>,
+
+    /// `"cors"`, `"no-cors"` or `"same-origin"`.
+    pub mode: Option<CorsMode>,
+}
+
+/// Static metadata of the client, set by the embedder.
+///
+/// The set fields are attached to every `Action::FetchData` by their names,
+/// and hosts may forward them (e.g., as headers).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestMetadataConfig {
+    pub player_name: Option<String>,
+    pub player_version: Option<String>,
+    pub session_id: Option<String>,
+
+    /// Additional entries (e.g., `"app"`); the fields above take precedence.
+    pub extra: BTreeMap<String, String>,
+}
+impl RequestMetadataConfig {
+    /// Returns the entries attached to the actions.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        let mut metadata = self.extra.clone();
+        let fields = [
+            ("player_name", &self.player_name),
+            ("player_version", &self.player_version),
+            ("session_id", &self.session_id),
+        ];
+        for &(key, value) in &fields {
+            if let Some(ref value) = *value {
+                metadata.insert(key.to_owned(), value.clone());
+            }
+        }
+        metadata
+    }
+}
+
+/// CDN failover settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CdnFailoverConfig {
+    /// Base URLs (e.g., `"https://cdn1.example.com/live/"`), in order of preference.
+    ///
+    /// Requested URLs starting with one of them are rewritten to start with the selected one.
+    pub base_urls: Vec<String>,
+
+    /// Number of consecutive request failures that switches to the next base URL.
+    pub max_consecutive_failures: u32,
+}
+impl Default for CdnFailoverConfig {
+    fn default() -> Self {
+        CdnFailoverConfig {
+            base_urls: Vec::new(),
+            max_consecutive_failures: 3,
+        }
+    }
+}
+
+/// CMCD (Common Media Client Data) settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CmcdConfig {
+    pub enabled: bool,
+
+    /// How the keys are sent to the server.
+    pub transmission: CmcdTransmission,
+
+    /// Session ID (`sid`) shared by all the requests of a playback session.
+    pub session_id: Option<String>,
+
+    /// Content ID (`cid`) of the played stream.
+    pub content_id: Option<String>,
+}
+
+/// Throughput hints of the response headers reported by the host, which are recorded
+/// by the throughput estimator (see `throughput`) along with the measured fetches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThroughputHintConfig {
+    pub enabled: bool,
+
+    /// `Server-Timing` metrics whose durations are spent by the servers before responding
+    /// (e.g., `["origin"]`), and are subtracted from the fetch durations.
+    pub server_timing_metrics: Vec<String>,
+}
+impl Default for ThroughputHintConfig {
+    fn default() -> Self {
+        ThroughputHintConfig {
+            enabled: true,
+            server_timing_metrics: Vec::new(),
+        }
+    }
+}
+
+/// Transmission mode of CMCD keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CmcdTransmission {
+    /// As the `CMCD` query parameter.
+    #[default]
+    Query,
+
+    /// As the `CMCD-Object`, `CMCD-Request` and `CMCD-Session` headers.
+    Headers,
+}
+
+/// Diagnostic trace settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraceConfig {
+    pub enabled: bool,
+
+    /// Maximum number of the retained records; the oldest ones are dropped first.
+    pub max_records: usize,
+
+    /// Number of the latest records retained while the trace is disabled,
+    /// which are attached to fatal `Event::Error`s.
+    pub history_size: usize,
+}
+impl TraceConfig {
+    /// Returns the number of the retained records.
+    pub fn capacity(&self) -> usize {
+        if self.enabled {
+            self.max_records
+        } else {
+            self.history_size
+        }
+    }
+}
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            enabled: false,
+            max_records: 10_000,
+            history_size: 32,
+        }
+    }
+}
+
+/// Request log settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestLogConfig {
+    /// Maximum number of the retained entries (`0` disables the log);
+    /// the oldest ones are dropped first.
+    pub max_entries: usize,
+}
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        RequestLogConfig { max_entries: 200 }
+    }
+}
+
+/// Recording settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Records the produced segments in addition to handing them to the host.
+    ///
+    /// Hosts should take the recorded bytes regularly, since they are held until then.
+    pub enabled: bool,
+}
+
+/// Input log settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputLogConfig {
+    /// Records every input of the playback, including the fetched bodies.
+    ///
+    /// The log is held until the next playback starts, so it is meant for reproducing issues
+    /// rather than for regular use.
+    pub enabled: bool,
+}
+
+/// Content steering settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentSteeringConfig {
+    /// Follows the steering server; otherwise `EXT-X-CONTENT-STEERING` is ignored and
+    /// the variant at `PlayerConfig::variant_index` is played regardless of its pathway.
+    pub enabled: bool,
+}
+impl Default for ContentSteeringConfig {
+    fn default() -> Self {
+        ContentSteeringConfig { enabled: true }
+    }
+}
+
+/// Live edge settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveEdgeConfig {
+    /// Starts live playbacks `HOLD-BACK` (three target durations by default) behind the end
+    /// of the playlist; otherwise they start at the oldest listed segment.
+    pub hold_back: bool,
+
+    /// Reloads live playlists with `CAN-BLOCK-RELOAD=YES` by blocking requests (`_HLS_msn`)
+    /// as soon as the previous one has been answered, instead of polling them.
+    pub blocking_reload: bool,
+
+    /// Distance behind the live edge up to which `HlsPlayer::resume` continues from the
+    /// position of `HlsPlayer::suspend`; further behind, it jumps to the live point.
+    ///
+    /// Unlimited if `None`, in which case it only jumps if the position has left
+    /// the playlist window.
+    pub max_catch_up_ms: Option<u32>,
+
+    /// Corrects the live latency for the skew between the clocks of the client and the origin,
+    /// and for the time spent by playlists in caches, by the `Date` and `Age` of the playlist
+    /// responses (see `HlsPlayer::handle_response_headers`).
+    pub correct_clock_skew: bool,
+}
+impl Default for LiveEdgeConfig {
+    fn default() -> Self {
+        LiveEdgeConfig {
+            hold_back: true,
+            blocking_reload: true,
+            max_catch_up_ms: None,
+            correct_clock_skew: true,
+        }
+    }
+}
+
+/// Preferences of the alternative renditions.
+///
+/// Languages are RFC 5646 tags (e.g., `["de", "en-US"]`) in the order of preference.
+/// Without a match, the `DEFAULT=YES` rendition of the group is selected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenditionConfig {
+    pub audio_languages: Vec<String>,
+
+    /// Subtitles are disabled unless one matches or the group has a default one.
+    pub subtitle_languages: Vec<String>,
+
+    /// `CHARACTERISTICS` wanted of the audio rendition
+    /// (e.g., `["public.accessibility.describes-video"]` for audio description).
+    ///
+    /// Renditions with accessibility characteristics are only selected if requested here
+    /// or if no other one is available.
+    pub audio_characteristics: Vec<String>,
+
+    /// `CHARACTERISTICS` wanted of the subtitle rendition (e.g., `public.easy-to-read` or
+    /// `public.accessibility.transcribes-spoken-dialog`), which enable the subtitles.
+    pub subtitle_characteristics: Vec<String>,
+
+    /// Loads the `FORCED=YES` subtitles in the language of the selected audio rendition
+    /// if it differs from the original language (see `Event::ForcedSubtitlesLoaded`).
+    pub forced_subtitles: bool,
+
+    /// Original language of the content; if `None`, the language of the `DEFAULT=YES`
+    /// audio rendition is assumed.
+    pub original_language: Option<String>,
+
+    /// Plays the media playlist of the selected audio rendition if it has a `URI`, aligning it
+    /// with the video whenever the rendition changes (see `HlsPlayer::set_audio_languages`).
+    ///
+    /// The audio of each video segment is fetched along it and merged into it, so that
+    /// the output segments stay muxed. Only TS audio segments are supported; renditions of
+    /// packed audio or fMP4 segments are rejected, and the video is played alone.
+    pub audio_playlists: bool,
+}
+impl Default for RenditionConfig {
+    fn default() -> Self {
+        RenditionConfig {
+            audio_languages: Vec::new(),
+            subtitle_languages: Vec::new(),
+            audio_characteristics: Vec::new(),
+            subtitle_characteristics: Vec::new(),
+            forced_subtitles: true,
+            original_language: None,
+            audio_playlists: false,
+        }
+    }
+}
+
+/// Playback capabilities of the device given by the host.
+///
+/// Variants exceeding them are excluded unless all the variants do.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Maximum `FRAME-RATE` the device can sustain (e.g., `30.0` to exclude 50/60fps variants).
+    pub max_frame_rate: Option<f64>,
+
+    /// Sample entry types or compatibility brands of the `SUPPLEMENTAL-CODECS` the device
+    /// decodes (e.g., `["dvh1", "db4h"]` for Dolby Vision).
+    ///
+    /// Variants enhanced by them are preferred to those of similar bandwidths without,
+    /// and the enhancement of the others is ignored (their base `CODECS` are played).
+    pub supplemental_codecs: Vec<String>,
+}
+
+/// Selection of the elementary streams of TS segments (e.g., one of several audio languages
+/// multiplexed into one program).
+///
+/// Only H.264 and AAC streams can be remuxed; the other audio and video streams are dropped.
+/// Without a selection, or if the selected stream is missing, the first one listed
+/// by the PMT is remuxed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackConfig {
+    /// PID of the H.264 stream.
+    pub video_pid: Option<u16>,
+
+    /// PID of the AAC stream, which takes precedence over `audio_index`.
+    pub audio_pid: Option<u16>,
+
+    /// Index of the AAC stream among those listed by the PMT (`0` for the first one).
+    pub audio_index: Option<usize>,
+}
+
+/// Monitoring of the drift between the audio and video tracks of the remuxed segments
+/// (see `Event::AvSyncDrift`).
+///
+/// The remuxer lays out the audio frames of a segment back to back from the start of its video,
+/// so audio whose timestamps start elsewhere or have gaps drifts from the video.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AvSyncConfig {
+    pub mode: AvSyncMode,
+
+    /// Minimum drift which is reported or corrected.
+    pub threshold_ms: u32,
+}
+impl Default for AvSyncConfig {
+    fn default() -> Self {
+        AvSyncConfig {
+            mode: AvSyncMode::Off,
+            threshold_ms: 100,
+        }
+    }
+}
+
+/// What is done about the audio/video drift of the remuxed segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AvSyncMode {
+    /// The drift is not measured, which saves parsing each segment twice.
+    #[default]
+    Off,
+
+    /// Emits `Event::AvSyncDrift` for drifting segments.
+    Report,
+
+    /// Drops or duplicates audio frames of drifting segments to put them back in place,
+    /// and emits `Event::AvSyncDrift`.
+    Correct,
+}
+
+/// Validation of the `EXTINF` durations of segments against their durations measured from
+/// the PES timestamps (see `Event::SegmentDurationMismatch`).
+///
+/// Packagers which declare wrong durations break seeks and the latency estimation,
+/// so the measured duration of a mismatching segment replaces the declared one in
+/// the timeline (see `HlsPlayer::timeline`), `SegmentInfo::duration_ms` and
+/// the live latency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DurationCheckConfig {
+    /// Disabled by default, as each segment is parsed once more.
+    pub enabled: bool,
+
+    /// Minimum difference between the declared and measured durations of a mismatching segment.
+    pub tolerance_ms: u32,
+}
+impl Default for DurationCheckConfig {
+    fn default() -> Self {
+        DurationCheckConfig {
+            enabled: false,
+            tolerance_ms: 500,
+        }
+    }
+}
+
+/// Hints for jumping over the small gaps in the buffered ranges reported by the host
+/// (see `HlsPlayer::set_buffered_ranges` and `Event::GapNudge`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GapJumpConfig {
+    pub enabled: bool,
+
+    /// Longest gap which is jumped over; longer ones are left to the buffering.
+    pub max_gap_ms: u32,
+
+    /// Distance from the end of a buffered range at which the playback is regarded as
+    /// having reached it, as browsers may stall slightly before the end.
+    pub end_tolerance_ms: u32,
+}
+impl Default for GapJumpConfig {
+    fn default() -> Self {
+        GapJumpConfig {
+            enabled: true,
+            max_gap_ms: 1000,
+            end_tolerance_ms: 100,
+        }
+    }
+}
+
+/// Generation of `emsg` boxes in the produced media segments from the ID3 tags of
+/// the timed metadata streams of TS segments, and from the `EXT-X-DATERANGE`s starting within
+/// them (see `HlsPlayer::date_ranges`), whose message data is the JSON of their `DateRange`.
+///
+/// The schemes can be changed to those expected by the SDKs reading the messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmsgConfig {
+    pub enabled: bool,
+
+    /// Scheme of the ID3 tags; `https://aomedia.org/emsg/ID3` (AOM ID3 in CMAF) by default.
+    pub id3: EmsgScheme,
+
+    /// Scheme of the date ranges; `urn:hls:daterange` with the empty value by default.
+    pub date_range: EmsgScheme,
+}
+impl Default for EmsgConfig {
+    fn default() -> Self {
+        EmsgConfig {
+            enabled: false,
+            id3: EmsgScheme {
+                scheme_id_uri: "https://aomedia.org/emsg/ID3".to_owned(),
+                value: String::new(),
+            },
+            date_range: EmsgScheme {
+                scheme_id_uri: "urn:hls:daterange".to_owned(),
+                value: String::new(),
+            },
+        }
+    }
+}
+
+/// `scheme_id_uri` and `value` of `emsg` boxes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmsgScheme {
+    pub scheme_id_uri: String,
+    pub value: String,
+}
+
+/// Low-power mode, which saves battery and bandwidth while the playback is in the background
+/// (e.g., in a hidden tab).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LowPowerConfig {
+    /// Starts in the mode; `HlsPlayer::set_low_power` toggles it.
+    pub enabled: bool,
+
+    /// Factor by which the media playlist reload intervals are multiplied.
+    ///
+    /// Blocking reloads (`_HLS_msn`) are not made in the mode.
+    pub reload_interval_factor: f64,
+
+    /// Buffer length (see `HlsPlayer::set_buffer_length`) beyond which no more segments
+    /// are fetched; unlimited if `None`.
+    pub max_buffer_ms: Option<u32>,
+
+    /// Plays the variant of the lowest bandwidth of the pathway of master playlists,
+    /// and switches back to the previous one when the mode is left.
+    pub lowest_variant: bool,
+}
+impl Default for LowPowerConfig {
+    fn default() -> Self {
+        LowPowerConfig {
+            enabled: false,
+            reload_interval_factor: 2.0,
+            max_buffer_ms: Some(10_000),
+            lowest_variant: true,
+        }
+    }
+}
+
+/// Blacklisting of failed variants, which are switched from and excluded from the selection
+/// for a cooldown (see `Event::VariantBlacklisted`).
+///
+/// A variant fails once its media playlist has been given up (see `PlayerConfig::playlist_retry`)
+/// or its segments keep failing to be remuxed. The preferred variant is played again once its
+/// cooldown has expired, which is measured by `Hooks::clock`; without the clock,
+/// blacklisted variants are never played again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VariantBlacklistConfig {
+    pub enabled: bool,
+
+    /// Number of consecutive segments which cannot be decrypted or remuxed before
+    /// the variant fails; `0` disables it.
+    pub max_undecodable_segments: u32,
+
+    /// Cooldown of the first blacklisting of a variant; it doubles on each subsequent one.
+    pub initial_cooldown_ms: u32,
+
+    /// Upper bound of the cooldown.
+    pub max_cooldown_ms: u32,
+}
+impl VariantBlacklistConfig {
+    /// Returns the cooldown of the `count`-th blacklisting of a variant.
+    pub fn cooldown(&self, count: u32) -> Duration {
+        let exponent = cmp::min(count.saturating_sub(1), 31);
+        let cooldown_ms = u64::from(self.initial_cooldown_ms) << exponent;
+        Duration::from_millis(cmp::min(cooldown_ms, u64::from(self.max_cooldown_ms)))
+    }
+}
+impl Default for VariantBlacklistConfig {
+    fn default() -> Self {
+        VariantBlacklistConfig {
+            enabled: true,
+            max_undecodable_segments: 3,
+            initial_cooldown_ms: 30_000,
+            max_cooldown_ms: 5 * 60 * 1000,
+        }
+    }
+}
+
+/// Prefetching of the media playlists of the variants which the next switch may take,
+/// so that it starts fetching segments without waiting for the playlist.
+///
+/// Those are the substitute of the played variant if it is blacklisted (only with
+/// `VariantBlacklistConfig::enabled`), and its lowest one for the low-power mode (only with
+/// `LowPowerConfig::lowest_variant`); nothing is prefetched without either of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistPrefetchConfig {
+    pub enabled: bool,
+
+    /// Maximum age of a prefetched live playlist used by a switch, as a multiple of its
+    /// target duration; older ones are fetched again.
+    ///
+    /// The age is measured by `Hooks::clock`; without the clock, the playlists are always used.
+    pub max_age_ratio: f64,
+
+    /// Buffer length (see `HlsPlayer::set_buffer_length`) from which the next segment of
+    /// the failover substitute is prefetched as well, so that a switch down to that variant
+    /// appends the segment without fetching it; disabled if `None` (the default).
+    ///
+    /// The segment is dropped once played past.
+    pub warm_up_buffer_ms: Option<u32>,
+}
+impl Default for PlaylistPrefetchConfig {
+    fn default() -> Self {
+        PlaylistPrefetchConfig {
+            enabled: false,
+            max_age_ratio: 1.5,
+            warm_up_buffer_ms: None,
+        }
+    }
+}
+
+/// Time-shift of live playbacks, so that they can be paused for longer than the playlist
+/// window (e.g., by not taking the produced segments, see `OverflowPolicy::Block`).
+///
+/// The segments waiting to be fetched are kept after they have left the window, and fetched
+/// as long as the server still serves them; those which fail are skipped by non-fatal
+/// `Event::Error`s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeShiftConfig {
+    pub enabled: bool,
+
+    /// Maximum total duration of the retained segments; the oldest ones are dropped first.
+    pub max_duration_ms: u32,
+}
+impl Default for TimeShiftConfig {
+    fn default() -> Self {
+        TimeShiftConfig {
+            enabled: false,
+            max_duration_ms: 30 * 60 * 1000,
+        }
+    }
+}
+
+/// Fetches of the segments following the next one while the buffer is low (e.g., after seeks,
+/// or when resuming behind the live edge), so that they are ready to be remuxed together
+/// (see `remux::remux_all`).
+///
+/// Only the segments without `EXT-X-KEY` sharing the init section of the next one are fetched
+/// ahead; failed fetches are made again in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CatchUpConfig {
+    /// Maximum number of the segments fetched at once, including the next one
+    /// (`1` fetches them one at a time).
+    ///
+    /// Defaults to `4` with the `parallel_remux` feature, and to `1` without it.
+    pub max_concurrent_fetches: usize,
+
+    /// Buffer length (see `HlsPlayer::set_buffer_length`) below which the segments are fetched
+    /// at once.
+    pub buffer_threshold_ms: u32,
+}
+impl Default for CatchUpConfig {
+    fn default() -> Self {
+        CatchUpConfig {
+            max_concurrent_fetches: if cfg!(feature = "parallel_remux") { 4 } else { 1 },
+            buffer_threshold_ms: 10_000,
+        }
+    }
+}
+
+/// Offline download settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Maximum number of the segments (and init sections) fetched or held for remuxing
+    /// at the same time.
+    pub max_concurrent_fetches: usize,
+}
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            max_concurrent_fetches: 4,
+        }
+    }
+}
+
+/// Thumbnail generation settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailConfig {
+    /// Maximum number of the keyframes fetched at the same time.
+    pub max_concurrent_fetches: usize,
+
+    /// Minimum interval between the generated thumbnails in milliseconds.
+    ///
+    /// Keyframes closer than this to the previously selected one are not fetched.
+    pub min_interval_ms: u64,
+
+    /// Loads the playlist of an image stream (`EXT-X-IMAGE-STREAM-INF`) of master playlists,
+    /// whose tiles preview the positions of the playlist (see `HlsPlayer::image_tile_at`).
+    pub image_streams: bool,
+
+    /// Maximum width of the images of the loaded stream, whose widest one up to it is loaded
+    /// (or the narrowest one).
+    pub max_image_width: Option<u32>,
+}
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        ThumbnailConfig {
+            max_concurrent_fetches: 2,
+            min_interval_ms: 0,
+            image_streams: false,
+            max_image_width: None,
+        }
+    }
+}
+
+/// Trick play settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrickPlayConfig {
+    /// Number of the keyframes fetched and shown per second.
+    ///
+    /// Each shown keyframe advances the media timeline by `rate / frames_per_second` seconds.
+    pub frames_per_second: f64,
+
+    /// Maximum number of the keyframes fetched at the same time.
+    pub max_concurrent_fetches: usize,
+}
+impl Default for TrickPlayConfig {
+    fn default() -> Self {
+        TrickPlayConfig {
+            frames_per_second: 4.0,
+            max_concurrent_fetches: 2,
+        }
+    }
+}
+
+/// Limits of the queue of the produced fMP4 segments (see `HlsPlayer::next_segment`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputQueueConfig {
+    /// Maximum number of the queued segments (unlimited if `None`).
+    pub max_segments: Option<usize>,
+
+    /// Maximum total size of the queued segments in bytes (unlimited if `None`).
+    pub max_bytes: Option<usize>,
+
+    /// What to do when the limits are reached.
+    pub overflow: OverflowPolicy,
+}
+impl Default for OutputQueueConfig {
+    fn default() -> Self {
+        OutputQueueConfig {
+            max_segments: None,
+            max_bytes: Some(128 * 1024 * 1024),
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Policy applied when the output queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Suspends fetching segments until the host takes queued ones.
+    #[default]
+    Block,
+
+    /// Drops the oldest queued segments (except for the initialization segment)
+    /// with non-fatal `Event::Error`s.
+    DropOldest,
+
+    /// Stops the playback with a fatal `Event::Error`.
+    Error,
+}
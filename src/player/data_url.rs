@@ -0,0 +1,51 @@
+//! Decoding of [data URLs] (RFC 2397).
+//!
+//! [data URLs]: https://tools.ietf.org/html/rfc2397
+use url::percent_encoding::percent_decode;
+use url::Url;
+
+use {ErrorKind, Result};
+
+pub fn is_data_url(url: &Url) -> bool {
+    url.scheme() == "data"
+}
+
+/// Returns the content of the data URL `url`.
+pub fn decode(url: &Url) -> Result<Vec<u8>> {
+    track_assert!(is_data_url(url), ErrorKind::InvalidInput, "Not a data URL: {}", url);
+
+    // `#` is frequent in inline playlists, so the fragment is taken as a part of the data.
+    let s = &url.as_str()["data:".len()..];
+    let comma = track_assert_some!(s.find(','), ErrorKind::InvalidInput, "No comma: {}", url);
+    let (media_type, data) = (&s[..comma], &s[comma + 1..]);
+    let data: Vec<u8> = percent_decode(data.as_bytes()).collect();
+    if media_type.ends_with(";base64") {
+        track!(decode_base64(&data))
+    } else {
+        Ok(data)
+    }
+}
+
+fn decode_base64(input: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &c in input {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => track_panic!(ErrorKind::InvalidInput, "Invalid base64 character: {:?}", c),
+        };
+        bits = (bits << 6) | u32::from(value);
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            output.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,303 @@
+//! Timeline of the `EXT-X-DATERANGE` tags which do not mark ad breaks (e.g., chapters and
+//! program boundaries), see `HlsPlayer::date_ranges`.
+//!
+//! Since the parser does not keep the tags, they are taken from the playlist text. Ranges are
+//! kept by `ID` across reloads, so that those which have left the window of a live playlist
+//! remain in the timeline, and later tags with the same `ID` complete them (e.g., `END-DATE`).
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use super::Event;
+use super::date_time;
+use super::text::{attributes, parse_seconds};
+
+const TAG: &str = "#EXT-X-DATERANGE:";
+
+/// Maximum number of the kept ranges; the earliest ones are dropped first.
+const MAX_RANGES: usize = 1000;
+
+/// `CLASS` of HLS interstitials, which are ads.
+const INTERSTITIAL_CLASS: &str = "com.apple.hls.interstitial";
+
+/// `EXT-X-DATERANGE` of the timeline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DateRange {
+    pub id: String,
+    pub class: Option<String>,
+
+    /// `START-DATE` in milliseconds since the UNIX epoch.
+    pub start_date_ms: f64,
+
+    /// `DURATION` or up to `END-DATE`, or up to the start of the next range of the same class
+    /// if `END-ON-NEXT=YES`; `None` while unknown.
+    pub duration_ms: Option<f64>,
+
+    /// The other attributes by name (e.g., `X-` client attributes), without the quotes
+    /// of quoted strings.
+    pub attributes: BTreeMap<String, String>,
+
+    #[serde(skip)]
+    start: Duration,
+
+    #[serde(skip)]
+    end: Option<Duration>,
+
+    #[serde(skip)]
+    end_on_next: bool,
+}
+impl DateRange {
+    /// Parses the attribute list of a tag, and returns `None` if it marks an ad break or
+    /// lacks `ID` or `START-DATE`.
+    fn parse(list: &str) -> Option<Self> {
+        let mut id = None;
+        let mut class = None;
+        let mut start = None;
+        let mut end_date = None;
+        let mut duration = None;
+        let mut end_on_next = false;
+        let mut others = BTreeMap::new();
+        for (name, value) in attributes(list) {
+            match name {
+                "ID" => id = Some(value.to_owned()),
+                "CLASS" => class = Some(value.to_owned()),
+                "START-DATE" => start = date_time::parse(value),
+                "END-DATE" => end_date = date_time::parse(value),
+                "DURATION" => duration = parse_seconds(value),
+                "END-ON-NEXT" => end_on_next = value == "YES",
+                "SCTE35-OUT" | "SCTE35-IN" | "SCTE35-CMD" => return None,
+                _ => {
+                    others.insert(name.to_owned(), value.to_owned());
+                }
+            }
+        }
+        if class.as_deref() == Some(INTERSTITIAL_CLASS) {
+            return None;
+        }
+        let start = start?;
+        let end = duration.map(|d| start + d).or(end_date.filter(|&e| e >= start));
+        let mut range = DateRange {
+            id: id?,
+            class,
+            start_date_ms: to_millis(start),
+            duration_ms: None,
+            attributes: others,
+            start,
+            end,
+            end_on_next,
+        };
+        range.set_end(end);
+        Some(range)
+    }
+
+    /// `START-DATE` since the UNIX epoch.
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    /// Returns `true` if the range contains `date_time`; ranges without an end contain those
+    /// after their start.
+    pub fn contains(&self, date_time: Duration) -> bool {
+        self.start <= date_time && self.end.is_none_or(|end| date_time < end)
+    }
+
+    fn set_end(&mut self, end: Option<Duration>) {
+        self.end = end;
+        self.duration_ms = end.map(|end| to_millis(end - self.start));
+    }
+
+    /// Completes the range with a later tag of the same `ID`.
+    fn merge(&mut self, other: DateRange) {
+        if self.end.is_none() || other.end.is_some() {
+            self.set_end(other.end);
+        }
+        self.end_on_next |= other.end_on_next;
+        self.attributes.extend(other.attributes);
+        if other.class.is_some() {
+            self.class = other.class;
+        }
+    }
+}
+
+/// `DateRange`s in the order of their start dates, and those containing the playback position.
+#[derive(Debug, Default)]
+pub struct DateRangeTimeline {
+    ranges: Vec<DateRange>,
+    active: BTreeSet<String>,
+}
+impl DateRangeTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ranges(&self) -> &[DateRange] {
+        &self.ranges
+    }
+
+    /// Adds the ranges of the tags of `m3u8`.
+    pub fn update(&mut self, m3u8: &str) {
+        let parsed = m3u8
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix(TAG))
+            .filter_map(DateRange::parse);
+        for range in parsed {
+            if let Some(known) = self.ranges.iter_mut().find(|r| r.id == range.id) {
+                known.merge(range);
+            } else {
+                self.ranges.push(range);
+            }
+        }
+        self.ranges.sort_by_key(|r| r.start);
+        if self.ranges.len() > MAX_RANGES {
+            let excess = self.ranges.len() - MAX_RANGES;
+            self.ranges.drain(..excess);
+        }
+
+        // `END-ON-NEXT` ranges end at the start of the next range of their class.
+        for i in 0..self.ranges.len() {
+            if !self.ranges[i].end_on_next {
+                continue;
+            }
+            let next_start = self.ranges[i + 1..]
+                .iter()
+                .find(|r| r.class == self.ranges[i].class)
+                .map(|r| r.start);
+            if next_start.is_some() {
+                self.ranges[i].set_end(next_start);
+            }
+        }
+    }
+
+    /// Returns `Event::DateRangeExited` and `Event::DateRangeEntered` for the ranges which
+    /// the playback has left or entered at `date_time` (the `EXT-X-PROGRAM-DATE-TIME` presented
+    /// at the playback position).
+    pub fn on_program_date_time(&mut self, date_time: Duration) -> Vec<Event> {
+        let mut events = Vec::new();
+        let containing = self.ranges
+            .iter()
+            .filter(|r| r.contains(date_time))
+            .map(|r| r.id.clone())
+            .collect::<BTreeSet<_>>();
+        for id in self.active.difference(&containing) {
+            events.push(Event::DateRangeExited { id: id.clone() });
+        }
+        let entered = self.ranges
+            .iter()
+            .filter(|r| containing.contains(&r.id) && !self.active.contains(&r.id));
+        for range in entered {
+            events.push(Event::DateRangeEntered {
+                range: range.clone(),
+            });
+        }
+        self.active = containing;
+        events
+    }
+
+    /// Returns the ranges containing `date_time`.
+    pub fn ranges_at(&self, date_time: Duration) -> Vec<&DateRange> {
+        self.ranges.iter().filter(|r| r.contains(date_time)).collect()
+    }
+
+    /// Returns the ranges starting at or after `from` and before `to`.
+    pub fn ranges_starting(&self, from: Duration, to: Duration) -> Vec<&DateRange> {
+        self.ranges
+            .iter()
+            .filter(|r| from <= r.start && r.start < to)
+            .collect()
+    }
+}
+
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn tag(attributes: &str) -> String {
+        format!("#EXTM3U\n{}{}\n#EXTINF:10,\n1.ts\n", TAG, attributes)
+    }
+
+    #[test]
+    fn ranges_are_merged_by_id_across_reloads() {
+        let mut timeline = DateRangeTimeline::new();
+        timeline.update(&tag(
+            "ID=\"c1\",START-DATE=\"1970-01-01T00:00:10Z\",X-TITLE=\"One\"",
+        ));
+        assert_eq!(timeline.ranges()[0].duration_ms, None);
+
+        // The tag has left the window; a later one with the same ID ends the range.
+        timeline.update(&tag(
+            "ID=\"c1\",CLASS=\"chapter\",START-DATE=\"1970-01-01T00:00:10Z\",\
+             END-DATE=\"1970-01-01T00:00:40Z\",X-COLOR=\"red\"",
+        ));
+        timeline.update(&tag("ID=\"c0\",START-DATE=\"1970-01-01T00:00:05Z\""));
+        timeline.update(&tag("ID=\"c0\",START-DATE=\"1970-01-01T00:00:05Z\""));
+        let ranges = timeline.ranges();
+        assert_eq!(ranges.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["c0", "c1"]);
+        let range = &ranges[1];
+        assert_eq!(range.class.as_deref(), Some("chapter"));
+        assert_eq!((range.start_date_ms, range.duration_ms), (10_000.0, Some(30_000.0)));
+        assert_eq!(range.attributes["X-TITLE"], "One");
+        assert_eq!(range.attributes["X-COLOR"], "red");
+
+        // A later tag without an end keeps the known one.
+        timeline.update(&tag("ID=\"c1\",START-DATE=\"1970-01-01T00:00:10Z\""));
+        assert_eq!(timeline.ranges()[1].duration_ms, Some(30_000.0));
+    }
+
+    #[test]
+    fn end_on_next_ranges_end_at_the_next_range_of_their_class() {
+        let mut timeline = DateRangeTimeline::new();
+        let m3u8 = [
+            "ID=\"a\",CLASS=\"p\",START-DATE=\"1970-01-01T00:00:00Z\",END-ON-NEXT=YES",
+            "ID=\"x\",CLASS=\"q\",START-DATE=\"1970-01-01T00:00:05Z\",DURATION=1.5",
+            "ID=\"b\",CLASS=\"p\",START-DATE=\"1970-01-01T00:00:20Z\",END-ON-NEXT=YES",
+        ]
+        .iter()
+        .map(|a| tag(a))
+        .collect::<String>();
+        timeline.update(&m3u8);
+        let durations = timeline.ranges().iter().map(|r| r.duration_ms).collect::<Vec<_>>();
+        assert_eq!(durations, [Some(20_000.0), Some(1500.0), None]);
+    }
+
+    #[test]
+    fn ad_breaks_are_not_ranges() {
+        let mut timeline = DateRangeTimeline::new();
+        timeline.update(&tag("ID=\"ad\",START-DATE=\"1970-01-01T00:00:00Z\",SCTE35-OUT=0xFC"));
+        timeline.update(&tag(
+            "ID=\"i\",CLASS=\"com.apple.hls.interstitial\",START-DATE=\"1970-01-01T00:00:00Z\"",
+        ));
+        timeline.update(&tag("ID=\"no-date\""));
+        assert!(timeline.ranges().is_empty());
+    }
+
+    #[test]
+    fn entered_and_exited_ranges_are_reported() {
+        let mut timeline = DateRangeTimeline::new();
+        timeline.update(&tag("ID=\"a\",START-DATE=\"1970-01-01T00:00:10Z\",DURATION=10"));
+        timeline.update(&tag("ID=\"b\",START-DATE=\"1970-01-01T00:00:15Z\""));
+        let events = |timeline: &mut DateRangeTimeline, at| {
+            timeline
+                .on_program_date_time(secs(at))
+                .into_iter()
+                .map(|e| match e {
+                    Event::DateRangeEntered { range } => format!("+{}", range.id),
+                    Event::DateRangeExited { id } => format!("-{}", id),
+                    e => panic!("{:?}", e),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(events(&mut timeline, 5).is_empty());
+        assert_eq!(events(&mut timeline, 12), ["+a"]);
+        assert_eq!(events(&mut timeline, 16), ["+b"]);
+        assert_eq!(events(&mut timeline, 20), ["-a"]);
+        assert_eq!(timeline.ranges_at(secs(30)).len(), 1);
+        assert_eq!(timeline.ranges_starting(secs(10), secs(15)).len(), 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Parsing and formatting of the date-time values of `EXT-X-PROGRAM-DATE-TIME`
+//! (ISO 8601 / RFC 3339), and parsing of HTTP dates.
+use std::time::Duration;
+
+/// Month names of HTTP dates.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses `s` (e.g., `"2010-02-19T14:54:23.031+08:00"`) into the time since the UNIX epoch.
+///
+/// Returns `None` if `s` is malformed or before the epoch.
+pub fn parse(s: &str) -> Option<Duration> {
+    let b = s.trim().as_bytes();
+    if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || (b[10] != b'T' && b[10] != b't')
+        || b[13] != b':' || b[16] != b':'
+    {
+        return None;
+    }
+    let year = digits(&b[0..4])? as i64;
+    let month = digits(&b[5..7])? as i64;
+    let day = digits(&b[8..10])? as i64;
+    let hour = digits(&b[11..13])? as i64;
+    let minute = digits(&b[14..16])? as i64;
+    let second = digits(&b[17..19])? as i64;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &b[19..];
+    let mut nanos = 0u64;
+    if rest.first() == Some(&b'.') {
+        let n = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if n == 0 {
+            return None;
+        }
+        for (i, &c) in rest[1..=n].iter().enumerate().take(9) {
+            nanos += u64::from(c - b'0') * 10u64.pow(8 - i as u32);
+        }
+        rest = &rest[n + 1..];
+    }
+
+    let offset_secs = match rest {
+        b"" | b"Z" | b"z" => 0,
+        _ if rest[0] == b'+' || rest[0] == b'-' => {
+            let (h, m) = match rest.len() {
+                6 if rest[3] == b':' => (digits(&rest[1..3])?, digits(&rest[4..6])?),
+                5 => (digits(&rest[1..3])?, digits(&rest[3..5])?),
+                3 => (digits(&rest[1..3])?, 0),
+                _ => return None,
+            };
+            let offset = (h * 3600 + m * 60) as i64;
+            if rest[0] == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second
+        - offset_secs;
+    if secs < 0 {
+        return None;
+    }
+    Some(Duration::new(secs as u64, nanos as u32))
+}
+
+/// Parses the HTTP date `s` (IMF-fixdate, e.g., `"Sun, 06 Nov 1994 08:49:37 GMT"`)
+/// into the time since the UNIX epoch.
+///
+/// The obsolete RFC 850 and asctime formats are not supported.
+pub fn parse_http_date(s: &str) -> Option<Duration> {
+    let fields = s.split_whitespace().collect::<Vec<_>>();
+    if fields.len() != 6 || !fields[0].ends_with(',') || fields[5] != "GMT" {
+        return None;
+    }
+    let day = digits(fields[1].as_bytes())? as i64;
+    let month = MONTHS.iter().position(|&m| m == fields[2])? as i64 + 1;
+    let year = digits(fields[3].as_bytes())? as i64;
+    let time = fields[4].as_bytes();
+    if fields[1].len() != 2 || fields[3].len() != 4 || time.len() != 8 || time[2] != b':'
+        || time[5] != b':'
+    {
+        return None;
+    }
+    let hour = digits(&time[0..2])? as i64;
+    let minute = digits(&time[3..5])? as i64;
+    let second = digits(&time[6..8])? as i64;
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(Duration::from_secs(secs as u64))
+}
+
+/// Formats the time since the UNIX epoch `t` in UTC with milliseconds
+/// (e.g., `"2010-02-19T06:54:23.031Z"`).
+pub fn format(t: Duration) -> String {
+    let secs = t.as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        t.subsec_millis()
+    )
+}
+
+fn digits(b: &[u8]) -> Option<u32> {
+    b.iter().try_fold(0u32, |acc, &c| {
+        if c.is_ascii_digit() {
+            Some(acc * 10 + u32::from(c - b'0'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the number of days since 1970-01-01 (proleptic Gregorian calendar).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,25 @@
+//! `EXT-X-DISCONTINUITY-SEQUENCE`, which the parser mistakes for `EXT-X-DISCONTINUITY`
+//! and rejects. It is taken out of the playlist text before parsing.
+use std::borrow::Cow;
+
+const TAG: &str = "#EXT-X-DISCONTINUITY-SEQUENCE:";
+
+/// Returns `m3u8` without the tag, and the discontinuity sequence number given by it
+/// (`0` if absent or malformed).
+pub fn split_sequence(m3u8: &str) -> (Cow<'_, str>, u64) {
+    if !m3u8.contains(TAG) {
+        return (Cow::Borrowed(m3u8), 0);
+    }
+    let mut sequence = 0;
+    let mut rest = String::with_capacity(m3u8.len());
+    for line in m3u8.lines() {
+        match line.trim().strip_prefix(TAG) {
+            Some(value) => sequence = value.trim().parse().unwrap_or(0),
+            None => {
+                rest.push_str(line);
+                rest.push('\n');
+            }
+        }
+    }
+    (Cow::Owned(rest), sequence)
+}
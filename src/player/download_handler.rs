@@ -0,0 +1,735 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use mse_fmp4::io::WriteTo;
+use url::Url;
+
+use {ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, BufferPool, ByteRange, Event, FailureClass,
+            FragmentTimeline, OutputQueue, OutputQueueConfig, OutputSegment, OverflowPolicy,
+            PlayerConfig, ResourceCache, ResourceKey, ResourceKind, SegmentInfo, Stats, Trace};
+use super::aes;
+use super::date_time;
+use super::discontinuity;
+use super::media_type;
+use super::recording;
+use super::remux::{self, Remuxed};
+use super::segment_resources::{self, Encryption};
+use super::target_duration;
+
+/// Byte layout of the fMP4 stream produced by a download (see `HlsPlayer::download`).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DownloadManifest {
+    /// Size of the initialization segment at the start of the stream.
+    pub init_bytes: u64,
+
+    /// Size of the stream produced so far.
+    pub total_bytes: u64,
+
+    pub duration_ms: f64,
+
+    /// Media segments of the stream in order.
+    pub segments: Vec<DownloadedSegment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedSegment {
+    pub sequence: u64,
+    pub discontinuity: u64,
+
+    /// Offset of the fMP4 media segment from the start of the stream.
+    pub offset: u64,
+
+    pub bytes: u64,
+    pub duration_ms: f64,
+}
+
+/// Handler that fetches all the segments of a VOD media playlist with bounded concurrency,
+/// and remuxes them in order into a single fMP4 stream with continuous timestamps.
+#[derive(Debug)]
+pub struct DownloadHandler {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    resource_cache: ResourceCache,
+    trace: Trace,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    segments: Vec<Segment>,
+    pending_fetches: VecDeque<Fetch>,
+    in_flight_fetches: HashMap<ActionId, Fetch>,
+    retry_timers: HashMap<ActionId, Fetch>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+
+    /// Fetched segment bodies waiting for the preceding ones, keyed by index.
+    fetched_segments: BTreeMap<usize, FetchedSegment>,
+
+    next_remux_index: usize,
+    output: OutputQueue,
+    manifest: DownloadManifest,
+    timeline: FragmentTimeline,
+    pool: BufferPool,
+    bytes_downloaded: u64,
+    segments_downloaded: u64,
+    is_finished: bool,
+}
+impl DownloadHandler {
+    pub fn new(config: &PlayerConfig, trace: Trace, url: Url, m3u8: &str) -> Result<Self> {
+        let (m3u8, mut discontinuity) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        track_assert!(
+            playlist.end_list_tag().is_some(),
+            ErrorKind::InvalidInput,
+            "Only VOD playlists can be downloaded: {}",
+            url
+        );
+
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        let keys_before_maps = segment_resources::keys_before_maps(&m3u8);
+        let mut segments = Vec::with_capacity(playlist.segments().len());
+        let mut pending_fetches = VecDeque::new();
+        let mut requested = HashSet::new();
+        let mut byte_range_end = 0;
+        let mut init = None;
+        let mut encryption = None;
+        let mut map_encryption = None;
+        let mut next_program_date_time = None;
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            if segment.discontinuity_tag().is_some() {
+                discontinuity += 1;
+            }
+            let program_date_time = segment
+                .program_date_time_tag()
+                .and_then(|t| date_time::parse(t.date_time()))
+                .or(next_program_date_time);
+            next_program_date_time = program_date_time.map(|t| t + segment.inf_tag().duration());
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+
+            // An `EXT-X-KEY` applies to the following segments and `EXT-X-MAP`s
+            // until the next one (see `MediaPlaylistHandler`).
+            let key_tags = segment.key_tags();
+            if segment.map_tag().is_some() {
+                let n = keys_before_maps.get(&i).map_or(0, |&n| cmp::min(n, key_tags.len()));
+                map_encryption = if n > 0 {
+                    track!(segment_resources::parse_key(config, &url, &key_tags[..n]))?
+                } else {
+                    encryption.clone()
+                };
+            }
+            if !key_tags.is_empty() {
+                encryption = track!(segment_resources::parse_key(config, &url, key_tags))?;
+            }
+
+            // An `EXT-X-MAP` applies to the following segments until the next one.
+            if let Some(tag) = segment.map_tag() {
+                init = Some(track!(segment_resources::map_key(&url, tag))?);
+            }
+
+            // Each key and init section is fetched once, ahead of the first segment using it.
+            let init_key = map_encryption.as_ref().filter(|_| init.is_some());
+            let mut request = |resource: Option<&ResourceKey>, target: fn(ResourceKey) -> Target| {
+                if let Some(resource) = resource.filter(|&r| requested.insert(r.clone())) {
+                    pending_fetches.push_back(Fetch::new(target(resource.clone())));
+                }
+            };
+            request(init_key.map(|e| &e.key), Target::Key);
+            request(init.as_ref(), Target::InitSegment);
+            request(encryption.as_ref().map(|e| &e.key), Target::Key);
+            pending_fetches.push_back(Fetch::new(Target::Segment(i)));
+            segments.push(Segment {
+                seq: first_seq + i as u64,
+                discontinuity,
+                url: track!(segment_resources::resolve_url(&url, segment.uri()))?,
+                range,
+                init: init.clone(),
+                key: encryption.clone(),
+                init_key: init_key.cloned(),
+                duration: segment.inf_tag().duration(),
+                program_date_time,
+            });
+        }
+
+        let mut action_factory = ActionFactory::new(0, config);
+        action_factory.set_target_duration(playlist.target_duration_tag().duration());
+        let output_queue = OutputQueueConfig {
+            // Dropping segments would corrupt the stream.
+            overflow: OverflowPolicy::Block,
+            ..config.output_queue.clone()
+        };
+        log!(
+            config,
+            Info,
+            "Starts downloading {}: segments={}",
+            url,
+            segments.len()
+        );
+        let mut this = DownloadHandler {
+            config: config.clone(),
+            action_factory,
+            resource_cache: ResourceCache::new(),
+            trace,
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            segments,
+            pending_fetches,
+            in_flight_fetches: HashMap::new(),
+            retry_timers: HashMap::new(),
+            partial_bodies: HashMap::new(),
+            fetched_segments: BTreeMap::new(),
+            next_remux_index: 0,
+            output: OutputQueue::new(&output_queue),
+            manifest: DownloadManifest::default(),
+            timeline: FragmentTimeline::new(),
+            pool: BufferPool::new(),
+            bytes_downloaded: 0,
+            segments_downloaded: 0,
+            is_finished: false,
+        };
+        this.remux_fetched_segments();
+        this.request_fetches();
+        Ok(this)
+    }
+
+    pub fn manifest(&self) -> &DownloadManifest {
+        &self.manifest
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        let segment = self.output.pop();
+        if segment.is_some() {
+            self.request_fetches();
+        }
+        segment
+    }
+
+    pub fn recycle_segment(&mut self, segment: Vec<u8>) {
+        self.pool.give(segment);
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        if let Some(fetch) = self.retry_timers.remove(&action_id) {
+            self.request(fetch);
+        }
+        Ok(())
+    }
+
+    /// Handles the response body of the fetch requested by `action_id`.
+    ///
+    /// The URIs of the playlist are resolved against its URL, so `_final_url` is not used.
+    pub fn handle_data(
+        &mut self,
+        action_id: ActionId,
+        data: &[u8],
+        fetch_duration_ms: u32,
+        _final_url: Option<&Url>,
+    ) -> Result<()> {
+        let fetch = match self.in_flight_fetches.remove(&action_id) {
+            None => return Ok(()),
+            Some(fetch) => fetch,
+        };
+        self.action_factory.record_fetch_success();
+        self.bytes_downloaded += data.len() as u64;
+        match fetch.target {
+            Target::Key(ref key) if data.len() != aes::KEY_LEN => {
+                self.fail(format!("Cannot download: the key {} is {} bytes", key.url, data.len()));
+                return Ok(());
+            }
+            // Init sections are decrypted once their keys are fetched, before remuxing.
+            Target::Key(key) | Target::InitSegment(key) => self.resource_cache.insert(key, data),
+            Target::Segment(index) => {
+                self.action_factory
+                    .record_throughput(data.len(), fetch_duration_ms);
+                self.segments_downloaded += 1;
+                let mut body = self.pool.take(data.len());
+                body.extend_from_slice(data);
+                self.fetched_segments.insert(
+                    index,
+                    FetchedSegment {
+                        body,
+                        fetch_duration_ms,
+                    },
+                );
+            }
+        }
+        self.remux_fetched_segments();
+        self.request_fetches();
+        Ok(())
+    }
+
+    /// Handles a chunk of the response body of the fetch requested by `action_id`.
+    ///
+    /// The body is handled once `handle_data_end` is called.
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) -> Result<()> {
+        if self.in_flight_fetches.contains_key(&action_id) {
+            let pool = &mut self.pool;
+            self.partial_bodies
+                .entry(action_id)
+                .or_insert_with(|| pool.take(chunk.len()))
+                .extend_from_slice(chunk);
+        }
+        Ok(())
+    }
+
+    /// Handles the end of the response body given by `handle_data_chunk`.
+    pub fn handle_data_end(
+        &mut self,
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        let result = track!(self.handle_data(action_id, &body, fetch_duration_ms, final_url));
+        self.pool.give(body);
+        result
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    ///
+    /// Transient failures are retried according to `PlayerConfig::retry`.
+    /// Since a download must not miss any segment, giving up one fails the whole download.
+    pub fn handle_error(&mut self, action_id: ActionId, status: u16) -> Result<()> {
+        let class = FailureClass::from_status(status);
+        self.handle_failure(action_id, class, format!("status={}", status));
+        Ok(())
+    }
+
+    pub fn handle_fetch_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        let description = "timed out".to_owned();
+        self.handle_failure(action_id, FailureClass::Transient, description);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            throughput_kbps: self.action_factory.throughput_kbps(),
+            buffer_length_ms: self.action_factory.buffer_length_ms(),
+            bytes_downloaded: self.bytes_downloaded,
+            segments_downloaded: self.segments_downloaded,
+            queued_output_bytes: self.output.bytes(),
+            ..Stats::default()
+        }
+    }
+
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        self.action_factory.set_buffer_length(buffer_length);
+    }
+
+    /// Cancels all the pending fetches and timers.
+    ///
+    /// Fetches that have already been handed to the host are aborted by `Action::Abort`.
+    pub fn stop(&mut self) {
+        self.is_finished = true;
+        self.action_queue.clear();
+        self.pending_fetches.clear();
+        self.retry_timers.clear();
+        self.partial_bodies.clear();
+        for (_, fetched) in mem::take(&mut self.fetched_segments) {
+            self.pool.give(fetched.body);
+        }
+        for (action_id, _) in mem::take(&mut self.in_flight_fetches) {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: String) {
+        self.partial_bodies.remove(&action_id);
+        let mut fetch = match self.in_flight_fetches.remove(&action_id) {
+            None => return,
+            Some(fetch) => fetch,
+        };
+        log!(self.config, Warn, "Fetch failed: action_id={:?}, {}", action_id, description);
+        let failover = self.action_factory.record_fetch_failure();
+        self.event_queue.extend(failover);
+
+        fetch.attempts += 1;
+        let kind = match fetch.target {
+            Target::Key(_) => ResourceKind::Key,
+            Target::InitSegment(_) => ResourceKind::InitSegment,
+            Target::Segment(_) => ResourceKind::Segment,
+        };
+        if !class.is_permanent() && fetch.attempts < self.config.retry(kind).max_attempts {
+            let delay = self.config.retry(kind).delay(fetch.attempts);
+            let action = self.action_factory.set_timeout(delay);
+            self.retry_timers.insert(action.id(), fetch);
+            self.action_queue.push_back(action);
+        } else {
+            let reason = format!(
+                "Cannot download {}: {}, class={:?}, attempts={}",
+                self.url_of(&fetch.target),
+                description,
+                class,
+                fetch.attempts
+            );
+            self.fail(reason);
+        }
+    }
+
+    fn fail(&mut self, reason: String) {
+        log!(self.config, Error, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: true,
+            reason,
+            history: self.trace.history(),
+        });
+        self.stop();
+    }
+
+    /// Requests the pending fetches up to `DownloadConfig::max_concurrent_fetches`.
+    ///
+    /// Fetched segments waiting for the preceding ones count towards the limit,
+    /// so that a slow fetch does not make the others pile up in memory.
+    fn request_fetches(&mut self) {
+        let max = cmp::max(self.config.download.max_concurrent_fetches, 1);
+        while !self.is_finished && !self.output.is_blocking() {
+            let active = self.in_flight_fetches.len()
+                + self.retry_timers.len()
+                + self.fetched_segments.len();
+            if active >= max {
+                break;
+            }
+            match self.pending_fetches.pop_front() {
+                None => break,
+                Some(fetch) => self.request(fetch),
+            }
+        }
+    }
+
+    fn request(&mut self, fetch: Fetch) {
+        let action = match fetch.target {
+            Target::Key(ref key) => {
+                self.action_factory
+                    .fetch_data(key.url.clone(), ResourceKind::Key, None)
+            }
+            Target::InitSegment(ref key) => {
+                self.action_factory
+                    .fetch_data(key.url.clone(), ResourceKind::InitSegment, key.range)
+            }
+            Target::Segment(index) => {
+                let segment = &self.segments[index];
+                self.action_factory
+                    .fetch_data(segment.url.clone(), ResourceKind::Segment, segment.range)
+            }
+        };
+        self.in_flight_fetches.insert(action.id(), fetch);
+        self.action_queue.push_back(action);
+    }
+
+    /// Remuxes the fetched segments that follow the already remuxed ones.
+    ///
+    /// Those ready at once are remuxed together (see `remux::remux_all`), and their outputs
+    /// are produced in order.
+    fn remux_fetched_segments(&mut self) {
+        let mut ready = Vec::new();
+        for index in self.next_remux_index..self.segments.len() {
+            if !self.is_ready(index) {
+                break;
+            }
+            match track!(self.decrypt(index)) {
+                Ok(init) => ready.push(init),
+                Err(e) => {
+                    let url = &self.segments[index].url;
+                    let reason = format!("Cannot decrypt the segment {}: {}", url, e);
+                    self.fail(reason);
+                    return;
+                }
+            }
+        }
+        let results = {
+            let jobs = ready
+                .iter()
+                .enumerate()
+                .map(|(i, init)| {
+                    let fetched = &self.fetched_segments[&(self.next_remux_index + i)];
+                    (init.as_ref().map_or(&[][..], |x| &x[..]), &fetched.body[..])
+                })
+                .collect::<Vec<_>>();
+            remux::remux_all(&self.config.tracks, &jobs)
+        };
+        for result in results {
+            let segment = self.segments[self.next_remux_index].clone();
+            let fetched = self.fetched_segments
+                .remove(&self.next_remux_index)
+                .expect("Never fails");
+            let result =
+                result.and_then(|remuxed| track!(self.handle_segment(&segment, remuxed, &fetched)));
+            self.pool.give(fetched.body);
+            if let Err(e) = result {
+                self.fail(format!("Cannot remux the segment {}: {}", segment.url, e));
+                return;
+            }
+            self.next_remux_index += 1;
+        }
+        if self.next_remux_index == self.segments.len() && !self.is_finished {
+            self.is_finished = true;
+            log!(
+                self.config,
+                Info,
+                "Downloaded {} segments: {} bytes",
+                self.segments.len(),
+                self.manifest.total_bytes
+            );
+            self.event_queue.push_back(Event::DownloadCompleted {
+                segments: self.segments.len(),
+                bytes: self.manifest.total_bytes,
+            });
+        }
+    }
+
+    /// Returns whether the segment `index`, its init section and their keys have been fetched.
+    fn is_ready(&self, index: usize) -> bool {
+        let segment = &self.segments[index];
+        let keys = segment.key.iter().chain(&segment.init_key).map(|e| &e.key);
+        self.fetched_segments.contains_key(&index)
+            && segment
+                .init
+                .iter()
+                .chain(keys)
+                .all(|k| self.resource_cache.contains(k))
+    }
+
+    /// Decrypts the fetched body of the segment `index` in place, and returns its init section.
+    fn decrypt(&mut self, index: usize) -> Result<Option<Rc<[u8]>>> {
+        let segment = &self.segments[index];
+        let cache = &self.resource_cache;
+        let decrypt = |encryption: &Encryption, data: &[u8]| {
+            let key = cache.get(&encryption.key).expect("Never fails");
+            track!(encryption.decrypt(&key, segment.seq, data))
+        };
+        let mut init = segment.init.as_ref().and_then(|k| cache.get(k));
+        if let (Some(data), Some(encryption)) = (init.clone(), segment.init_key.as_ref()) {
+            init = Some(Rc::from(track!(decrypt(encryption, &data))?));
+        }
+        if let Some(ref encryption) = segment.key {
+            let fetched = self.fetched_segments.get_mut(&index).expect("Never fails");
+            let body = track!(decrypt(encryption, &fetched.body))?;
+            let encrypted = mem::replace(&mut fetched.body, body);
+            self.pool.give(encrypted);
+        }
+        Ok(init)
+    }
+
+    fn handle_segment(
+        &mut self,
+        segment: &Segment,
+        remuxed: Remuxed,
+        fetched: &FetchedSegment,
+    ) -> Result<()> {
+        let Remuxed { initialization_segment, media_segment, .. } = remuxed;
+        let is_initial = self.manifest.segments.is_empty();
+       
+        let mut output = self.pool.take(fetched.body.len());
+        let result: Result<usize> = (|| {
+            if is_initial {
+                track!(initialization_segment.write_to(&mut output))?;
+            }
+            let offset = output.len();
+            track!(media_segment.write_to(&mut output))?;
+            let durations = recording::track_durations(&media_segment);
+            track!(self.timeline.apply(&mut output[offset..], &durations))?;
+            Ok(offset)
+        })();
+        let offset = match result {
+            Ok(offset) => offset,
+            Err(e) => {
+                self.pool.give(output);
+                return Err(e);
+            }
+        };
+
+        let manifest = &mut self.manifest;
+        if is_initial {
+            manifest.init_bytes = offset as u64;
+        }
+        let duration_ms = segment.duration.as_secs_f64() * 1000.0;
+        manifest.segments.push(DownloadedSegment {
+            sequence: segment.seq,
+            discontinuity: segment.discontinuity,
+            offset: manifest.total_bytes + offset as u64,
+            bytes: (output.len() - offset) as u64,
+            duration_ms,
+        });
+        manifest.total_bytes += output.len() as u64;
+        manifest.duration_ms += duration_ms;
+        log!(
+            self.config,
+            Debug,
+            "Remuxed the segment #{}: {} bytes -> {} bytes",
+            segment.seq,
+            fetched.body.len(),
+            output.len()
+        );
+        self.event_queue.push_back(Event::SegmentLoaded {
+            sequence: segment.seq,
+            discontinuity: segment.discontinuity,
+            variant: None,
+            bytes: fetched.body.len(),
+            fetch_duration_ms: fetched.fetch_duration_ms,
+            remux_duration_ms: None,
+            fmp4_bytes: output.len(),
+        });
+        let info = SegmentInfo {
+            sequence: segment.seq,
+            discontinuity: segment.discontinuity,
+            variant: None,
+            duration_ms,
+            program_date_time_ms: segment.program_date_time.map(|t| t.as_secs_f64() * 1000.0),
+            init_bytes: if is_initial { offset } else { 0 },
+            media_type: Some(&initialization_segment)
+                .filter(|_| is_initial)
+                .and_then(media_type::of),
+            audio_sequence: None,
+        };
+        self.output.push(OutputSegment { data: output, info });
+        Ok(())
+    }
+
+    fn url_of<'a>(&'a self, target: &'a Target) -> &'a Url {
+        match *target {
+            Target::Key(ref key) | Target::InitSegment(ref key) => &key.url,
+            Target::Segment(index) => &self.segments[index].url,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    seq: u64,
+    discontinuity: u64,
+    url: Url,
+    range: Option<ByteRange>,
+    init: Option<ResourceKey>,
+
+    /// Encryption of the segment.
+    key: Option<Encryption>,
+
+    /// Encryption of `init`.
+    init_key: Option<Encryption>,
+
+    duration: Duration,
+
+    /// Time since the UNIX epoch.
+    program_date_time: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct FetchedSegment {
+    body: Vec<u8>,
+    fetch_duration_ms: u32,
+}
+
+#[derive(Debug)]
+enum Target {
+    Key(ResourceKey),
+    InitSegment(ResourceKey),
+
+    /// Index of the segment in the playlist.
+    Segment(usize),
+}
+
+#[derive(Debug)]
+struct Fetch {
+    target: Target,
+    attempts: u32,
+}
+impl Fetch {
+    fn new(target: Target) -> Self {
+        Fetch {
+            target,
+            attempts: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const M3U8: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"k1\"\n#EXT-X-MAP:URI=\"init.ts\"\n\
+                        #EXTINF:4,\ns1.ts\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"k2\"\n#EXTINF:4,\ns2.ts\n\
+                        #EXT-X-ENDLIST\n";
+
+    fn download() -> DownloadHandler {
+        let config = PlayerConfig::default();
+        let url = Url::parse("https://example.com/media.m3u8").unwrap();
+        DownloadHandler::new(&config, Trace::new(&config), url, M3U8).unwrap()
+    }
+
+    fn fetches(handler: &mut DownloadHandler) -> Vec<(ActionId, String, ResourceKind)> {
+        let mut fetches = Vec::new();
+        while let Some(action) = handler.next_action() {
+            if let Action::FetchData {
+                action_id,
+                url,
+                kind,
+                ..
+            } = action
+            {
+                let name = url.path().trim_start_matches('/').to_owned();
+                fetches.push((action_id, name, kind));
+            }
+        }
+        fetches
+    }
+
+    fn error_reason(handler: &mut DownloadHandler) -> Option<String> {
+        while let Some(event) = handler.next_event() {
+            if let Event::Error { reason, .. } = event {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn keys_are_fetched_ahead_of_the_resources_they_decrypt() {
+        let mut handler = download();
+        let fetches = fetches(&mut handler)
+            .into_iter()
+            .map(|(_, name, kind)| (name, kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fetches,
+            [
+                ("k1".to_owned(), ResourceKind::Key),
+                ("init.ts".to_owned(), ResourceKind::InitSegment),
+                ("s1.ts".to_owned(), ResourceKind::Segment),
+                ("k2".to_owned(), ResourceKind::Key),
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_are_decrypted_before_remuxing() {
+        let mut handler = download();
+        let fetches = fetches(&mut handler);
+        for &(action_id, ref name, _) in &fetches[..3] {
+            let data = if name == "k1" { vec![7; 16] } else { vec![0; 32] };
+            handler.handle_data(action_id, &data, 10, None).unwrap();
+        }
+        let reason = error_reason(&mut handler).unwrap();
+        assert!(reason.starts_with("Cannot decrypt the segment"), "{}", reason);
+        assert!(reason.contains("PKCS#7"), "{}", reason);
+    }
+
+    #[test]
+    fn keys_of_wrong_size_fail_the_download() {
+        let mut handler = download();
+        let (action_id, _, _) = fetches(&mut handler)[0].clone();
+        handler.handle_data(action_id, &[0; 3], 10, None).unwrap();
+        let reason = error_reason(&mut handler).unwrap();
+        assert!(reason.ends_with("is 3 bytes"), "{}", reason);
+    }
+}
@@ -0,0 +1,201 @@
+//! `emsg` boxes (ISO/IEC 23009-1) carrying the timed metadata of the produced segments,
+//! for the metadata and ad-verification SDKs which read them from in-band event messages
+//! (see `PlayerConfig::emsg`).
+//!
+//! The boxes precede the `moof` box of their media segment, and are of version 0,
+//! whose times are relative to the start of the segment.
+use std::cmp;
+use std::time::Duration;
+use serde_json;
+
+use super::{DateRange, EmsgScheme};
+use super::timestamps::{self, StreamTimestamps, PTS_TIMESCALE};
+
+/// `event_duration` of events whose duration is unknown.
+pub const UNKNOWN_DURATION: u32 = 0xFFFF_FFFF;
+
+/// Event message of a media segment.
+#[derive(Debug)]
+pub struct EventMessage<'a> {
+    pub scheme: &'a EmsgScheme,
+    pub timescale: u32,
+
+    /// Time of the event from the start of the segment, in `timescale`.
+    pub presentation_time_delta: u32,
+
+    pub event_duration: u32,
+
+    /// Identifier of the event, which is the same for the same event in other segments
+    /// (or variants) so that hosts can discard duplicates.
+    pub id: u32,
+
+    pub message_data: &'a [u8],
+}
+impl<'a> EventMessage<'a> {
+    /// Appends the box to `output`.
+    pub fn write_to(&self, output: &mut Vec<u8>) {
+        let scheme_id_uri = self.scheme.scheme_id_uri.as_bytes();
+        let value = self.scheme.value.as_bytes();
+        let size = 8 + 4 + scheme_id_uri.len() + 1 + value.len() + 1 + 16 + self.message_data.len();
+        output.extend_from_slice(&(size as u32).to_be_bytes());
+        output.extend_from_slice(b"emsg");
+        output.extend_from_slice(&[0; 4]); // Version and flags
+        output.extend_from_slice(scheme_id_uri);
+        output.push(0);
+        output.extend_from_slice(value);
+        output.push(0);
+        output.extend_from_slice(&self.timescale.to_be_bytes());
+        output.extend_from_slice(&self.presentation_time_delta.to_be_bytes());
+        output.extend_from_slice(&self.event_duration.to_be_bytes());
+        output.extend_from_slice(&self.id.to_be_bytes());
+        output.extend_from_slice(self.message_data);
+    }
+}
+
+/// Returns the event identifier derived from `key` (FNV-1a).
+pub fn id_of(key: &str) -> u32 {
+    key.bytes()
+        .fold(0x811c_9dc5, |h, b| (h ^ u32::from(b)).wrapping_mul(0x0100_0193))
+}
+
+/// Appends the messages of the ID3 tags of `timestamps` to `output`.
+///
+/// The identifiers are taken from the PTS of the tags.
+pub fn write_id3(scheme: &EmsgScheme, timestamps: &StreamTimestamps, output: &mut Vec<u8>) {
+    let start = match timestamps.media_start() {
+        None => return,
+        Some(x) => x,
+    };
+    for &(pts, ref tag) in timestamps.metadata() {
+        let delta = cmp::max(timestamps::elapsed_signed(start, pts), 0);
+        EventMessage {
+            scheme,
+            timescale: PTS_TIMESCALE as u32,
+            presentation_time_delta: delta as u32,
+            event_duration: UNKNOWN_DURATION,
+            id: pts as u32,
+            message_data: tag,
+        }.write_to(output);
+    }
+}
+
+/// Appends the messages of `ranges` starting in the segment which starts
+/// at `program_date_time` to `output`.
+///
+/// The identifiers are taken from the `ID`s of the ranges.
+pub fn write_date_ranges(
+    scheme: &EmsgScheme,
+    ranges: &[&DateRange],
+    program_date_time: Duration,
+    output: &mut Vec<u8>,
+) {
+    for range in ranges {
+        let delta = range.start().saturating_sub(program_date_time);
+        let data = serde_json::to_vec(range).expect("Never fails");
+        EventMessage {
+            scheme,
+            timescale: 1000,
+            presentation_time_delta: delta.as_millis() as u32,
+            event_duration: range.duration_ms.map_or(UNKNOWN_DURATION, |d| d as u32),
+            id: id_of(&range.id),
+            message_data: &data,
+        }.write_to(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DateRangeTimeline;
+
+    fn scheme() -> EmsgScheme {
+        EmsgScheme {
+            scheme_id_uri: "urn:x".to_owned(),
+            value: "1".to_owned(),
+        }
+    }
+
+    /// Fields of a version 0 box: `scheme_id_uri`, `value`, `timescale`,
+    /// `presentation_time_delta`, `event_duration`, `id` and `message_data`.
+    type Fields = (String, String, u32, u32, u32, u32, Vec<u8>);
+
+    /// Parses the consecutive version 0 boxes of `bytes`.
+    fn parse(mut bytes: &[u8]) -> Vec<Fields> {
+        let mut boxes = Vec::new();
+        while !bytes.is_empty() {
+            let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+            assert_eq!(&bytes[4..12], b"emsg\0\0\0\0");
+            let body = &bytes[12..size];
+            let mut strings = body.splitn(3, |&b| b == 0);
+            let mut string = || String::from_utf8(strings.next().unwrap().to_vec()).unwrap();
+            let (scheme_id_uri, value) = (string(), string());
+            let rest = strings.next().unwrap();
+            let field = |i: usize| {
+                u32::from_be_bytes([rest[i * 4], rest[i * 4 + 1], rest[i * 4 + 2], rest[i * 4 + 3]])
+            };
+            boxes.push((
+                scheme_id_uri,
+                value,
+                field(0),
+                field(1),
+                field(2),
+                field(3),
+                rest[16..].to_vec(),
+            ));
+            bytes = &bytes[size..];
+        }
+        boxes
+    }
+
+    #[test]
+    fn boxes_are_of_version_0() {
+        let scheme = scheme();
+        let mut output = Vec::new();
+        EventMessage {
+            scheme: &scheme,
+            timescale: 90_000,
+            presentation_time_delta: 0x0102_0304,
+            event_duration: UNKNOWN_DURATION,
+            id: 7,
+            message_data: b"ID3",
+        }.write_to(&mut output);
+        let expected: &[u8] = &[
+            0, 0, 0, 0x27, b'e', b'm', b's', b'g', 0, 0, 0, 0, b'u', b'r', b'n', b':', b'x', 0,
+            b'1', 0, 0, 1, 0x5F, 0x90, 1, 2, 3, 4, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 7, b'I',
+            b'D', b'3',
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn date_ranges_are_timed_from_the_segment_start() {
+        let mut timeline = DateRangeTimeline::new();
+        timeline.update(
+            "#EXT-X-DATERANGE:ID=\"a\",START-DATE=\"1970-01-01T00:00:12.5Z\",DURATION=30\n\
+             #EXT-X-DATERANGE:ID=\"b\",START-DATE=\"1970-01-01T00:00:08Z\"\n",
+        );
+        let ranges = timeline.ranges().iter().collect::<Vec<_>>();
+        let mut output = Vec::new();
+        write_date_ranges(&scheme(), &ranges, Duration::from_secs(10), &mut output);
+        let boxes = parse(&output);
+        assert_eq!(boxes.len(), 2);
+
+        // Ranges starting before the segment are presented at its start.
+        let (ref uri, ref value, timescale, delta, duration, id, ref data) = boxes[0];
+        assert_eq!((uri.as_str(), value.as_str()), ("urn:x", "1"));
+        assert_eq!((timescale, delta, duration), (1000, 0, UNKNOWN_DURATION));
+        assert_eq!(id, id_of("b"));
+        let json = serde_json::from_slice::<serde_json::Value>(data).unwrap();
+        assert_eq!(json["id"], "b");
+
+        let (_, _, _, delta, duration, id, _) = boxes[1];
+        assert_eq!((delta, duration, id), (2500, 30_000, id_of("a")));
+    }
+
+    #[test]
+    fn ids_are_fnv_1a_hashes() {
+        assert_eq!(id_of(""), 0x811c_9dc5);
+        assert_eq!(id_of("a"), 0xe40c_292c);
+        assert_eq!(id_of("foobar"), 0xbf9c_f968);
+    }
+}
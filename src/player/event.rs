@@ -0,0 +1,254 @@
+use super::{DateRange, SpliceCue, SubtitleCue, TraceRecord};
+
+/// Notification emitted by the player to the host.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    /// An error occurred.
+    ///
+    /// If `fatal` is `true`, the player cannot continue the playback,
+    /// and `history` holds the latest records of the trace leading up to the failure.
+    Error {
+        fatal: bool,
+        reason: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        history: Vec<TraceRecord>,
+    },
+
+    /// Subsequent requests are sent to the base URL `to` instead of `from`
+    /// due to repeated failures.
+    CdnFailover { from: String, to: String },
+
+    /// The variants are fetched from the pathway `to` (`PATHWAY-ID`) instead of `from`,
+    /// as steered by the steering server or due to the failure of `from`.
+    PathwayChanged { from: String, to: String },
+
+    /// A media segment has been fetched and remuxed to fMP4.
+    SegmentLoaded {
+        sequence: u64,
+
+        /// Discontinuity sequence number of the segment (`EXT-X-DISCONTINUITY-SEQUENCE` plus
+        /// the preceding `EXT-X-DISCONTINUITY` tags), which is the same across variants.
+        discontinuity: u64,
+
+        /// Index of the variant in the master playlist, if started from one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        variant: Option<usize>,
+
+        bytes: usize,
+        fetch_duration_ms: u32,
+
+        /// Only measured if `Hooks::clock` is set.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remux_duration_ms: Option<f64>,
+
+        fmp4_bytes: usize,
+    },
+
+    /// The playback has recovered from a stall (see `QoeTracker`).
+    ///
+    /// `duration_ms` is only measured if `Hooks::clock` is set.
+    Rebuffered { duration_ms: Option<f64> },
+
+    /// The media sequence of the live playlist has decreased from `previous` to `current`
+    /// (e.g., by an encoder restart).
+    ///
+    /// The queued segments have been discarded, and the playback continues with the segments
+    /// of the new sequence, which are discontinuous with the preceding ones.
+    MediaSequenceReset { previous: u64, current: u64 },
+
+    /// The live playlist starts at `current`, more than `ReloadConfig::max_sequence_gap`
+    /// segments after the last listed one `previous` (e.g., by an encoder restart
+    /// or a long suspension of the host).
+    ///
+    /// The queued segments have been discarded, and the playback rejoins the new window
+    /// near its end.
+    MediaSequenceJumped { previous: u64, current: u64 },
+
+    /// The live playlist has not listed new segments for `stalled_ms`,
+    /// which exceeds `ReloadConfig::stall_threshold_ratio` times the target duration.
+    ///
+    /// The player keeps reloading the playlist; hosts may show a message rather than spinning.
+    LiveStreamStalled { stalled_ms: u64 },
+
+    /// The live playlist has listed new segments again after `Event::LiveStreamStalled`.
+    LiveStreamResumed { stalled_ms: u64 },
+
+    /// `HlsPlayer::resume` has resumed the fetches after `suspended_ms`
+    /// (`None` without `Hooks::clock`).
+    ///
+    /// If `jumped_to_live`, the position had fallen too far behind the live edge,
+    /// and the queued segments have been discarded to rejoin at the live point.
+    PlaybackResumed {
+        suspended_ms: Option<u64>,
+        jumped_to_live: bool,
+    },
+
+    /// All the segments of the download started by `HlsPlayer::download` have been produced
+    /// (see `HlsPlayer::download_manifest`).
+    DownloadCompleted { segments: usize, bytes: u64 },
+
+    /// The keyframe taken by the next `HlsPlayer::next_segment` call
+    /// (see `HlsPlayer::generate_thumbnails`).
+    ThumbnailLoaded {
+        /// Index of the keyframe in the I-frame playlist (after `min_interval_ms` thinning).
+        index: usize,
+
+        /// Start of the keyframe on the media timeline.
+        time_ms: f64,
+
+        duration_ms: f64,
+
+        /// Presentation timestamp of the access unit.
+        pts_ms: Option<f64>,
+
+        /// RFC 6381 codec string taken from the SPS, if any.
+        codec: Option<String>,
+
+        bytes: usize,
+    },
+
+    /// All the keyframes of `HlsPlayer::generate_thumbnails` have been fetched or skipped.
+    ThumbnailsCompleted { thumbnails: usize },
+
+    /// The trick-play fragment taken by the next `HlsPlayer::next_segment` call
+    /// (see `HlsPlayer::start_trick_play`).
+    TrickPlayFrameLoaded {
+        /// Start of the keyframe on the media timeline.
+        time_ms: f64,
+
+        /// Presentation timestamp of the access unit.
+        pts_ms: Option<f64>,
+
+        /// Size of the fragment, including the preceding initialization segment if any.
+        bytes: usize,
+    },
+
+    /// The trick play has reached the end (or the start) of the I-frame playlist.
+    ///
+    /// The player stays in trick play until `HlsPlayer::stop_trick_play` is called.
+    TrickPlayCompleted { frames: usize },
+
+    /// An ad break has been found in the media playlist (see `HlsPlayer::insert_ad_pod`).
+    AdBreakDetected {
+        id: Option<String>,
+        sequence: u64,
+
+        /// Planned duration of the break, if given by the cue.
+        duration_ms: Option<f64>,
+    },
+
+    /// An ad pod has replaced the main content from the segment `sequence`.
+    AdBreakStarted {
+        id: Option<String>,
+        sequence: u64,
+
+        /// Duration of the pod.
+        duration_ms: f64,
+    },
+
+    /// The main content has resumed after the ad pod.
+    AdBreakEnded { id: Option<String> },
+
+    /// The VOD media playlist has been loaded, whose timeline is given by
+    /// `HlsPlayer::timeline`.
+    TimelineLoaded { duration_ms: f64, segments: usize },
+
+    /// The media playlist `url` queued by `HlsPlayer::queue_next` has replaced the ended one.
+    NextAssetStarted { url: String },
+
+    /// The forced subtitles (see `RenditionConfig::forced_subtitles`) of a segment of
+    /// the subtitle rendition have been fetched.
+    ///
+    /// They are reported apart from the regular subtitles, which are left to the host.
+    ForcedSubtitlesLoaded {
+        language: Option<String>,
+        sequence: u64,
+        cues: Vec<SubtitleCue>,
+    },
+
+    /// The media playlist of the audio rendition `name` (see `RenditionConfig::audio_playlists`)
+    /// has been loaded and aligned with the video, from the segment `sequence` presented
+    /// with the next video segment.
+    AudioRenditionSwitched {
+        name: String,
+        language: Option<String>,
+        sequence: u64,
+    },
+
+    /// The media playlist of the image stream (see `ThumbnailConfig::image_streams`) has been
+    /// loaded, and lists `images` images up to `duration_ms` (see `HlsPlayer::image_tile_at`).
+    ImageStreamLoaded {
+        url: String,
+        images: usize,
+        duration_ms: f64,
+    },
+
+    /// The live latency has changed by `PlayerConfig::latency_event_threshold_ms` or more.
+    LatencyChanged { latency_ms: u32 },
+
+    /// A new fMP4 initialization segment precedes the segment `sequence` in the output
+    /// (see `SegmentInfo::init_bytes`), after which the codec parameters may differ.
+    ///
+    /// Hosts may need to reset the decoder or call `SourceBuffer.changeType` before appending it.
+    Reinitialized { sequence: u64, reason: ReinitReason },
+
+    /// The `variant`-th variant has failed, and is excluded from the selection for `cooldown_ms`
+    /// (see `PlayerConfig::variant_blacklist`).
+    VariantBlacklisted {
+        variant: usize,
+        reason: String,
+        cooldown_ms: u32,
+    },
+
+    /// The audio of the segment `sequence` drifts from its video by `drift_ms` (positive if
+    /// the audio is output ahead of its timestamps), and audio frames have been dropped or
+    /// duplicated to cancel it if `corrected` (see `PlayerConfig::av_sync`).
+    AvSyncDrift {
+        sequence: u64,
+        drift_ms: f64,
+        corrected: bool,
+    },
+
+    /// The segment `sequence` lasts `measured_ms` rather than its `EXTINF` duration
+    /// `declared_ms`, which has been replaced by the measured one
+    /// (see `PlayerConfig::duration_check`).
+    SegmentDurationMismatch {
+        sequence: u64,
+        declared_ms: f64,
+        measured_ms: f64,
+    },
+
+    /// The playback position `position_ms` is at a gap in the buffered ranges, which the host
+    /// should jump over by seeking to `jump_to_ms` (see `PlayerConfig::gap_jump`).
+    GapNudge {
+        position_ms: f64,
+        jump_to_ms: f64,
+        gap_ms: f64,
+    },
+
+    /// The playback position has entered `range` (see `HlsPlayer::date_ranges`).
+    DateRangeEntered { range: DateRange },
+
+    /// The playback position has left the range `id`.
+    DateRangeExited { id: String },
+
+    /// The segment `sequence` carries the SCTE-35 splice command `cue`.
+    Scte35Cue { sequence: u64, cue: SpliceCue },
+}
+
+/// Reason of `Event::Reinitialized`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ReinitReason {
+    /// An `EXT-X-DISCONTINUITY`, or the boundary of an ad or of the next playlist.
+    Discontinuity,
+
+    /// The `EXT-X-MAP` (URI or byte range) differs from that of the previous segment.
+    MapChanged,
+
+    /// The segment belongs to another variant than the previous one.
+    VariantChanged,
+
+    /// The normal playback has resumed after the trick play.
+    TrickPlayEnded,
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Classification of fetch failures reported by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FailureClass {
+    /// The request may succeed if it is retried (e.g., 5xx, 429, network errors).
+    Transient,
+
+    /// Retrying the same request is pointless (e.g., 403, 404).
+    Permanent,
+}
+impl FailureClass {
+    /// Classifies a failure by its HTTP status code (`0` means no response was received).
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            408 | 425 | 429 => FailureClass::Transient,
+            400..=499 => FailureClass::Permanent,
+            _ => FailureClass::Transient,
+        }
+    }
+
+    pub fn is_permanent(self) -> bool {
+        self == FailureClass::Permanent
+    }
+}
+
+/// Time spent failing since the last successful fetch
+/// (see `PlayerConfig::no_progress_timeout_ms`).
+///
+/// It is measured by `Hooks::clock` if set, and otherwise approximated by the retry delays.
+#[derive(Debug, Default)]
+pub struct FailureStreak {
+    is_failing: bool,
+    since: Option<Duration>,
+    waited: Duration,
+}
+impl FailureStreak {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_success(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Records a failure at `now` (the time given by `Hooks::now`).
+    pub fn on_failure(&mut self, now: Option<Duration>) {
+        if !self.is_failing {
+            self.is_failing = true;
+            self.since = now;
+        }
+    }
+
+    /// Records the delay of the retry following a failure.
+    pub fn on_retry(&mut self, delay: Duration) {
+        self.waited += delay;
+    }
+
+    /// Returns how long the fetches have been failing at `now`.
+    pub fn duration(&self, now: Option<Duration>) -> Duration {
+        match (self.since, now) {
+            (Some(since), Some(now)) => now.checked_sub(since).unwrap_or_default(),
+            _ => self.waited,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Entry points for fuzzers (e.g., `cargo fuzz`), enabled by the `fuzzing` feature.
+//!
+//! They run the parsing and remuxing paths on arbitrary bytes without a host, and return
+//! normally for any input; a panic or hang is a bug.
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| hls_wasm::player::fuzz::fuzz_playlist(data));
+//! ```
+use std::io::Read;
+use mpeg2ts::ts::TsPacketReader;
+use mse_fmp4::io::WriteTo;
+use mse_fmp4::mpeg2_ts;
+use url::Url;
+
+use super::{Action, HlsPlayer, PlayerConfig, ResourceKind};
+use super::{keyframe, recording, text, ts_sync};
+
+/// Maximum number of the actions handled by `fuzz_playlist`, which bounds live reload loops.
+const MAX_ACTIONS: usize = 64;
+
+/// Starts playing `data` as a playlist, and gives it again as the response
+/// to the playlist fetches, firing the timers immediately.
+pub fn fuzz_playlist(data: &[u8]) {
+    let m3u8 = text::decode(data);
+    let url = Url::parse("http://fuzz.invalid/playlist.m3u8").expect("Never fails");
+    let mut player = HlsPlayer::with_config(PlayerConfig::default());
+    if player.play(url, &m3u8).is_err() {
+        return;
+    }
+    for _ in 0..MAX_ACTIONS {
+        match player.next_action() {
+            None => break,
+            Some(Action::FetchData {
+                action_id,
+                kind: ResourceKind::Playlist,
+                ..
+            }) => {
+                let _ = player.handle_data(action_id, data, 0);
+            }
+            Some(Action::SetTimeout { action_id, .. }) => {
+                let _ = player.handle_timeout(action_id);
+            }
+            Some(_) => {}
+        }
+        while player.next_event().is_some() {}
+    }
+}
+
+/// Remuxes `data` as an MPEG-2 TS segment into fMP4, and also extracts its keyframe.
+///
+/// The first two bytes give the length of an initialization section preceding the segment,
+/// so that the split between them is fuzzed too.
+pub fn fuzz_segment(data: &[u8]) {
+    let (init, segment) = match data.len() {
+        0 | 1 => (&[][..], data),
+        _ => {
+            let len = (usize::from(data[0]) << 8 | usize::from(data[1])).min(data.len() - 2);
+            data[2..].split_at(len)
+        }
+    };
+    let _ = keyframe::extract(segment);
+
+    let (init, _) = ts_sync::resync(init);
+    let (segment, _) = ts_sync::resync(segment);
+    let reader = init.chain(&segment[..]);
+    let (initialization_segment, media_segment) =
+        match mpeg2_ts::to_fmp4(TsPacketReader::new(reader)) {
+            Err(_) => return,
+            Ok(x) => x,
+        };
+    let mut output = Vec::new();
+    if initialization_segment.write_to(&mut output).is_err() {
+        return;
+    }
+    let offset = output.len();
+    if media_segment.write_to(&mut output).is_err() {
+        return;
+    }
+    let durations = recording::track_durations(&media_segment);
+    let _ = recording::FragmentTimeline::new().apply(&mut output[offset..], &durations);
+}
@@ -0,0 +1,80 @@
+//! Hints for jumping over small gaps in the buffered media, on which `HTMLMediaElement`
+//! stalls forever although the media after them is buffered.
+//!
+//! Gaps come from frames dropped by the remuxer, segments which have been skipped
+//! (e.g., `Event::Error` for corrupt ones), or audio and video tracks starting at different
+//! times. They are found in the buffered ranges reported by the host
+//! (see `HlsPlayer::set_buffered_ranges`).
+use std::time::Duration;
+
+use super::{Event, GapJumpConfig};
+
+/// Detector of the gaps reached by the playback position.
+#[derive(Debug, Default)]
+pub struct GapJumper {
+    position: Option<Duration>,
+    ranges: Vec<(Duration, Duration)>,
+
+    /// End of the last gap notified by `Event::GapNudge`, which is not notified again.
+    notified_until: Option<Duration>,
+}
+impl GapJumper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the buffered ranges, and returns `Event::GapNudge` if the position is at a gap.
+    pub fn on_buffered_ranges(
+        &mut self,
+        config: &GapJumpConfig,
+        mut ranges: Vec<(Duration, Duration)>,
+    ) -> Option<Event> {
+        ranges.retain(|&(start, end)| start < end);
+        ranges.sort();
+        self.ranges = ranges;
+        self.check(config)
+    }
+
+    /// Updates the playback position, and returns `Event::GapNudge` if it is at a gap.
+    pub fn on_playback_position(
+        &mut self,
+        config: &GapJumpConfig,
+        position: Duration,
+    ) -> Option<Event> {
+        self.position = Some(position);
+        self.check(config)
+    }
+
+    fn check(&mut self, config: &GapJumpConfig) -> Option<Event> {
+        if !config.enabled {
+            return None;
+        }
+        let position = self.position?;
+        let tolerance = Duration::from_millis(u64::from(config.end_tolerance_ms));
+        let max_gap = Duration::from_millis(u64::from(config.max_gap_ms));
+
+        // The position is at a gap if no range is playable from there, i.e., it is outside
+        // the ranges or at less than the tolerance from the end of its range.
+        if self.ranges
+            .iter()
+            .any(|&(start, end)| start <= position && position + tolerance < end)
+        {
+            return None;
+        }
+        let (next_start, _) = *self.ranges.iter().find(|&&(start, _)| start > position)?;
+        let gap = next_start - position;
+        if gap > max_gap || self.notified_until == Some(next_start) {
+            return None;
+        }
+        self.notified_until = Some(next_start);
+        Some(Event::GapNudge {
+            position_ms: to_millis(position),
+            jump_to_ms: to_millis(next_start),
+            gap_ms: to_millis(gap),
+        })
+    }
+}
+
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
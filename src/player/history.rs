@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::ResourceKey;
+
+/// Maximum number of the remembered segments.
+const CAPACITY: usize = 1024;
+
+/// Identities of the segments already queued for fetching, shared among media playlists.
+///
+/// A segment is identified by its resolved URL and byte range, and by its
+/// `EXT-X-PROGRAM-DATE-TIME` if any, so that the same media is not fetched (and appended)
+/// twice after overlapping playlist windows or switching variants.
+///
+/// Clones of a history refer to the same entries. The oldest entries are forgotten first.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentHistory {
+    inner: Rc<RefCell<Inner>>,
+}
+impl SegmentHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if a segment with `key` or `program_date_time` has been recorded.
+    pub fn contains(&self, key: &ResourceKey, program_date_time: Option<Duration>) -> bool {
+        let inner = self.inner.borrow();
+        inner.keys.contains(key)
+            || program_date_time.is_some_and(|t| inner.program_date_times.contains(&to_ms(t)))
+    }
+
+    pub fn insert(&self, key: ResourceKey, program_date_time: Option<Duration>) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.entries.len() >= CAPACITY {
+            if let Some((key, program_date_time)) = inner.entries.pop_front() {
+                inner.keys.remove(&key);
+                if let Some(t) = program_date_time {
+                    inner.program_date_times.remove(&t);
+                }
+            }
+        }
+        let program_date_time = program_date_time.map(to_ms);
+        inner.keys.insert(key.clone());
+        inner.program_date_times.extend(program_date_time);
+        inner.entries.push_back((key, program_date_time));
+    }
+
+    /// Forgets the segment with `key` (e.g., discarded before being fetched).
+    pub fn remove(&self, key: &ResourceKey) {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.keys.remove(key) {
+            return;
+        }
+        if let Some(i) = inner.entries.iter().position(|e| e.0 == *key) {
+            if let Some((_, Some(t))) = inner.entries.remove(i) {
+                inner.program_date_times.remove(&t);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.inner.borrow_mut() = Inner::default();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: VecDeque<(ResourceKey, Option<u64>)>,
+    keys: HashSet<ResourceKey>,
+    program_date_times: HashSet<u64>,
+}
+
+/// Milliseconds since the UNIX epoch, which absorbs rounding differences among variants.
+fn to_ms(time: Duration) -> u64 {
+    time.as_millis() as u64
+}
@@ -0,0 +1,102 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+use url::Url;
+
+use super::{Logger, ResourceKind};
+
+/// Host-provided transformation of the URLs requested by `Action::FetchData`
+/// (e.g., for injecting signed query tokens or selecting a CDN).
+pub trait UrlRewriter {
+    fn rewrite_url(&self, kind: ResourceKind, url: Url) -> Url;
+}
+impl<F> UrlRewriter for F
+where
+    F: Fn(ResourceKind, Url) -> Url,
+{
+    fn rewrite_url(&self, kind: ResourceKind, url: Url) -> Url {
+        self(kind, url)
+    }
+}
+
+/// Host-provided refresh of the short-lived tokens carried by signed URLs.
+pub trait TokenRefresher {
+    /// Returns the query parameters carrying a fresh token after the fetch of `url` has been
+    /// rejected as unauthorized (`401` or `403`), or `None` if the token cannot be refreshed.
+    /// `url` is the one in the playlist, before the query parameters are added.
+    ///
+    /// They are added to `PlayerConfig::query_params` of the following fetches, replacing
+    /// those of the previous refresh and the same-named parameters of the requested URLs.
+    fn refresh_token(&self, kind: ResourceKind, url: &Url) -> Option<BTreeMap<String, String>>;
+}
+impl<F> TokenRefresher for F
+where
+    F: Fn(ResourceKind, &Url) -> Option<BTreeMap<String, String>>,
+{
+    fn refresh_token(&self, kind: ResourceKind, url: &Url) -> Option<BTreeMap<String, String>> {
+        self(kind, url)
+    }
+}
+
+/// Host-provided wall clock.
+///
+/// `now` returns the time since the UNIX epoch; it is used both for measuring durations
+/// and for comparing with `EXT-X-PROGRAM-DATE-TIME`.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// `Clock` whose time is set by the host (e.g., before each call into the player).
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>,
+}
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_now(&self, now: Duration) {
+        self.now.set(now);
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// Host-provided callbacks.
+///
+/// They are part of `PlayerConfig` but, unlike the other fields, cannot be given as JSON.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub url_rewriter: Option<Rc<dyn UrlRewriter>>,
+
+    /// Refresh of expired tokens; without it, unauthorized fetches are not retried.
+    pub token_refresher: Option<Rc<dyn TokenRefresher>>,
+
+    /// Clock used for timing measurements (e.g., remux durations and live latency);
+    /// they are omitted if `None`.
+    pub clock: Option<Rc<dyn Clock>>,
+
+    /// Receiver of the log records enabled by `PlayerConfig::log_level`.
+    pub logger: Option<Rc<dyn Logger>>,
+}
+impl Hooks {
+    pub fn now(&self) -> Option<Duration> {
+        self.clock.as_ref().map(|c| c.now())
+    }
+}
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("url_rewriter", &self.url_rewriter.is_some())
+            .field("token_refresher", &self.token_refresher.is_some())
+            .field("clock", &self.clock.is_some())
+            .field("logger", &self.logger.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,551 @@
+//! Image streams of master playlists (`EXT-X-IMAGE-STREAM-INF`), whose segments are JPEG
+//! images of tiled previews (`EXT-X-TILES`) shown while seeking or scrubbing.
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use url::Url;
+
+use {Error, ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, Event, FailureClass, PlayerConfig, ResourceKind,
+            StreamId};
+use super::date_time;
+use super::text::{self, attribute};
+
+/// Stream ID of the actions of `ImageStreamLoader`, distinct from those of media playlists.
+pub const IMAGE_STREAM_ID: StreamId = 0xF9;
+
+/// `EXT-X-IMAGE-STREAM-INF` of a master playlist.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageStream {
+    pub url: String,
+    pub bandwidth: u64,
+
+    /// Width and height of the images (not of their tiles), if listed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<(u32, u32)>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codecs: Option<String>,
+}
+impl ImageStream {
+    /// Parses the image streams of the master playlist `m3u8` fetched from `url`.
+    pub fn parse_all(url: &Url, m3u8: &str) -> Result<Vec<Self>> {
+        let mut streams = Vec::new();
+        for attributes in m3u8
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("#EXT-X-IMAGE-STREAM-INF:"))
+        {
+            let uri = track_assert_some!(
+                attribute(attributes, "URI"),
+                ErrorKind::InvalidInput,
+                "No URI of EXT-X-IMAGE-STREAM-INF: {}",
+                attributes
+            );
+            let stream_url = track!(
+                Url::options()
+                    .base_url(Some(url))
+                    .parse(uri)
+                    .map_err(Error::from)
+            )?;
+            streams.push(ImageStream {
+                url: stream_url.to_string(),
+                bandwidth: attribute(attributes, "BANDWIDTH")
+                    .and_then(|b| b.parse().ok())
+                    .unwrap_or(0),
+                resolution: attribute(attributes, "RESOLUTION").and_then(parse_resolution),
+                codecs: attribute(attributes, "CODECS").map(|c| c.to_owned()),
+            });
+        }
+        Ok(streams)
+    }
+
+    /// Returns the widest of `streams` up to `max_width` (or the narrowest one),
+    /// preferring the lower bandwidth among those of the same width.
+    pub fn select(streams: &[Self], max_width: Option<u32>) -> Option<&Self> {
+        let width = |s: &Self| s.resolution.map_or(0, |r| r.0);
+        let key = |s: &&Self| (width(s), u64::MAX - s.bandwidth);
+        streams
+            .iter()
+            .filter(|s| max_width.is_none_or(|max| width(s) <= max))
+            .max_by_key(key)
+            .or_else(|| streams.iter().min_by_key(key))
+    }
+}
+
+/// Region of an image holding the preview of a position.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageTile {
+    /// URL of the image.
+    pub url: String,
+
+    /// Position shown by the tile, from the start of the image playlist.
+    pub start_ms: f64,
+    pub duration_ms: f64,
+
+    /// `EXT-X-PROGRAM-DATE-TIME` of the tile (milliseconds since the UNIX epoch), if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_date_time_ms: Option<f64>,
+
+    /// Crop rectangle in pixels.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Loader of the media playlist of an image stream.
+///
+/// Live playlists are reloaded every target duration. The images themselves are fetched
+/// by the host, from the URLs returned by `tile_at`.
+#[derive(Debug)]
+pub struct ImageStreamLoader {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    stream: ImageStream,
+    url: Url,
+    fetch_playlist_action_id: Option<ActionId>,
+    playlist_failures: u32,
+    reload_action_id: Option<ActionId>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+    images: Vec<Image>,
+    last_sequence: Option<u64>,
+}
+impl ImageStreamLoader {
+    pub fn new(config: &PlayerConfig, stream: ImageStream) -> Result<Self> {
+        let url = track!(Url::parse(&stream.url).map_err(Error::from))?;
+        let mut loader = ImageStreamLoader {
+            config: config.clone(),
+            action_factory: ActionFactory::new(IMAGE_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            stream,
+            url,
+            fetch_playlist_action_id: None,
+            playlist_failures: 0,
+            reload_action_id: None,
+            partial_bodies: HashMap::new(),
+            images: Vec::new(),
+            last_sequence: None,
+        };
+        loader.request_playlist();
+        Ok(loader)
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == IMAGE_STREAM_ID
+    }
+
+    pub fn stream(&self) -> &ImageStream {
+        &self.stream
+    }
+
+    /// Returns the tile showing `position`, measured from the start of the image playlist.
+    pub fn tile_at(&self, position: Duration) -> Option<ImageTile> {
+        let image = self.images
+            .iter()
+            .rev()
+            .find(|i| i.start <= position)
+            .filter(|i| position < i.start + i.duration)?;
+        let (columns, rows) = image.layout;
+        let count = columns * rows;
+        let tile_duration = image
+            .tile_duration
+            .unwrap_or_else(|| image.duration / count);
+        let offset = (position - image.start).as_secs_f64();
+        let index = if tile_duration.as_secs_f64() > 0.0 {
+            (offset / tile_duration.as_secs_f64()) as u32
+        } else {
+            0
+        };
+        let index = index.min(count - 1);
+        let (width, height) = image
+            .tile_resolution
+            .or_else(|| {
+                let (width, height) = self.stream.resolution?;
+                Some((width / columns, height / rows))
+            })
+            .unwrap_or((0, 0));
+        let start = image.start + tile_duration * index;
+        Some(ImageTile {
+            url: image.url.to_string(),
+            start_ms: start.as_secs_f64() * 1000.0,
+            duration_ms: tile_duration.as_secs_f64() * 1000.0,
+            program_date_time_ms: image
+                .program_date_time
+                .map(|t| (t + tile_duration * index).as_secs_f64() * 1000.0),
+            x: (index % columns) * width,
+            y: (index / columns) * height,
+            width,
+            height,
+        })
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8]) {
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let result = track!(self.handle_playlist(&text::decode(data)));
+            if let Err(e) = result {
+                self.handle_playlist_failure(FailureClass::Permanent, e.to_string());
+            }
+        }
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        self.partial_bodies
+            .entry(action_id)
+            .or_default()
+            .extend_from_slice(chunk);
+    }
+
+    pub fn handle_data_end(&mut self, action_id: ActionId) {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        self.handle_data(action_id, &body);
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) {
+        if self.reload_action_id == Some(action_id) {
+            self.reload_action_id = None;
+            self.request_playlist();
+        }
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    pub fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        self.partial_bodies.remove(&action_id);
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let reason = format!(
+                "Cannot fetch the image playlist {}: {}",
+                self.url, description
+            );
+            self.handle_playlist_failure(class, reason);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.reload_action_id = None;
+        self.partial_bodies.clear();
+        if let Some(action_id) = self.fetch_playlist_action_id.take() {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn request_playlist(&mut self) {
+        let action = self.action_factory
+            .fetch_data(self.url.clone(), ResourceKind::Playlist, None);
+        self.fetch_playlist_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+
+    fn handle_playlist(&mut self, m3u8: &str) -> Result<()> {
+        let playlist = track!(ImagePlaylist::parse(&self.url, m3u8))?;
+        self.playlist_failures = 0;
+        self.action_factory
+            .set_target_duration(playlist.target_duration);
+        let last_sequence = playlist.images.last().map(|i| i.seq);
+        if last_sequence != self.last_sequence {
+            let duration = playlist.images.last().map_or(Duration::from_secs(0), |i| {
+                i.start + i.duration
+            });
+            log!(
+                self.config,
+                Debug,
+                "Loaded the image playlist {}: {} images",
+                self.url,
+                playlist.images.len()
+            );
+            self.event_queue.push_back(Event::ImageStreamLoaded {
+                url: self.stream.url.clone(),
+                images: playlist.images.len(),
+                duration_ms: duration.as_secs_f64() * 1000.0,
+            });
+            self.last_sequence = last_sequence;
+        }
+        self.images = playlist.images;
+        if !playlist.is_ended {
+            self.schedule_reload(playlist.target_duration);
+        }
+        Ok(())
+    }
+
+    fn handle_playlist_failure(&mut self, class: FailureClass, reason: String) {
+        self.playlist_failures += 1;
+        if !class.is_permanent() && self.playlist_failures < self.config.playlist_retry.max_attempts
+        {
+            let delay = self.config.playlist_retry.delay(self.playlist_failures);
+            self.schedule_reload(delay);
+            return;
+        }
+        log!(self.config, Warn, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    fn schedule_reload(&mut self, delay: Duration) {
+        let action = self.action_factory.set_timeout(delay);
+        self.reload_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
+
+/// Media playlist of an image stream, which the playlist parser rejects
+/// (`EXT-X-IMAGES-ONLY`, `EXT-X-TILES`).
+#[derive(Debug)]
+struct ImagePlaylist {
+    target_duration: Duration,
+    images: Vec<Image>,
+    is_ended: bool,
+}
+impl ImagePlaylist {
+    fn parse(url: &Url, m3u8: &str) -> Result<Self> {
+        let mut target_duration = None;
+        let mut seq = 0;
+        let mut images = Vec::new();
+        let mut is_ended = false;
+        let mut position = Duration::from_secs(0);
+        let mut program_date_time = None;
+        let mut next = NextImage::default();
+        for line in m3u8.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                target_duration = value.parse().ok().map(Duration::from_secs);
+            } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+                seq = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+                program_date_time = date_time::parse(value);
+            } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+                let value = value.split(',').next().unwrap_or("");
+                next.duration = text::parse_seconds(value);
+            } else if let Some(attributes) = line.strip_prefix("#EXT-X-TILES:") {
+                next.layout = attribute(attributes, "LAYOUT").and_then(parse_resolution);
+                next.tile_resolution =
+                    attribute(attributes, "RESOLUTION").and_then(parse_resolution);
+                next.tile_duration =
+                    attribute(attributes, "DURATION").and_then(text::parse_seconds);
+            } else if line == "#EXT-X-ENDLIST" {
+                is_ended = true;
+            } else if !line.starts_with('#') {
+                let duration = track_assert_some!(
+                    next.duration,
+                    ErrorKind::InvalidInput,
+                    "No EXTINF of the image {}",
+                    line
+                );
+                let image_url = track!(
+                    Url::options()
+                        .base_url(Some(url))
+                        .parse(line)
+                        .map_err(Error::from)
+                )?;
+                let layout = next.layout.filter(|&(c, r)| c > 0 && r > 0).unwrap_or((1, 1));
+                images.push(Image {
+                    seq,
+                    url: image_url,
+                    start: position,
+                    duration,
+                    program_date_time,
+                    layout,
+                    tile_resolution: next.tile_resolution,
+                    tile_duration: next.tile_duration,
+                });
+                seq += 1;
+                position += duration;
+                program_date_time = program_date_time.map(|t| t + duration);
+                next = NextImage::default();
+            }
+        }
+        let target_duration = track_assert_some!(
+            target_duration,
+            ErrorKind::InvalidInput,
+            "No EXT-X-TARGETDURATION of the image playlist {}",
+            url
+        );
+        Ok(ImagePlaylist {
+            target_duration,
+            images,
+            is_ended,
+        })
+    }
+}
+
+/// Image segment of a media playlist.
+#[derive(Debug)]
+struct Image {
+    seq: u64,
+    url: Url,
+
+    /// Time from the start of the playlist.
+    start: Duration,
+    duration: Duration,
+
+    /// Time since the UNIX epoch.
+    program_date_time: Option<Duration>,
+
+    /// Columns and rows of the tiles (`1x1` without `EXT-X-TILES`).
+    layout: (u32, u32),
+
+    tile_resolution: Option<(u32, u32)>,
+
+    /// Time shown by each tile, which divides `duration` evenly if `None`.
+    tile_duration: Option<Duration>,
+}
+
+/// Tags preceding the URI of the next image.
+#[derive(Debug, Default)]
+struct NextImage {
+    duration: Option<Duration>,
+    layout: Option<(u32, u32)>,
+    tile_resolution: Option<(u32, u32)>,
+    tile_duration: Option<Duration>,
+}
+
+/// Parses `<width>x<height>` (of `RESOLUTION`) or `<columns>x<rows>` (of `LAYOUT`).
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000
+video.m3u8
+#EXT-X-IMAGE-STREAM-INF:BANDWIDTH=20000,RESOLUTION=320x180,CODECS=\"jpeg\",URI=\"small.m3u8\"
+#EXT-X-IMAGE-STREAM-INF:BANDWIDTH=60000,RESOLUTION=1600x720,URI=\"large.m3u8\"
+#EXT-X-IMAGE-STREAM-INF:BANDWIDTH=40000,RESOLUTION=1600x720,URI=\"images/large.m3u8\"
+";
+
+    const IMAGES: &str = "#EXTM3U
+#EXT-X-VERSION:7
+#EXT-X-TARGETDURATION:60
+#EXT-X-IMAGES-ONLY
+#EXT-X-PROGRAM-DATE-TIME:1970-01-01T00:01:00Z
+#EXT-X-TILES:RESOLUTION=320x180,LAYOUT=5x4,DURATION=3.0
+#EXTINF:60,
+a.jpg
+#EXT-X-TILES:LAYOUT=2x1
+#EXTINF:10,
+b.jpg
+";
+
+    fn streams() -> Vec<ImageStream> {
+        let url = Url::parse("http://x/master.m3u8").unwrap();
+        ImageStream::parse_all(&url, MASTER).unwrap()
+    }
+
+    /// Returns a loader of the widest image stream which has handled the playlist `m3u8`.
+    fn loader(m3u8: &str) -> ImageStreamLoader {
+        let stream = streams().swap_remove(2);
+        let mut loader = ImageStreamLoader::new(&PlayerConfig::default(), stream).unwrap();
+        let action_id = loader.fetch_playlist_action_id.unwrap();
+        loader.handle_data(action_id, m3u8.as_bytes());
+        loader
+    }
+
+    #[test]
+    fn image_streams_are_parsed() {
+        let streams = streams();
+        assert_eq!(streams.len(), 3);
+        assert_eq!(
+            streams[0],
+            ImageStream {
+                url: "http://x/small.m3u8".to_owned(),
+                bandwidth: 20_000,
+                resolution: Some((320, 180)),
+                codecs: Some("jpeg".to_owned()),
+            }
+        );
+        assert_eq!(streams[2].url, "http://x/images/large.m3u8");
+        assert_eq!(streams[2].codecs, None);
+
+        let url = Url::parse("http://x/master.m3u8").unwrap();
+        let m3u8 = "#EXTM3U\n#EXT-X-IMAGE-STREAM-INF:BANDWIDTH=20000\n";
+        assert!(ImageStream::parse_all(&url, m3u8).is_err());
+    }
+
+    #[test]
+    fn the_widest_image_stream_within_the_max_width_is_selected() {
+        let streams = streams();
+        let url = |max_width| ImageStream::select(&streams, max_width).map(|s| &s.url[9..]);
+
+        // The lower bandwidth wins among the streams of the same width.
+        assert_eq!(url(None), Some("images/large.m3u8"));
+        assert_eq!(url(Some(1600)), Some("images/large.m3u8"));
+        assert_eq!(url(Some(1599)), Some("small.m3u8"));
+        assert_eq!(url(Some(100)), Some("small.m3u8"));
+        assert_eq!(ImageStream::select(&[], None), None);
+    }
+
+    #[test]
+    fn tiles_are_located_by_position() {
+        let loader = loader(&format!("{}#EXT-X-ENDLIST\n", IMAGES));
+        let tile = |secs| loader.tile_at(Duration::from_millis((secs * 1000.0) as u64));
+        assert_eq!(
+            tile(7.0),
+            Some(ImageTile {
+                url: "http://x/images/a.jpg".to_owned(),
+                start_ms: 6000.0,
+                duration_ms: 3000.0,
+                program_date_time_ms: Some(66_000.0),
+                x: 640,
+                y: 0,
+                width: 320,
+                height: 180,
+            })
+        );
+        let last = tile(59.5).unwrap();
+        assert_eq!((last.x, last.y, last.start_ms), (1280, 540, 57_000.0));
+
+        // Without RESOLUTION and DURATION, the tiles divide the image and its duration.
+        let tile = tile(66.0).unwrap();
+        assert_eq!(tile.url, "http://x/images/b.jpg");
+        assert_eq!((tile.start_ms, tile.duration_ms), (65_000.0, 5000.0));
+        assert_eq!((tile.x, tile.y, tile.width, tile.height), (800, 0, 800, 720));
+        assert_eq!(loader.tile_at(Duration::from_secs(70)), None);
+    }
+
+    #[test]
+    fn live_image_playlists_are_reloaded() {
+        let mut loader = loader(IMAGES);
+        assert!(matches!(loader.next_action(), Some(Action::FetchData { .. })));
+        let reload_id = match loader.next_action() {
+            Some(Action::SetTimeout {
+                action_id,
+                duration: 60_000,
+            }) => action_id,
+            action => panic!("{:?}", action),
+        };
+        match loader.next_event() {
+            Some(Event::ImageStreamLoaded {
+                images: 2,
+                duration_ms,
+                ..
+            }) => assert_eq!(duration_ms, 70_000.0),
+            event => panic!("{:?}", event),
+        }
+
+        // Unchanged playlists are not reported again.
+        loader.handle_timeout(reload_id);
+        let action_id = loader.fetch_playlist_action_id.unwrap();
+        loader.handle_data_chunk(action_id, &IMAGES.as_bytes()[..10]);
+        loader.handle_data_chunk(action_id, &IMAGES.as_bytes()[10..]);
+        loader.handle_data_end(action_id);
+        assert!(matches!(loader.next_action(), Some(Action::FetchData { .. })));
+        assert!(matches!(loader.next_action(), Some(Action::SetTimeout { .. })));
+        assert!(loader.next_event().is_none());
+    }
+}
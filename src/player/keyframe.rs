@@ -0,0 +1,314 @@
+//! Extraction of H.264 keyframes from the MPEG-2 TS byte ranges of I-frame playlists.
+//!
+//! The ranges of `EXT-X-I-FRAMES-ONLY` playlists may lack the PAT and PMT, so the video
+//! stream is located by the PES stream ID instead of the program tables.
+use {ErrorKind, Result};
+use super::ts_sync;
+
+const PACKET_SIZE: usize = 188;
+
+/// Video access unit of a keyframe.
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    /// Presentation timestamp in the 90 kHz clock.
+    pub pts: Option<u64>,
+
+    /// NAL units in the Annex B byte stream format (start code prefixed).
+    pub data: Vec<u8>,
+
+    /// RFC 6381 codec string (e.g., `"avc1.64001F"`) if the access unit has an SPS.
+    pub codec: Option<String>,
+}
+
+/// Returns the first video access unit of `ts`.
+pub fn extract(ts: &[u8]) -> Result<Keyframe> {
+    let (ts, _) = ts_sync::resync(ts);
+    let mut video_pid = None;
+    let mut pes = Vec::new();
+    for packet in ts.chunks(PACKET_SIZE) {
+        let is_unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let payload = match payload(packet) {
+            None => continue,
+            Some(payload) => payload,
+        };
+        match video_pid {
+            None => {
+                if is_unit_start && is_video_pes(payload) {
+                    video_pid = Some(pid);
+                    pes.extend_from_slice(payload);
+                }
+            }
+            Some(video_pid) if video_pid == pid => {
+                if is_unit_start {
+                    break;
+                }
+                pes.extend_from_slice(payload);
+            }
+            Some(_) => {}
+        }
+    }
+    track_assert!(video_pid.is_some(), ErrorKind::InvalidInput, "No video PES packet");
+
+    track_assert!(pes.len() >= 9, ErrorKind::InvalidInput);
+    let has_pts = pes[7] & 0x80 != 0;
+    let header_end = 9 + pes[8] as usize;
+    track_assert!(pes.len() >= header_end, ErrorKind::InvalidInput);
+    let pts = if has_pts && header_end >= 14 {
+        Some(read_timestamp(&pes[9..14]))
+    } else {
+        None
+    };
+    let data = pes.split_off(header_end);
+    let codec =
+        find_sps(&data).map(|sps| format!("avc1.{:02X}{:02X}{:02X}", sps[0], sps[1], sps[2]));
+    Ok(Keyframe { pts, data, codec })
+}
+
+fn payload(packet: &[u8]) -> Option<&[u8]> {
+    let adaptation_field_control = (packet[3] >> 4) & 0b11;
+    if adaptation_field_control & 0b01 == 0 {
+        return None;
+    }
+    let start = if adaptation_field_control & 0b10 != 0 {
+        5 + packet[4] as usize
+    } else {
+        4
+    };
+    packet.get(start..)
+}
+
+fn is_video_pes(payload: &[u8]) -> bool {
+    payload.len() >= 4 && payload[..3] == [0, 0, 1] && (0xE0..=0xEF).contains(&payload[3])
+}
+
+/// Reads the 33-bit PTS or DTS field of a PES header.
+pub fn read_timestamp(bytes: &[u8]) -> u64 {
+    (u64::from(bytes[0] & 0x0E) << 29)
+        | (u64::from(bytes[1]) << 22)
+        | (u64::from(bytes[2] & 0xFE) << 14)
+        | (u64::from(bytes[3]) << 7)
+        | (u64::from(bytes[4]) >> 1)
+}
+
+/// Fields of an SPS NAL unit needed for the `avcC` box and the track dimensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpsSummary {
+    pub profile_idc: u8,
+    pub constraint_set_flag: u8,
+    pub level_idc: u8,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Parses the SPS NAL unit `nal` (starting with its header byte).
+///
+/// As with the remuxer, the High profiles (whose SPS carries the chroma format and
+/// scaling lists) are not supported.
+pub fn parse_sps(nal: &[u8]) -> Result<SpsSummary> {
+    track_assert!(!nal.is_empty() && nal[0] & 0x1F == 7, ErrorKind::InvalidInput, "Not an SPS");
+    let rbsp = unescape(&nal[1..]);
+    track_assert!(rbsp.len() >= 4, ErrorKind::InvalidInput, "Too short SPS");
+    let profile_idc = rbsp[0];
+    match profile_idc {
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135 => {
+            track_panic!(ErrorKind::Other, "Unsupported profile_idc={}", profile_idc)
+        }
+        _ => {}
+    }
+
+    let mut reader = BitReader::new(&rbsp[3..]);
+    let _seq_parameter_set_id = track!(reader.read_ue())?;
+    let _log2_max_frame_num_minus4 = track!(reader.read_ue())?;
+    match track!(reader.read_ue())? {
+        0 => {
+            let _log2_max_pic_order_cnt_lsb_minus4 = track!(reader.read_ue())?;
+        }
+        1 => {
+            let _delta_pic_order_always_zero_flag = track!(reader.read_bit())?;
+            let _offset_for_non_ref_pic = track!(reader.read_ue())?;
+            let _offset_for_top_to_bottom_field = track!(reader.read_ue())?;
+            for _ in 0..track!(reader.read_ue())? {
+                let _offset_for_ref_frame = track!(reader.read_ue())?;
+            }
+        }
+        2 => {}
+        n => track_panic!(ErrorKind::InvalidInput, "pic_order_cnt_type={}", n),
+    }
+    let _max_num_ref_frames = track!(reader.read_ue())?;
+    let _gaps_in_frame_num_value_allowed_flag = track!(reader.read_bit())?;
+    let width_in_mbs = track!(reader.read_ue())? + 1;
+    let height_in_map_units = track!(reader.read_ue())? + 1;
+    let frame_mbs_only_flag = track!(reader.read_bit())?;
+    if !frame_mbs_only_flag {
+        let _mb_adaptive_frame_field_flag = track!(reader.read_bit())?;
+    }
+    let _direct_8x8_inference_flag = track!(reader.read_bit())?;
+    let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+    if track!(reader.read_bit())? {
+        crop_left = track!(reader.read_ue())?;
+        crop_right = track!(reader.read_ue())?;
+        crop_top = track!(reader.read_ue())?;
+        crop_bottom = track!(reader.read_ue())?;
+    }
+
+    // 4:2:0 chroma, cropped in units of two luma samples (and two fields if interlaced).
+    let field_factor = if frame_mbs_only_flag { 1 } else { 2 };
+    let width = (width_in_mbs * 16).checked_sub((crop_left + crop_right) * 2);
+    let height = (height_in_map_units * 16 * field_factor)
+        .checked_sub((crop_top + crop_bottom) * 2 * field_factor);
+    let (width, height) = match (width, height) {
+        (Some(w), Some(h)) if w <= 0xFFFF && h <= 0xFFFF => (w as u16, h as u16),
+        _ => track_panic!(ErrorKind::InvalidInput, "Invalid picture size"),
+    };
+    Ok(SpsSummary {
+        profile_idc,
+        constraint_set_flag: rbsp[1],
+        level_idc: rbsp[2],
+        width,
+        height,
+    })
+}
+
+/// Returns the NAL units (without start codes) of `data` in the Annex B byte stream format.
+pub fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let mut units = Vec::with_capacity(starts.len());
+    for (k, &start) in starts.iter().enumerate() {
+        let end = starts.get(k + 1).map_or(data.len(), |&next| next);
+        let mut unit = &data[start + 3..end];
+
+        // Drops the leading zero of the next four-byte start code.
+        while let Some((&0, rest)) = unit.split_last() {
+            unit = rest;
+        }
+        if !unit.is_empty() {
+            units.push(unit);
+        }
+    }
+    units
+}
+
+/// Returns the profile, constraint flags and level bytes of the first SPS NAL unit.
+fn find_sps(data: &[u8]) -> Option<&[u8]> {
+    nal_units(data)
+        .into_iter()
+        .find(|nal| nal[0] & 0x1F == 7 && nal.len() >= 4)
+        .map(|nal| &nal[1..4])
+}
+
+/// Removes the emulation prevention bytes (`0x03` of `0x000003`) from `data`.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        rbsp.push(b);
+    }
+    rbsp
+}
+
+/// Reader of the bits and Exp-Golomb codes of an RBSP.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        let byte = track_assert_some!(
+            self.data.get(self.position / 8),
+            ErrorKind::InvalidInput,
+            "Truncated SPS"
+        );
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit == 1)
+    }
+
+    fn read_ue(&mut self) -> Result<u64> {
+        let mut leading_zeros = 0;
+        while !track!(self.read_bit())? {
+            leading_zeros += 1;
+            track_assert!(leading_zeros < 32, ErrorKind::InvalidInput, "Invalid Exp-Golomb code");
+        }
+        let mut value = 0;
+        for _ in 0..leading_zeros {
+            value = (value << 1) | u64::from(track!(self.read_bit())?);
+        }
+        Ok((1 << leading_zeros) - 1 + value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ts_fixture::{self, PPS, SPS};
+
+    #[test]
+    fn the_first_access_unit_is_extracted() {
+        let keyframe = extract(&ts_fixture::segment(90_000, 3)).unwrap();
+        assert_eq!(keyframe.pts, Some(90_000));
+        assert_eq!(keyframe.data, ts_fixture::access_unit(true));
+        assert_eq!(keyframe.codec.as_deref(), Some("avc1.42C01E"));
+    }
+
+    #[test]
+    fn ranges_without_program_tables_are_extracted() {
+        let ts = ts_fixture::segment(0, 3);
+        let keyframe = extract(&ts[2 * PACKET_SIZE..]).unwrap();
+        assert_eq!(keyframe.pts, Some(0));
+        assert_eq!(keyframe.data, ts_fixture::access_unit(true));
+
+        // The PAT and the PMT alone.
+        assert!(extract(&ts[..2 * PACKET_SIZE]).is_err());
+    }
+
+    #[test]
+    fn nal_units_are_split_at_start_codes() {
+        let data = ts_fixture::access_unit(true);
+        let units = nal_units(&data);
+        assert_eq!(units.len(), 4);
+        assert_eq!(units[0], [0x09, 0xF0]);
+        assert_eq!(units[1], SPS);
+        assert_eq!(units[2], PPS);
+        assert_eq!(units[3][0] & 0x1F, 5);
+    }
+
+    #[test]
+    fn sps_gives_the_picture_size() {
+        let summary = parse_sps(&SPS).unwrap();
+        assert_eq!(
+            summary,
+            SpsSummary {
+                profile_idc: 66,
+                constraint_set_flag: 0xC0,
+                level_idc: 30,
+                width: 64,
+                height: 64,
+            }
+        );
+
+        // The High profile, and a PPS.
+        let mut sps = SPS;
+        sps[1] = 100;
+        assert!(parse_sps(&sps).is_err());
+        assert!(parse_sps(&PPS).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Index of the keyframes of the produced segments, for frame-accurate seeking.
+use std::collections::VecDeque;
+use std::time::Duration;
+use mse_fmp4::fmp4::MediaSegment;
+
+/// Maximum number of the segments remembered by `KeyframeIndex`.
+const MAX_SEGMENTS: usize = 4096;
+
+/// Timescale of the video tracks produced by the remuxer (the 90 kHz clock of MPEG-2 TS).
+const VIDEO_TIMESCALE: u64 = 90_000;
+
+/// H.264 `nal_unit_type` of IDR pictures.
+const NAL_UNIT_TYPE_IDR: u8 = 5;
+
+/// Keyframes of a produced segment.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedSegment {
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+
+    /// Position of the segment on the media timeline of the produced segments.
+    pub start_ms: f64,
+
+    pub duration_ms: f64,
+
+    /// Presentation times of the keyframes relative to `start_ms`, in ascending order.
+    pub keyframes_ms: Vec<f64>,
+}
+
+/// Keyframe returned by `KeyframeIndex::nearest`.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyframePosition {
+    /// Media sequence number of the segment containing the keyframe.
+    pub sequence: u64,
+
+    /// Presentation time of the keyframe relative to the start of the segment.
+    pub offset_ms: f64,
+
+    /// Position of the keyframe on the media timeline of the produced segments.
+    pub position_ms: f64,
+}
+
+/// Index of the keyframes found while remuxing, laid out on the media timeline
+/// which starts at zero (see `HlsPlayer::set_playback_position`).
+#[derive(Debug, Default)]
+pub struct KeyframeIndex {
+    segments: VecDeque<IndexedSegment>,
+    timeline_end: Duration,
+}
+impl KeyframeIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a segment appended to the media timeline, `keyframes` being relative to its start.
+    pub fn on_segment_appended(
+        &mut self,
+        sequence: u64,
+        duration: Duration,
+        keyframes: &[Duration],
+    ) {
+        if self.segments.len() == MAX_SEGMENTS {
+            self.segments.pop_front();
+        }
+        self.segments.push_back(IndexedSegment {
+            sequence,
+            start_ms: to_millis(self.timeline_end),
+            duration_ms: to_millis(duration),
+            keyframes_ms: keyframes.iter().map(|&k| to_millis(k)).collect(),
+        });
+        self.timeline_end += duration;
+    }
+
+    pub fn segments(&self) -> &VecDeque<IndexedSegment> {
+        &self.segments
+    }
+
+    /// Returns the last keyframe at or before `position`, or the first one if there is none.
+    pub fn nearest(&self, position: Duration) -> Option<KeyframePosition> {
+        let position_ms = to_millis(position);
+        let keyframes = self.segments.iter().flat_map(|s| {
+            s.keyframes_ms.iter().map(move |&k| KeyframePosition {
+                sequence: s.sequence,
+                offset_ms: k,
+                position_ms: s.start_ms + k,
+            })
+        });
+        let mut nearest = None;
+        for keyframe in keyframes {
+            if nearest.is_some() && keyframe.position_ms > position_ms {
+                break;
+            }
+            nearest = Some(keyframe);
+        }
+        nearest
+    }
+}
+
+/// Returns the presentation times of the IDR pictures of the video track of `media_segment`
+/// (as produced by `mpeg2_ts::to_fmp4`), relative to its first sample.
+pub fn keyframe_offsets(media_segment: &MediaSegment) -> Vec<Duration> {
+    let traf = media_segment.moof_box.traf_boxes.first();
+    let mdat = media_segment.mdat_boxes.first();
+    let (samples, mut data) = match (traf, mdat) {
+        (Some(traf), Some(mdat)) => (&traf.trun_box.samples, &mdat.data[..]),
+        _ => return Vec::new(),
+    };
+    let first_offset = samples
+        .first()
+        .and_then(|s| s.composition_time_offset)
+        .unwrap_or(0);
+    let mut decode_time = 0;
+    let mut offsets = Vec::new();
+    for sample in samples {
+        let size = sample.size.unwrap_or(0) as usize;
+        if size > data.len() {
+            break;
+        }
+        let (sample_data, rest) = data.split_at(size);
+        data = rest;
+        if is_idr(sample_data) {
+            let offset = i64::from(sample.composition_time_offset.unwrap_or(0) - first_offset);
+            let pts = (decode_time as i64 + offset).max(0) as u64;
+            offsets.push(Duration::from_micros(pts * 1_000_000 / VIDEO_TIMESCALE));
+        }
+        decode_time += u64::from(sample.duration.unwrap_or(0));
+    }
+    offsets.sort();
+    offsets
+}
+
+/// Returns `true` if the sample (NAL units prefixed by 4-byte lengths) has an IDR picture.
+fn is_idr(mut sample: &[u8]) -> bool {
+    while sample.len() > 4 {
+        let len = u32::from_be_bytes([sample[0], sample[1], sample[2], sample[3]]) as usize;
+        let nal_unit = &sample[4..];
+        if nal_unit[0] & 0x1F == NAL_UNIT_TYPE_IDR {
+            return true;
+        }
+        sample = nal_unit.get(len..).unwrap_or(&[]);
+    }
+    false
+}
+
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::time::Duration;
+
+use super::date_time;
+
+/// Maximum number of the segments remembered for mapping playback positions to date-times.
+const MAX_TIMELINE_ENTRIES: usize = 64;
+
+/// Number of the playlist responses whose clock offsets are averaged.
+const MAX_CLOCK_OFFSETS: usize = 8;
+
+/// Estimator of the end-to-end live latency.
+///
+/// Playback positions are mapped onto the `EXT-X-PROGRAM-DATE-TIME` of the appended segments.
+/// Without date-times, the latency is estimated as the time since the last playlist load
+/// plus the distance from the playback position to the live edge of that playlist.
+///
+/// The `Date` and `Age` of the playlist responses correct the former for the skew between
+/// the clocks of the client and the origin, and the latter for the time spent in caches.
+#[derive(Debug, Default)]
+pub struct LatencyEstimator {
+    timeline: VecDeque<TimelineEntry>,
+    timeline_end: Duration,
+    playlist_loaded_at: Option<Duration>,
+    live_edge: Duration,
+    playback_position: Option<Duration>,
+
+    /// Offsets of the client clock from that of the origin, in milliseconds.
+    clock_offsets_ms: VecDeque<i64>,
+
+    /// `Age` of the playlist response to be loaded next.
+    response_age: Duration,
+}
+impl LatencyEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a segment appended to the media timeline, which starts at zero.
+    pub fn on_segment_appended(&mut self, duration: Duration, program_date_time: Option<Duration>) {
+        if self.timeline.len() == MAX_TIMELINE_ENTRIES {
+            self.timeline.pop_front();
+        }
+        self.timeline.push_back(TimelineEntry {
+            start: self.timeline_end,
+            program_date_time,
+        });
+        self.timeline_end += duration;
+    }
+
+    /// Records the playlist response received at `now` after `fetch_duration`,
+    /// before it is loaded by `on_playlist_loaded`.
+    ///
+    /// `Date` has a precision of one second, so the origin is assumed to have stamped
+    /// the middle of that second at the middle of the fetch.
+    pub fn on_playlist_response(
+        &mut self,
+        now: Duration,
+        fetch_duration: Duration,
+        response: ResponseDate,
+    ) {
+        self.response_age = response.age;
+        let date = match response.date {
+            None => return,
+            Some(date) => date,
+        };
+        let millis = |d: Duration| d.as_millis() as i64;
+        let origin_ms = millis(date + response.age) + 500;
+        let client_ms = millis(now.saturating_sub(fetch_duration / 2));
+        if self.clock_offsets_ms.len() == MAX_CLOCK_OFFSETS {
+            self.clock_offsets_ms.pop_front();
+        }
+        self.clock_offsets_ms.push_back(client_ms - origin_ms);
+    }
+
+    /// Records a playlist load at `now`, `pending` being the duration of the segments
+    /// yet to be appended.
+    ///
+    /// The playlist is as old as the `Age` given by `on_playlist_response`, if any.
+    pub fn on_playlist_loaded(&mut self, now: Option<Duration>, pending: Duration) {
+        let age = mem::take(&mut self.response_age);
+        self.playlist_loaded_at = now.map(|now| now.saturating_sub(age));
+        self.live_edge = self.timeline_end + pending;
+    }
+
+    /// Returns the average offset of the client clock from that of the origin
+    /// in milliseconds (positive if the client is ahead), if any `Date` has been recorded.
+    pub fn clock_offset_ms(&self) -> Option<i64> {
+        if self.clock_offsets_ms.is_empty() {
+            return None;
+        }
+        let sum = self.clock_offsets_ms.iter().sum::<i64>();
+        Some(sum / self.clock_offsets_ms.len() as i64)
+    }
+
+    pub fn set_playback_position(&mut self, position: Duration) {
+        self.playback_position = Some(position);
+    }
+
+    /// Returns the playback position notified by `set_playback_position`.
+    pub fn playback_position(&self) -> Option<Duration> {
+        self.playback_position
+    }
+
+    /// Returns the latency at `now` (the wall-clock time since the UNIX epoch).
+    pub fn latency(&self, now: Duration) -> Option<Duration> {
+        let position = self.playback_position?;
+        if let Some(playing) = self.program_date_time_at(position) {
+            let origin_now = match self.clock_offset_ms() {
+                Some(offset) if offset >= 0 => {
+                    now.saturating_sub(Duration::from_millis(offset as u64))
+                }
+                Some(offset) => now + Duration::from_millis(offset.unsigned_abs()),
+                None => now,
+            };
+            return Some(origin_now.saturating_sub(playing));
+        }
+
+        let loaded_at = self.playlist_loaded_at?;
+        let behind_edge = self.live_edge.saturating_sub(position);
+        Some(now.saturating_sub(loaded_at) + behind_edge)
+    }
+
+    /// Returns the `EXT-X-PROGRAM-DATE-TIME` (since the UNIX epoch) presented at `position`
+    /// on the media timeline, if the segment containing it has one.
+    pub fn program_date_time_at(&self, position: Duration) -> Option<Duration> {
+        let entry = self.timeline
+            .iter()
+            .rev()
+            .find(|e| e.start <= position)
+            .or_else(|| self.timeline.front())?;
+        let program_date_time = entry.program_date_time?;
+        (program_date_time + position).checked_sub(entry.start)
+    }
+
+    /// Returns the position on the media timeline presenting `date_time`
+    /// (the inverse of `program_date_time_at`).
+    ///
+    /// Date-times beyond the remembered segments are extrapolated from the nearest one.
+    pub fn position_at(&self, date_time: Duration) -> Option<Duration> {
+        let mut dated = self.timeline
+            .iter()
+            .filter_map(|e| e.program_date_time.map(|t| (e.start, t)));
+        let (start, program_date_time) = dated
+            .clone()
+            .rfind(|&(_, t)| t <= date_time)
+            .or_else(|| dated.next())?;
+        (start + date_time).checked_sub(program_date_time)
+    }
+
+    /// Returns the hint for aligning the playback to `date_time` (see `SyncHint`).
+    pub fn sync_hint(&self, date_time: Duration) -> Option<SyncHint> {
+        let position = self.position_at(date_time)?;
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        Some(SyncHint {
+            position_ms: millis(position),
+            offset_ms: self.playback_position
+                .map(|p| millis(position) - millis(p)),
+        })
+    }
+}
+
+/// `Date` and `Age` headers of a playlist response (see `LiveEdgeConfig::correct_clock_skew`).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResponseDate {
+    /// `Date` since the UNIX epoch.
+    pub date: Option<Duration>,
+
+    /// Time the response has spent in caches.
+    pub age: Duration,
+}
+impl ResponseDate {
+    /// Reads the `Date` and `Age` of `headers`, whose names are case-insensitive.
+    pub fn parse(headers: &BTreeMap<String, String>) -> Self {
+        let mut response = ResponseDate::default();
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("Date") {
+                response.date = date_time::parse_http_date(value);
+            } else if name.eq_ignore_ascii_case("Age") {
+                if let Ok(secs) = value.trim().parse() {
+                    response.age = Duration::from_secs(secs);
+                }
+            }
+        }
+        response
+    }
+}
+
+/// Hint for keeping players of the same event (e.g., camera angles) in sync
+/// by their `EXT-X-PROGRAM-DATE-TIME` (see `HlsPlayer::sync_to`).
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncHint {
+    /// Position on the media timeline presenting the requested date-time.
+    pub position_ms: f64,
+
+    /// `position_ms` minus the notified playback position, i.e., positive if the playback
+    /// is behind the date-time; hosts seek on large offsets and otherwise adjust
+    /// the playback rate slightly.
+    pub offset_ms: Option<f64>,
+}
+
+#[derive(Debug)]
+struct TimelineEntry {
+    start: Duration,
+    program_date_time: Option<Duration>,
+}
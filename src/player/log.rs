@@ -0,0 +1,38 @@
+use std::fmt;
+
+/// Severity of log records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+         Deserialize)]
+pub enum LogLevel {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Host-provided sink of log records.
+pub trait Logger {
+    fn log(&self, level: LogLevel, message: &str);
+}
+impl<F> Logger for F
+where
+    F: Fn(LogLevel, &str),
+{
+    fn log(&self, level: LogLevel, message: &str) {
+        self(level, message)
+    }
+}
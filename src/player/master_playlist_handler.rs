@@ -1,41 +1,360 @@
+use std::cmp::{self, Ordering};
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::time::Duration;
 use hls_m3u8::MasterPlaylist;
 use url::Url;
 
 use {Error, ErrorKind, Result};
-use super::{Action, ActionFactory, ActionId, MediaPlaylistHandler};
+use super::{Action, ActionFactory, ActionId, AdPod, AudioRenditionLoader, ContentSteering,
+            DateRangeTimeline, Event, FailureClass, ImageStream, ImageStreamLoader, ImageTile,
+            KeyframeIndex, LatencyEstimator, MediaPlaylistHandler, OutputSegment, PlayerConfig,
+            PlaylistPrefetcher, ReinitReason, Rendition, ResourceCache, SegmentHistory, Stats,
+            SteeringTag, StreamId, SubtitleLoader, SwitchReason, Timeline, Trace, TraceEntry,
+            TrickPlayHandler, VariantSwitch};
+use super::image_stream::IMAGE_STREAM_ID;
+use super::codecs::{self, SupplementalCodec};
+use super::rendition::{self, RenditionType};
+use super::steering::{self, DEFAULT_PATHWAY_ID};
+use super::text::attribute;
+
+/// Maximum relative difference of the bandwidths of variants whose `SCORE` decides the ranking.
+const SIMILAR_BANDWIDTH_RATIO: f64 = 0.1;
 
 #[derive(Debug)]
 pub struct MasterPlaylistHandler {
     media_playlist_handler: MediaPlaylistHandler,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+
+    /// Segments produced by the handlers replaced by pathway switches, in order.
+    segment_queue: Vec<OutputSegment>,
+
+    /// Fixed until the master playlist is replaced (see `start_next_master`).
+    variants: Box<[Variant]>,
+    variant_index: usize,
+    renditions: Box<[Rendition]>,
+    variant_switches: Vec<VariantSwitch>,
+    steering: Option<Box<ContentSteering>>,
+    forced_subtitles: Option<Box<SubtitleLoader>>,
+
+    /// Follows the playlist of the selected audio rendition
+    /// (see `RenditionConfig::audio_playlists`).
+    audio: Option<Box<AudioRenditionLoader>>,
+
+    /// Follows the playlist of the image stream (see `ThumbnailConfig::image_streams`).
+    image_stream: Option<Box<ImageStreamLoader>>,
+
+    /// Reloads the playlists of the variants which the next switch may take
+    /// (see `PlaylistPrefetchConfig`).
+    playlist_prefetch: Option<Box<PlaylistPrefetcher>>,
+
+    /// Replaces the media playlist handler (stopped in the meantime) during trick play.
+    trick_play: Option<Box<TrickPlayHandler>>,
 }
 impl MasterPlaylistHandler {
-    pub fn new(url: Url, m3u8: &str) -> Result<Self> {
-        let master_playlist: MasterPlaylist = track!(m3u8.parse())?;
-
-        let stream_inf_tag = track_assert_some!(
-            master_playlist.stream_inf_tags().get(0),
+    pub fn new(config: &PlayerConfig, trace: Trace, url: Url, m3u8: &str) -> Result<Self> {
+        let (master_playlist, mut variants, renditions) = track!(Self::parse(&url, m3u8))?;
+        track_assert!(
+            config.variant_index < variants.len(),
             ErrorKind::InvalidInput
         );
-        let media_playlist_url = track!(
-            Url::options()
-                .base_url(Some(&url))
-                .parse(stream_inf_tag.uri())
-                .map_err(Error::from)
-        )?;
 
-        let action_factory = ActionFactory::new(0);
-        let media_playlist_handler = MediaPlaylistHandler::new(action_factory, media_playlist_url);
-        Ok(MasterPlaylistHandler {
+        Self::exclude_variants(config, &mut variants);
+        let mut variant_index = Self::substitute_variant(&variants, config.variant_index);
+        if variant_index != config.variant_index {
+            log!(
+                config,
+                Info,
+                "The variant {} exceeds the device capabilities; {} is played instead",
+                config.variant_index,
+                variant_index
+            );
+        } else if let Some(i) = Self::preferred_variant(&variants, variant_index) {
+            log!(
+                config,
+                Info,
+                "The variant {} is played instead of {} for its SUPPLEMENTAL-CODECS or SCORE",
+                i,
+                variant_index
+            );
+            variant_index = i;
+        }
+        let mut steering = None;
+        if config.content_steering.enabled {
+            if let Some(tag) = track!(SteeringTag::parse(&url, m3u8))? {
+                // `variant_index` selects the rendition, and the tag selects its pathway.
+                let pathways = variants.iter().map(|v| v.pathway.clone()).collect::<Vec<_>>();
+                if let Some(ref pathway) = tag.pathway_id {
+                    variant_index = steering::equivalent_variant(&pathways, variant_index, pathway)
+                        .map_or(variant_index, |i| Self::substitute_variant(&variants, i));
+                }
+                let mut client = ContentSteering::new(config, &tag);
+                client.request(&variants[variant_index].pathway, None);
+                steering = Some(Box::new(client));
+            }
+        }
+
+        let switch = VariantSwitch {
+            timestamp_ms: config.hooks.now().map(|t| t.as_secs_f64() * 1000.0),
+            from: None,
+            to: variant_index,
+            bandwidth: variants[variant_index].bandwidth,
+            reason: SwitchReason::Initial,
+        };
+        trace.record(|| TraceEntry::VariantSwitched {
+            switch: switch.clone(),
+        });
+        let mut variant_switches = vec![switch];
+        if config.low_power.enabled && config.low_power.lowest_variant {
+            let lowest = Self::lowest_variant(&variants, variant_index);
+            if lowest != variant_index {
+                log!(
+                    config,
+                    Info,
+                    "The variant {} is played instead of {} in the low-power mode",
+                    lowest,
+                    variant_index
+                );
+                let switch = VariantSwitch {
+                    timestamp_ms: config.hooks.now().map(|t| t.as_secs_f64() * 1000.0),
+                    from: Some(variant_index),
+                    to: lowest,
+                    bandwidth: variants[lowest].bandwidth,
+                    reason: SwitchReason::LowPower,
+                };
+                trace.record(|| TraceEntry::VariantSwitched {
+                    switch: switch.clone(),
+                });
+                variant_switches.push(switch);
+                variant_index = lowest;
+            }
+        }
+
+        let mut action_factory = ActionFactory::new(0, config);
+        let action_queue = Self::preconnect_actions(
+            config,
+            &mut action_factory,
+            &url,
+            &master_playlist,
+        );
+        let media_playlist_handler = MediaPlaylistHandler::new(
+            config,
+            action_factory,
+            ResourceCache::new(),
+            SegmentHistory::new(),
+            trace,
+            variants[variant_index].url.clone(),
+        );
+        let image_stream = if config.thumbnails.image_streams {
+            track!(Self::image_stream_loader(config, &url, m3u8))?
+        } else {
+            None
+        };
+        let playlist_prefetch = if config.playlist_prefetch.enabled {
+            Some(Box::new(PlaylistPrefetcher::new(config)))
+        } else {
+            None
+        };
+        let mut handler = MasterPlaylistHandler {
             media_playlist_handler,
-        })
+            action_queue,
+            event_queue: VecDeque::new(),
+            segment_queue: Vec::new(),
+            variants: variants.into_boxed_slice(),
+            variant_index,
+            renditions: renditions.into_boxed_slice(),
+            variant_switches,
+            steering,
+            forced_subtitles: None,
+            audio: None,
+            image_stream,
+            playlist_prefetch,
+            trick_play: None,
+        };
+        handler.log_renditions();
+        handler.update_forced_subtitles();
+        handler.update_audio_rendition();
+        handler.update_prefetch_targets();
+        Ok(handler)
+    }
+
+    /// Parses the variants and renditions of the master playlist `url`.
+    fn parse(url: &Url, m3u8: &str) -> Result<(MasterPlaylist, Vec<Variant>, Vec<Rendition>)> {
+        // The parser panics on playlists without any master playlist tag.
+        track_assert!(
+            m3u8.contains("#EXT-X-STREAM-INF"),
+            ErrorKind::InvalidInput,
+            "No variant streams: {}",
+            url
+        );
+        let master_playlist: MasterPlaylist = track!(m3u8.parse())?;
+
+        let mut i_frame_variants = Vec::new();
+        for tag in master_playlist.i_fream_stream_inf_tags() {
+            let url = track!(
+                Url::options()
+                    .base_url(Some(url))
+                    .parse(tag.uri())
+                    .map_err(Error::from)
+            )?;
+            i_frame_variants.push(IFrameVariant {
+                url,
+                bandwidth: tag.bandwidth(),
+            });
+        }
+        let mut pathways = steering::variant_pathways(m3u8).into_iter();
+
+        // The parser does not keep `SCORE` nor `SUPPLEMENTAL-CODECS`.
+        let mut attribute_lists = m3u8
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("#EXT-X-STREAM-INF:"));
+        let mut variants = Vec::new();
+        for tag in master_playlist.stream_inf_tags() {
+            let url = track!(
+                Url::options()
+                    .base_url(Some(url))
+                    .parse(tag.uri())
+                    .map_err(Error::from)
+            )?;
+            let attributes = attribute_lists.next().unwrap_or("");
+            variants.push(Variant {
+                url,
+                bandwidth: tag.bandwidth(),
+                pathway: pathways.next().unwrap_or_else(|| DEFAULT_PATHWAY_ID.to_owned()),
+                audio: tag.audio().map(|g| g.as_ref().to_owned()),
+                subtitles: tag.subtitles().map(|g| g.as_ref().to_owned()),
+                frame_rate: tag.frame_rate().map(|f| f.as_f64()),
+                score: attribute(attributes, "SCORE").and_then(|s| s.parse().ok()),
+                codecs: tag.codecs().map(|c| c.as_ref().to_owned()),
+                supplemental_codecs: attribute(attributes, "SUPPLEMENTAL-CODECS")
+                    .map_or_else(Vec::new, codecs::parse_supplemental),
+                i_frame_url: Self::i_frame_url(&i_frame_variants, tag.bandwidth()).cloned(),
+                is_excluded: false,
+                is_enhanced: false,
+                is_blacklisted: false,
+                blacklisted_until: None,
+                blacklist_count: 0,
+            });
+        }
+        let mut renditions = Vec::new();
+        for tag in master_playlist.media_tags() {
+            renditions.extend(track!(Rendition::from_tag(url, tag))?);
+        }
+        Ok((master_playlist, variants, renditions))
     }
 
     pub fn next_action(&mut self) -> Option<Action> {
+        if let Some(action) = self.action_queue.pop_front() {
+            return Some(action);
+        }
+        if let Some(action) = self.steering.as_mut().and_then(|s| s.next_action()) {
+            return Some(action);
+        }
+        if let Some(action) = self.forced_subtitles.as_mut().and_then(|s| s.next_action()) {
+            return Some(action);
+        }
+        if let Some(action) = self.audio.as_mut().and_then(|a| a.next_action()) {
+            return Some(action);
+        }
+        if let Some(action) = self.image_stream.as_mut().and_then(|l| l.next_action()) {
+            return Some(action);
+        }
+        if let Some(action) = self.playlist_prefetch.as_mut().and_then(|p| p.next_action()) {
+            return Some(action);
+        }
+        if let Some(action) = self.trick_play.as_mut().and_then(|t| t.next_action()) {
+            return Some(action);
+        }
         self.media_playlist_handler.next_action()
     }
 
-    pub fn next_segment(&mut self) -> Option<Vec<u8>> {
-        self.media_playlist_handler.next_segment()
+    pub fn next_event(&mut self) -> Option<Event> {
+        let mut event = self.event_queue
+            .pop_front()
+            .or_else(|| self.forced_subtitles.as_mut().and_then(|s| s.next_event()))
+            .or_else(|| self.audio.as_mut().and_then(|a| a.next_event()))
+            .or_else(|| self.image_stream.as_mut().and_then(|l| l.next_event()))
+            .or_else(|| self.trick_play.as_mut().and_then(|t| t.next_event()))
+            .or_else(|| self.media_playlist_handler.next_event());
+        if let Some(Event::SegmentLoaded { ref mut variant, .. }) = event {
+            *variant = Some(self.variant_index);
+        }
+        if let Some(Event::NextAssetStarted { .. }) = event {
+            self.start_next_master();
+        }
+        if let Some(Event::AdBreakEnded { .. }) = event {
+            // Applies the steering deferred by the variant lock.
+            self.apply_steering();
+        }
+        event
+    }
+
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        if !self.segment_queue.is_empty() {
+            return Some(self.segment_queue.remove(0));
+        }
+        if let Some(trick_play) = self.trick_play.as_mut() {
+            return trick_play.next_segment();
+        }
+        let mut segment = self.media_playlist_handler.next_segment()?;
+        segment.info.variant = Some(self.variant_index);
+        Some(segment)
+    }
+
+    pub fn recycle_segment(&mut self, segment: Vec<u8>) {
+        self.media_playlist_handler.recycle_segment(segment);
+    }
+
+    pub fn take_recording(&mut self) -> Vec<u8> {
+        self.media_playlist_handler.take_recording()
+    }
+
+    pub fn insert_ad_pod(&mut self, pod: AdPod) {
+        self.media_playlist_handler.insert_ad_pod(pod);
+    }
+
+    /// Queues the media playlist `url` to be played after the current one
+    /// (see `MediaPlaylistHandler::queue_next`).
+    pub fn queue_next(&mut self, url: Url, m3u8: String) {
+        self.media_playlist_handler.queue_next(url, Some(m3u8), None);
+    }
+
+    /// Queues the master playlist `url` to be played after the current one.
+    ///
+    /// Its variant with the highest bandwidth up to that of the played variant
+    /// (or the lowest one) is prefetched and played, unless one of a similar bandwidth
+    /// is preferred to it (see `preferred_variant`).
+    pub fn queue_next_master(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        let (_, mut variants, renditions) = track!(Self::parse(&url, m3u8))?;
+        let config = self.media_playlist_handler.config().clone();
+        Self::exclude_variants(&config, &mut variants);
+        let bandwidth = self.variants[self.variant_index].bandwidth;
+        let candidates = || variants.iter().enumerate().filter(|&(_, v)| !v.is_excluded);
+        let variant_index = candidates()
+            .filter(|&(_, v)| v.bandwidth <= bandwidth)
+            .max_by_key(|&(_, v)| v.bandwidth)
+            .or_else(|| candidates().min_by_key(|&(_, v)| v.bandwidth))
+            .map(|(i, _)| i)
+            .expect("Never fails");
+        let variant_index =
+            Self::preferred_variant(&variants, variant_index).unwrap_or(variant_index);
+        log!(
+            config,
+            Info,
+            "Queued the next master playlist {}: variant={}",
+            url,
+            variant_index
+        );
+        let variant_url = variants[variant_index].url.clone();
+        let next = NextMaster {
+            variants,
+            variant_index,
+            renditions,
+        };
+        self.media_playlist_handler
+            .queue_next(variant_url, None, Some(Box::new(next)));
+        Ok(())
     }
 
     pub fn handle_data(
@@ -43,14 +362,1455 @@ impl MasterPlaylistHandler {
         action_id: ActionId,
         data: &[u8],
         fetch_duration_ms: u32,
+        final_url: Option<&Url>,
     ) -> Result<()> {
+        if let Some(kind) = self.sub_loader(action_id) {
+            let is_updated = self.sub_loader_mut(kind)
+                .is_some_and(|l| l.handle_data(action_id, data, fetch_duration_ms));
+            self.sub_loader_updated(kind, is_updated);
+            return Ok(());
+        }
         track!(
             self.media_playlist_handler
-                .handle_data(action_id, data, fetch_duration_ms)
-        )
+                .handle_data(action_id, data, fetch_duration_ms, final_url)
+        )?;
+        self.sync_audio_rendition();
+        self.check_pathway_failure();
+        self.check_variant_failure();
+        Ok(())
+    }
+
+    /// Handles the response headers of the fetch requested by `action_id`.
+    ///
+    /// Only those of the fetches of the media playlist handler are read;
+    /// the others are ignored by it.
+    pub fn handle_response_headers(
+        &mut self,
+        action_id: ActionId,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        track!(self.media_playlist_handler.handle_response_headers(action_id, headers))
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) -> Result<()> {
+        if let Some(kind) = self.sub_loader(action_id) {
+            if let Some(loader) = self.sub_loader_mut(kind) {
+                loader.handle_data_chunk(action_id, chunk);
+            }
+            return Ok(());
+        }
+        track!(self.media_playlist_handler.handle_data_chunk(action_id, chunk))
+    }
+
+    pub fn handle_data_end(
+        &mut self,
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        if let Some(kind) = self.sub_loader(action_id) {
+            let is_updated = self.sub_loader_mut(kind)
+                .is_some_and(|l| l.handle_data_end(action_id, fetch_duration_ms));
+            self.sub_loader_updated(kind, is_updated);
+            return Ok(());
+        }
+        track!(
+            self.media_playlist_handler
+                .handle_data_end(action_id, fetch_duration_ms, final_url)
+        )?;
+        self.sync_audio_rendition();
+        self.check_pathway_failure();
+        self.check_variant_failure();
+        Ok(())
     }
 
     pub fn handle_timeout(&mut self, action_id: ActionId) -> Result<()> {
-        track!(self.media_playlist_handler.handle_timeout(action_id))
+        if let Some(kind) = self.sub_loader(action_id) {
+            let is_due = self.sub_loader_mut(kind)
+                .is_some_and(|l| l.handle_timeout(action_id));
+            if is_due {
+                self.reload_sub_loader(kind);
+            }
+            self.sub_loader_updated(kind, false);
+            return Ok(());
+        }
+        track!(self.media_playlist_handler.handle_timeout(action_id))?;
+        self.sync_audio_rendition();
+        self.check_variant_failure();
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let variant = &self.variants[self.variant_index];
+        Stats {
+            bitrate: Some(variant.bandwidth),
+            variant_index: Some(self.variant_index),
+            variant_switches: self.variant_switches.clone(),
+            pathway: self.steering.as_ref().map(|_| variant.pathway.clone()),
+            codecs: self.codecs(),
+            excluded_variants: self.variants
+                .iter()
+                .enumerate()
+                .filter(|&(_, v)| v.is_excluded)
+                .map(|(i, _)| i)
+                .collect(),
+            blacklisted_variants: self.variants
+                .iter()
+                .enumerate()
+                .filter(|&(_, v)| v.is_blacklisted)
+                .map(|(i, _)| i)
+                .collect(),
+            audio_rendition: self.audio_rendition().cloned(),
+            subtitle_rendition: self.subtitle_rendition().cloned(),
+            forced_subtitle_rendition: self.forced_subtitle_rendition().cloned(),
+            image_stream: self.image_stream.as_ref().map(|l| l.stream().clone()),
+            renditions: self.renditions.to_vec(),
+            ..self.media_playlist_handler.stats()
+        }
+    }
+
+    pub fn set_playback_position(&mut self, position: Duration) {
+        self.media_playlist_handler.set_playback_position(position);
+    }
+
+    /// Locks the played variant until `locked` is `false` or the end of the next ad break,
+    /// deferring the switches requested by the steering server.
+    ///
+    /// Pathway failovers are not deferred, since the locked variant has failed.
+    pub fn lock_variant(&mut self, locked: bool) {
+        log!(
+            self.media_playlist_handler.config(),
+            Info,
+            "{} the variant {}",
+            if locked { "Locked" } else { "Unlocked" },
+            self.variant_index
+        );
+        self.media_playlist_handler.lock_variant(locked);
+        if !locked {
+            self.apply_steering();
+        }
+    }
+
+    /// Replaces `RenditionConfig::audio_languages`, selecting the audio rendition
+    /// (and the forced subtitles) of the played variant again.
+    pub fn set_audio_languages(&mut self, languages: Vec<String>) {
+        self.media_playlist_handler.set_audio_languages(languages);
+        self.log_renditions();
+        self.update_forced_subtitles();
+        self.update_audio_rendition();
+    }
+
+    /// Enters or leaves the low-power mode, switching to the variant of the lowest bandwidth
+    /// of the pathway and back to the previous one (see `LowPowerConfig::lowest_variant`).
+    ///
+    /// The variant is kept while it is locked or during trick play.
+    pub fn set_low_power(&mut self, enabled: bool) {
+        let config = self.media_playlist_handler.config();
+        if config.low_power.enabled == enabled {
+            return;
+        }
+        let is_switching = config.low_power.lowest_variant
+            && self.trick_play.is_none()
+            && !self.media_playlist_handler.is_variant_locked()
+            && !self.media_playlist_handler.is_suspended();
+        self.media_playlist_handler.set_low_power(enabled);
+        if !enabled {
+            self.resume_prefetch();
+        }
+        if !is_switching {
+            return;
+        }
+        let to = if enabled {
+            Some(Self::lowest_variant(&self.variants, self.variant_index))
+        } else {
+            self.variant_before_low_power()
+        };
+        if let Some(to) = to.filter(|&to| to != self.variant_index) {
+            log!(
+                self.media_playlist_handler.config(),
+                Info,
+                "Switched the variant for the low-power mode: {} -> {}",
+                self.variant_index,
+                to
+            );
+            self.switch_variant(to, SwitchReason::LowPower);
+        }
+    }
+
+    /// Halts the fetches of the media playlist until `resume`
+    /// (see `MediaPlaylistHandler::suspend`), deferring the steering reloads and switches.
+    pub fn suspend(&mut self) {
+        self.media_playlist_handler.suspend();
+    }
+
+    pub fn resume(&mut self) {
+        self.media_playlist_handler.resume();
+        let throughput_kbps = self.media_playlist_handler.stats().throughput_kbps;
+        let pathway = &self.variants[self.variant_index].pathway;
+        if let Some(steering) = self.steering.as_mut().filter(|s| s.is_idle()) {
+            steering.request(pathway, throughput_kbps);
+        }
+        self.apply_steering();
+        self.resume_prefetch();
+        self.sync_audio_rendition();
+    }
+
+    /// Returns the variant of the current pathway equivalent to the one played before
+    /// the last switch to the lowest variant, unless the variants have been replaced since.
+    fn variant_before_low_power(&self) -> Option<usize> {
+        let switch = self.variant_switches
+            .iter()
+            .rev()
+            .take_while(|s| s.from.is_some())
+            .find(|s| s.reason == SwitchReason::LowPower)?;
+        let from = switch.from?;
+        if self.variants[switch.to].bandwidth >= self.variants[from].bandwidth {
+            // Already switched back.
+            return None;
+        }
+        let pathways = self.variants
+            .iter()
+            .map(|v| v.pathway.clone())
+            .collect::<Vec<_>>();
+        let pathway = &self.variants[self.variant_index].pathway;
+        steering::equivalent_variant(&pathways, from, pathway)
+            .map(|i| Self::substitute_variant(&self.variants, i))
+    }
+
+    pub fn latency_estimator(&self) -> &LatencyEstimator {
+        self.media_playlist_handler.latency_estimator()
+    }
+
+    pub fn keyframe_index(&self) -> &KeyframeIndex {
+        self.media_playlist_handler.keyframe_index()
+    }
+
+    pub fn timeline(&self) -> Option<&Timeline> {
+        self.media_playlist_handler.timeline()
+    }
+
+    /// Returns the tile of the image stream showing `position` (see `ImageStreamLoader`).
+    pub fn image_tile_at(&self, position: Duration) -> Option<ImageTile> {
+        self.image_stream.as_ref().and_then(|l| l.tile_at(position))
+    }
+
+    pub fn date_ranges(&self) -> &DateRangeTimeline {
+        self.media_playlist_handler.date_ranges()
+    }
+
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        self.media_playlist_handler.set_buffer_length(buffer_length);
+        let next_sequence = self.media_playlist_handler.next_sequence();
+        let is_paused = self.is_prefetch_paused();
+        if let Some(prefetcher) = self.playlist_prefetch.as_mut() {
+            prefetcher.set_buffer_length(buffer_length);
+            if !is_paused {
+                prefetcher.warm_up(next_sequence);
+            }
+        }
+        self.sync_audio_rendition();
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        if let Some(steering) = self.steering.as_mut() {
+            steering.stop();
+        }
+        if let Some(loader) = self.forced_subtitles.as_mut() {
+            loader.stop();
+        }
+        if let Some(loader) = self.audio.as_mut() {
+            loader.stop();
+        }
+        if let Some(loader) = self.image_stream.as_mut() {
+            loader.stop();
+        }
+        if let Some(prefetcher) = self.playlist_prefetch.as_mut() {
+            prefetcher.stop();
+        }
+        if let Some(trick_play) = self.trick_play.as_mut() {
+            trick_play.stop();
+        }
+        self.media_playlist_handler.stop();
+    }
+
+    /// Starts (or restarts) the trick play, stopping the normal playback.
+    pub fn start_trick_play(&mut self, rate: f64, position: Duration) -> Result<()> {
+        track_assert!(
+            rate.is_finite() && rate != 0.0,
+            ErrorKind::InvalidInput,
+            "rate={}",
+            rate
+        );
+        let url = track_assert_some!(
+            self.variants[self.variant_index].i_frame_url.clone(),
+            ErrorKind::InvalidInput,
+            "No I-frame playlists"
+        );
+        match self.trick_play.take() {
+            Some(mut previous) => {
+                previous.stop();
+                while let Some(action) = previous.next_action() {
+                    self.action_queue.push_back(action);
+                }
+            }
+            None => {
+                // The stopped handler still emits its aborts and events, but its segments
+                // are not appended during trick play.
+                self.media_playlist_handler.stop();
+                let handler = &mut self.media_playlist_handler;
+                for segment in self.segment_queue.drain(..) {
+                    handler.recycle_segment(segment.data);
+                }
+                while let Some(segment) = handler.next_segment() {
+                    handler.recycle_segment(segment.data);
+                }
+            }
+        }
+        let config = self.media_playlist_handler.config();
+        let handler = TrickPlayHandler::new(config, url, rate, position);
+        self.trick_play = Some(Box::new(handler));
+        Ok(())
+    }
+
+    /// Leaves the trick play, and resumes the variant being played from `position`.
+    pub fn stop_trick_play(&mut self, position: Duration) -> Result<()> {
+        let mut trick_play = track_assert_some!(
+            self.trick_play.take(),
+            ErrorKind::InvalidInput,
+            "Not in trick play"
+        );
+        trick_play.stop();
+        while let Some(action) = trick_play.next_action() {
+            self.action_queue.push_back(action);
+        }
+        while let Some(event) = trick_play.next_event() {
+            self.event_queue.push_back(event);
+        }
+
+        let config = self.media_playlist_handler.config().clone();
+        log!(config, Info, "Resumes the normal playback at {:?}", position);
+        let action_factory = ActionFactory::new(self.next_stream_id(), &config);
+        let mut handler = MediaPlaylistHandler::new(
+            &config,
+            action_factory,
+            ResourceCache::new(),
+            SegmentHistory::new(),
+            self.media_playlist_handler.trace().clone(),
+            self.variants[self.variant_index].url.clone(),
+        );
+        handler.start_at(position);
+        handler.inherit_refreshed_token(&self.media_playlist_handler);
+        handler.lock_variant(self.media_playlist_handler.is_variant_locked());
+        handler.reinitialize(ReinitReason::TrickPlayEnded);
+        if let Some((url, m3u8, master)) = self.media_playlist_handler.take_next() {
+            handler.queue_next(url, m3u8, master);
+        }
+        handler.inherit_date_ranges(&mut self.media_playlist_handler);
+        handler.set_demuxed(self.is_merging_audio());
+        let mut previous = mem::replace(&mut self.media_playlist_handler, handler);
+        while let Some(action) = previous.next_action() {
+            self.action_queue.push_back(action);
+        }
+        while let Some(event) = previous.next_event() {
+            self.event_queue.push_back(event);
+        }
+        self.resume_prefetch();
+        Ok(())
+    }
+
+    pub fn handle_fetch_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        if let Some(kind) = self.sub_loader(action_id) {
+            if let Some(loader) = self.sub_loader_mut(kind) {
+                loader.handle_failure(action_id, FailureClass::Transient, "timed out");
+            }
+            self.sub_loader_updated(kind, false);
+            return Ok(());
+        }
+        track!(self.media_playlist_handler.handle_fetch_timeout(action_id))?;
+        self.sync_audio_rendition();
+        self.check_pathway_failure();
+        self.check_variant_failure();
+        Ok(())
+    }
+
+    pub fn handle_error(&mut self, action_id: ActionId, status: u16) -> Result<()> {
+        if let Some(kind) = self.sub_loader(action_id) {
+            if let Some(loader) = self.sub_loader_mut(kind) {
+                let class = FailureClass::from_status(status);
+                loader.handle_failure(action_id, class, &format!("status={}", status));
+            }
+            self.sub_loader_updated(kind, false);
+            return Ok(());
+        }
+        track!(self.media_playlist_handler.handle_error(action_id, status))?;
+        self.sync_audio_rendition();
+        self.check_pathway_failure();
+        self.check_variant_failure();
+        Ok(())
+    }
+
+    /// Marks the variants beyond `PlayerConfig::device`, unless all of them are,
+    /// and those enhanced by the supported supplemental codecs.
+    fn exclude_variants(config: &PlayerConfig, variants: &mut [Variant]) {
+        let supported = &config.device.supplemental_codecs;
+        for variant in variants.iter_mut() {
+            variant.is_enhanced =
+                codecs::find_supported(&variant.supplemental_codecs, supported).is_some();
+        }
+
+        let max_frame_rate = config.device.max_frame_rate;
+        let is_excluded = |v: &Variant| match (v.frame_rate, max_frame_rate) {
+            (Some(f), Some(max)) => f > max,
+            _ => false,
+        };
+        if variants.iter().all(is_excluded) {
+            log!(
+                config,
+                Warn,
+                "All the variants exceed the device capabilities; none is excluded"
+            );
+            return;
+        }
+        for variant in variants.iter_mut() {
+            variant.is_excluded = is_excluded(variant);
+        }
+    }
+
+    /// Returns `index` if the variant is neither excluded nor blacklisted, and otherwise the one
+    /// of the same pathway with the highest bandwidth up to its own (or the lowest one).
+    ///
+    /// Among variants of similar bandwidths, the enhanced ones and then the one with
+    /// the highest `SCORE` are preferred.
+    fn substitute_variant(variants: &[Variant], index: usize) -> usize {
+        let requested = &variants[index];
+        if requested.is_playable() {
+            return index;
+        }
+        let candidates = variants
+            .iter()
+            .enumerate()
+            .filter(|&(_, v)| v.is_playable())
+            .filter(|&(_, v)| v.pathway == requested.pathway)
+            .collect::<Vec<_>>();
+        let candidates = if candidates.is_empty() {
+            variants
+                .iter()
+                .enumerate()
+                .filter(|&(_, v)| v.is_playable())
+                .collect()
+        } else {
+            candidates
+        };
+        let best = match candidates
+            .iter()
+            .filter(|&&(_, v)| v.bandwidth <= requested.bandwidth)
+            .max_by_key(|&&(_, v)| v.bandwidth)
+            .or_else(|| candidates.iter().min_by_key(|&&(_, v)| v.bandwidth))
+        {
+            None => return index,
+            Some(&(_, v)) => v,
+        };
+        let lower = best.bandwidth as f64 * (1.0 - SIMILAR_BANDWIDTH_RATIO);
+        let upper = cmp::max(best.bandwidth, requested.bandwidth);
+        candidates
+            .iter()
+            .filter(|&&(_, v)| v.bandwidth as f64 >= lower && v.bandwidth <= upper)
+            .max_by(|&&(_, a), &&(_, b)| Self::compare_preference(a, b))
+            .map_or(index, |&(i, _)| i)
+    }
+
+    /// Returns the variant of the lowest bandwidth of the pathway of the `index`-th one,
+    /// except for the excluded and blacklisted ones.
+    fn lowest_variant(variants: &[Variant], index: usize) -> usize {
+        let pathway = &variants[index].pathway;
+        variants
+            .iter()
+            .enumerate()
+            .filter(|&(_, v)| v.is_playable() && v.pathway == *pathway)
+            .min_by_key(|&(_, v)| v.bandwidth)
+            .map_or(index, |(i, _)| i)
+    }
+
+    /// Returns the variant of the pathway of the `index`-th one with a similar bandwidth
+    /// which is enhanced or has a higher `SCORE` (see `compare_rank`), if any.
+    fn preferred_variant(variants: &[Variant], index: usize) -> Option<usize> {
+        let requested = &variants[index];
+        let tolerance = requested.bandwidth as f64 * SIMILAR_BANDWIDTH_RATIO;
+        variants
+            .iter()
+            .enumerate()
+            .filter(|&(_, v)| v.is_playable() && v.pathway == requested.pathway)
+            .filter(|&(_, v)| (v.bandwidth as f64 - requested.bandwidth as f64).abs() <= tolerance)
+            .filter(|&(_, v)| Self::compare_rank(v, requested) == Ordering::Greater)
+            .max_by(|&(_, a), &(_, b)| Self::compare_preference(a, b))
+            .map(|(i, _)| i)
+    }
+
+    /// Orders variants by the enhancement, `SCORE`, and bandwidth.
+    fn compare_preference(a: &Variant, b: &Variant) -> Ordering {
+        Self::compare_rank(a, b).then(a.bandwidth.cmp(&b.bandwidth))
+    }
+
+    /// Orders variants by the enhancement and `SCORE`.
+    fn compare_rank(a: &Variant, b: &Variant) -> Ordering {
+        let score = |v: &Variant| v.score.unwrap_or(f64::MIN);
+        a.is_enhanced
+            .cmp(&b.is_enhanced)
+            .then(score(a).partial_cmp(&score(b)).unwrap_or(Ordering::Equal))
+    }
+
+    /// Returns the `CODECS` of the played variant, enhanced by the supported
+    /// supplemental codec if any.
+    fn codecs(&self) -> Option<String> {
+        let variant = &self.variants[self.variant_index];
+        let codecs = variant.codecs.as_ref()?;
+        let supported = &self.media_playlist_handler.config().device.supplemental_codecs;
+        match codecs::find_supported(&variant.supplemental_codecs, supported) {
+            None => Some(codecs.clone()),
+            Some(supplemental) => Some(codecs::enhance(codecs, supplemental)),
+        }
+    }
+
+    /// Returns the rendition of the `AUDIO` group of the played variant.
+    fn audio_rendition(&self) -> Option<&Rendition> {
+        let group_id = self.variants[self.variant_index].audio.as_ref()?;
+        let config = &self.media_playlist_handler.config().renditions;
+        rendition::select(
+            &self.renditions,
+            RenditionType::Audio,
+            group_id,
+            &config.audio_languages,
+            &config.audio_characteristics,
+            true,
+        ).map(|i| &self.renditions[i])
+    }
+
+    /// Returns the rendition of the `SUBTITLES` group of the played variant, if enabled.
+    fn subtitle_rendition(&self) -> Option<&Rendition> {
+        let group_id = self.variants[self.variant_index].subtitles.as_ref()?;
+        let config = &self.media_playlist_handler.config().renditions;
+        rendition::select(
+            &self.renditions,
+            RenditionType::Subtitles,
+            group_id,
+            &config.subtitle_languages,
+            &config.subtitle_characteristics,
+            false,
+        ).map(|i| &self.renditions[i])
+    }
+
+    /// Returns the forced subtitle rendition to be loaded for the selected audio rendition.
+    fn forced_subtitle_rendition(&self) -> Option<&Rendition> {
+        let config = &self.media_playlist_handler.config().renditions;
+        if !config.forced_subtitles {
+            return None;
+        }
+        let variant = &self.variants[self.variant_index];
+        let group_id = variant.subtitles.as_ref()?;
+        let audio = self.audio_rendition()?;
+        let original_language = config.original_language.as_deref().or_else(|| {
+            let audio_group_id = variant.audio.as_ref()?;
+            rendition::default_language(&self.renditions, audio_group_id)
+        })?;
+        rendition::select_forced(&self.renditions, group_id, audio, original_language)
+            .map(|i| &self.renditions[i])
+    }
+
+    /// Starts loading the forced subtitles of the played variant,
+    /// replacing the loader of the previous one if the rendition differs.
+    fn update_forced_subtitles(&mut self) {
+        let target = self.forced_subtitle_rendition().and_then(|r| {
+            let url = Url::parse(r.uri.as_ref()?).ok()?;
+            Some((url, r.language.clone()))
+        });
+        let current = self.forced_subtitles.as_ref().map(|l| l.url());
+        if current == target.as_ref().map(|t| &t.0) {
+            return;
+        }
+        let mut last_sequence = None;
+        if let Some(mut loader) = self.forced_subtitles.take() {
+            loader.stop();
+            while let Some(action) = loader.next_action() {
+                self.action_queue.push_back(action);
+            }
+            while let Some(event) = loader.next_event() {
+                self.event_queue.push_back(event);
+            }
+            last_sequence = loader.last_sequence();
+        }
+        if let Some((url, language)) = target {
+            let config = self.media_playlist_handler.config();
+            log!(config, Info, "Loading the forced subtitles: {}", url);
+            let mut loader = SubtitleLoader::new(config, url, language);
+            loader.resume_after(last_sequence);
+            self.forced_subtitles = Some(Box::new(loader));
+        }
+    }
+
+    /// Starts following the playlist of the selected audio rendition if it has a `URI`,
+    /// replacing the loader of the previous one if the rendition differs.
+    fn update_audio_rendition(&mut self) {
+        let config = self.media_playlist_handler.config();
+        let target = self.audio_rendition()
+            .filter(|_| config.renditions.audio_playlists)
+            .and_then(|r| Some((Url::parse(r.uri.as_ref()?).ok()?, r.clone())));
+        let current = self.audio.as_ref().map(|l| l.url());
+        if current == target.as_ref().map(|t| &t.0) {
+            self.media_playlist_handler.set_demuxed(self.is_merging_audio());
+            return;
+        }
+        if let Some(mut loader) = self.audio.take() {
+            loader.stop();
+            while let Some(action) = loader.next_action() {
+                self.action_queue.push_back(action);
+            }
+            while let Some(event) = loader.next_event() {
+                self.event_queue.push_back(event);
+            }
+        }
+        if let Some((url, rendition)) = target {
+            let config = self.media_playlist_handler.config();
+            log!(config, Info, "Loading the audio rendition {:?}: {}", rendition.name, url);
+            self.audio = Some(Box::new(AudioRenditionLoader::new(config, rendition, url)));
+        }
+        self.media_playlist_handler.set_demuxed(self.is_merging_audio());
+        self.sync_audio_rendition();
+    }
+
+    /// Returns `true` if the audio rendition is merged into the video segments.
+    fn is_merging_audio(&self) -> bool {
+        self.audio.as_ref().is_some_and(|l| !l.is_rejected())
+    }
+
+    /// Aligns the loaded playlist of the audio rendition with the next segment of the video,
+    /// requests the audio of the fetched one, and merges it once fetched.
+    ///
+    /// The video is played alone once the rendition is rejected.
+    fn sync_audio_rendition(&mut self) {
+        if self.audio.as_ref().is_some_and(|l| l.is_rejected()) {
+            self.media_playlist_handler.set_demuxed(false);
+            return;
+        }
+        let loader = match self.audio.as_mut() {
+            None => return,
+            Some(loader) => loader,
+        };
+        if loader.is_unaligned() {
+            if let Some(point) = self.media_playlist_handler.switch_point() {
+                loader.align(&point);
+            }
+        }
+        while let Some(span) = self.media_playlist_handler.audio_span() {
+            loader.request_audio(&span);
+            if !self.media_playlist_handler.is_awaiting_audio() {
+                break;
+            }
+            match loader.take_audio(&span) {
+                None => break,
+                Some((sequence, audio)) => {
+                    self.media_playlist_handler.attach_audio(sequence, &audio);
+                }
+            }
+        }
+    }
+
+    /// Returns the variants which the next switch may take: the substitute played if the current
+    /// one is blacklisted (see `check_variant_failure`), and the lowest one of the low-power mode.
+    fn switch_targets(&mut self) -> (Option<usize>, Option<usize>) {
+        let config = self.media_playlist_handler.config();
+        let (is_blacklisting, is_lowering) =
+            (config.variant_blacklist.enabled, config.low_power.lowest_variant);
+        let current = self.variant_index;
+        let failover = if is_blacklisting {
+            self.variants[current].is_blacklisted = true;
+            let to = Self::substitute_variant(&self.variants, current);
+            self.variants[current].is_blacklisted = false;
+            Some(to)
+        } else {
+            None
+        };
+        let lowest = Some(Self::lowest_variant(&self.variants, current))
+            .filter(|_| is_lowering)
+            .filter(|&to| Some(to) != failover);
+        let other = |i: &usize| self.variants[*i].url != self.variants[current].url;
+        (failover.filter(&other), lowest.filter(&other))
+    }
+
+    /// Returns the loader which the action `action_id` is routed to, or `None` if it is one of
+    /// the media playlist handler.
+    ///
+    /// During trick play, the actions of the media playlist handler go to the trick-play handler,
+    /// which ignores them.
+    fn sub_loader(&self, action_id: ActionId) -> Option<SubLoaderKind> {
+        if ContentSteering::is_own_action(action_id) {
+            Some(SubLoaderKind::Steering)
+        } else if SubtitleLoader::is_own_action(action_id) {
+            Some(SubLoaderKind::ForcedSubtitles)
+        } else if AudioRenditionLoader::is_own_action(action_id) {
+            Some(SubLoaderKind::Audio)
+        } else if ImageStreamLoader::is_own_action(action_id) {
+            Some(SubLoaderKind::ImageStream)
+        } else if PlaylistPrefetcher::is_own_action(action_id) {
+            Some(SubLoaderKind::PlaylistPrefetch)
+        } else if self.trick_play.is_some() {
+            Some(SubLoaderKind::TrickPlay)
+        } else {
+            None
+        }
+    }
+
+    fn sub_loader_mut(&mut self, kind: SubLoaderKind) -> Option<&mut dyn SubLoader> {
+        match kind {
+            SubLoaderKind::Steering => self.steering.as_mut().map(|l| &mut **l as _),
+            SubLoaderKind::ForcedSubtitles => self.forced_subtitles.as_mut().map(|l| &mut **l as _),
+            SubLoaderKind::Audio => self.audio.as_mut().map(|l| &mut **l as _),
+            SubLoaderKind::ImageStream => self.image_stream.as_mut().map(|l| &mut **l as _),
+            SubLoaderKind::PlaylistPrefetch => {
+                self.playlist_prefetch.as_mut().map(|l| &mut **l as _)
+            }
+            SubLoaderKind::TrickPlay => self.trick_play.as_mut().map(|l| &mut **l as _),
+        }
+    }
+
+    /// Applies the state of the loader `kind` after it has handled an action.
+    fn sub_loader_updated(&mut self, kind: SubLoaderKind, is_updated: bool) {
+        match kind {
+            SubLoaderKind::Steering if is_updated => self.apply_steering(),
+            SubLoaderKind::Audio => self.sync_audio_rendition(),
+            _ => {}
+        }
+    }
+
+    /// Makes the reload of the loader `kind` which has become due.
+    fn reload_sub_loader(&mut self, kind: SubLoaderKind) {
+        match kind {
+            SubLoaderKind::Steering => {
+                // The reload is left due until `resume`.
+                if self.media_playlist_handler.is_suspended() {
+                    return;
+                }
+                let throughput_kbps = self.media_playlist_handler.stats().throughput_kbps;
+                let pathway = &self.variants[self.variant_index].pathway;
+                if let Some(steering) = self.steering.as_mut() {
+                    steering.request(pathway, throughput_kbps);
+                }
+            }
+            SubLoaderKind::PlaylistPrefetch => {
+                // The reload is left due until the pause ends.
+                if self.is_prefetch_paused() {
+                    return;
+                }
+                if let Some(prefetcher) = self.playlist_prefetch.as_mut() {
+                    prefetcher.request();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns `true` if the prefetching is paused, i.e., while the media playlist is suspended,
+    /// in the low-power mode or during trick play.
+    fn is_prefetch_paused(&self) -> bool {
+        self.media_playlist_handler.is_suspended()
+            || self.media_playlist_handler.config().low_power.enabled
+            || self.trick_play.is_some()
+    }
+
+    /// Prefetches the playlists of the variants which the next switch may take.
+    fn update_prefetch_targets(&mut self) {
+        if self.playlist_prefetch.is_none() {
+            return;
+        }
+        let (failover, lowest) = self.switch_targets();
+        let url = |i: usize| self.variants[i].url.clone();
+        let (failover, lowest) = (failover.map(url), lowest.map(url));
+        let is_paused = self.is_prefetch_paused();
+        if let Some(prefetcher) = self.playlist_prefetch.as_mut() {
+            prefetcher.set_targets(failover, lowest, is_paused);
+        }
+    }
+
+    /// Restarts the reloads of the prefetched playlists left due during a pause.
+    fn resume_prefetch(&mut self) {
+        if self.is_prefetch_paused() {
+            return;
+        }
+        if let Some(prefetcher) = self.playlist_prefetch.as_mut().filter(|p| p.is_idle()) {
+            prefetcher.request();
+        }
+    }
+
+    fn log_renditions(&self) {
+        let name = |r: Option<&Rendition>| r.map(|r| r.name.clone());
+        log!(
+            self.media_playlist_handler.config(),
+            Info,
+            "Selected renditions: audio={:?}, subtitles={:?}",
+            name(self.audio_rendition()),
+            name(self.subtitle_rendition())
+        );
+    }
+
+    /// Returns the pathways of the variants in the order of their first appearance.
+    fn pathways(&self) -> Vec<String> {
+        let mut pathways: Vec<String> = Vec::new();
+        for variant in &self.variants {
+            if !pathways.contains(&variant.pathway) {
+                pathways.push(variant.pathway.clone());
+            }
+        }
+        pathways
+    }
+
+    /// Switches to the pathway preferred by the steering server, if it differs
+    /// and the variant is not locked.
+    fn apply_steering(&mut self) {
+        if self.media_playlist_handler.is_variant_locked()
+            || self.media_playlist_handler.is_suspended()
+        {
+            return;
+        }
+        let available = self.pathways();
+        let selected = self.steering.as_ref().and_then(|s| s.select(&available));
+        if let Some(pathway) = selected {
+            self.switch_pathway(pathway, SwitchReason::Steering);
+        }
+    }
+
+    /// Fails over to another pathway if the media playlist has been given up.
+    fn check_pathway_failure(&mut self) {
+        if !self.media_playlist_handler.is_playlist_failed() {
+            return;
+        }
+        let available = self.pathways();
+        let current = &self.variants[self.variant_index].pathway;
+        let selected = self.steering.as_mut().and_then(|s| {
+            s.penalize(current);
+            s.select(&available)
+        });
+        if let Some(pathway) = selected {
+            self.switch_pathway(pathway, SwitchReason::PathwayFailover);
+        }
+    }
+
+    /// Blacklists the played variant and switches to another one if it has failed,
+    /// and switches back to the variant selected before once its cooldown has expired.
+    fn check_variant_failure(&mut self) {
+        let config = self.media_playlist_handler.config();
+        if !config.variant_blacklist.enabled || self.trick_play.is_some() {
+            return;
+        }
+        let now = config.hooks.now();
+        for (i, variant) in self.variants.iter_mut().enumerate() {
+            let is_expired = variant
+                .blacklisted_until
+                .is_some_and(|until| now.is_some_and(|now| now >= until));
+            if variant.is_blacklisted && is_expired {
+                log!(config, Info, "The cooldown of the blacklisted variant {} has expired", i);
+                variant.is_blacklisted = false;
+                variant.blacklisted_until = None;
+            }
+        }
+
+        if let Some(reason) = self.media_playlist_handler.variant_failure() {
+            let from = self.variant_index;
+            let count = self.variants[from].blacklist_count + 1;
+            let cooldown = config.variant_blacklist.cooldown(count);
+            self.variants[from].is_blacklisted = true;
+            let to = Self::substitute_variant(&self.variants, from);
+            if to == from {
+                // No other variant to play; the failure is left to the host.
+                self.variants[from].is_blacklisted = false;
+                return;
+            }
+            log!(
+                config,
+                Warn,
+                "Blacklisted the variant {} for {:?} ({}); {} is played instead",
+                from,
+                cooldown,
+                reason,
+                to
+            );
+            let variant = &mut self.variants[from];
+            variant.blacklisted_until = now.map(|now| now + cooldown);
+            variant.blacklist_count = count;
+            self.switch_variant(to, SwitchReason::VariantFailover);
+            self.event_queue.push_back(Event::VariantBlacklisted {
+                variant: from,
+                reason,
+                cooldown_ms: cooldown.as_millis() as u32,
+            });
+            return;
+        }
+
+        if self.media_playlist_handler.is_variant_locked()
+            || self.media_playlist_handler.is_suspended()
+        {
+            return;
+        }
+        let to = Self::substitute_variant(&self.variants, self.variant_before_failover());
+        if to != self.variant_index {
+            log!(
+                config,
+                Info,
+                "Switched back from the variant {} to {} after the blacklist cooldown",
+                self.variant_index,
+                to
+            );
+            self.switch_variant(to, SwitchReason::BlacklistExpired);
+        }
+    }
+
+    /// Returns the variant selected before the switches due to blacklisting.
+    fn variant_before_failover(&self) -> usize {
+        self.variant_switches
+            .iter()
+            .rev()
+            .find(|s| {
+                !matches!(
+                    s.reason,
+                    SwitchReason::VariantFailover | SwitchReason::BlacklistExpired
+                )
+            })
+            .map_or(self.variant_index, |s| s.to)
+    }
+
+    /// Plays the variant of `pathway` equivalent to the current one (see `switch_variant`).
+    fn switch_pathway(&mut self, pathway: &str, reason: SwitchReason) {
+        let from_pathway = self.variants[self.variant_index].pathway.clone();
+        if pathway == from_pathway {
+            return;
+        }
+        let pathways = self.variants
+            .iter()
+            .map(|v| v.pathway.clone())
+            .collect::<Vec<_>>();
+        let to = match steering::equivalent_variant(&pathways, self.variant_index, pathway) {
+            None => return,
+            Some(x) => Self::substitute_variant(&self.variants, x),
+        };
+        log!(
+            self.media_playlist_handler.config(),
+            Info,
+            "Switched the pathway: {} -> {} (reason={:?})",
+            from_pathway,
+            pathway,
+            reason
+        );
+        self.switch_variant(to, reason);
+        self.event_queue.push_back(Event::PathwayChanged {
+            from: from_pathway,
+            to: pathway.to_owned(),
+        });
+    }
+
+    /// Plays the `to`-th variant, continuing from the next segment of the current one
+    /// (see `MediaPlaylistHandler::resume_at`), which starts with a new initialization segment.
+    ///
+    /// Segments already produced by the current handler are still served,
+    /// and its fatal errors are reported as non-fatal since the playback continues.
+    ///
+    /// The playlist and the next segment prefetched for the variant (if any) are used
+    /// instead of fetching them.
+    fn switch_variant(&mut self, to: usize, reason: SwitchReason) {
+        let config = self.media_playlist_handler.config().clone();
+        let action_factory = ActionFactory::new(self.next_stream_id(), &config);
+        let url = self.variants[to].url.clone();
+        let prefetched = self.playlist_prefetch.as_mut().and_then(|p| p.take(&url));
+        let warmed = self.playlist_prefetch
+            .as_mut()
+            .and_then(|p| p.take_segment(&url));
+        let trace = self.media_playlist_handler.trace().clone();
+        let mut handler = if prefetched.is_some() {
+            MediaPlaylistHandler::unloaded(
+                &config,
+                action_factory,
+                ResourceCache::new(),
+                SegmentHistory::new(),
+                trace,
+                url,
+            )
+        } else {
+            MediaPlaylistHandler::new(
+                &config,
+                action_factory,
+                ResourceCache::new(),
+                SegmentHistory::new(),
+                trace,
+                url,
+            )
+        };
+        handler.inherit_refreshed_token(&self.media_playlist_handler);
+        if let Some(point) = self.media_playlist_handler.switch_point() {
+            handler.resume_at(point);
+        }
+        handler.lock_variant(self.media_playlist_handler.is_variant_locked());
+        if self.media_playlist_handler.has_produced_output() {
+            handler.reinitialize(ReinitReason::VariantChanged);
+        }
+        if let Some((url, m3u8, master)) = self.media_playlist_handler.take_next() {
+            handler.queue_next(url, m3u8, master);
+        }
+        if let Some((resource, data, fetch_duration_ms)) = warmed {
+            handler.preload_segment(resource, data, fetch_duration_ms);
+        }
+        if let Some(m3u8) = prefetched {
+            log!(config, Debug, "Uses the prefetched playlist of the variant {}", to);
+            handler.load_playlist(&m3u8);
+        }
+        let mut previous = mem::replace(&mut self.media_playlist_handler, handler);
+        previous.stop();
+        while let Some(action) = previous.next_action() {
+            self.action_queue.push_back(action);
+        }
+        while let Some(mut event) = previous.next_event() {
+            if let Event::Error { ref mut fatal, .. } = event {
+                *fatal = false;
+            }
+            if let Event::SegmentLoaded { ref mut variant, .. } = event {
+                *variant = Some(self.variant_index);
+            }
+            self.event_queue.push_back(event);
+        }
+        while let Some(mut segment) = previous.next_segment() {
+            segment.info.variant = Some(self.variant_index);
+            self.segment_queue.push(segment);
+        }
+
+        let switch = VariantSwitch {
+            timestamp_ms: config.hooks.now().map(|t| t.as_secs_f64() * 1000.0),
+            from: Some(self.variant_index),
+            to,
+            bandwidth: self.variants[to].bandwidth,
+            reason,
+        };
+        let trace = self.media_playlist_handler.trace();
+        trace.record(|| TraceEntry::VariantSwitched {
+            switch: switch.clone(),
+        });
+        self.variant_switches.push(switch);
+        self.variant_index = to;
+        self.log_renditions();
+        self.update_forced_subtitles();
+        self.update_audio_rendition();
+        self.update_prefetch_targets();
+    }
+
+    /// Replaces the variants by those of the master playlist queued by `queue_next_master`,
+    /// once its media playlist has started.
+    fn start_next_master(&mut self) {
+        let next = match self.media_playlist_handler.take_started_master() {
+            None => return,
+            Some(next) => *next,
+        };
+        if let Some(mut steering) = self.steering.take() {
+            // The steering manifest belongs to the previous master playlist.
+            steering.stop();
+            while let Some(action) = steering.next_action() {
+                self.action_queue.push_back(action);
+            }
+        }
+        let switch = VariantSwitch {
+            timestamp_ms: self.media_playlist_handler
+                .config()
+                .hooks
+                .now()
+                .map(|t| t.as_secs_f64() * 1000.0),
+            from: None,
+            to: next.variant_index,
+            bandwidth: next.variants[next.variant_index].bandwidth,
+            reason: SwitchReason::Initial,
+        };
+        let trace = self.media_playlist_handler.trace();
+        trace.record(|| TraceEntry::VariantSwitched {
+            switch: switch.clone(),
+        });
+        self.variant_switches.push(switch);
+        self.variants = next.variants.into_boxed_slice();
+        self.variant_index = next.variant_index;
+        self.renditions = next.renditions.into_boxed_slice();
+        self.log_renditions();
+        self.update_forced_subtitles();
+        self.update_audio_rendition();
+        self.update_prefetch_targets();
+    }
+
+    /// Returns the stream ID of the next media playlist handler.
+    fn next_stream_id(&self) -> StreamId {
+        // IDs from `IMAGE_STREAM_ID` are reserved for the image stream, the audio rendition,
+        // the playlist prefetching, the source loading, the trick play, the subtitles
+        // and the steering.
+        (self.media_playlist_handler.stream_id() + 1) % IMAGE_STREAM_ID
+    }
+
+    /// Makes the loader of the image stream selected by `ThumbnailConfig::max_image_width`,
+    /// if the master playlist `m3u8` lists any.
+    fn image_stream_loader(
+        config: &PlayerConfig,
+        url: &Url,
+        m3u8: &str,
+    ) -> Result<Option<Box<ImageStreamLoader>>> {
+        let streams = track!(ImageStream::parse_all(url, m3u8))?;
+        let stream = match ImageStream::select(&streams, config.thumbnails.max_image_width) {
+            None => return Ok(None),
+            Some(stream) => stream.clone(),
+        };
+        log!(config, Info, "Loading the image stream: {}", stream.url);
+        let loader = track!(ImageStreamLoader::new(config, stream))?;
+        Ok(Some(Box::new(loader)))
+    }
+
+    /// Returns the I-frame playlist with the highest bandwidth up to `bandwidth`
+    /// (or the lowest one).
+    fn i_frame_url(i_frame_variants: &[IFrameVariant], bandwidth: u64) -> Option<&Url> {
+        i_frame_variants
+            .iter()
+            .filter(|v| v.bandwidth <= bandwidth)
+            .max_by_key(|v| v.bandwidth)
+            .or_else(|| i_frame_variants.iter().min_by_key(|v| v.bandwidth))
+            .map(|v| &v.url)
+    }
+
+    /// Makes `Action::Preconnect` for the origins referred to by the master playlist.
+    fn preconnect_actions(
+        config: &PlayerConfig,
+        action_factory: &mut ActionFactory,
+        url: &Url,
+        master_playlist: &MasterPlaylist,
+    ) -> VecDeque<Action> {
+        let uris = master_playlist
+            .stream_inf_tags()
+            .iter()
+            .map(|t| t.uri().as_ref())
+            .chain(master_playlist.media_tags().iter().filter_map(|t| t.uri()).map(|u| u.as_ref()))
+            .chain(master_playlist.i_fream_stream_inf_tags().iter().map(|t| t.uri().as_ref()))
+            .chain(master_playlist.session_key_tags().iter().map(|t| t.key().uri.as_ref()))
+            .chain(config.cdn_failover.base_urls.iter().map(|u| u.as_str()));
+
+        action_factory.mark_connected(url);
+        let mut actions = VecDeque::new();
+        for uri in uris {
+            if let Ok(uri) = Url::options().base_url(Some(url)).parse(uri) {
+                actions.extend(action_factory.preconnect(&uri));
+            }
+        }
+        actions
+    }
+}
+
+/// Loaders of a master playlist handler beside its media playlist handler
+/// (see `MasterPlaylistHandler::sub_loader`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubLoaderKind {
+    Steering,
+    ForcedSubtitles,
+    Audio,
+    ImageStream,
+    PlaylistPrefetch,
+    TrickPlay,
+}
+
+/// Handlers of the fetches and timers of a loader, which the master playlist handler
+/// routes the actions of the loader's own stream ID to.
+trait SubLoader {
+    /// Returns `true` if the fetched resource has updated the state to apply.
+    fn handle_data(&mut self, action_id: ActionId, data: &[u8], fetch_duration_ms: u32) -> bool;
+
+    fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]);
+
+    /// Returns `true` if the fetched resource has updated the state to apply.
+    fn handle_data_end(&mut self, action_id: ActionId, fetch_duration_ms: u32) -> bool;
+
+    /// Returns `true` if a reload has become due.
+    fn handle_timeout(&mut self, action_id: ActionId) -> bool;
+
+    fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str);
+}
+impl SubLoader for ContentSteering {
+    fn handle_data(&mut self, action_id: ActionId, data: &[u8], _: u32) -> bool {
+        ContentSteering::handle_data(self, action_id, data)
+    }
+    fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        ContentSteering::handle_data_chunk(self, action_id, chunk);
+    }
+    fn handle_data_end(&mut self, action_id: ActionId, _: u32) -> bool {
+        ContentSteering::handle_data_end(self, action_id)
+    }
+    fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+        ContentSteering::handle_timeout(self, action_id)
+    }
+    fn handle_failure(&mut self, action_id: ActionId, _: FailureClass, description: &str) {
+        ContentSteering::handle_failure(self, action_id, description);
+    }
+}
+impl SubLoader for PlaylistPrefetcher {
+    fn handle_data(&mut self, action_id: ActionId, data: &[u8], fetch_duration_ms: u32) -> bool {
+        PlaylistPrefetcher::handle_data(self, action_id, data, fetch_duration_ms);
+        false
+    }
+    fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        PlaylistPrefetcher::handle_data_chunk(self, action_id, chunk);
+    }
+    fn handle_data_end(&mut self, action_id: ActionId, fetch_duration_ms: u32) -> bool {
+        PlaylistPrefetcher::handle_data_end(self, action_id, fetch_duration_ms);
+        false
+    }
+    fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+        PlaylistPrefetcher::handle_timeout(self, action_id)
+    }
+    fn handle_failure(&mut self, action_id: ActionId, _: FailureClass, description: &str) {
+        PlaylistPrefetcher::handle_failure(self, action_id, description);
+    }
+}
+impl SubLoader for TrickPlayHandler {
+    fn handle_data(&mut self, action_id: ActionId, data: &[u8], fetch_duration_ms: u32) -> bool {
+        if Self::is_own_action(action_id) {
+            TrickPlayHandler::handle_data(self, action_id, data, fetch_duration_ms);
+        }
+        false
+    }
+    fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        if Self::is_own_action(action_id) {
+            TrickPlayHandler::handle_data_chunk(self, action_id, chunk);
+        }
+    }
+    fn handle_data_end(&mut self, action_id: ActionId, fetch_duration_ms: u32) -> bool {
+        if Self::is_own_action(action_id) {
+            TrickPlayHandler::handle_data_end(self, action_id, fetch_duration_ms);
+        }
+        false
+    }
+    fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+        if Self::is_own_action(action_id) {
+            TrickPlayHandler::handle_timeout(self, action_id);
+        }
+        false
+    }
+    fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        if Self::is_own_action(action_id) {
+            TrickPlayHandler::handle_failure(self, action_id, class, description);
+        }
+    }
+}
+
+/// Implements `SubLoader` for the loaders of renditions, which apply their state by themselves.
+macro_rules! impl_rendition_sub_loader {
+    ($($loader:ident),*) => {$(
+        impl SubLoader for $loader {
+            fn handle_data(&mut self, action_id: ActionId, data: &[u8], _: u32) -> bool {
+                $loader::handle_data(self, action_id, data);
+                false
+            }
+            fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+                $loader::handle_data_chunk(self, action_id, chunk);
+            }
+            fn handle_data_end(&mut self, action_id: ActionId, _: u32) -> bool {
+                $loader::handle_data_end(self, action_id);
+                false
+            }
+            fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+                $loader::handle_timeout(self, action_id);
+                false
+            }
+            fn handle_failure(
+                &mut self,
+                action_id: ActionId,
+                class: FailureClass,
+                description: &str,
+            ) {
+                $loader::handle_failure(self, action_id, class, description);
+            }
+        }
+    )*};
+}
+impl_rendition_sub_loader!(SubtitleLoader, AudioRenditionLoader, ImageStreamLoader);
+
+/// `EXT-X-I-FRAME-STREAM-INF` entry of a master playlist.
+#[derive(Debug)]
+struct IFrameVariant {
+    url: Url,
+    bandwidth: u64,
+}
+
+/// `EXT-X-STREAM-INF` entry of a master playlist.
+#[derive(Debug)]
+struct Variant {
+    url: Url,
+    bandwidth: u64,
+    pathway: String,
+
+    /// `AUDIO` and `SUBTITLES` group IDs.
+    audio: Option<String>,
+    subtitles: Option<String>,
+
+    frame_rate: Option<f64>,
+    score: Option<f64>,
+    codecs: Option<String>,
+    supplemental_codecs: Vec<SupplementalCodec>,
+
+    /// I-frame playlist used for the trick play of the variant.
+    i_frame_url: Option<Url>,
+
+    /// Excluded by `PlayerConfig::device`.
+    is_excluded: bool,
+
+    /// Has a supplemental codec supported by `PlayerConfig::device`.
+    is_enhanced: bool,
+
+    /// Blacklisted by `PlayerConfig::variant_blacklist` until `blacklisted_until`
+    /// (or indefinitely without `Hooks::clock`).
+    is_blacklisted: bool,
+    blacklisted_until: Option<Duration>,
+
+    /// Number of the times the variant has been blacklisted, by which the cooldown grows.
+    blacklist_count: u32,
+}
+impl Variant {
+    fn is_playable(&self) -> bool {
+        !self.is_excluded && !self.is_blacklisted
+    }
+}
+
+/// Master playlist queued by `MasterPlaylistHandler::queue_next_master`,
+/// kept along with its media playlist by `MediaPlaylistHandler::queue_next`.
+#[derive(Debug)]
+pub struct NextMaster {
+    variants: Vec<Variant>,
+    variant_index: usize,
+    renditions: Vec<Rendition>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::simulation::{Response, Simulation, SimulationRecord};
+    use super::super::ts_fixture;
+    use super::*;
+
+    const VOD: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nseg0.ts\n#EXT-X-ENDLIST\n";
+
+    /// Returns the paths of the fetched playlists, starting with the played one.
+    fn playlist_fetches(master: &str) -> Vec<String> {
+        playlist_fetches_with(PlayerConfig::default(), master)
+    }
+
+    fn playlist_fetches_with(config: PlayerConfig, master: &str) -> Vec<String> {
+        let mut sim = Simulation::new(config);
+        sim.respond("http://x/master.m3u8", Response::data(master));
+        for line in master.lines().filter(|l| l.ends_with(".m3u8")) {
+            sim.respond(&format!("http://x/{}", line), Response::data(VOD));
+        }
+        sim.play("http://x/master.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(1)).unwrap();
+        sim.records()
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Action {
+                    action: Action::FetchData { ref url, .. },
+                    ..
+                } => Some(url.path().to_owned()),
+                _ => None,
+            })
+            .filter(|path| path.ends_with(".m3u8"))
+            .collect()
+    }
+
+    #[test]
+    fn only_the_switch_targets_are_prefetched() {
+        let master = "#EXTM3U\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=250000\nlowest.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=500000\nlow.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=1000000\nmid.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=2000000\nhigh.m3u8\n";
+        let mut config = PlayerConfig {
+            variant_index: 2,
+            ..PlayerConfig::default()
+        };
+        config.playlist_prefetch.enabled = true;
+
+        // The failover substitute and the lowest variant of the low-power mode.
+        let mut fetches = playlist_fetches_with(config.clone(), master);
+        fetches.sort();
+        assert_eq!(fetches, ["/low.m3u8", "/lowest.m3u8", "/mid.m3u8"]);
+
+        config.variant_blacklist.enabled = false;
+        config.low_power.lowest_variant = false;
+        assert_eq!(playlist_fetches_with(config, master), ["/mid.m3u8"]);
+    }
+
+    #[test]
+    fn warmed_up_segments_are_appended_on_downswitches() {
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:1\n\
+                     #EXTINF:1,\nseg0.ts\n#EXTINF:1,\nseg1.ts\n#EXTINF:1,\nseg2.ts\n\
+                     #EXT-X-ENDLIST\n";
+        let mut config = PlayerConfig {
+            variant_index: 1,
+            ..PlayerConfig::default()
+        };
+        config.playlist_prefetch.enabled = true;
+        config.playlist_prefetch.warm_up_buffer_ms = Some(5000);
+        let mut sim = Simulation::new(config);
+        sim.respond(
+            "http://x/master.m3u8",
+            Response::data(
+                "#EXTM3U\n\
+                 #EXT-X-STREAM-INF:BANDWIDTH=500000\nlow/media.m3u8\n\
+                 #EXT-X-STREAM-INF:BANDWIDTH=1000000\nhigh/media.m3u8\n",
+            ),
+        );
+        for variant in &["low", "high"] {
+            sim.respond(&format!("http://x/{}/media.m3u8", variant), Response::data(media));
+            for seq in 0..3 {
+                // The played variant is slow enough to be switched from while fetching.
+                let latency = if *variant == "high" { 1000 } else { 0 };
+                let ts = ts_fixture::segment(seq * 90_000, 30);
+                sim.respond(
+                    &format!("http://x/{}/seg{}.ts", variant, seq),
+                    Response::data(ts).with_latency(Duration::from_millis(latency)),
+                );
+            }
+        }
+        sim.play("http://x/master.m3u8").unwrap();
+        sim.run_for(Duration::from_millis(1500)).unwrap();
+
+        // The segment #1 of the lower variant is warmed up while its own one is fetched.
+        sim.player_mut().set_buffer_length(Duration::from_secs(10));
+        sim.run_for(Duration::from_millis(100)).unwrap();
+        sim.player_mut().set_low_power(true);
+        sim.run_for(Duration::from_secs(1)).unwrap();
+
+        let warmed = sim.records()
+            .iter()
+            .filter(|r| match **r {
+                SimulationRecord::Action {
+                    action: Action::FetchData { ref url, .. },
+                    ..
+                } => url.path() == "/low/seg1.ts",
+                _ => false,
+            })
+            .count();
+        assert_eq!(warmed, 1);
+        let appended = sim.records()
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Segment { ref info, .. } => Some((info.sequence, info.variant)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(appended, [(0, Some(1)), (1, Some(0)), (2, Some(0))]);
+    }
+
+    #[test]
+    fn higher_scores_are_preferred_among_similar_bandwidths() {
+        let master = "#EXTM3U\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=1000000\nlow.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=1050000,SCORE=2.0\nscored.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=1040000\nother.m3u8\n";
+        assert_eq!(playlist_fetches(master)[0], "/scored.m3u8");
+
+        // Bandwidths alone do not replace the requested variant.
+        let master = master.replace(",SCORE=2.0", "");
+        assert_eq!(playlist_fetches(&master)[0], "/low.m3u8");
     }
 }
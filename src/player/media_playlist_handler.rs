@@ -1,185 +1,3065 @@
+use std::borrow::Cow;
 use std::cmp;
-use std::collections::VecDeque;
+use std::mem;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::time::Duration;
 use hls_m3u8::MediaPlaylist;
+use hls_m3u8::tags::ExtXKey;
 use mpeg2ts::ts::TsPacketReader;
-use mse_fmp4::mpeg2_ts;
+use mse_fmp4::fmp4::MediaSegment;
 use mse_fmp4::io::WriteTo;
 use url::Url;
 
-use {Error, Result};
-use super::{Action, ActionFactory, ActionId};
+use {ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, AvSyncMode, BufferPool, ByteRange, Event,
+            FailureClass, FailureStreak, KeyframeIndex, LatencyEstimator, OutputQueue,
+            OutputSegment, PlayerConfig, PlaylistRefreshTracker, PushOutcome, Recorder,
+            ReinitReason, ResourceCache, ResourceKey, ResourceKind, SegmentHistory, SegmentInfo,
+            Stats, StreamId, Trace, TraceEntry};
+use super::aes;
+use super::av_sync;
+use super::data_url;
+use super::discontinuity;
+use super::emsg;
+use super::keyframe_index;
+use super::latency::ResponseDate;
+use super::master_playlist_handler::NextMaster;
+use super::media_type;
+use super::server_control::ServerControl;
+use super::playlist_diff::PlaylistDiff;
+use super::text;
+use super::target_duration;
+use super::throughput_hint::ThroughputHint;
+use super::timeline::Timeline;
+use super::timestamps::StreamTimestamps;
+use super::track_selection::{SelectedTracks, TrackFilter};
+use super::date_ranges::DateRangeTimeline;
+use super::date_time;
+use super::random::Random;
+use super::recording;
+use super::remux::{self, Remuxed, TsInput};
+use super::scte35;
+use super::segment_resources::{self, Encryption};
+use super::stitching::{self, AdPod, Cue};
 
 type SequenceNumber = u64;
 
+/// Maximum number of consecutive token refreshes, beyond which unauthorized fetches fail.
+const MAX_TOKEN_REFRESHES: u32 = 3;
+
 #[derive(Debug)]
 pub struct MediaPlaylistHandler {
+    config: PlayerConfig,
     media_playlist_url: Url,
+    base_url: Url,
     action_factory: ActionFactory,
+    resource_cache: ResourceCache,
+    segment_history: SegmentHistory,
+    trace: Trace,
     action_queue: VecDeque<Action>,
-    segment_queue: VecDeque<(SequenceNumber, bool, Url)>,
-    buffered_segments: VecDeque<Vec<u8>>,
-    last_media_sequence: SequenceNumber,
+    event_queue: VecDeque<Event>,
+    segment_queue: VecDeque<Segment>,
+    fetching_segment: Option<SegmentFetch>,
+    timers: HashMap<ActionId, Timer>,
+    in_flight_fetches: BTreeSet<ActionId>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+
+    /// Hints of the response headers of the in-flight fetches (see `handle_response_headers`).
+    throughput_hints: HashMap<ActionId, ThroughputHint>,
+
+    /// `Date` and `Age` of the in-flight playlist fetch (see `LiveEdgeConfig::correct_clock_skew`).
+    playlist_response: Option<ResponseDate>,
+    buffered_segments: OutputQueue,
+    last_media_sequence: Option<SequenceNumber>,
+    playlist_media_sequence: Option<SequenceNumber>,
+    byte_range_end: u64,
+    next_program_date_time: Option<Duration>,
+
+    /// `EXT-X-MAP` in effect for the last listed segment.
+    map: Option<ResourceKey>,
+
+    /// `EXT-X-KEY` in effect for the last listed segment.
+    encryption: Option<Encryption>,
+
+    /// `EXT-X-KEY` in effect for the last `EXT-X-MAP`.
+    map_encryption: Option<Encryption>,
+
+    /// Discontinuity sequence number of the last listed segment.
+    discontinuity: u64,
+
+    /// Discontinuity sequence number of the last remuxed segment.
+    last_remuxed_discontinuity: Option<u64>,
+
+    /// `EXT-X-MAP` of the last remuxed segment.
+    last_remuxed_map: Option<ResourceKey>,
+
+    /// Reason of the initialization segment to be produced with the next remuxed segment,
+    /// if any output precedes it (see `Event::Reinitialized`).
+    reinit_reason: Option<ReinitReason>,
+
     is_initialized: bool,
     fetch_playlist_action_id: ActionId,
+    playlist_failures: u32,
+    failure_streak: FailureStreak,
+
+    /// Number of the token refreshes since the last successful fetch (see `TokenRefresher`).
+    token_refreshes: u32,
+
+    /// Names of the query parameters given by the last token refresh.
+    refreshed_query_keys: BTreeSet<String>,
+    is_playlist_loaded: bool,
+    unchanged_reloads: u32,
+    reload_due_at: Option<Duration>,
+    target_duration: Option<Duration>,
+    stalled_for: Duration,
+    is_stall_reported: bool,
+    random: Random,
     segments_total: u32,
     segment_durations_total: Duration,
+    is_live: bool,
+    bytes_downloaded: u64,
+    segments_downloaded: u64,
+    latency: LatencyEstimator,
+    last_reported_latency_ms: Option<u32>,
+    keyframes: KeyframeIndex,
+
+    /// Timeline of the playlist if it is a VOD one.
+    timeline: Option<Timeline>,
+
+    /// `EXT-X-DATERANGE`s of the played playlists, which are not ad breaks.
+    date_ranges: DateRangeTimeline,
+
+    /// Streams remuxed from the last segment (see `TrackConfig`).
+    tracks: Option<SelectedTracks>,
+
+    refresh: PlaylistRefreshTracker,
+    pool: BufferPool,
+    recorder: Option<Recorder>,
+    ad_pods: VecDeque<AdPod>,
+    ad_break: Option<AdBreak>,
+
+    /// Segments up to this sequence number are skipped (see `resume_after`).
+    resume_after: Option<SequenceNumber>,
+
+    /// Segment boundary of another variant to be matched by the first playlist (see `resume_at`).
+    resume_point: Option<SwitchPoint>,
+
+    /// Position on the media timeline to start from (see `start_at`).
+    start_position: Option<Duration>,
+
+    is_playlist_failed: bool,
+
+    /// Number of the consecutive segments which could not be decrypted or remuxed.
+    undecodable_segments: u32,
+    server_control: ServerControl,
+
+    /// Playlist to be played after the current one (see `queue_next`).
+    next_asset: Option<Box<NextAsset>>,
+
+    /// Whether the next listed segment starts the playlist queued by `queue_next`.
+    is_next_asset_starting: bool,
+
+    /// Master playlist of the started playlist, taken by `take_started_master`.
+    started_master: Option<Box<NextMaster>>,
+
+    /// Whether the variant is locked until the end of the next ad break (see `lock_variant`).
+    is_variant_locked: bool,
+
+    /// Sequence numbers and durations of the segments listed by the last playlist,
+    /// updated by the reduced reloads as well (see `playlist_diff`).
+    window: VecDeque<(SequenceNumber, Duration)>,
+
+    suspension: Option<Suspension>,
+
+    /// Whether the next playlist is joined at the live point, as resumed too far behind it.
+    is_rejoining_live: bool,
+
+    /// Last reloaded playlist, to which the next reload is compared.
+    playlist_diff: PlaylistDiff,
+
+    /// Segments fetched in advance (see `preload_segment` and `fetch_ahead`).
+    preloaded_segments: Vec<PreloadedSegment>,
+
+    /// In-flight fetches of the segments following the next one (see `fetch_ahead`).
+    ahead_fetches: BTreeMap<ActionId, ResourceKey>,
+
+    /// Preloaded segments remuxed along with the previous one (see `remux_with_next`).
+    remuxed_ahead: Vec<(ResourceKey, Result<Remuxed>)>,
+
+    /// Whether the audio of the segments is merged from an audio rendition (see `set_demuxed`).
+    is_demuxed: bool,
+
+    /// Fetched segment waiting for its audio (see `attach_audio`).
+    awaiting_audio: Option<Box<AwaitingSegment>>,
 }
 impl MediaPlaylistHandler {
-    pub fn new(mut action_factory: ActionFactory, media_playlist_url: Url) -> Self {
-        let mut action_queue = VecDeque::new();
-        let action = action_factory.fetch_data(media_playlist_url.clone());
-        let action_id = action.id();
-        action_queue.push_back(action);
-        MediaPlaylistHandler {
-            media_playlist_url,
+    /// Makes a handler that starts by fetching the media playlist.
+    ///
+    /// `data:` URLs are decoded in place instead of being fetched.
+    pub fn new(
+        config: &PlayerConfig,
+        action_factory: ActionFactory,
+        resource_cache: ResourceCache,
+        segment_history: SegmentHistory,
+        trace: Trace,
+        media_playlist_url: Url,
+    ) -> Self {
+        let mut this = Self::without_playlist(
+            config,
             action_factory,
-            action_queue,
-            segment_queue: VecDeque::new(),
-            buffered_segments: VecDeque::new(),
-            last_media_sequence: 0,
-            is_initialized: false,
-            fetch_playlist_action_id: action_id,
-            segments_total: 0,
-            segment_durations_total: Duration::from_secs(0),
-        }
+            resource_cache,
+            segment_history,
+            trace,
+            media_playlist_url,
+        );
+        this.request_playlist();
+        this
     }
 
     pub fn with_m3u8(
+        config: &PlayerConfig,
         action_factory: ActionFactory,
+        resource_cache: ResourceCache,
+        segment_history: SegmentHistory,
+        trace: Trace,
         media_playlist_url: Url,
         m3u8: &str,
     ) -> Result<Self> {
-        let mut this = Self::new(action_factory, media_playlist_url);
-        let _ = this.next_action();
-        track!(this.handle_playlist(m3u8, 0))?;
+        let mut this = Self::without_playlist(
+            config,
+            action_factory,
+            resource_cache,
+            segment_history,
+            trace,
+            media_playlist_url,
+        );
+        track!(this.handle_playlist(m3u8.as_bytes(), 0))?;
+        this.is_playlist_loaded = true;
         Ok(this)
     }
 
+    /// Makes a handler which does not fetch the playlist until `load_playlist`,
+    /// so that it can be set up (e.g., by `resume_after`) before the playlist is handled.
+    pub fn unloaded(
+        config: &PlayerConfig,
+        action_factory: ActionFactory,
+        resource_cache: ResourceCache,
+        segment_history: SegmentHistory,
+        trace: Trace,
+        media_playlist_url: Url,
+    ) -> Self {
+        Self::without_playlist(
+            config,
+            action_factory,
+            resource_cache,
+            segment_history,
+            trace,
+            media_playlist_url,
+        )
+    }
+
+    /// Handles `m3u8` obtained in advance (e.g., prefetched) as the playlist of a handler
+    /// made by `unloaded`, or fetches the playlist if it cannot be handled.
+    pub fn load_playlist(&mut self, m3u8: &str) {
+        match track!(self.handle_playlist(m3u8.as_bytes(), 0)) {
+            Ok(()) => self.is_playlist_loaded = true,
+            Err(e) => {
+                log!(self.config, Warn, "Cannot handle the given media playlist: {}", e);
+                self.request_playlist();
+            }
+        }
+    }
+
+    fn without_playlist(
+        config: &PlayerConfig,
+        action_factory: ActionFactory,
+        resource_cache: ResourceCache,
+        segment_history: SegmentHistory,
+        trace: Trace,
+        media_playlist_url: Url,
+    ) -> Self {
+        MediaPlaylistHandler {
+            config: config.clone(),
+            base_url: media_playlist_url.clone(),
+            media_playlist_url,
+            action_factory,
+            resource_cache,
+            segment_history,
+            trace,
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            segment_queue: VecDeque::new(),
+            fetching_segment: None,
+            timers: HashMap::new(),
+            in_flight_fetches: BTreeSet::new(),
+            partial_bodies: HashMap::new(),
+            throughput_hints: HashMap::new(),
+            playlist_response: None,
+            buffered_segments: OutputQueue::new(&config.output_queue),
+            last_media_sequence: None,
+            playlist_media_sequence: None,
+            byte_range_end: 0,
+            next_program_date_time: None,
+            map: None,
+            encryption: None,
+            map_encryption: None,
+            discontinuity: 0,
+            last_remuxed_discontinuity: None,
+            last_remuxed_map: None,
+            reinit_reason: None,
+            is_initialized: false,
+            fetch_playlist_action_id: ActionId::default(),
+            playlist_failures: 0,
+            failure_streak: FailureStreak::new(),
+            token_refreshes: 0,
+            refreshed_query_keys: BTreeSet::new(),
+            is_playlist_loaded: false,
+            unchanged_reloads: 0,
+            reload_due_at: None,
+            target_duration: None,
+            stalled_for: Duration::from_secs(0),
+            is_stall_reported: false,
+            random: Random::new(config.reload.random_seed),
+            segments_total: 0,
+            segment_durations_total: Duration::from_secs(0),
+            is_live: true,
+            bytes_downloaded: 0,
+            segments_downloaded: 0,
+            latency: LatencyEstimator::new(),
+            last_reported_latency_ms: None,
+            keyframes: KeyframeIndex::new(),
+            timeline: None,
+            date_ranges: DateRangeTimeline::new(),
+            tracks: None,
+            refresh: PlaylistRefreshTracker::new(),
+            pool: BufferPool::new(),
+            recorder: if config.recording.enabled {
+                Some(Recorder::new())
+            } else {
+                None
+            },
+            ad_pods: VecDeque::new(),
+            ad_break: None,
+            resume_after: None,
+            resume_point: None,
+            start_position: None,
+            is_playlist_failed: false,
+            undecodable_segments: 0,
+            server_control: ServerControl::default(),
+            next_asset: None,
+            is_next_asset_starting: false,
+            started_master: None,
+            is_variant_locked: false,
+            window: VecDeque::new(),
+            suspension: None,
+            is_rejoining_live: false,
+            playlist_diff: PlaylistDiff::default(),
+            preloaded_segments: Vec::new(),
+            ahead_fetches: BTreeMap::new(),
+            remuxed_ahead: Vec::new(),
+            is_demuxed: false,
+            awaiting_audio: None,
+        }
+    }
+
     pub fn next_action(&mut self) -> Option<Action> {
-        self.action_queue.pop_front()
+        let action = self.action_queue.pop_front();
+        if let Some(ref action) = action {
+            if action.is_fetch_data() {
+                self.in_flight_fetches.insert(action.id());
+            }
+        }
+        action
     }
 
-    pub fn next_segment(&mut self) -> Option<Vec<u8>> {
-        self.buffered_segments.pop_front()
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
     }
 
-    pub fn handle_timeout(&mut self, _action_id: ActionId) -> Result<()> {
-        let action = self.action_factory
-            .fetch_data(self.media_playlist_url.clone());
-        let action_id = action.id();
-        self.action_queue.push_back(action);
-        self.fetch_playlist_action_id = action_id;
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        let segment = self.buffered_segments.pop();
+        if segment.is_some() {
+            // Resumes the fetches suspended by `OverflowPolicy::Block`.
+            self.fetch_next_segment();
+        }
+        segment
+    }
+
+    pub fn recycle_segment(&mut self, segment: Vec<u8>) {
+        self.pool.give(segment);
+    }
+
+    pub fn take_recording(&mut self) -> Vec<u8> {
+        self.recorder.as_mut().map_or_else(Vec::new, |r| r.take())
+    }
+
+    /// Queues `pod` to be played at the next ad break (see `stitching`).
+    pub fn insert_ad_pod(&mut self, pod: AdPod) {
+        log!(
+            self.config,
+            Info,
+            "Queued the ad pod {}: segments={}",
+            pod.url,
+            pod.segments.len()
+        );
+        self.ad_pods.push_back(pod);
+    }
+
+    /// Queues the media playlist `url` to be played once the current playlist has ended,
+    /// replacing any previously queued one.
+    ///
+    /// The playlist is prefetched unless given as `m3u8`, and its first segment is fetched
+    /// right after the last one of the current playlist, starting with a new
+    /// initialization segment. `master` is the master playlist it was selected from, if any,
+    /// which is returned by `take_started_master` once the playlist has started.
+    pub fn queue_next(
+        &mut self,
+        url: Url,
+        m3u8: Option<String>,
+        master: Option<Box<NextMaster>>,
+    ) {
+        log!(self.config, Info, "Queued the next playlist {}", url);
+        if let Some(action_id) = self.next_asset.take().and_then(|n| n.fetch_action_id) {
+            if self.in_flight_fetches.remove(&action_id) {
+                self.action_queue.push_back(Action::Abort { action_id });
+            }
+        }
+        let mut next = NextAsset {
+            base_url: url.clone(),
+            url,
+            m3u8,
+            fetch_action_id: None,
+            master,
+        };
+        if next.m3u8.is_none() && !data_url::is_data_url(&next.url) && self.suspension.is_none() {
+            let action = self.action_factory
+                .fetch_data(next.url.clone(), ResourceKind::Playlist, None);
+            next.fetch_action_id = Some(action.id());
+            self.action_queue.push_back(action);
+        }
+        self.next_asset = Some(Box::new(next));
+        self.fetch_next_segment();
+    }
+
+    /// Takes the playlist queued by `queue_next`, e.g., to be queued to the handler replacing
+    /// this one, along with its prefetched content if any.
+    pub fn take_next(&mut self) -> Option<(Url, Option<String>, Option<Box<NextMaster>>)> {
+        self.next_asset.take().map(|n| (n.url, n.m3u8, n.master))
+    }
+
+    pub fn take_started_master(&mut self) -> Option<Box<NextMaster>> {
+        self.started_master.take()
+    }
+
+    /// Locks (or unlocks) the variant of this handler, e.g., during ad pods whose segments would
+    /// show the discontinuities of mid-break switches.
+    ///
+    /// The lock is released at the end of the next ad break.
+    pub fn lock_variant(&mut self, locked: bool) {
+        self.is_variant_locked = locked;
+    }
+
+    pub fn is_variant_locked(&self) -> bool {
+        self.is_variant_locked
+    }
+
+    /// Reports the initialization segment produced with the next remuxed segment
+    /// by `Event::Reinitialized`, as it follows the output of another handler.
+    pub fn reinitialize(&mut self, reason: ReinitReason) {
+        self.is_initialized = false;
+        self.reinit_reason = Some(reason);
+    }
+
+    /// Returns `true` if a segment has been remuxed, or the output of another handler precedes
+    /// that of this one.
+    pub fn has_produced_output(&self) -> bool {
+        self.last_remuxed_discontinuity.is_some() || self.reinit_reason.is_some()
+    }
+
+    /// Returns why the variant has failed (see `VariantBlacklistConfig`), if it has.
+    pub fn variant_failure(&self) -> Option<String> {
+        if self.is_playlist_failed {
+            return Some(format!("the media playlist {} has failed", self.media_playlist_url));
+        }
+        let max = self.config.variant_blacklist.max_undecodable_segments;
+        if max > 0 && self.undecodable_segments >= max {
+            return Some(format!(
+                "{} consecutive segments are undecodable",
+                self.undecodable_segments
+            ));
+        }
+        None
+    }
+
+    pub fn config(&self) -> &PlayerConfig {
+        &self.config
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Timeline of the produced segments, which maps positions to date-times.
+    pub fn latency_estimator(&self) -> &LatencyEstimator {
+        &self.latency
+    }
+
+    pub fn keyframe_index(&self) -> &KeyframeIndex {
+        &self.keyframes
+    }
+
+    /// Returns the timeline of the playlist once loaded, unless it is a live one.
+    pub fn timeline(&self) -> Option<&Timeline> {
+        self.timeline.as_ref()
+    }
+
+    pub fn date_ranges(&self) -> &DateRangeTimeline {
+        &self.date_ranges
+    }
+
+    /// Takes the date ranges over from the handler of another variant, so that the ranges
+    /// containing the playback position are not entered again.
+    pub fn inherit_date_ranges(&mut self, previous: &mut MediaPlaylistHandler) {
+        self.date_ranges = mem::take(&mut previous.date_ranges);
+    }
+
+    /// Stream ID of the actions of this handler.
+    pub fn stream_id(&self) -> StreamId {
+        self.action_factory.media_playlist_id()
+    }
+
+    /// Skips the segments up to `seq`, e.g., those already played from another variant
+    /// with the same media sequence numbers.
+    pub fn resume_after(&mut self, seq: SequenceNumber) {
+        self.resume_after = Some(seq);
+    }
+
+    /// Continues from `point` of another variant, i.e., from the first segment whose middle
+    /// is after it, matched by the program date-times or the VOD positions of both variants
+    /// (or by the media sequence number if neither is known), so that no media overlaps.
+    pub fn resume_at(&mut self, point: SwitchPoint) {
+        if let Some(last) = point.sequence.checked_sub(1) {
+            self.resume_after = Some(last);
+        }
+        self.resume_point = Some(point);
+    }
+
+    /// Returns the boundary before the next segment to be fetched, at which a switch to
+    /// another variant continues (see `resume_at`).
+    pub fn switch_point(&self) -> Option<SwitchPoint> {
+        let sequence = self.next_sequence()?;
+        let next = self.pending_segments().find(|s| !s.is_ad);
+        let program_date_time = match next {
+            Some(segment) => segment.program_date_time,
+            None => self.next_program_date_time,
+        };
+        let position = self.timeline
+            .as_ref()
+            .and_then(|t| t.segments.iter().find(|s| s.sequence == sequence))
+            .map(|s| Duration::from_secs_f64(s.start_ms / 1000.0));
+        Some(SwitchPoint {
+            sequence,
+            program_date_time,
+            position,
+        })
+    }
+
+    /// Makes each fetched segment wait for the audio of the same time span (see `attach_audio`),
+    /// which is merged into it before remuxing.
+    pub fn set_demuxed(&mut self, enabled: bool) {
+        self.is_demuxed = enabled;
+        if !enabled {
+            if let Some(awaiting) = self.awaiting_audio.take() {
+                let AwaitingSegment {
+                    fetch,
+                    data,
+                    fetch_duration_ms,
+                } = *awaiting;
+                self.remux_fetched(fetch, &data, None, fetch_duration_ms);
+            }
+        }
+    }
+
+    /// Returns the time span of the segment whose audio is awaited, i.e., the fetched or
+    /// fetching one (ads excepted), if demuxed.
+    pub fn audio_span(&self) -> Option<SegmentSpan> {
+        if !self.is_demuxed {
+            return None;
+        }
+        let segment = self.awaiting_audio
+            .as_ref()
+            .map(|a| &a.fetch.segment)
+            .or_else(|| self.fetching_segment.as_ref().map(|f| &f.segment))
+            .filter(|s| !s.is_ad)?;
+        let position = self.timeline
+            .as_ref()
+            .and_then(|t| t.segments.iter().find(|s| s.sequence == segment.seq))
+            .map(|s| Duration::from_secs_f64(s.start_ms / 1000.0));
+        Some(SegmentSpan {
+            start: SwitchPoint {
+                sequence: segment.seq,
+                program_date_time: segment.program_date_time,
+                position,
+            },
+            duration: segment.duration,
+        })
+    }
+
+    /// Returns `true` if a fetched segment is waiting for its audio.
+    pub fn is_awaiting_audio(&self) -> bool {
+        self.awaiting_audio.is_some()
+    }
+
+    /// Remuxes the segment awaiting its audio with the TS packets `audio`, whose first segment
+    /// is `sequence` (the video is remuxed alone if `audio` is empty).
+    pub fn attach_audio(&mut self, sequence: Option<u64>, audio: &[u8]) {
+        let awaiting = match self.awaiting_audio.take() {
+            None => return,
+            Some(awaiting) => *awaiting,
+        };
+        let audio = Some((sequence, audio)).filter(|_| !audio.is_empty());
+        if audio.is_none() {
+            log!(
+                self.config,
+                Warn,
+                "No audio for the segment #{}",
+                awaiting.fetch.segment.seq
+            );
+        }
+        self.remux_fetched(awaiting.fetch, &awaiting.data, audio, awaiting.fetch_duration_ms);
+    }
+
+    /// Uses `data` as the body of `resource` instead of fetching it, if it is the next segment
+    /// requested (e.g., one warmed up before switching to the variant).
+    pub fn preload_segment(
+        &mut self,
+        resource: ResourceKey,
+        data: Vec<u8>,
+        fetch_duration_ms: u32,
+    ) {
+        self.preloaded_segments.retain(|p| p.resource != resource);
+        self.preloaded_segments.push(PreloadedSegment {
+            resource,
+            data,
+            fetch_duration_ms,
+        });
+    }
+
+    /// Starts from the segment containing `position` (measured from the start of the playlist)
+    /// instead of the first one (or the live point) when the playlist is loaded.
+    pub fn start_at(&mut self, position: Duration) {
+        self.start_position = Some(position);
+    }
+
+    /// Returns the sequence number of the next segment of the playlist to be fetched, if known.
+    ///
+    /// Ad segments are not counted, since they do not belong to the playlist.
+    pub fn next_sequence(&self) -> Option<SequenceNumber> {
+        self.pending_segments()
+            .find(|s| !s.is_ad)
+            .map(|s| s.seq)
+            .or_else(|| {
+                let last = self.last_media_sequence.or(self.resume_after);
+                last.map(|seq| seq + 1)
+            })
+    }
+
+    /// Returns the segments not remuxed yet, in order.
+    fn pending_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.awaiting_audio
+            .iter()
+            .map(|a| &a.fetch.segment)
+            .chain(self.fetching_segment.iter().map(|f| &f.segment))
+            .chain(self.segment_queue.iter())
+    }
+
+    /// Returns `true` if reloads of the live playlist are blocking ones (`_HLS_msn`).
+    ///
+    /// They keep a request open, so they are not made in the low-power mode.
+    fn is_blocking_reload(&self) -> bool {
+        self.is_live
+            && self.server_control.can_block_reload
+            && self.config.live_edge.blocking_reload
+            && !self.config.low_power.enabled
+    }
+
+    /// Returns `true` if the media playlist has been given up after repeated failures.
+    pub fn is_playlist_failed(&self) -> bool {
+        self.is_playlist_failed
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        match self.timers.remove(&action_id) {
+            Some(Timer::ReloadPlaylist) => self.request_playlist(),
+            Some(Timer::RetrySegment) => {
+                if let Some(fetch) = self.fetching_segment.take() {
+                    self.request_segment(fetch);
+                }
+            }
+            Some(Timer::PreloadedSegment) => {
+                let resource = self.fetching_segment.as_ref().map(|f| f.resource.clone());
+                let index = self.preloaded_segments
+                    .iter()
+                    .position(|p| Some(&p.resource) == resource.as_ref());
+                if let Some(preloaded) = index.map(|i| self.preloaded_segments.remove(i)) {
+                    self.in_flight_fetches.insert(action_id);
+                    let duration_ms = preloaded.fetch_duration_ms;
+                    track!(self.handle_data(action_id, &preloaded.data, duration_ms, None))?;
+                }
+            }
+            None => {}
+        }
         Ok(())
     }
 
+    /// Handles the response body of the fetch requested by `action_id`.
+    ///
+    /// `final_url` is the URL the response was actually served from after redirects, if known.
+    /// For media playlists it becomes the base URL of relative segment URIs.
     pub fn handle_data(
         &mut self,
         action_id: ActionId,
         data: &[u8],
         fetch_duration_ms: u32,
+        final_url: Option<&Url>,
     ) -> Result<()> {
+        if !self.in_flight_fetches.remove(&action_id) {
+            return Ok(());
+        }
+        let hint = self.throughput_hints.remove(&action_id).unwrap_or_default();
+        self.action_factory.record_fetch_success();
+        self.token_refreshes = 0;
+        if let Some(resource) = self.ahead_fetches.remove(&action_id) {
+            // Handled as fetched once the segment is requested (see `request_segment`).
+            if self.is_upcoming(&resource) {
+                self.preload_segment(resource, data.to_vec(), fetch_duration_ms);
+            }
+            return Ok(());
+        }
+        self.bytes_downloaded += data.len() as u64;
+        if let Some(next) = self.next_asset.as_mut() {
+            if next.fetch_action_id == Some(action_id) {
+                next.fetch_action_id = None;
+                next.base_url = final_url.unwrap_or(&next.url).clone();
+                next.m3u8 = Some(text::decode(data).into_owned());
+                self.fetch_next_segment();
+                return Ok(());
+            }
+        }
         if action_id == self.fetch_playlist_action_id {
-            use std::str;
+            let response = self.playlist_response.take().unwrap_or_default();
+            if let Some(now) = self.config.hooks.now() {
+                let fetch_duration = Duration::from_millis(u64::from(fetch_duration_ms));
+                self.latency
+                    .on_playlist_response(now, fetch_duration, response);
+            }
 
-            let m3u8 = track!(str::from_utf8(data).map_err(Error::from))?;
-            track!(self.handle_playlist(m3u8, fetch_duration_ms))?;
-        } else {
-            track!(self.handle_segment(data))?;
+            // Later reloads still go to `media_playlist_url`, so that load balancers can
+            // redirect them elsewhere.
+            self.base_url = final_url.unwrap_or(&self.media_playlist_url).clone();
+
+            let result = track!(self.handle_playlist(data, fetch_duration_ms));
+            match result {
+                Ok(()) => {
+                    self.playlist_failures = 0;
+                    self.is_playlist_loaded = true;
+                    // Reloads do not count as progress while a segment keeps failing.
+                    if self.fetching_segment.as_ref().is_none_or(|f| f.attempts == 0) {
+                        self.failure_streak.on_success();
+                    }
+                }
+                Err(e) => {
+                    log!(self.config, Warn, "Cannot handle the media playlist: {}", e);
+                    self.handle_playlist_failure(FailureClass::Transient, e.to_string());
+                }
+            }
+        } else if self.is_fetching_segment(action_id) {
+            let mut fetch = self.fetching_segment.take().expect("Never fails");
+            self.failure_streak.on_success();
+            match fetch.kind {
+                ResourceKind::Key if data.len() != aes::KEY_LEN => {
+                    self.skip_undecodable_segment(format!(
+                        "Skipped the segment {}: the key {} is {} bytes",
+                        fetch.segment.url,
+                        fetch.resource.url,
+                        data.len()
+                    ));
+                    self.fetch_next_segment();
+                    return Ok(());
+                }
+                ResourceKind::InitSegment if fetch.segment.init_key.is_some() => {
+                    let encryption = fetch.segment.init_key.as_ref().expect("Never fails");
+                    match track!(self.decrypt(encryption, fetch.segment.seq, data)) {
+                        Ok(init) => self.resource_cache.insert(fetch.resource.clone(), &init),
+                        Err(e) => {
+                            self.skip_undecodable_segment(format!(
+                                "Skipped the segment {}: cannot decrypt the init section {}: {}",
+                                fetch.segment.url, fetch.resource.url, e
+                            ));
+                            self.fetch_next_segment();
+                            return Ok(());
+                        }
+                    }
+                }
+                ResourceKind::Key | ResourceKind::InitSegment => {
+                    self.resource_cache.insert(fetch.resource.clone(), data);
+                }
+                _ => {}
+            }
+            if fetch.kind != ResourceKind::Segment {
+                fetch.attempts = 0;
+                self.request_segment(fetch);
+                return Ok(());
+            }
+
+            self.record_throughput(data.len(), fetch_duration_ms, hint);
+            self.segments_downloaded += 1;
+            if self.is_demuxed && !fetch.segment.is_ad {
+                self.awaiting_audio = Some(Box::new(AwaitingSegment {
+                    fetch,
+                    data: data.to_vec(),
+                    fetch_duration_ms,
+                }));
+                return Ok(());
+            }
+            self.remux_fetched(fetch, data, None, fetch_duration_ms);
         }
         Ok(())
     }
 
-    fn handle_playlist(&mut self, m3u8: &str, fetch_duration_ms: u32) -> Result<()> {
-        let playlist: MediaPlaylist = track!(m3u8.parse())?;
-        let media_sequence = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
-        while self.segment_queue
-            .front()
-            .map_or(false, |x| x.0 < media_sequence)
+    /// Remuxes the fetched segment `data`, merged with the TS packets of the audio rendition
+    /// (and the sequence number of their first segment) if any.
+    fn remux_fetched(
+        &mut self,
+        mut fetch: SegmentFetch,
+        data: &[u8],
+        audio: Option<(Option<u64>, &[u8])>,
+        fetch_duration_ms: u32,
+    ) {
+        self.fetch_next_segment();
+        let has_output = self.has_produced_output();
+        let discontinuity = fetch.segment.discontinuity;
+        let last_discontinuity = self.last_remuxed_discontinuity.replace(discontinuity);
+        let last_map = mem::replace(&mut self.last_remuxed_map, fetch.segment.init.clone());
+        let reinit_reason = if fetch.segment.is_discontinuity
+            || last_discontinuity.is_some_and(|d| d != discontinuity)
         {
-            self.segment_queue.pop_front();
+            // Ads and the main content, or both sides of an `EXT-X-DISCONTINUITY`,
+            // may differ in codec parameters.
+            Some(ReinitReason::Discontinuity)
+        } else if last_discontinuity.is_some() && last_map != fetch.segment.init {
+            // The new init section may list other streams.
+            Some(ReinitReason::MapChanged)
+        } else {
+            None
+        };
+        if let Some(reason) = reinit_reason {
+            self.is_initialized = false;
+            if has_output {
+                self.reinit_reason = Some(reason);
+            }
         }
-
-        let mut is_updated = false;
-        let mut polling_interval = playlist.target_duration_tag().duration();
-        for (i, segment) in playlist.segments().iter().enumerate() {
-            let seq = media_sequence + i as u64;
-            if seq <= self.last_media_sequence {
-                continue;
+        let decrypted = fetch
+            .segment
+            .key
+            .as_ref()
+            .map(|e| track!(self.decrypt(e, fetch.segment.seq, data)))
+            .transpose();
+        let decrypted = match decrypted {
+            Ok(x) => x,
+            Err(e) => {
+                // Drops the segment as well as the corrupt ones.
+                let reason = format!("Cannot decrypt the segment {}: {}", fetch.segment.url, e);
+                self.skip_undecodable_segment(reason);
+                return;
+            }
+        };
+        let payload = decrypted.as_ref().map_or(data, |d| &d[..]);
+        let started_at = self.config.hooks.now();
+        let init = fetch.segment.init.as_ref().and_then(|k| self.resource_cache.get(k));
+        let result = track!(self.handle_segment(
+            &mut fetch.segment,
+            init.as_ref().map(|x| &x[..]),
+            payload,
+            audio
+        ));
+        let (fmp4_bytes, keyframes) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                // Drops the corrupt segment and continues with the next one.
+                let reason = format!("Cannot remux the segment {}: {}", fetch.segment.url, e);
+                self.skip_undecodable_segment(reason);
+                return;
             }
-            is_updated = true;
+        };
+        self.undecodable_segments = 0;
+        self.latency
+            .on_segment_appended(fetch.segment.duration, fetch.segment.program_date_time);
+        self.keyframes
+            .on_segment_appended(fetch.segment.seq, fetch.segment.duration, &keyframes);
+        let remux_duration_ms = started_at
+            .and_then(|t| self.config.hooks.now().map(|now| now.saturating_sub(t)))
+            .map(|d| d.as_secs_f64() * 1000.0);
+        log!(
+            self.config,
+            Debug,
+            "Remuxed the segment #{}: {} bytes -> {} bytes",
+            fetch.segment.seq,
+            payload.len(),
+            fmp4_bytes
+        );
+        if let Some(reason) = self.reinit_reason.take() {
+            log!(
+                self.config,
+                Info,
+                "Produced a new initialization segment at the segment #{}: {:?}",
+                fetch.segment.seq,
+                reason
+            );
+            self.event_queue.push_back(Event::Reinitialized {
+                sequence: fetch.segment.seq,
+                reason,
+            });
+        }
+        self.event_queue.push_back(Event::SegmentLoaded {
+            sequence: fetch.segment.seq,
+            discontinuity,
+            variant: None,
+            bytes: data.len(),
+            fetch_duration_ms,
+            remux_duration_ms,
+            fmp4_bytes,
+        });
+    }
 
-            self.last_media_sequence = seq;
-            self.segments_total += 1;
-            self.segment_durations_total += segment.inf_tag().duration();
+    /// Handles a chunk of the response body of the fetch requested by `action_id`.
+    ///
+    /// The body is handled once `handle_data_end` is called.
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) -> Result<()> {
+        if self.in_flight_fetches.contains(&action_id) {
+            let pool = &mut self.pool;
+            self.partial_bodies
+                .entry(action_id)
+                .or_insert_with(|| pool.take(chunk.len()))
+                .extend_from_slice(chunk);
+        }
+        Ok(())
+    }
 
-            let segment_url = track!(self.parse_segment_url(segment.uri()))?;
-            let ongoing = if self.segment_queue.is_empty() {
-                self.action_queue
-                    .push_back(self.action_factory.fetch_data(segment_url.clone()));
-                true
-            } else {
-                false
-            };
-            self.segment_queue.push_back((seq, ongoing, segment_url));
-            polling_interval = cmp::min(polling_interval, segment.inf_tag().duration());
+    /// Handles the response headers of the fetch requested by `action_id`,
+    /// whose throughput hints are recorded along with the fetch (see `ThroughputHintConfig`),
+    /// as well as the `Date` and `Age` of playlists (see `LiveEdgeConfig::correct_clock_skew`).
+    pub fn handle_response_headers(
+        &mut self,
+        action_id: ActionId,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        if !self.in_flight_fetches.contains(&action_id) {
+            return Ok(());
         }
-        if self.segments_total > 0 {
-            let average_segment_duration = self.segment_durations_total / self.segments_total;
-            polling_interval = cmp::min(polling_interval, average_segment_duration);
-        } else {
-            self.segment_durations_total = Duration::from_secs(0);
+        if self.config.throughput_hints.enabled {
+            let hint = ThroughputHint::parse(&self.config.throughput_hints, headers);
+            self.throughput_hints.insert(action_id, hint);
         }
+        if action_id == self.fetch_playlist_action_id && self.config.live_edge.correct_clock_skew {
+            self.playlist_response = Some(ResponseDate::parse(headers));
+        }
+        Ok(())
+    }
 
-        let transfer_delay = Duration::from_millis(u64::from(fetch_duration_ms / 2));
-        if polling_interval > transfer_delay {
-            polling_interval -= transfer_delay;
-        } else {
-            polling_interval = Duration::from_secs(0);
+    /// Handles the end of the response body given by `handle_data_chunk`.
+    pub fn handle_data_end(
+        &mut self,
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        let result = track!(self.handle_data(action_id, &body, fetch_duration_ms, final_url));
+        self.pool.give(body);
+        result
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    ///
+    /// `status` is the HTTP status code of the response, or `0` if no response was received.
+    /// Transient failures are retried according to the configured `RetryConfig`,
+    /// while permanent ones give up the resource immediately.
+    pub fn handle_error(&mut self, action_id: ActionId, status: u16) -> Result<()> {
+        let mut class = FailureClass::from_status(status);
+        if status == 404
+            && action_id == self.fetch_playlist_action_id
+            && self.is_playlist_loaded
+            && self.config.reload.retry_not_found
+        {
+            // The origin may be momentarily behind; backs off instead of giving up.
+            class = FailureClass::Transient;
         }
-        if !is_updated {
-            polling_interval /= 2;
+        if (status == 401 || status == 403) && self.refresh_token(action_id) {
+            // Retried with the fresh token.
+            class = FailureClass::Transient;
         }
+        let description = format!("status={}", status);
+        self.handle_failure(action_id, class, description);
+        Ok(())
+    }
 
-        self.action_queue
-            .push_back(self.action_factory.set_timeout(polling_interval));
+    /// Handles the fetch requested by `action_id` that has exceeded its timeout.
+    pub fn handle_fetch_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        let description = "timed out".to_owned();
+        self.handle_failure(action_id, FailureClass::Transient, description);
         Ok(())
     }
 
-    fn handle_segment(&mut self, ts_segment: &[u8]) -> Result<()> {
-        if let Some(x) = self.segment_queue.pop_front() {
-            if !x.1 {
-                self.segment_queue.push_front(x);
+    pub fn stats(&self) -> Stats {
+        let buffer_length_ms = self.action_factory.buffer_length_ms();
+        let live_latency_ms = self.measure_latency_ms().or_else(|| {
+            let pending = to_millis(self.pending_duration());
+            buffer_length_ms
+                .filter(|_| self.is_live)
+                .map(|b| b.saturating_add(pending))
+        });
+        Stats {
+            bitrate: None,
+            variant_index: None,
+            variant_switches: Vec::new(),
+            throughput_kbps: self.action_factory.throughput_kbps(),
+            buffer_length_ms,
+            bytes_downloaded: self.bytes_downloaded,
+            segments_downloaded: self.segments_downloaded,
+            queued_output_bytes: self.buffered_segments.bytes(),
+            live_latency_ms,
+            program_date_time_ms: self.latency
+                .playback_position()
+                .and_then(|p| self.latency.program_date_time_at(p))
+                .map(|t| t.as_secs_f64() * 1000.0),
+            clock_offset_ms: self.latency.clock_offset_ms(),
+            playlist_refresh: self.refresh.stats().clone(),
+            server_control: self.server_control.clone(),
+            ..Stats::default()
+        }
+    }
+
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        let was_capped = self.is_buffer_capped();
+        self.action_factory.set_buffer_length(buffer_length);
+        if was_capped && !self.is_buffer_capped() {
+            self.fetch_next_segment();
+        }
+        self.fetch_ahead();
+    }
+
+    /// Replaces `RenditionConfig::audio_languages`, from which the master playlist handler
+    /// selects the audio rendition.
+    pub fn set_audio_languages(&mut self, languages: Vec<String>) {
+        self.config.renditions.audio_languages = languages;
+    }
+
+    /// Enters or leaves the low-power mode (see `PlayerConfig::low_power`),
+    /// which takes effect from the next reload and segment fetch.
+    pub fn set_low_power(&mut self, enabled: bool) {
+        let was_capped = self.is_buffer_capped();
+        self.config.low_power.enabled = enabled;
+        if was_capped && !self.is_buffer_capped() {
+            self.fetch_next_segment();
+        }
+    }
+
+    /// Returns `true` if the buffer has reached `LowPowerConfig::max_buffer_ms`.
+    fn is_buffer_capped(&self) -> bool {
+        let low_power = &self.config.low_power;
+        let max_buffer_ms = low_power.max_buffer_ms.filter(|_| low_power.enabled);
+        match (max_buffer_ms, self.action_factory.buffer_length_ms()) {
+            (Some(max), Some(buffer_length_ms)) => buffer_length_ms >= max,
+            _ => false,
+        }
+    }
+
+    pub fn set_playback_position(&mut self, position: Duration) {
+        self.latency.set_playback_position(position);
+        if let Some(date_time) = self.latency.program_date_time_at(position) {
+            let events = self.date_ranges.on_program_date_time(date_time);
+            self.event_queue.extend(events);
+        }
+        let threshold = match self.config.latency_event_threshold_ms {
+            None => return,
+            Some(x) => x,
+        };
+        if let Some(latency_ms) = self.measure_latency_ms() {
+            let last = self.last_reported_latency_ms;
+            if last.is_none_or(|last| latency_ms.abs_diff(last) >= threshold) {
+                self.last_reported_latency_ms = Some(latency_ms);
+                self.event_queue
+                    .push_back(Event::LatencyChanged { latency_ms });
             }
         }
-        if let Some((_, _, url)) = self.segment_queue.pop_front() {
-            self.action_queue
-                .push_back(self.action_factory.fetch_data(url));
+    }
+
+    fn measure_latency_ms(&self) -> Option<u32> {
+        if !self.is_live {
+            return None;
         }
+        let now = self.config.hooks.now()?;
+        self.latency.latency(now).map(to_millis)
+    }
 
-        let fmp4_segments = track!(mpeg2_ts::to_fmp4(TsPacketReader::new(ts_segment)))?;
+    /// Returns the total duration of the segments yet to be fetched.
+    fn pending_duration(&self) -> Duration {
+        self.fetching_segment
+            .iter()
+            .map(|f| &f.segment)
+            .chain(self.segment_queue.iter())
+            .fold(Duration::from_secs(0), |acc, s| acc + s.duration)
+    }
 
-        if !self.is_initialized {
-            let mut initialization_segment = Vec::new();
-            track!(fmp4_segments.0.write_to(&mut initialization_segment))?;
-            self.buffered_segments.push_back(initialization_segment);
+    /// Halts the fetches and timers until `resume`, keeping the position in the playlist.
+    pub fn suspend(&mut self) {
+        if self.suspension.is_some() {
+            return;
+        }
+        log!(self.config, Info, "Suspended the playback of {}", self.media_playlist_url);
+        let next_duration = self.fetching_segment
+            .iter()
+            .map(|f| &f.segment)
+            .chain(self.segment_queue.iter())
+            .next()
+            .map_or(Duration::from_secs(0), |s| s.duration);
+        self.suspension = Some(Suspension {
+            since: self.config.hooks.now(),
+            behind_live: self.pending_duration(),
+            next_duration,
+        });
+        self.action_queue.retain(|a| !a.is_fetch_data());
+        self.timers.clear();
+        self.reload_due_at = None;
+        if let Some(fetch) = self.fetching_segment.take() {
+            self.segment_queue.push_front(fetch.segment);
+        }
+        if let Some(next) = self.next_asset.as_mut() {
+            // Fetched again when the playlist starts.
+            next.fetch_action_id = None;
+        }
+        self.partial_bodies.clear();
+        self.throughput_hints.clear();
+        self.playlist_response = None;
+        self.ahead_fetches.clear();
+        for action_id in mem::take(&mut self.in_flight_fetches) {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
 
-            self.is_initialized = true;
+    /// Resumes the playback halted by `suspend`, reloading the playlist.
+    ///
+    /// Live playbacks continue from the position unless it has left the playlist window
+    /// or fallen more than `LiveEdgeConfig::max_catch_up_ms` behind the live edge
+    /// in the meantime, in which case they jump to the live point.
+    /// Without `Hooks::clock`, they always continue.
+    pub fn resume(&mut self) {
+        let suspension = match self.suspension.take() {
+            None => return,
+            Some(x) => x,
+        };
+        let suspended_for = self.config
+            .hooks
+            .now()
+            .zip(suspension.since)
+            .map(|(now, since)| now.saturating_sub(since));
+        let behind_live = suspended_for.map(|d| suspension.behind_live + d);
+        let max_catch_up = self.config
+            .live_edge
+            .max_catch_up_ms
+            .map(|ms| Duration::from_millis(u64::from(ms)));
+        // Retained segments outlive the window (see `drop_expired_segments`).
+        let time_shift = &self.config.time_shift;
+        let window_duration = self.window.iter().map(|&(_, d)| d).sum();
+        let window = if time_shift.enabled {
+            let max = Duration::from_millis(u64::from(time_shift.max_duration_ms));
+            cmp::max(window_duration, max)
+        } else {
+            window_duration
+        };
+        let jumped_to_live = self.is_live && behind_live.is_some_and(|behind| {
+            let next_end_behind = behind.saturating_sub(suspension.next_duration);
+            next_end_behind >= window || max_catch_up.is_some_and(|max| behind > max)
+        });
+        log!(
+            self.config,
+            Info,
+            "Resumed the playback of {}: suspended_for={:?}, behind_live={:?}, jump={}",
+            self.media_playlist_url,
+            suspended_for,
+            behind_live,
+            jumped_to_live
+        );
+        if jumped_to_live {
+            // Those still listed may be fetched again from the live point.
+            for segment in self.segment_queue.iter().filter(|s| !s.is_ad) {
+                self.segment_history.remove(&ResourceKey {
+                    url: segment.url.clone(),
+                    range: segment.range,
+                });
+            }
+            self.discard_pending_segments();
+            self.is_rejoining_live = true;
+        }
+        self.event_queue.push_back(Event::PlaybackResumed {
+            suspended_ms: suspended_for.map(|d| d.as_millis() as u64),
+            jumped_to_live,
+        });
+        self.stalled_for = Duration::from_secs(0);
+        if !self.is_playlist_failed {
+            self.request_playlist();
         }
+        self.fetch_next_segment();
+    }
 
-        let mut media_segment = Vec::new();
-        track!(fmp4_segments.1.write_to(&mut media_segment))?;
-        self.buffered_segments.push_back(media_segment);
+    pub fn is_suspended(&self) -> bool {
+        self.suspension.is_some()
+    }
 
-        Ok(())
+    /// Cancels all the pending fetches and timers.
+    ///
+    /// Fetches that have already been handed to the host are aborted by `Action::Abort`.
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.timers.clear();
+        self.segment_queue.clear();
+        self.fetching_segment = None;
+        self.awaiting_audio = None;
+        self.partial_bodies.clear();
+        self.throughput_hints.clear();
+        self.playlist_response = None;
+        self.ahead_fetches.clear();
+        for action_id in mem::take(&mut self.in_flight_fetches) {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
     }
 
-    fn parse_segment_url(&self, segment_url: &str) -> Result<Url> {
-        track!(
-            Url::options()
-                .base_url(Some(&self.media_playlist_url))
-                .parse(segment_url)
-                .map_err(Error::from)
-        )
+    /// Records the throughput of a fetched segment, corrected by the `hint` of its response.
+    fn record_throughput(&mut self, bytes: usize, fetch_duration_ms: u32, hint: ThroughputHint) {
+        let transfer_duration_ms = hint.transfer_duration_ms(fetch_duration_ms);
+        self.action_factory
+            .record_throughput(bytes, transfer_duration_ms);
+        if let Some(etp_kbps) = hint.etp_kbps {
+            log!(self.config, Debug, "Throughput hinted by CMSD: {} kbps", etp_kbps);
+            self.action_factory.record_throughput_hint(etp_kbps);
+        }
+    }
+
+    fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: String) {
+        self.partial_bodies.remove(&action_id);
+        self.throughput_hints.remove(&action_id);
+        if action_id == self.fetch_playlist_action_id {
+            self.playlist_response = None;
+        }
+        if !self.in_flight_fetches.remove(&action_id) {
+            return;
+        }
+        if let Some(resource) = self.ahead_fetches.remove(&action_id) {
+            // Fetched again (with retries) in order.
+            log!(
+                self.config,
+                Debug,
+                "Fetch ahead of {} failed: {}",
+                resource.url,
+                description
+            );
+            return;
+        }
+        log!(self.config, Warn, "Fetch failed: action_id={:?}, {}", action_id, description);
+        self.trace.record(|| TraceEntry::FetchFailed {
+            action_id,
+            reason: description.clone(),
+        });
+        self.failure_streak.on_failure(self.config.hooks.now());
+        if self.check_progress_timeout() {
+            return;
+        }
+        let failover = self.action_factory.record_fetch_failure();
+        let is_failed_over = failover.is_some();
+        if let Some(Event::CdnFailover { ref from, ref to }) = failover {
+            log!(self.config, Warn, "CDN failover: {} -> {}", from, to);
+        }
+        self.event_queue.extend(failover);
+
+        if let Some(next) = self.next_asset.as_mut() {
+            if next.fetch_action_id == Some(action_id) {
+                // Fetched again (with retries) when the playlist starts.
+                next.fetch_action_id = None;
+                self.fetch_next_segment();
+                return;
+            }
+        }
+        if action_id == self.fetch_playlist_action_id {
+            if is_failed_over {
+                // Reloads from the new CDN right away.
+                self.set_timer(Duration::from_secs(0), Timer::ReloadPlaylist);
+                return;
+            }
+            let reason = format!(
+                "Cannot fetch the media playlist {}: {}, class={:?}",
+                self.media_playlist_url, description, class
+            );
+            self.handle_playlist_failure(class, reason);
+            return;
+        }
+        if !self.is_fetching_segment(action_id) {
+            return;
+        }
+
+        let mut fetch = self.fetching_segment.take().expect("Never fails");
+        fetch.attempts += 1;
+        let kind = fetch.kind;
+        if is_failed_over {
+            self.set_timer(Duration::from_secs(0), Timer::RetrySegment);
+            self.fetching_segment = Some(fetch);
+        } else if !class.is_permanent() && fetch.attempts < self.config.retry(kind).max_attempts {
+            let delay = self.config.retry(kind).delay(fetch.attempts);
+            self.failure_streak.on_retry(delay);
+            self.set_timer(delay, Timer::RetrySegment);
+            self.fetching_segment = Some(fetch);
+        } else {
+            // Gives up the segment and continues with the next one.
+            let reason = format!(
+                "Skipped the segment {}: {}, class={:?}, attempts={}",
+                fetch.segment.url, description, class, fetch.attempts
+            );
+            log!(self.config, Warn, "{}", reason);
+            self.event_queue.push_back(Event::Error {
+                fatal: false,
+                reason,
+                history: Vec::new(),
+            });
+            self.fetch_next_segment();
+        }
+    }
+
+    /// Asks `Hooks::token_refresher` for a fresh token after the fetch `action_id` has been
+    /// rejected as unauthorized, and returns `true` if it is applied to the following fetches.
+    ///
+    /// Segments waiting to be fetched get the token, since their URLs are resolved on fetching.
+    fn refresh_token(&mut self, action_id: ActionId) -> bool {
+        let refresher = match self.config.hooks.token_refresher {
+            Some(ref refresher) if self.token_refreshes < MAX_TOKEN_REFRESHES => refresher.clone(),
+            _ => return false,
+        };
+        let (kind, url) = if action_id == self.fetch_playlist_action_id {
+            (ResourceKind::Playlist, self.media_playlist_url.clone())
+        } else {
+            match self.fetching_segment {
+                Some(ref f) if f.action_id == action_id => (f.kind, f.resource.url.clone()),
+                _ => return false,
+            }
+        };
+        let params = match refresher.refresh_token(kind, &url) {
+            Some(params) => params,
+            None => return false,
+        };
+        self.token_refreshes += 1;
+        log!(self.config, Info, "Refreshed the token after the rejected fetch of {}", url);
+        // The parameters of the previous refresh are replaced as a whole, so that no stale token
+        // goes out along with the fresh one, even under another name.
+        let stale = mem::replace(&mut self.refreshed_query_keys, params.keys().cloned().collect());
+        self.config.query_params.retain(|k, _| !stale.contains(k));
+        self.config.query_params.extend(params);
+        self.action_factory
+            .set_query_params(&self.config.query_params);
+        true
+    }
+
+    /// Takes over the token refreshed by `other`, whose `config` this handler has been made with.
+    pub fn inherit_refreshed_token(&mut self, other: &MediaPlaylistHandler) {
+        self.refreshed_query_keys = other.refreshed_query_keys.clone();
+    }
+
+    /// Stops the playback with a fatal error if the fetches have kept failing
+    /// for `PlayerConfig::no_progress_timeout_ms`.
+    fn check_progress_timeout(&mut self) -> bool {
+        let timeout_ms = match self.config.no_progress_timeout_ms {
+            Some(timeout_ms) => timeout_ms,
+            None => return false,
+        };
+        let failing_for = self.failure_streak.duration(self.config.hooks.now());
+        if failing_for < Duration::from_millis(u64::from(timeout_ms)) {
+            return false;
+        }
+        let reason = format!(
+            "The playback of {} has made no progress for {} ms (no_progress_timeout_ms={})",
+            self.media_playlist_url,
+            to_millis(failing_for),
+            timeout_ms
+        );
+        log!(self.config, Error, "{}", reason);
+        self.stop();
+        self.is_playlist_failed = true;
+        self.event_queue.push_back(Event::Error {
+            fatal: true,
+            reason,
+            history: self.trace.history(),
+        });
+        true
+    }
+
+    fn request_playlist(&mut self) {
+        if !data_url::is_data_url(&self.media_playlist_url) {
+            let mut url = self.media_playlist_url.clone();
+            let next_seq = self.last_media_sequence
+                .filter(|_| self.is_blocking_reload())
+                .map(|last| last + 1);
+            if let Some(seq) = next_seq {
+                url.query_pairs_mut()
+                    .append_pair("_HLS_msn", &seq.to_string());
+            }
+            let mut action = self.action_factory
+                .fetch_data(url, ResourceKind::Playlist, None);
+            if let (Some(_), Some(d)) = (next_seq, self.target_duration) {
+                // Blocked requests may be held for up to three target durations.
+                if let Action::FetchData { ref mut timeout, .. } = action {
+                    *timeout = timeout.saturating_add(to_millis(d * 3));
+                }
+            }
+            self.fetch_playlist_action_id = action.id();
+            self.action_queue.push_back(action);
+            return;
+        }
+
+        let result = data_url::decode(&self.media_playlist_url)
+            .and_then(|data| track!(self.handle_playlist(&data, 0)));
+        match result {
+            Ok(()) => self.is_playlist_loaded = true,
+            Err(e) => self.handle_playlist_failure(FailureClass::Permanent, e.to_string()),
+        }
+    }
+
+    /// Handles the body `data` of the media playlist.
+    ///
+    /// Reloads which repeat the segments handled before are reduced to the new ones
+    /// (see `playlist_diff`), which are decoded, repaired and parsed alone.
+    fn handle_playlist(&mut self, data: &[u8], fetch_duration_ms: u32) -> Result<()> {
+        let previous_media_sequence = self.playlist_media_sequence;
+        let tail = self.playlist_diff
+            .reload(data, self.last_media_sequence)
+            .filter(|t| previous_media_sequence.is_none_or(|p| p <= t.media_sequence))
+            .filter(|t| target_duration::is_valid(&t.m3u8));
+        let m3u8 = match tail {
+            Some(ref tail) => Cow::Borrowed(&tail.m3u8[..]),
+            None => text::decode(data),
+        };
+        let (m3u8, discontinuity_sequence) = discontinuity::split_sequence(&m3u8);
+        let (m3u8, inferred_target_duration) = target_duration::repair(&m3u8);
+        let m3u8 = &*m3u8;
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        let media_sequence = tail.as_ref().map_or(first_seq, |t| t.media_sequence);
+        let total_segments = tail.as_ref()
+            .map_or(playlist.segments().len(), |t| t.segments);
+
+        // A tail continues from the state after the last handled segment.
+        let (mut byte_range_end, mut next_program_date_time, mut discontinuity, mut map) =
+            match tail {
+                Some(_) => (
+                    self.byte_range_end,
+                    self.next_program_date_time,
+                    self.discontinuity,
+                    self.map.clone(),
+                ),
+                None => (0, None, discontinuity_sequence, None),
+            };
+        let (mut encryption, mut map_encryption) = match tail {
+            Some(_) => (self.encryption.clone(), self.map_encryption.clone()),
+            None => (None, None),
+        };
+        log!(
+            self.config,
+            Debug,
+            "Parsed the media playlist {}: media_sequence={}, segments={}, new_from={}",
+            self.media_playlist_url,
+            media_sequence,
+            total_segments,
+            first_seq
+        );
+        if let Some(previous) = previous_media_sequence.filter(|&p| media_sequence < p) {
+            self.handle_media_sequence_reset(previous, media_sequence);
+        }
+        let max_gap = self.config.reload.max_sequence_gap;
+        let jumped_from = self.last_media_sequence
+            .filter(|&last| media_sequence > last.saturating_add(max_gap).saturating_add(1));
+        if let Some(last) = jumped_from {
+            self.handle_media_sequence_jump(last, media_sequence);
+        }
+        self.playlist_media_sequence = Some(media_sequence);
+        self.drop_expired_segments(media_sequence);
+
+        let mut new_segments = 0;
+        self.is_live = playlist.end_list_tag().is_none();
+        if tail.is_none() {
+            self.window.clear();
+        }
+        self.window.retain(|&(seq, _)| seq >= media_sequence);
+        let window_end = self.window.back().map(|&(seq, _)| seq + 1);
+        let listed = playlist.segments().iter().enumerate();
+        self.window.extend(
+            listed
+                .map(|(i, s)| (first_seq + i as u64, s.inf_tag().duration()))
+                .filter(|&(seq, _)| window_end.is_none_or(|end| seq >= end)),
+        );
+        if !self.is_live && tail.is_none() {
+            let timeline = Timeline::new(&playlist, media_sequence);
+            self.event_queue.push_back(Event::TimelineLoaded {
+                duration_ms: timeline.duration_ms,
+                segments: timeline.segments.len(),
+            });
+            self.timeline = Some(timeline);
+        }
+        self.trace.record(|| TraceEntry::PlaylistParsed {
+            url: self.media_playlist_url.clone(),
+            media_sequence,
+            segments: total_segments,
+            is_live: self.is_live,
+        });
+        self.date_ranges.update(m3u8);
+        let mut cues = stitching::parse_cues(m3u8).into_iter().peekable();
+        let keys_before_maps = segment_resources::keys_before_maps(m3u8);
+        let target_duration = playlist.target_duration_tag().duration();
+        if inferred_target_duration.is_some() && self.target_duration != Some(target_duration) {
+            log!(
+                self.config,
+                Warn,
+                "Missing or invalid EXT-X-TARGETDURATION in {}: inferred {:?} from EXTINF",
+                self.media_playlist_url,
+                target_duration
+            );
+        }
+        let mut polling_interval = target_duration;
+        self.action_factory.set_target_duration(target_duration);
+        self.target_duration = Some(target_duration);
+        self.server_control = ServerControl::parse(m3u8);
+
+        if let Some(point) = self.resume_point.take().filter(|_| tail.is_none()) {
+            if let Some(seq) = aligned_sequence(&playlist, first_seq, &point, self.is_live) {
+                if seq != point.sequence {
+                    log!(
+                        self.config,
+                        Info,
+                        "Aligned the switch to the segment #{} of {} (from #{})",
+                        seq,
+                        self.media_playlist_url,
+                        point.sequence
+                    );
+                }
+                self.resume_after = seq.checked_sub(1);
+            }
+        }
+
+        // Live playbacks (re)join `HOLD-BACK` behind the end, rather than at the oldest segment,
+        // both when starting and when the position has fallen out of the playlist window.
+        let position = self.last_media_sequence.or(self.resume_after);
+        let live_point = if self.is_live
+            && (self.config.live_edge.hold_back || jumped_from.is_some() || self.is_rejoining_live)
+            && tail.is_none()
+            && position.is_none_or(|last| last + 1 < first_seq)
+        {
+            let durations = playlist
+                .segments()
+                .iter()
+                .map(|s| s.inf_tag().duration())
+                .collect::<Vec<_>>();
+            let hold_back = self.server_control.hold_back(target_duration);
+            let seq = first_seq + live_point(&durations, hold_back) as u64;
+            if seq > first_seq {
+                log!(
+                    self.config,
+                    Info,
+                    "Joined the live playlist {} at the segment #{} (hold_back={:?})",
+                    self.media_playlist_url,
+                    seq,
+                    hold_back
+                );
+            }
+            seq.checked_sub(1)
+        } else {
+            None
+        };
+        if tail.is_none() {
+            self.is_rejoining_live = false;
+        }
+        let start_point = match self.start_position.take() {
+            Some(start) if tail.is_none() && position.is_none() => {
+                let mut end = Duration::from_secs(0);
+                let index = playlist
+                    .segments()
+                    .iter()
+                    .position(|s| {
+                        end += s.inf_tag().duration();
+                        end > start
+                    })
+                    .unwrap_or_else(|| playlist.segments().len().saturating_sub(1));
+                let seq = first_seq + index as u64;
+                log!(
+                    self.config,
+                    Info,
+                    "Starts the media playlist {} at the segment #{} (position={:?})",
+                    self.media_playlist_url,
+                    seq,
+                    start
+                );
+                seq.checked_sub(1)
+            }
+            _ => None,
+        };
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            let seq = first_seq + i as u64;
+            let mut cue = None;
+            while let Some((_, c)) = cues.next_if(|&(index, _)| index == i) {
+                cue = Some(c);
+            }
+            if segment.discontinuity_tag().is_some() {
+                discontinuity += 1;
+            }
+
+            // Segments without `EXT-X-PROGRAM-DATE-TIME` follow the preceding ones.
+            let program_date_time = segment
+                .program_date_time_tag()
+                .and_then(|t| date_time::parse(t.date_time()))
+                .or(next_program_date_time);
+            next_program_date_time = program_date_time.map(|t| t + segment.inf_tag().duration());
+
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+
+            // An `EXT-X-KEY` applies to the following segments and `EXT-X-MAP`s
+            // until the next one.
+            let key_tags = segment.key_tags();
+            if segment.map_tag().is_some() {
+                let n = keys_before_maps.get(&i).map_or(0, |&n| cmp::min(n, key_tags.len()));
+                map_encryption = if n > 0 {
+                    track!(self.parse_key(&key_tags[..n]))?
+                } else {
+                    encryption.clone()
+                };
+            }
+            if !key_tags.is_empty() {
+                encryption = track!(self.parse_key(key_tags))?;
+            }
+
+            // An `EXT-X-MAP` applies to the following segments until the next one,
+            // possibly across discontinuities.
+            if let Some(tag) = segment.map_tag() {
+                map = Some(track!(segment_resources::map_key(&self.base_url, tag))?);
+            }
+
+            let last = cmp::max(cmp::max(position, live_point), start_point);
+            if last.is_some_and(|last| seq <= last) {
+                continue;
+            }
+            new_segments += 1;
+
+            self.last_media_sequence = Some(seq);
+            self.segments_total += 1;
+            self.segment_durations_total += segment.inf_tag().duration();
+
+            let url = track!(self.parse_segment_url(segment.uri()))?;
+            let key = ResourceKey {
+                url: url.clone(),
+                range,
+            };
+            if let Some(Cue::Out { ref id, duration }) = cue {
+                log!(self.config, Info, "Detected an ad break at the segment #{}", seq);
+                self.event_queue.push_back(Event::AdBreakDetected {
+                    id: id.clone(),
+                    sequence: seq,
+                    duration_ms: duration.map(|d| d.as_secs_f64() * 1000.0),
+                });
+            }
+            if self.segment_history.contains(&key, program_date_time) {
+                log!(self.config, Debug, "Skipped the already queued segment {}", url);
+                continue;
+            }
+            self.segment_history.insert(key, program_date_time);
+            self.action_queue.extend(self.action_factory.preconnect(&url));
+            self.segment_queue.push_back(Segment {
+                seq,
+                discontinuity,
+                url,
+                range,
+                init: map.clone(),
+                key: encryption.clone(),
+                init_key: map_encryption.clone(),
+                duration: segment.inf_tag().duration(),
+                program_date_time,
+                cue,
+                is_ad: false,
+                is_discontinuity: mem::take(&mut self.is_next_asset_starting),
+            });
+            polling_interval = cmp::min(polling_interval, segment.inf_tag().duration());
+        }
+        self.byte_range_end = byte_range_end;
+        self.next_program_date_time = next_program_date_time;
+        self.map = map;
+        self.encryption = encryption;
+        self.map_encryption = map_encryption;
+        self.discontinuity = discontinuity;
+        self.refresh.on_playlist_loaded(
+            &self.config.hooks,
+            data.len(),
+            total_segments,
+            new_segments,
+            target_duration,
+        );
+        self.fetch_next_segment();
+        let pending = self.pending_duration();
+        self.latency
+            .on_playlist_loaded(self.config.hooks.now(), pending);
+
+        if self.segments_total > 0 {
+            let average_segment_duration = self.segment_durations_total / self.segments_total;
+            polling_interval = cmp::min(polling_interval, average_segment_duration);
+        } else {
+            self.segment_durations_total = Duration::from_secs(0);
+        }
+
+        // Anchored reloads already account for the time spent since the reload was due.
+        let anchor = self.config.hooks.now().zip(self.reload_due_at);
+        if anchor.is_none() {
+            let transfer_delay = Duration::from_millis(u64::from(fetch_duration_ms / 2));
+            polling_interval = polling_interval.saturating_sub(transfer_delay);
+        }
+        if new_segments > 0 {
+            self.unchanged_reloads = 0;
+            self.on_playlist_advanced();
+        } else {
+            polling_interval /= 2;
+            polling_interval = self.unchanged_backoff(polling_interval, target_duration);
+            self.unchanged_reloads += 1;
+        }
+
+        if data_url::is_data_url(&self.media_playlist_url) {
+            // Inline playlists never change.
+            return Ok(());
+        }
+        if new_segments == 0 {
+            self.check_stall(Duration::from_millis(u64::from(fetch_duration_ms)));
+        }
+        if new_segments > 0 && self.is_blocking_reload() {
+            // The server holds the next reload until the following segment is listed.
+            self.set_timer(Duration::from_secs(0), Timer::ReloadPlaylist);
+            return Ok(());
+        }
+        if self.config.low_power.enabled {
+            let factor = self.config.low_power.reload_interval_factor.clamp(1.0, 100.0);
+            polling_interval = polling_interval.mul_f64(factor);
+        }
+        let polling_interval = self.jitter(polling_interval);
+        let delay = match anchor {
+            None => polling_interval,
+            Some((now, due)) => anchored_delay(now, due + polling_interval, polling_interval),
+        };
+        self.set_timer(delay, Timer::ReloadPlaylist);
+        Ok(())
+    }
+
+    fn on_playlist_advanced(&mut self) {
+        if self.is_stall_reported {
+            let stalled_ms = self.stalled_for.as_millis() as u64;
+            log!(self.config, Info, "The live playlist {} has resumed", self.media_playlist_url);
+            self.event_queue
+                .push_back(Event::LiveStreamResumed { stalled_ms });
+        }
+        self.stalled_for = Duration::from_secs(0);
+        self.is_stall_reported = false;
+    }
+
+    /// Adds `elapsed` to the time without new segments,
+    /// and emits `Event::LiveStreamStalled` once it exceeds the threshold.
+    ///
+    /// The time is the sum of the playlist fetch durations and the elapsed reload intervals
+    /// since the last new segment, so that it is measured without `Hooks::clock`.
+    fn check_stall(&mut self, elapsed: Duration) {
+        self.stalled_for += elapsed;
+        let ratio = self.config.reload.stall_threshold_ratio;
+        let target_duration = match self.target_duration {
+            Some(d) if self.is_live && ratio > 0.0 && !self.is_stall_reported => d,
+            _ => return,
+        };
+        if self.stalled_for.as_secs_f64() <= target_duration.as_secs_f64() * ratio {
+            return;
+        }
+        self.is_stall_reported = true;
+        let stalled_ms = self.stalled_for.as_millis() as u64;
+        log!(
+            self.config,
+            Warn,
+            "The live playlist {} has not advanced for {} ms",
+            self.media_playlist_url,
+            stalled_ms
+        );
+        self.event_queue
+            .push_back(Event::LiveStreamStalled { stalled_ms });
+    }
+
+    /// Handles the decrease of the media sequence (e.g., by an encoder restart),
+    /// after which all the listed segments are regarded as new ones.
+    fn handle_media_sequence_reset(&mut self, previous: SequenceNumber, current: SequenceNumber) {
+        log!(
+            self.config,
+            Warn,
+            "The media sequence of {} has been reset: {} -> {}",
+            self.media_playlist_url,
+            previous,
+            current
+        );
+        self.discard_pending_segments();
+
+        // Restarted encoders may reuse the URIs for new media.
+        self.segment_history.clear();
+        self.event_queue
+            .push_back(Event::MediaSequenceReset { previous, current });
+    }
+
+    /// Drops the queued segments which have left the playlist window starting at
+    /// `media_sequence`, except for the latest ones retained by `PlayerConfig::time_shift`.
+    fn drop_expired_segments(&mut self, media_sequence: SequenceNumber) {
+        let time_shift = &self.config.time_shift;
+        let mut retainable = if time_shift.enabled {
+            Duration::from_millis(u64::from(time_shift.max_duration_ms))
+        } else {
+            Duration::from_secs(0)
+        };
+        let mut oldest_retained = media_sequence;
+        let expired = self.segment_queue
+            .iter()
+            .rev()
+            .filter(|s| !s.is_ad && s.seq < media_sequence);
+        for segment in expired {
+            if segment.duration > retainable {
+                break;
+            }
+            retainable -= segment.duration;
+            oldest_retained = segment.seq;
+        }
+        if time_shift.enabled && oldest_retained < media_sequence {
+            log!(
+                self.config,
+                Debug,
+                "Retained the segments from #{} behind the window of {}",
+                oldest_retained,
+                self.media_playlist_url
+            );
+        }
+        let is_dropped = |s: &Segment| !s.is_ad && s.seq < oldest_retained;
+        if time_shift.enabled && self.segment_queue.iter().any(is_dropped) {
+            log!(
+                self.config,
+                Warn,
+                "Dropped the segments before #{} beyond the time-shift limit",
+                oldest_retained
+            );
+        }
+        self.segment_queue.retain(|s| !is_dropped(s));
+    }
+
+    /// Handles a live playlist starting far after the last listed segment `previous`,
+    /// whose segments in between have expired.
+    fn handle_media_sequence_jump(&mut self, previous: SequenceNumber, current: SequenceNumber) {
+        log!(
+            self.config,
+            Warn,
+            "The media sequence of {} has jumped: {} -> {} ({} segments missed)",
+            self.media_playlist_url,
+            previous,
+            current,
+            current - previous - 1
+        );
+        self.discard_pending_segments();
+        self.event_queue
+            .push_back(Event::MediaSequenceJumped { previous, current });
+    }
+
+    /// Forgets the position in the playlist, and aborts the queued and in-flight segments.
+    fn discard_pending_segments(&mut self) {
+        self.last_media_sequence = None;
+        self.resume_after = None;
+        self.segment_queue.clear();
+        self.ad_break = None;
+        self.awaiting_audio = None;
+        if let Some(fetch) = self.fetching_segment.take() {
+            if self.in_flight_fetches.remove(&fetch.action_id) {
+                self.action_queue.push_back(Action::Abort {
+                    action_id: fetch.action_id,
+                });
+            }
+            self.partial_bodies.remove(&fetch.action_id);
+            self.throughput_hints.remove(&fetch.action_id);
+        }
+        self.timers.retain(|_, timer| *timer != Timer::RetrySegment);
+        for (action_id, _) in mem::take(&mut self.ahead_fetches) {
+            if self.in_flight_fetches.remove(&action_id) {
+                self.action_queue.push_back(Action::Abort { action_id });
+            }
+            self.partial_bodies.remove(&action_id);
+            self.throughput_hints.remove(&action_id);
+        }
+        self.preloaded_segments.clear();
+        self.remuxed_ahead.clear();
+    }
+
+    /// Lengthens `interval` according to the number of consecutive reloads without new segments.
+    fn unchanged_backoff(&self, interval: Duration, target_duration: Duration) -> Duration {
+        let reload = &self.config.reload;
+        if self.unchanged_reloads == 0 || reload.unchanged_backoff_factor <= 1.0 {
+            return interval;
+        }
+        let exponent = cmp::min(self.unchanged_reloads, 32) as i32;
+        let factor = reload.unchanged_backoff_factor.powi(exponent);
+        let max_secs = target_duration.as_secs_f64() * reload.max_backoff_ratio.clamp(0.0, 100.0);
+        let secs = (interval.as_secs_f64() * factor).min(max_secs);
+        cmp::max(interval, Duration::from_secs_f64(secs))
+    }
+
+    /// Randomly deviates `interval` by up to `ReloadConfig::jitter_ratio`.
+    fn jitter(&mut self, interval: Duration) -> Duration {
+        let ratio = self.config.reload.jitter_ratio.clamp(0.0, 1.0);
+        let deviation = (self.random.next_f64() * 2.0 - 1.0) * ratio;
+        interval.mul_f64(1.0 + deviation)
+    }
+
+    fn handle_playlist_failure(&mut self, class: FailureClass, reason: String) {
+        self.playlist_failures += 1;
+        if !class.is_permanent() && self.playlist_failures < self.config.playlist_retry.max_attempts
+        {
+            self.check_stall(Duration::from_secs(0));
+            let delay = self.config.playlist_retry.delay(self.playlist_failures);
+            self.failure_streak.on_retry(delay);
+            self.set_timer(delay, Timer::ReloadPlaylist);
+        } else {
+            // Stops reloading; the already queued segments are still served.
+            log!(self.config, Error, "{}", reason);
+            self.is_playlist_failed = true;
+            self.event_queue.push_back(Event::Error {
+                fatal: true,
+                reason,
+                history: self.trace.history(),
+            });
+        }
+    }
+
+    /// Reports the segment which cannot be decrypted or remuxed by a non-fatal `Event::Error`.
+    fn skip_undecodable_segment(&mut self, reason: String) {
+        log!(self.config, Warn, "{}", reason);
+        self.undecodable_segments += 1;
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    /// Logs the streams remuxed from the segment `seq` if they differ from the previous ones.
+    fn log_tracks(&mut self, seq: SequenceNumber, tracks: Option<SelectedTracks>) {
+        if tracks.is_none() || tracks == self.tracks {
+            return;
+        }
+        if let Some(ref t) = tracks {
+            if t.is_fallback {
+                log!(
+                    self.config,
+                    Warn,
+                    "The streams requested by the track config are not in the segment {}: \
+                     remuxing video={:?}, audio={:?}",
+                    seq,
+                    t.video_pid,
+                    t.audio_pid
+                );
+            } else if !t.dropped_pids.is_empty() {
+                log!(
+                    self.config,
+                    Info,
+                    "Remuxing video={:?}, audio={:?} of the segment {} (dropped PIDs: {:?})",
+                    t.video_pid,
+                    t.audio_pid,
+                    seq,
+                    t.dropped_pids
+                );
+            }
+        }
+        self.tracks = tracks;
+    }
+
+    /// Replaces the duration of `segment` by the one measured from `timestamps` if they differ
+    /// by `DurationCheckConfig::tolerance_ms` or more.
+    fn check_duration(
+        &mut self,
+        segment: &mut Segment,
+        timestamps: &StreamTimestamps,
+        sample_rate: Option<u32>,
+    ) {
+        let measured = match timestamps.duration(sample_rate) {
+            None => return,
+            Some(x) => x,
+        };
+        let declared = segment.duration;
+        let difference = cmp::max(measured, declared) - cmp::min(measured, declared);
+        let tolerance = Duration::from_millis(u64::from(self.config.duration_check.tolerance_ms));
+        if difference < tolerance {
+            return;
+        }
+        log!(
+            self.config,
+            Warn,
+            "The segment {} lasts {:?} rather than its EXTINF duration {:?}",
+            segment.seq,
+            measured,
+            declared
+        );
+        segment.duration = measured;
+        if !segment.is_ad {
+            if let Some(timeline) = self.timeline.as_mut() {
+                timeline.adjust(segment.seq, measured);
+            }
+        }
+        self.event_queue.push_back(Event::SegmentDurationMismatch {
+            sequence: segment.seq,
+            declared_ms: declared.as_secs_f64() * 1000.0,
+            measured_ms: measured.as_secs_f64() * 1000.0,
+        });
+    }
+
+    /// Measures the drift of the audio of `media_segment` from `timestamps`,
+    /// and corrects and reports it according to `PlayerConfig::av_sync`.
+    fn sync_audio(
+        &mut self,
+        seq: u64,
+        timestamps: &StreamTimestamps,
+        sample_rate: u32,
+        media_segment: &mut MediaSegment,
+    ) {
+        let drift_ms = match av_sync::measure(timestamps, media_segment, sample_rate) {
+            None => return,
+            Some(x) => x,
+        };
+        if drift_ms.abs() < f64::from(self.config.av_sync.threshold_ms) {
+            return;
+        }
+
+        let mut corrected = false;
+        if self.config.av_sync.mode == AvSyncMode::Correct {
+            match track!(av_sync::correct(timestamps, media_segment, sample_rate)) {
+                Ok((duplicated, dropped)) => {
+                    corrected = duplicated + dropped > 0;
+                    log!(
+                        self.config,
+                        Info,
+                        "Duplicated {} and dropped {} audio frames of the segment {} \
+                         drifting by {:.1} ms",
+                        duplicated,
+                        dropped,
+                        seq,
+                        drift_ms
+                    );
+                }
+                Err(e) => {
+                    log!(
+                        self.config,
+                        Warn,
+                        "Cannot correct the audio drift of the segment {}: {}",
+                        seq,
+                        e
+                    );
+                }
+            }
+        } else {
+            log!(
+                self.config,
+                Warn,
+                "The audio of the segment {} drifts by {:.1} ms",
+                seq,
+                drift_ms
+            );
+        }
+        self.event_queue.push_back(Event::AvSyncDrift {
+            sequence: seq,
+            drift_ms,
+            corrected,
+        });
+    }
+
+    /// Remuxes `input` of `segment` (`ts_segment` preceded by `init`).
+    ///
+    /// The following segments may have been preloaded (e.g., fetched ahead while catching up,
+    /// see `CatchUpConfig`). If the `parallel_remux` feature is enabled, those which are ready,
+    /// need neither decryption nor audio and share the init section are remuxed at once
+    /// (see `remux::remux_all`), and their results are kept in `remuxed_ahead`.
+    fn remux_with_next(
+        &mut self,
+        segment: &Segment,
+        input: &TsInput,
+        init: &[u8],
+        ts_segment: &[u8],
+    ) -> Result<Remuxed> {
+        let next = self.fetching_segment
+            .iter()
+            .filter(|f| f.kind == ResourceKind::Segment)
+            .map(|f| &f.segment)
+            .chain(self.segment_queue.iter())
+            .filter(|_| cfg!(feature = "parallel_remux") && !self.is_demuxed)
+            .take_while(|s| s.key.is_none() && s.init == segment.init)
+            .filter_map(|s| {
+                let resource = ResourceKey {
+                    url: s.url.clone(),
+                    range: s.range,
+                };
+                self.preloaded_segments.iter().find(|p| p.resource == resource)
+            })
+            .filter(|p| !self.remuxed_ahead.iter().any(|r| r.0 == p.resource))
+            .collect::<Vec<_>>();
+        if next.is_empty() {
+            return track!(input.remux(&self.config.tracks));
+        }
+        let jobs = Some((init, ts_segment))
+            .into_iter()
+            .chain(next.iter().map(|p| (init, &p.data[..])))
+            .collect::<Vec<_>>();
+        let mut results = remux::remux_all(&self.config.tracks, &jobs).into_iter();
+        let result = results.next().expect("Never fails");
+        let resources = next.iter().map(|p| p.resource.clone()).collect::<Vec<_>>();
+        self.remuxed_ahead.extend(resources.into_iter().zip(results));
+        track!(result)
+    }
+
+    /// Remuxes `ts_segment` of `segment` preceded by `init` and returns the size of the produced
+    /// fMP4 segment along with the offsets of its keyframes
+    /// (see `keyframe_index::keyframe_offsets`).
+    ///
+    /// Bytes not aligned to TS packets are skipped, and so are the packets of SCTE-35 streams,
+    /// whose cues are emitted as events (see `remux::TsInput`).
+    ///
+    /// The fMP4 initialization segment (only for the first one) and media segment are written
+    /// directly into a single output buffer, which is handed to the host by `next_segment`.
+    fn handle_segment(
+        &mut self,
+        segment: &mut Segment,
+        init: Option<&[u8]>,
+        ts_segment: &[u8],
+        audio: Option<(Option<u64>, &[u8])>,
+    ) -> Result<(usize, Vec<Duration>)> {
+        let seq = segment.seq;
+        let init = init.unwrap_or(&[]);
+        let input = track!(TsInput::new(init, ts_segment, audio.map(|(_, a)| a)))?;
+        if input.skipped > 0 {
+            log!(
+                self.config,
+                Warn,
+                "Skipped {} bytes of the segment {} to resynchronize TS packets",
+                input.skipped,
+                seq
+            );
+        }
+        let resource = ResourceKey {
+            url: segment.url.clone(),
+            range: segment.range,
+        };
+        let ahead = self.remuxed_ahead.iter().position(|r| r.0 == resource);
+        let remuxed = match ahead.map(|i| self.remuxed_ahead.remove(i)) {
+            Some((_, result)) => track!(result)?,
+            None => track!(self.remux_with_next(segment, &input, init, ts_segment))?,
+        };
+        let Remuxed {
+            initialization_segment,
+            mut media_segment,
+            tracks,
+        } = remuxed;
+        self.log_tracks(seq, tracks);
+        let mut cues = input.cues.clone();
+        let is_emsg_enabled = self.config.emsg.enabled;
+        let mut messages = Vec::new();
+        if self.config.av_sync.mode != AvSyncMode::Off
+            || self.config.duration_check.enabled
+            || is_emsg_enabled
+            || cues.iter().any(|c| c.pts.is_some())
+        {
+            let reader = TsPacketReader::new(input.reader());
+            let mut tracks = None;
+            let reader = TrackFilter::new(reader, &self.config.tracks, &mut tracks).keep_metadata();
+            match track!(StreamTimestamps::read(reader)) {
+                Ok(timestamps) => {
+                    let sample_rate = av_sync::sample_rate(&initialization_segment);
+                    if self.config.duration_check.enabled {
+                        self.check_duration(segment, &timestamps, sample_rate);
+                    }
+                    if is_emsg_enabled {
+                        emsg::write_id3(&self.config.emsg.id3, &timestamps, &mut messages);
+                    }
+                    if let Some(start) = timestamps.media_start() {
+                        for cue in &mut cues {
+                            cue.offset_ms = scte35::offset_ms(cue, start);
+                        }
+                    }
+                    let is_syncing = self.config.av_sync.mode != AvSyncMode::Off;
+                    if let Some(rate) = sample_rate.filter(|_| is_syncing) {
+                        self.sync_audio(seq, &timestamps, rate, &mut media_segment);
+                    }
+                }
+                Err(e) => {
+                    log!(
+                        self.config,
+                        Warn,
+                        "Cannot read the timestamps of the segment {}: {}",
+                        seq,
+                        e
+                    );
+                }
+            }
+        }
+
+        for cue in cues {
+            log!(self.config, Debug, "SCTE-35 cue in the segment {}: {:?}", seq, cue);
+            self.event_queue.push_back(Event::Scte35Cue { sequence: seq, cue });
+        }
+
+        if let Some(program_date_time) = segment.program_date_time.filter(|_| is_emsg_enabled) {
+            let end = program_date_time + segment.duration;
+            let ranges = self.date_ranges.ranges_starting(program_date_time, end);
+            let scheme = &self.config.emsg.date_range;
+            emsg::write_date_ranges(scheme, &ranges, program_date_time, &mut messages);
+        }
+
+        // fMP4 segments are slightly smaller than the TS ones.
+        let mut output = self.pool.take(input.segment.len() + messages.len());
+        let result = track!(self.write_segment(
+            seq,
+            &initialization_segment,
+            &messages,
+            &media_segment,
+            &mut output
+        ));
+        let offset = match result {
+            Ok(offset) => offset,
+            Err(e) => {
+                self.pool.give(output);
+                return Err(e);
+            }
+        };
+        let fmp4_bytes = output.len();
+        let is_initial = !self.is_initialized;
+        if let Some(ref mut recorder) = self.recorder {
+            let durations = recording::track_durations(&media_segment);
+            let init = Some(&output[..offset]).filter(|_| is_initial);
+            if let Err(e) = track!(recorder.record(init, &output[offset..], &durations)) {
+                let reason = format!("Cannot record the segment #{}: {}", seq, e);
+                log!(self.config, Warn, "{}", reason);
+                self.event_queue.push_back(Event::Error {
+                    fatal: false,
+                    reason,
+                    history: Vec::new(),
+                });
+            }
+        }
+        self.is_initialized = true;
+        let info = SegmentInfo {
+            sequence: seq,
+            discontinuity: segment.discontinuity,
+            variant: None,
+            duration_ms: segment.duration.as_secs_f64() * 1000.0,
+            program_date_time_ms: segment.program_date_time.map(|t| t.as_secs_f64() * 1000.0),
+            init_bytes: if is_initial { offset } else { 0 },
+            media_type: Some(&initialization_segment)
+                .filter(|_| is_initial)
+                .and_then(media_type::of),
+            audio_sequence: audio.and_then(|(sequence, _)| sequence),
+        };
+        self.push_output(OutputSegment { data: output, info });
+        Ok((fmp4_bytes, keyframe_index::keyframe_offsets(&media_segment)))
+    }
+
+    /// Writes the initialization segment (unless initialized), and the media segment
+    /// preceded by the `emsg` boxes `messages`, and returns the offset of the latter.
+    fn write_segment<I, M>(
+        &self,
+        seq: SequenceNumber,
+        initialization_segment: &I,
+        messages: &[u8],
+        media_segment: &M,
+        output: &mut Vec<u8>,
+    ) -> Result<usize>
+    where
+        I: WriteTo,
+        M: WriteTo,
+    {
+        if !self.is_initialized {
+            track!(initialization_segment.write_to(&mut *output))?;
+            let bytes = output.len();
+            self.trace.record(|| TraceEntry::FragmentProduced {
+                sequence: seq,
+                is_init: true,
+                bytes,
+            });
+        }
+        let offset = output.len();
+        output.extend_from_slice(messages);
+        track!(media_segment.write_to(&mut *output))?;
+        self.trace.record(|| TraceEntry::FragmentProduced {
+            sequence: seq,
+            is_init: false,
+            bytes: output.len() - offset,
+        });
+        Ok(offset)
+    }
+
+    fn is_fetching_segment(&self, action_id: ActionId) -> bool {
+        self.fetching_segment
+            .as_ref()
+            .is_some_and(|f| f.action_id == action_id)
+    }
+
+    fn push_output(&mut self, segment: OutputSegment) {
+        let seq = segment.info.sequence;
+        match self.buffered_segments.push(segment) {
+            PushOutcome::Queued => {}
+            PushOutcome::Dropped(segments) => {
+                for segment in segments {
+                    let reason = format!(
+                        "Dropped the queued segment #{} of {} bytes (output queue overflow)",
+                        segment.info.sequence,
+                        segment.data.len()
+                    );
+                    log!(self.config, Warn, "{}", reason);
+                    self.pool.give(segment.data);
+                    self.event_queue.push_back(Event::Error {
+                        fatal: false,
+                        reason,
+                        history: Vec::new(),
+                    });
+                }
+            }
+            PushOutcome::Overflowed => {
+                let reason = format!(
+                    "Output queue overflow at the segment #{}: {} bytes queued",
+                    seq,
+                    self.buffered_segments.bytes()
+                );
+                log!(self.config, Error, "{}", reason);
+                self.stop();
+                self.event_queue.push_back(Event::Error {
+                    fatal: true,
+                    reason,
+                    history: self.trace.history(),
+                });
+            }
+        }
+    }
+
+    fn fetch_next_segment(&mut self) {
+        if self.fetching_segment.is_some()
+            || self.awaiting_audio.is_some()
+            || self.buffered_segments.is_blocking()
+            || self.is_buffer_capped()
+            || self.suspension.is_some()
+        {
+            return;
+        }
+        while let Some(mut segment) = self.segment_queue.pop_front() {
+            if !segment.is_ad && !self.stitch(&mut segment) {
+                continue;
+            }
+            let fetch = SegmentFetch {
+                action_id: ActionId::default(),
+                kind: ResourceKind::Segment,
+                resource: ResourceKey {
+                    url: segment.url.clone(),
+                    range: segment.range,
+                },
+                segment,
+                attempts: 0,
+            };
+            self.request_segment(fetch);
+            self.fetch_ahead();
+            return;
+        }
+        let is_ended = !self.is_live && self.is_playlist_loaded;
+        let is_next_ready = self.next_asset
+            .as_ref()
+            .is_some_and(|n| n.fetch_action_id.is_none());
+        if is_ended && is_next_ready {
+            self.start_next_asset();
+        }
+    }
+
+    /// Continues with the playlist queued by `queue_next`.
+    fn start_next_asset(&mut self) {
+        let next = *self.next_asset.take().expect("Never fails");
+        log!(self.config, Info, "Starts playing the next playlist {}", next.url);
+        if self.in_flight_fetches.remove(&self.fetch_playlist_action_id) {
+            let action_id = self.fetch_playlist_action_id;
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+        self.timers.retain(|_, t| *t != Timer::ReloadPlaylist);
+        self.media_playlist_url = next.url.clone();
+        self.base_url = next.base_url;
+        self.fetch_playlist_action_id = ActionId::default();
+        self.last_media_sequence = None;
+        self.playlist_media_sequence = None;
+        self.playlist_diff.clear();
+        self.resume_after = None;
+        self.start_position = None;
+        self.byte_range_end = 0;
+        self.next_program_date_time = None;
+        self.map = None;
+        self.encryption = None;
+        self.map_encryption = None;
+        self.is_playlist_loaded = false;
+        self.unchanged_reloads = 0;
+        self.reload_due_at = None;
+        self.stalled_for = Duration::from_secs(0);
+        self.is_stall_reported = false;
+        self.is_next_asset_starting = true;
+        self.started_master = next.master;
+        self.event_queue.push_back(Event::NextAssetStarted {
+            url: next.url.to_string(),
+        });
+
+        let m3u8 = match next.m3u8 {
+            None => {
+                self.request_playlist();
+                return;
+            }
+            Some(m3u8) => m3u8,
+        };
+        match track!(self.handle_playlist(m3u8.as_bytes(), 0)) {
+            Ok(()) => {
+                self.playlist_failures = 0;
+                self.is_playlist_loaded = true;
+            }
+            Err(e) => {
+                log!(self.config, Warn, "Cannot handle the media playlist: {}", e);
+                self.handle_playlist_failure(FailureClass::Transient, e.to_string());
+            }
+        }
+    }
+
+    /// Splices the next ad pod at the ad break starting at `segment`, and skips the segments
+    /// of the main content replaced by the pod.
+    ///
+    /// Returns `false` if `segment` is skipped. Otherwise, `segment` is replaced by the first
+    /// segment of the pod at the start of a break.
+    fn stitch(&mut self, segment: &mut Segment) -> bool {
+        if let Some(mut ad_break) = self.ad_break.take() {
+            // Breaks shorter than half a segment do not skip it.
+            let is_over = ad_break.remaining < segment.duration / 2;
+            if !is_over && segment.cue != Some(Cue::In) {
+                log!(self.config, Debug, "Skipped the segment #{} for an ad", segment.seq);
+                ad_break.remaining = ad_break.remaining.saturating_sub(segment.duration);
+                self.ad_break = Some(ad_break);
+                return false;
+            }
+            log!(self.config, Info, "Resumes the main content at the segment #{}", segment.seq);
+            if mem::take(&mut self.is_variant_locked) {
+                log!(self.config, Info, "Unlocked the variant at the end of the ad break");
+            }
+            self.event_queue
+                .push_back(Event::AdBreakEnded { id: ad_break.id });
+            segment.is_discontinuity = true;
+        }
+
+        let id = match segment.cue {
+            Some(Cue::Out { ref id, .. }) if !self.ad_pods.is_empty() => id.clone(),
+            _ => return true,
+        };
+        let pod = self.ad_pods.pop_front().expect("Never fails");
+        let duration = match segment.cue {
+            Some(Cue::Out {
+                duration: Some(d), ..
+            }) => d,
+            _ => pod.duration,
+        };
+        log!(
+            self.config,
+            Info,
+            "Starts the ad pod {} at the segment #{}",
+            pod.url,
+            segment.seq
+        );
+        self.event_queue.push_back(Event::AdBreakStarted {
+            id: id.clone(),
+            sequence: segment.seq,
+            duration_ms: pod.duration.as_secs_f64() * 1000.0,
+        });
+        // Ads are numbered as the main content they replace.
+        let discontinuity = segment.discontinuity;
+        let mut ad_segments = pod.segments.into_iter().map(|s| Segment {
+            seq: s.seq,
+            discontinuity,
+            url: s.url,
+            range: s.range,
+            init: s.init,
+            key: None,
+            init_key: None,
+            duration: s.duration,
+            program_date_time: None,
+            cue: None,
+            is_ad: true,
+            is_discontinuity: false,
+        });
+        let mut first = ad_segments.next().expect("Never fails");
+        first.is_discontinuity = true;
+        for (i, ad_segment) in ad_segments.enumerate() {
+            self.segment_queue.insert(i, ad_segment);
+        }
+        self.ad_break = Some(AdBreak {
+            id,
+            remaining: duration.saturating_sub(segment.duration),
+        });
+        *segment = first;
+        true
+    }
+
+    /// Requests the first of the key of the init section, the init section, the key of the segment
+    /// and the segment itself that has not been cached yet.
+    fn request_segment(&mut self, mut fetch: SegmentFetch) {
+        let uncached = |key: &ResourceKey| !self.resource_cache.contains(key);
+        let uncached_init = fetch.segment.init.clone().filter(&uncached);
+        let init_key = fetch
+            .segment
+            .init_key
+            .as_ref()
+            .map(|e| e.key.clone())
+            .filter(|_| uncached_init.is_some());
+        let segment_key = fetch.segment.key.as_ref().map(|e| e.key.clone());
+        let (kind, resource) = if let Some(key) = init_key.filter(&uncached) {
+            (ResourceKind::Key, key)
+        } else if let Some(init) = uncached_init {
+            (ResourceKind::InitSegment, init)
+        } else if let Some(key) = segment_key.filter(&uncached) {
+            (ResourceKind::Key, key)
+        } else {
+            let next_url = self.segment_queue.front().map(|s| s.url.clone());
+            self.action_factory.set_next_object_url(next_url);
+            let key = ResourceKey {
+                url: fetch.segment.url.clone(),
+                range: fetch.segment.range,
+            };
+            (ResourceKind::Segment, key)
+        };
+        if kind == ResourceKind::Segment {
+            // Only the upcoming segments may be preloaded.
+            let queue = &self.segment_queue;
+            let is_upcoming = |r: &ResourceKey| *r == resource || is_queued(queue, r);
+            self.preloaded_segments.retain(|p| is_upcoming(&p.resource));
+            self.remuxed_ahead.retain(|r| is_upcoming(&r.0));
+        }
+        let is_preloaded = kind == ResourceKind::Segment
+            && self.preloaded_segments.iter().any(|p| p.resource == resource);
+        let ahead_fetch = self.ahead_fetches
+            .iter()
+            .find(|&(_, r)| *r == resource)
+            .map(|(&id, _)| id)
+            .filter(|_| kind == ResourceKind::Segment);
+        let action_id = if let Some(action_id) = ahead_fetch {
+            // Handled as the fetch of the segment once it completes.
+            self.ahead_fetches.remove(&action_id);
+            action_id
+        } else {
+            let action = if is_preloaded {
+                let action = self.action_factory.set_timeout(Duration::from_secs(0));
+                self.timers.insert(action.id(), Timer::PreloadedSegment);
+                action
+            } else {
+                self.action_factory
+                    .fetch_data(resource.url.clone(), kind, resource.range)
+            };
+            let action_id = action.id();
+            self.action_queue.push_back(action);
+            action_id
+        };
+        fetch.action_id = action_id;
+        fetch.kind = kind;
+        fetch.resource = resource;
+        self.trace.record(|| TraceEntry::SegmentScheduled {
+            sequence: fetch.segment.seq,
+            url: fetch.resource.url.clone(),
+            is_init: fetch.kind == ResourceKind::InitSegment,
+        });
+        log!(
+            self.config,
+            Debug,
+            "Scheduled the segment #{}: action_id={:?}, kind={:?}",
+            fetch.segment.seq,
+            fetch.action_id,
+            fetch.kind
+        );
+        self.fetching_segment = Some(fetch);
+    }
+
+    /// Fetches the segments following the next one while the buffer is low
+    /// (see `CatchUpConfig`), so that they are remuxed together with it.
+    fn fetch_ahead(&mut self) {
+        let catch_up = &self.config.catch_up;
+        let is_low = self.action_factory
+            .buffer_length_ms()
+            .is_some_and(|ms| ms < catch_up.buffer_threshold_ms);
+        let next = match self.fetching_segment {
+            Some(ref f) if is_low && !self.is_demuxed && f.kind == ResourceKind::Segment => f,
+            _ => return,
+        };
+        let max_ahead = catch_up.max_concurrent_fetches.saturating_sub(1);
+        let ahead = self.ahead_fetches.len() + self.preloaded_segments.len();
+        let room = max_ahead.saturating_sub(ahead);
+        let resources = self.segment_queue
+            .iter()
+            .take(max_ahead)
+            .take_while(|s| !s.is_ad && s.key.is_none() && s.init == next.segment.init)
+            .map(|s| ResourceKey {
+                url: s.url.clone(),
+                range: s.range,
+            })
+            .filter(|r| {
+                !self.ahead_fetches.values().any(|a| a == r)
+                    && !self.preloaded_segments.iter().any(|p| p.resource == *r)
+            })
+            .take(room)
+            .collect::<Vec<_>>();
+        for resource in resources {
+            log!(self.config, Debug, "Fetches {} ahead", resource.url);
+            let action = self.action_factory
+                .fetch_data(resource.url.clone(), ResourceKind::Segment, resource.range);
+            self.ahead_fetches.insert(action.id(), resource);
+            self.action_queue.push_back(action);
+        }
+    }
+
+    /// Returns `true` if `resource` is that of the segment being fetched or a queued one.
+    fn is_upcoming(&self, resource: &ResourceKey) -> bool {
+        self.fetching_segment.as_ref().is_some_and(|f| f.resource == *resource)
+            || is_queued(&self.segment_queue, resource)
+    }
+
+    fn parse_key(&self, tags: &[ExtXKey]) -> Result<Option<Encryption>> {
+        track!(segment_resources::parse_key(&self.config, &self.base_url, tags))
+    }
+
+    /// Decrypts `data` of (or preceding) the segment `seq` with the cached key of `encryption`.
+    fn decrypt(
+        &self,
+        encryption: &Encryption,
+        seq: SequenceNumber,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = track_assert_some!(
+            self.resource_cache.get(&encryption.key),
+            ErrorKind::Other,
+            "The key {} has not been fetched",
+            encryption.key.url
+        );
+        track!(encryption.decrypt(&key, seq, data))
+    }
+
+    fn set_timer(&mut self, duration: Duration, timer: Timer) {
+        if timer == Timer::ReloadPlaylist {
+            self.stalled_for += duration;
+            self.reload_due_at = self.config.hooks.now().map(|now| now + duration);
+        }
+        let action = self.action_factory.set_timeout(duration);
+        self.timers.insert(action.id(), timer);
+        self.action_queue.push_back(action);
+    }
+
+    fn parse_segment_url(&self, segment_url: &str) -> Result<Url> {
+        track!(segment_resources::resolve_url(&self.base_url, segment_url))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    seq: SequenceNumber,
+    discontinuity: u64,
+    url: Url,
+    range: Option<ByteRange>,
+    init: Option<ResourceKey>,
+
+    /// Encryption of the segment.
+    key: Option<Encryption>,
+
+    /// Encryption of `init`.
+    init_key: Option<Encryption>,
+
+    duration: Duration,
+
+    /// Time since the UNIX epoch.
+    program_date_time: Option<Duration>,
+
+    cue: Option<Cue>,
+
+    /// Whether the segment belongs to an ad pod rather than to the playlist.
+    is_ad: bool,
+
+    /// Whether the segment starts an ad, resumes the main content, or starts the next playlist,
+    /// which is preceded by a new initialization segment.
+    is_discontinuity: bool,
+}
+
+/// Ad break whose pod is being played.
+#[derive(Debug)]
+struct AdBreak {
+    id: Option<String>,
+
+    /// Duration of the main content yet to be skipped.
+    remaining: Duration,
+}
+
+/// Playlist queued by `MediaPlaylistHandler::queue_next`.
+#[derive(Debug)]
+struct NextAsset {
+    url: Url,
+
+    /// Base URL of relative segment URIs, which is the final URL of the prefetch if any.
+    base_url: Url,
+
+    m3u8: Option<String>,
+
+    /// Prefetch of the playlist in flight.
+    fetch_action_id: Option<ActionId>,
+
+    master: Option<Box<NextMaster>>,
+}
+
+/// Playback halted by `MediaPlaylistHandler::suspend`.
+#[derive(Debug)]
+struct Suspension {
+    /// Time of the suspension (`None` without `Hooks::clock`).
+    since: Option<Duration>,
+
+    /// Duration of the listed segments from the position to the end of the playlist.
+    behind_live: Duration,
+
+    /// Duration of the next segment, which expires once the window ends after it.
+    next_duration: Duration,
+}
+
+#[derive(Debug)]
+struct SegmentFetch {
+    action_id: ActionId,
+    segment: Segment,
+    attempts: u32,
+
+    /// Resource being fetched for the segment: its key, init section or itself.
+    kind: ResourceKind,
+    resource: ResourceKey,
+}
+
+/// Boundary between two segments of a media playlist (see `MediaPlaylistHandler::resume_at`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwitchPoint {
+    /// Media sequence number of the segment following the boundary.
+    pub sequence: u64,
+
+    /// Time since the UNIX epoch, if the playlist has `EXT-X-PROGRAM-DATE-TIME`.
+    pub program_date_time: Option<Duration>,
+
+    /// Time from the start of a VOD playlist.
+    pub position: Option<Duration>,
+}
+
+/// Time span of a segment (see `MediaPlaylistHandler::audio_span`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentSpan {
+    pub start: SwitchPoint,
+    pub duration: Duration,
+}
+
+/// Fetched segment waiting for the audio of an audio rendition.
+#[derive(Debug)]
+struct AwaitingSegment {
+    fetch: SegmentFetch,
+    data: Vec<u8>,
+    fetch_duration_ms: u32,
+}
+
+/// Body of a segment obtained before it is requested.
+#[derive(Debug)]
+struct PreloadedSegment {
+    resource: ResourceKey,
+    data: Vec<u8>,
+    fetch_duration_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timer {
+    ReloadPlaylist,
+    RetrySegment,
+
+    /// Delivers the preloaded body of the segment being fetched as if it had been fetched.
+    PreloadedSegment,
+}
+
+/// Returns `true` if `resource` is that of a segment of `queue`.
+fn is_queued(queue: &VecDeque<Segment>, resource: &ResourceKey) -> bool {
+    queue
+        .iter()
+        .any(|s| s.url == resource.url && s.range == resource.range)
+}
+
+/// Returns the index of the segment at which live playbacks start,
+/// i.e., the last one starting at least `hold_back` before the end of the playlist.
+fn live_point(durations: &[Duration], hold_back: Duration) -> usize {
+    let mut distance = Duration::from_secs(0);
+    for (i, duration) in durations.iter().enumerate().rev() {
+        distance += *duration;
+        if distance >= hold_back {
+            return i;
+        }
+    }
+    0
+}
+
+/// Returns the sequence number of the first segment of `playlist` whose middle is after `point`
+/// (or the one following the playlist), or `None` if the segments cannot be matched in time.
+pub fn aligned_sequence(
+    playlist: &MediaPlaylist,
+    first_seq: SequenceNumber,
+    point: &SwitchPoint,
+    is_live: bool,
+) -> Option<SequenceNumber> {
+    let mut program_date_time = None;
+    let mut position = Duration::from_secs(0);
+    for (i, segment) in playlist.segments().iter().enumerate() {
+        let duration = segment.inf_tag().duration();
+        program_date_time = segment
+            .program_date_time_tag()
+            .and_then(|t| date_time::parse(t.date_time()))
+            .or(program_date_time);
+        let (start, boundary) = match (program_date_time, point.program_date_time) {
+            (Some(start), Some(boundary)) => (start, boundary),
+            _ if is_live => return None,
+            _ => (position, point.position?),
+        };
+        if start + duration / 2 > boundary {
+            return Some(first_seq + i as u64);
+        }
+        program_date_time = program_date_time.map(|t| t + duration);
+        position += duration;
+    }
+    Some(first_seq + playlist.segments().len() as u64)
+}
+
+fn to_millis(duration: Duration) -> u32 {
+    duration.as_millis().min(u128::from(u32::MAX)) as u32
+}
+
+/// Returns the delay until the reload due at `due`, so that reloads keep the cadence of the
+/// previous ones regardless of timer lateness and processing time.
+///
+/// If the reload is late by more than `interval` (e.g., in a throttled background tab),
+/// the cadence restarts from `now`.
+fn anchored_delay(now: Duration, due: Duration, interval: Duration) -> Duration {
+    if due + interval < now {
+        interval
+    } else {
+        due.saturating_sub(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use url::Url;
+
+    use player::simulation::{Response, Simulation, SimulationRecord};
+    use player::{Action, Event, PlayerConfig, ResourceKind};
+
+    const VOD: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\nseg0.ts\n#EXT-X-ENDLIST\n";
+
+    fn fetched_urls(sim: &Simulation) -> Vec<String> {
+        sim.records()
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Action {
+                    action: Action::FetchData { ref url, .. },
+                    ..
+                } => Some(url.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the virtual times of the fetches of `url`.
+    fn fetch_times(sim: &Simulation, url: &str) -> Vec<u64> {
+        sim.records()
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Action {
+                    at_ms,
+                    action: Action::FetchData { url: ref u, .. },
+                } if u.as_str() == url => Some(at_ms),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns a live playlist of the `n` segments from `first`.
+    fn live(first: u64, n: u64) -> String {
+        let mut m3u8 = format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            first
+        );
+        for seq in first..first + n {
+            m3u8 += &format!("#EXTINF:4,\nseg{}.ts\n", seq);
+        }
+        m3u8
+    }
+
+    /// Simulates a live stream whose playlist gains a segment once and then stays unchanged.
+    fn live_simulation() -> Simulation {
+        let mut config = PlayerConfig::default();
+        config.reload.jitter_ratio = 0.0;
+        let mut sim = Simulation::new(config);
+        sim.respond("http://x/a.m3u8", Response::data(live(0, 3)))
+            .respond("http://x/a.m3u8", Response::data(live(1, 3)));
+        for seq in 0..4 {
+            let segment = Response::data(vec![0x47; 188]).with_latency(Duration::from_millis(100));
+            sim.respond(&format!("http://x/seg{}.ts", seq), segment);
+        }
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(20)).unwrap();
+        sim
+    }
+
+    #[test]
+    fn segment_fetches_are_retried_with_exponential_backoff() {
+        let mut sim = Simulation::new(PlayerConfig::default());
+        sim.respond("http://x/a.m3u8", Response::data(VOD))
+            .respond("http://x/seg0.ts", Response::error(503));
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(5)).unwrap();
+
+        // `RetryConfig::default()`: 3 attempts, 500 ms doubling.
+        assert_eq!(fetch_times(&sim, "http://x/seg0.ts"), [0, 500, 1500]);
+        let skipped = sim.events().into_iter().any(|e| match *e {
+            Event::Error { fatal, ref reason, .. } => {
+                !fatal && reason.starts_with("Skipped the segment http://x/seg0.ts")
+                    && reason.ends_with("attempts=3")
+            }
+            _ => false,
+        });
+        assert!(skipped);
+    }
+
+    #[test]
+    fn unchanged_reloads_back_off_up_to_the_limit() {
+        let sim = live_simulation();
+
+        // A target duration after the start, then half of it once unchanged, growing by
+        // `unchanged_backoff_factor` up to `max_backoff_ratio` times the target duration.
+        assert_eq!(
+            fetch_times(&sim, "http://x/a.m3u8"),
+            [4000, 8000, 10_000, 13_000, 17_500]
+        );
+    }
+
+    #[test]
+    fn reloads_fetch_only_the_new_segments() {
+        let sim = live_simulation();
+        let urls = fetched_urls(&sim)
+            .into_iter()
+            .filter(|u| u.ends_with(".ts"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://x/seg0.ts",
+                "http://x/seg1.ts",
+                "http://x/seg2.ts",
+                "http://x/seg3.ts",
+            ]
+        );
+        assert_eq!(fetch_times(&sim, "http://x/seg3.ts"), [4000]);
+    }
+
+    #[test]
+    fn resumes_compare_to_the_window_grown_by_reduced_reloads() {
+        let mut config = PlayerConfig::default();
+        config.reload.jitter_ratio = 0.0;
+        let mut sim = Simulation::new(config);
+        for n in 2..7 {
+            sim.respond("http://x/a.m3u8", Response::data(live(0, n)));
+        }
+        for seq in 0..6 {
+            sim.respond(&format!("http://x/seg{}.ts", seq), Response::data(vec![0x47; 188]));
+        }
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(30)).unwrap();
+
+        // 15 seconds behind a window grown from 8 to 24 seconds.
+        sim.player_mut().suspend().unwrap();
+        sim.run_for(Duration::from_secs(15)).unwrap();
+        sim.player_mut().resume().unwrap();
+        sim.run_for(Duration::from_secs(1)).unwrap();
+        let jumps = sim.events()
+            .into_iter()
+            .filter_map(|e| match *e {
+                Event::PlaybackResumed { jumped_to_live, .. } => Some(jumped_to_live),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(jumps, [false]);
+    }
+
+    #[test]
+    fn segments_are_fetched_ahead_while_the_buffer_is_low() {
+        let mut config = PlayerConfig::default();
+        config.catch_up.max_concurrent_fetches = 3;
+        let mut sim = Simulation::new(config);
+        let mut m3u8 = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n".to_owned();
+        for seq in 0..5 {
+            m3u8 += &format!("#EXTINF:4,\nseg{}.ts\n", seq);
+            let segment = Response::data(vec![0x47; 188]).with_latency(Duration::from_millis(100));
+            sim.respond(&format!("http://x/seg{}.ts", seq), segment);
+        }
+        m3u8 += "#EXT-X-ENDLIST\n";
+        sim.respond("http://x/a.m3u8", Response::data(m3u8));
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.player_mut().set_buffer_length(Duration::from_secs(0));
+        sim.run_for(Duration::from_secs(5)).unwrap();
+
+        // The next segment and the two following ones at once, each fetched only once.
+        let times = (0..5)
+            .map(|seq| fetch_times(&sim, &format!("http://x/seg{}.ts", seq)))
+            .collect::<Vec<_>>();
+        assert_eq!(times, [[0], [0], [0], [100], [100]]);
+    }
+
+    #[test]
+    fn token_refreshes_replace_the_previous_parameters() {
+        let refreshes = Rc::new(Cell::new(0));
+        let counter = refreshes.clone();
+        let mut config = PlayerConfig::default();
+        config.hooks.token_refresher = Some(Rc::new(move |_: ResourceKind, _: &Url| {
+            counter.set(counter.get() + 1);
+            let mut params = BTreeMap::new();
+            if counter.get() == 1 {
+                params.insert("token".to_owned(), "a".to_owned());
+            } else {
+                params.insert("sig".to_owned(), "b".to_owned());
+            }
+            Some(params)
+        }));
+        let mut sim = Simulation::new(config);
+        sim.respond("http://x/a.m3u8", Response::data(VOD))
+            .respond("http://x/seg0.ts", Response::error(403))
+            .respond("http://x/seg0.ts?token=a", Response::error(403))
+            .respond("http://x/seg0.ts?sig=b", Response::data(vec![0x47; 188]));
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(refreshes.get(), 2);
+        let urls = fetched_urls(&sim);
+        assert_eq!(
+            &urls[..3],
+            &[
+                "http://x/seg0.ts",
+                "http://x/seg0.ts?token=a",
+                "http://x/seg0.ts?sig=b",
+            ][..]
+        );
     }
 }
@@ -0,0 +1,65 @@
+//! MIME types and codec strings of the produced fMP4 initialization segments,
+//! for hosts which append to several `SourceBuffer`s or call `SourceBuffer.changeType`.
+use mse_fmp4::fmp4::{InitializationSegment, SampleEntry};
+
+/// Type of an initialization segment (see `SegmentInfo::media_type`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MediaType {
+    /// MIME type with the codecs parameter (e.g., `video/mp4; codecs="avc1.64001F,mp4a.40.2"`).
+    pub mime_type: String,
+
+    /// RFC 6381 codec strings separated by commas.
+    pub codecs: String,
+
+    pub content: ContentType,
+}
+
+/// Tracks of an initialization segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ContentType {
+    Audio,
+    Video,
+
+    /// Both audio and video tracks.
+    Muxed,
+}
+
+/// Returns the type of `init`, or `None` if it has no tracks.
+pub fn of(init: &InitializationSegment) -> Option<MediaType> {
+    let mut has_audio = false;
+    let mut has_video = false;
+    let mut codecs = Vec::new();
+    let entries = init.moov_box
+        .trak_boxes
+        .iter()
+        .flat_map(|t| &t.mdia_box.minf_box.stbl_box.stsd_box.sample_entries);
+    for entry in entries {
+        match *entry {
+            SampleEntry::Avc(ref avc) => {
+                let c = &avc.avcc_box.configuration;
+                has_video = true;
+                codecs.push(format!(
+                    "avc1.{:02X}{:02X}{:02X}",
+                    c.profile_idc, c.constraint_set_flag, c.level_idc
+                ));
+            }
+            SampleEntry::Aac(ref aac) => {
+                // The audio object type is the profile plus one (e.g., 2 for AAC LC).
+                has_audio = true;
+                codecs.push(format!("mp4a.40.{}", aac.esds_box.profile as u8 + 1));
+            }
+        }
+    }
+    let (content, kind) = match (has_audio, has_video) {
+        (true, true) => (ContentType::Muxed, "video"),
+        (false, true) => (ContentType::Video, "video"),
+        (true, false) => (ContentType::Audio, "audio"),
+        (false, false) => return None,
+    };
+    let codecs = codecs.join(",");
+    Some(MediaType {
+        mime_type: format!("{}/mp4; codecs=\"{}\"", kind, codecs),
+        codecs,
+        content,
+    })
+}
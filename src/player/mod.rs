@@ -1,54 +1,893 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::iter;
+use std::mem;
+use std::time::Duration;
+use serde_json;
 use url::Url;
 
-pub use self::action::{Action, ActionFactory, ActionId};
+pub use self::action::{Action, ActionFactory, ActionId, ByteRange, CorsMode, CredentialsMode,
+                       FetchPriority, ResourceKind};
+pub use self::audio_rendition::AudioRenditionLoader;
+pub use self::cache::{ResourceCache, ResourceKey};
+pub use self::cdn::CdnSelector;
+pub use self::cmcd::CmcdState;
+pub use self::config::{AvSyncConfig, AvSyncMode, CatchUpConfig, CdnFailoverConfig, CmcdConfig,
+                       CmcdTransmission, ContentSteeringConfig, DeviceConfig, DownloadConfig,
+                       DurationCheckConfig, EmsgConfig, EmsgScheme, GapJumpConfig, HeadersConfig,
+                       InputLogConfig, LiveEdgeConfig, LowPowerConfig, OutputQueueConfig,
+                       OverflowPolicy, PlayerConfig, PlaylistPrefetchConfig, RecordingConfig,
+                       ReloadConfig, RenditionConfig, RequestLogConfig, RequestMetadataConfig,
+                       RequestModeConfig, RequestModes, RetryConfig, ThroughputHintConfig,
+                       ThumbnailConfig, TimeShiftConfig, TimeoutConfig, TraceConfig, TrackConfig,
+                       TrickPlayConfig, VariantBlacklistConfig};
+pub use self::date_ranges::{DateRange, DateRangeTimeline};
+pub use self::download_handler::{DownloadHandler, DownloadManifest, DownloadedSegment};
+pub use self::event::{Event, ReinitReason};
+pub use self::failure::{FailureClass, FailureStreak};
+pub use self::gap_jump::GapJumper;
+pub use self::history::SegmentHistory;
+pub use self::hooks::{Clock, Hooks, ManualClock, TokenRefresher, UrlRewriter};
+pub use self::image_stream::{ImageStream, ImageStreamLoader, ImageTile};
+pub use self::keyframe_index::{IndexedSegment, KeyframeIndex, KeyframePosition};
+pub use self::latency::{LatencyEstimator, SyncHint};
+pub use self::log::{LogLevel, Logger};
 pub use self::master_playlist_handler::MasterPlaylistHandler;
+pub use self::media_type::{ContentType, MediaType};
+pub use self::output_ring::OutputRing;
+pub use self::output_queue::{OutputQueue, OutputSegment, PushOutcome, SegmentInfo};
+pub use self::playlist_prefetch::PlaylistPrefetcher;
+pub use self::pool::BufferPool;
+pub use self::qoe::{QoeStats, QoeTracker};
+pub use self::recording::{FragmentTimeline, Recorder};
+pub use self::replay::{Input, InputLog, InputRecord, Replay};
+pub use self::rendition::{Rendition, RenditionType};
+pub use self::refresh::{PlaylistRefreshStats, PlaylistRefreshTracker};
+pub use self::request_log::{RequestLog, RequestLogEntry, RequestOutcome};
+pub use self::scte35::{CueKind, SpliceCue};
+pub use self::server_control::ServerControl;
+pub use self::stats::{Metrics, Stats};
+pub use self::steering::{ContentSteering, SteeringManifest, SteeringTag};
+pub use self::source_loader::SourceLoader;
+pub use self::subtitles::SubtitleLoader;
+pub use self::stitching::{AdPod, AdSegment, Cue};
+pub use self::thumbnail_handler::ThumbnailHandler;
+pub use self::throughput::ThroughputEstimator;
+pub use self::timeline::{Timeline, TimelineSegment};
+pub use self::trace::{Trace, TraceEntry, TraceRecord};
+pub use self::trick_play::TrickPlayHandler;
+pub use self::validation::{validate, IssueKind, Severity, ValidationIssue};
+pub use self::variant_switch::{SwitchReason, VariantSwitch};
+pub use self::webvtt::SubtitleCue;
 pub use self::media_playlist_handler::MediaPlaylistHandler;
 
+/// Formats and forwards a log record to `Hooks::logger` if `$level` is enabled
+/// by `PlayerConfig::log_level`.
+macro_rules! log {
+    ($config:expr, $level:ident, $($arg:tt)*) => {{
+        let config: &$crate::player::PlayerConfig = &$config;
+        let level = $crate::player::LogLevel::$level;
+        if level <= config.log_level {
+            if let Some(ref logger) = config.hooks.logger {
+                logger.log(level, &format!($($arg)*));
+            }
+        }
+    }};
+}
+
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub mod replay;
+pub mod simulation;
+
 mod action;
+mod aes;
+mod audio_rendition;
+mod av_sync;
+mod cache;
+mod cdn;
+mod cmcd;
+mod codecs;
+mod config;
+mod data_url;
+mod download_handler;
+mod emsg;
+mod event;
+mod failure;
+mod gap_jump;
+mod date_ranges;
+mod date_time;
+mod discontinuity;
+mod history;
+mod hooks;
+mod image_stream;
+mod keyframe;
+mod keyframe_index;
+mod latency;
+mod log;
 mod master_playlist_handler;
 mod media_playlist_handler;
+mod media_type;
+mod playlist_diff;
+mod playlist_prefetch;
+mod output_queue;
+mod output_ring;
+mod pool;
+mod qoe;
+mod random;
+mod recording;
+mod refresh;
+mod remux;
+mod rendition;
+mod request_log;
+mod scte35;
+mod segment_resources;
+mod server_control;
+mod source_loader;
+mod stats;
+mod steering;
+mod stitching;
+mod subtitles;
+mod target_duration;
+mod text;
+mod thumbnail_handler;
+mod throughput;
+mod throughput_hint;
+mod timeline;
+mod timestamps;
+mod trace;
+mod track_selection;
+mod trick_play;
+#[cfg(test)]
+mod ts_fixture;
+mod ts_merge;
+mod ts_sync;
+mod validation;
+mod variant_switch;
+mod webvtt;
 
-use Result;
+use {Error, ErrorKind, Result};
 
 pub type StreamId = u8;
 
 #[derive(Debug)]
-pub enum HlsPlayer {
-    NotStarted,
-    MasterPlaylist(MasterPlaylistHandler),
-    MediaPlayilst(MediaPlaylistHandler),
+pub struct HlsPlayer {
+    config: PlayerConfig,
+    handler: PlaylistHandler,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    qoe: QoeTracker,
+    gap_jumper: GapJumper,
+    trace: Trace,
+    request_log: RequestLog,
+    input_log: InputLog,
+
+    /// Timers set by the current playback, which are cancelled when it stops.
+    pending_timers: BTreeSet<ActionId>,
+
+    /// Info of the segment last taken by `next_segment`.
+    last_segment_info: Option<SegmentInfo>,
 }
 impl HlsPlayer {
     pub fn new() -> Self {
-        HlsPlayer::NotStarted
+        Self::with_config(PlayerConfig::default())
+    }
+
+    pub fn with_config(config: PlayerConfig) -> Self {
+        HlsPlayer {
+            trace: Trace::new(&config),
+            request_log: RequestLog::new(config.request_log.max_entries, &config.hooks),
+            config,
+            handler: PlaylistHandler::NotStarted,
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            qoe: QoeTracker::default(),
+            gap_jumper: GapJumper::new(),
+            input_log: InputLog::new(),
+            pending_timers: BTreeSet::new(),
+            last_segment_info: None,
+        }
+    }
+
+    pub fn config(&self) -> &PlayerConfig {
+        &self.config
+    }
+
+    /// Replaces the configuration.
+    ///
+    /// The new configuration takes effect at the next `play_*` call.
+    pub fn set_config(&mut self, config: PlayerConfig) {
+        self.config = config;
+    }
+
+    /// Replaces the configuration except for `PlayerConfig::hooks`, which cannot be given as JSON.
+    pub fn set_config_json(&mut self, json: &str) -> Result<()> {
+        let mut config = track!(PlayerConfig::from_json(json))?;
+        config.hooks = self.config.hooks.clone();
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.config.hooks = hooks;
+    }
+
+    /// Starts playing `m3u8`, which may be either a master or a media playlist.
+    ///
+    /// A leading BOM, trailing NULs and CRLF line endings are accepted.
+    pub fn play(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        let m3u8 = &text::normalize(m3u8);
+        if text::is_media_playlist(m3u8) {
+            track!(self.play_media_playlist(url, m3u8))
+        } else {
+            track!(self.play_master_playlist(url, m3u8))
+        }
+    }
+
+    /// Switches to the master or media playlist at `url` (e.g., on a channel change),
+    /// which is fetched by the player and then played as `play` does.
+    ///
+    /// The current playback is stopped at once, and its fetches in flight are cancelled
+    /// by `Action::Abort` actions; hosts should reset their media buffers, since the
+    /// segments of the new source start with a new initialization segment.
+    pub fn load_source(&mut self, url: Url) -> Result<()> {
+        if data_url::is_data_url(&url) {
+            return track!(self.play_data_url(url));
+        }
+        self.stop();
+        log!(self.config, Info, "Starts loading the source {}", url);
+        self.start_input_log(|| Input::LoadSource {
+            url: url.to_string(),
+        });
+        self.handler = PlaylistHandler::Loading(SourceLoader::new(&self.config, url));
+        Ok(())
+    }
+
+    /// Starts playing the playlist fetched by `SourceLoader`.
+    fn play_loaded_source(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        log!(self.config, Info, "Loaded the source {}", url);
+        self.handler = PlaylistHandler::NotStarted;
+        track!(self.play(url, m3u8))
+    }
+
+    /// Starts playing the playlist inlined in the `data:` URL `url`.
+    ///
+    /// Invalid UTF-8 sequences in the playlist are replaced with `U+FFFD`.
+    pub fn play_data_url(&mut self, url: Url) -> Result<()> {
+        let data = track!(data_url::decode(&url))?;
+        track!(self.play(url, &text::decode(&data)))
     }
 
     pub fn play_master_playlist(&mut self, url: Url, m3u8: &str) -> Result<()> {
-        let handler = track!(MasterPlaylistHandler::new(url, m3u8))?;
-        *self = HlsPlayer::MasterPlaylist(handler);
+        self.stop();
+        log!(self.config, Info, "Starts playing the master playlist {}", url);
+        self.start_input_log(|| Input::PlayMasterPlaylist {
+            url: url.to_string(),
+            m3u8: m3u8.to_owned(),
+        });
+        self.qoe = QoeTracker::new(&self.config.hooks);
+        self.gap_jumper = GapJumper::new();
+        self.trace = Trace::new(&self.config);
+        self.request_log = RequestLog::new(self.config.request_log.max_entries, &self.config.hooks);
+        let handler = track!(MasterPlaylistHandler::new(
+            &self.config,
+            self.trace.clone(),
+            url,
+            m3u8
+        ))?;
+        self.handler = PlaylistHandler::MasterPlaylist(handler);
         Ok(())
     }
 
     pub fn play_media_playlist(&mut self, url: Url, m3u8: &str) -> Result<()> {
-        let action_factory = ActionFactory::new(0);
-        let handler = track!(MediaPlaylistHandler::with_m3u8(action_factory, url, m3u8))?;
-        *self = HlsPlayer::MediaPlayilst(handler);
+        self.stop();
+        log!(self.config, Info, "Starts playing the media playlist {}", url);
+        self.start_input_log(|| Input::PlayMediaPlaylist {
+            url: url.to_string(),
+            m3u8: m3u8.to_owned(),
+        });
+        self.qoe = QoeTracker::new(&self.config.hooks);
+        self.gap_jumper = GapJumper::new();
+        self.trace = Trace::new(&self.config);
+        self.request_log = RequestLog::new(self.config.request_log.max_entries, &self.config.hooks);
+        let action_factory = ActionFactory::new(0, &self.config);
+        let handler = track!(MediaPlaylistHandler::with_m3u8(
+            &self.config,
+            action_factory,
+            ResourceCache::new(),
+            SegmentHistory::new(),
+            self.trace.clone(),
+            url,
+            m3u8
+        ))?;
+        self.handler = PlaylistHandler::MediaPlaylist(handler);
+        Ok(())
+    }
+
+    /// Starts downloading all the segments of the VOD media playlist `m3u8` for offline playback.
+    ///
+    /// The segments are fetched with up to `DownloadConfig::max_concurrent_fetches` concurrent
+    /// fetches, and taken by `next_segment` in order as a single fMP4 stream, whose layout is
+    /// described by `download_manifest`. `Event::DownloadCompleted` is emitted at the end.
+    pub fn download(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        self.stop();
+        self.start_input_log(|| Input::Download {
+            url: url.to_string(),
+            m3u8: m3u8.to_owned(),
+        });
+        self.qoe = QoeTracker::new(&self.config.hooks);
+        self.gap_jumper = GapJumper::new();
+        self.trace = Trace::new(&self.config);
+        self.request_log = RequestLog::new(self.config.request_log.max_entries, &self.config.hooks);
+        let handler = track!(DownloadHandler::new(
+            &self.config,
+            self.trace.clone(),
+            url,
+            &text::normalize(m3u8)
+        ))?;
+        self.handler = PlaylistHandler::Download(handler);
+        Ok(())
+    }
+
+    /// Returns the layout of the stream produced so far by the current (or last) download.
+    pub fn download_manifest(&self) -> Option<&DownloadManifest> {
+        match self.handler {
+            PlaylistHandler::Download(ref x) => Some(x.manifest()),
+            _ => None,
+        }
+    }
+
+    /// Starts fetching the keyframes of the I-frame playlist `m3u8` for scrubbing previews.
+    ///
+    /// Each keyframe taken by `next_segment` is a raw H.264 access unit in the Annex B format,
+    /// described by the `Event::ThumbnailLoaded` emitted before it; the host decodes it
+    /// (e.g., by `VideoDecoder` of WebCodecs). `Event::ThumbnailsCompleted` is emitted at the end.
+    pub fn generate_thumbnails(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        self.stop();
+        self.start_input_log(|| Input::GenerateThumbnails {
+            url: url.to_string(),
+            m3u8: m3u8.to_owned(),
+        });
+        self.qoe = QoeTracker::new(&self.config.hooks);
+        self.gap_jumper = GapJumper::new();
+        self.trace = Trace::new(&self.config);
+        self.request_log = RequestLog::new(self.config.request_log.max_entries, &self.config.hooks);
+        let handler = track!(ThumbnailHandler::new(
+            &self.config,
+            url,
+            &text::normalize(m3u8)
+        ))?;
+        self.handler = PlaylistHandler::Thumbnails(handler);
+        Ok(())
+    }
+
+    /// Switches the master playlist being played to trick play at `rate` times the normal speed
+    /// (negative for rewinding) from `position` on the media timeline.
+    ///
+    /// The keyframes of the I-frame playlist closest in bandwidth to the played variant are
+    /// fetched at `TrickPlayConfig::frames_per_second`, and taken by `next_segment` as
+    /// video-only fMP4 fragments, each described by the `Event::TrickPlayFrameLoaded` emitted
+    /// before it. The fetches of the normal playback are cancelled, and its segments not yet
+    /// taken are discarded, so the host should reset its media buffer before appending them.
+    /// Calling this again during trick play restarts it with the new `rate` and `position`.
+    pub fn start_trick_play(&mut self, rate: f64, position: Duration) -> Result<()> {
+        self.log_input(|| Input::StartTrickPlay {
+            rate,
+            position_ms: position.as_millis() as u64,
+        });
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.start_trick_play(rate, position))
+            }
+            _ => track_panic!(ErrorKind::InvalidInput, "No master playlist is being played"),
+        }
+    }
+
+    /// Leaves trick play, and resumes the normal playback from the segment containing `position`.
+    ///
+    /// The fragments of the trick play not yet taken are discarded, and the first segment of
+    /// the resumed playback is preceded by its initialization segment.
+    pub fn stop_trick_play(&mut self, position: Duration) -> Result<()> {
+        self.log_input(|| Input::StopTrickPlay {
+            position_ms: position.as_millis() as u64,
+        });
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => track!(x.stop_trick_play(position)),
+            _ => track_panic!(ErrorKind::InvalidInput, "No master playlist is being played"),
+        }
+    }
+
+    /// Queues the ad pod `m3u8` (a VOD media playlist) to replace the main content
+    /// at the next ad break (`EXT-X-CUE-OUT` or `EXT-X-DATERANGE` with `SCTE35-OUT`).
+    ///
+    /// The main content resumes after the planned duration of the break (or the duration of
+    /// the pod), or at the `EXT-X-CUE-IN`. The first segments of the pod and of the resumed
+    /// content are preceded by their own initialization segments.
+    /// Hosts may queue pods when `Event::AdBreakDetected` is emitted.
+    pub fn insert_ad_pod(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        let pod = track!(AdPod::parse(url, &text::normalize(m3u8)))?;
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.insert_ad_pod(pod),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.insert_ad_pod(pod),
+            _ => track_panic!(ErrorKind::InvalidInput, "No playlist is being played"),
+        }
+        Ok(())
+    }
+
+    /// Queues the master or media playlist `m3u8` to be played once the current
+    /// (VOD) playlist has ended, replacing any previously queued one.
+    ///
+    /// Its media playlist and first segments are fetched while the current playlist finishes,
+    /// and its segments follow those of the current one (starting with a new initialization
+    /// segment), so hosts can keep appending them to the same media buffer.
+    /// `Event::NextAssetStarted` is emitted at the transition.
+    pub fn queue_next(&mut self, url: Url, m3u8: &str) -> Result<()> {
+        self.log_input(|| Input::QueueNext {
+            url: url.to_string(),
+            m3u8: m3u8.to_owned(),
+        });
+        let m3u8 = text::normalize(m3u8).into_owned();
+        let is_media = text::is_media_playlist(&m3u8);
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) if is_media => x.queue_next(url, m3u8),
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.queue_next_master(url, &m3u8))?;
+            }
+            PlaylistHandler::MediaPlaylist(ref mut x) => {
+                track_assert!(
+                    is_media,
+                    ErrorKind::InvalidInput,
+                    "Master playlists cannot follow a media playlist: {}",
+                    url
+                );
+                x.queue_next(url, Some(m3u8), None);
+            }
+            _ => track_panic!(ErrorKind::InvalidInput, "No playlist is being played"),
+        }
         Ok(())
     }
 
+    /// Locks the played variant of the master playlist until `locked` is `false` or the end of
+    /// the next ad break (see `insert_ad_pod`), so that the steering server does not switch it
+    /// in the middle of the break.
+    pub fn set_variant_locked(&mut self, locked: bool) -> Result<()> {
+        self.log_input(|| Input::SetVariantLocked { locked });
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.lock_variant(locked),
+            _ => track_panic!(ErrorKind::InvalidInput, "No master playlist is being played"),
+        }
+        Ok(())
+    }
+
+    /// Halts fetching until `resume` (e.g., while the page is hidden), remembering the position
+    /// in the playlist instead of stopping the playback.
+    ///
+    /// In-flight fetches and pending timers are cancelled by `Action::Abort` actions.
+    pub fn suspend(&mut self) -> Result<()> {
+        self.log_input(|| Input::Suspend);
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.suspend(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.suspend(),
+            _ => track_panic!(ErrorKind::InvalidInput, "No playlist is being played"),
+        }
+        Ok(())
+    }
+
+    /// Resumes the playback halted by `suspend`, and emits `Event::PlaybackResumed`.
+    ///
+    /// Live playbacks catch up from the remembered position if it is still in the playlist
+    /// window (see `LiveEdgeConfig::max_catch_up_ms`), and otherwise jump to the live point.
+    pub fn resume(&mut self) -> Result<()> {
+        self.log_input(|| Input::Resume);
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.resume(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.resume(),
+            _ => track_panic!(ErrorKind::InvalidInput, "No playlist is being played"),
+        }
+        Ok(())
+    }
+
+    /// Enters or leaves the low-power mode (see `PlayerConfig::low_power`),
+    /// e.g., when the page is hidden or shown.
+    pub fn set_low_power(&mut self, enabled: bool) {
+        self.log_input(|| Input::SetLowPower { enabled });
+        self.config.low_power.enabled = enabled;
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.set_low_power(enabled),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.set_low_power(enabled),
+            _ => {}
+        }
+    }
+
+    /// Replaces `RenditionConfig::audio_languages` (e.g., when the user changes the language),
+    /// selecting the audio rendition of the played variant again.
+    ///
+    /// With `RenditionConfig::audio_playlists`, the new rendition resumes at the segment
+    /// presented with the next video segment rather than at the start of its playlist.
+    pub fn set_audio_languages(&mut self, languages: Vec<String>) {
+        self.log_input(|| Input::SetAudioLanguages {
+            languages: languages.clone(),
+        });
+        self.config.renditions.audio_languages = languages.clone();
+        if let PlaylistHandler::MasterPlaylist(ref mut x) = self.handler {
+            x.set_audio_languages(languages);
+        }
+    }
+
+    /// Notifies the length of the media buffered ahead of the playback position.
+    ///
+    /// It is reported to servers as CMCD (see `PlayerConfig::cmcd`).
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        self.log_input(|| Input::SetBufferLength {
+            buffer_length_ms: buffer_length.as_millis() as u64,
+        });
+        let event = self.qoe.on_buffer_length(&self.config.hooks, buffer_length);
+        self.event_queue.extend(event);
+        match self.handler {
+            PlaylistHandler::NotStarted => {}
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.set_buffer_length(buffer_length),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.set_buffer_length(buffer_length),
+            PlaylistHandler::Download(ref mut x) => x.set_buffer_length(buffer_length),
+            PlaylistHandler::Thumbnails(_) | PlaylistHandler::Loading(_) => {}
+        }
+    }
+
+    /// Notifies the playback position on the media timeline of the produced segments,
+    /// which starts at zero (e.g., `HTMLMediaElement.currentTime`).
+    ///
+    /// It is used for measuring the live latency and by `sync_to`.
+    pub fn set_playback_position(&mut self, position: Duration) {
+        self.log_input(|| Input::SetPlaybackPosition {
+            position_ms: position.as_millis() as u64,
+        });
+        let event = self.gap_jumper.on_playback_position(&self.config.gap_jump, position);
+        self.event_queue.extend(event);
+        match self.handler {
+            PlaylistHandler::NotStarted => {}
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.set_playback_position(position),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.set_playback_position(position),
+            PlaylistHandler::Download(_) => {}
+            PlaylistHandler::Thumbnails(_) | PlaylistHandler::Loading(_) => {}
+        }
+    }
+
+    /// Notifies the time ranges of the media buffered by the host
+    /// (e.g., `SourceBuffer.buffered`) on the media timeline of the produced segments.
+    ///
+    /// Small gaps reached by the playback position are notified by `Event::GapNudge`
+    /// (see `PlayerConfig::gap_jump`).
+    pub fn set_buffered_ranges(&mut self, ranges: &[(Duration, Duration)]) {
+        self.log_input(|| Input::SetBufferedRanges {
+            ranges_ms: ranges
+                .iter()
+                .map(|&(start, end)| (start.as_millis() as u64, end.as_millis() as u64))
+                .collect(),
+        });
+        let event = self.gap_jumper.on_buffered_ranges(&self.config.gap_jump, ranges.to_vec());
+        self.event_queue.extend(event);
+    }
+
+    /// Returns the `EXT-X-PROGRAM-DATE-TIME` (since the UNIX epoch) presented at `position`
+    /// on the media timeline of the produced segments.
+    pub fn program_date_time_at(&self, position: Duration) -> Option<Duration> {
+        self.latency_estimator()
+            .and_then(|x| x.program_date_time_at(position))
+    }
+
+    /// Returns the position on the media timeline of the produced segments
+    /// presenting `date_time` (since the UNIX epoch).
+    pub fn position_at(&self, date_time: Duration) -> Option<Duration> {
+        self.latency_estimator().and_then(|x| x.position_at(date_time))
+    }
+
+    /// Returns the hint for aligning the playback to the wall-clock `date_time`
+    /// (since the UNIX epoch) of the content, e.g., the date-time played by another player
+    /// of the same event (see `Stats::program_date_time_ms`).
+    ///
+    /// `None` is returned until a segment with `EXT-X-PROGRAM-DATE-TIME` has been produced.
+    pub fn sync_to(&self, date_time: Duration) -> Option<SyncHint> {
+        self.latency_estimator().and_then(|x| x.sync_hint(date_time))
+    }
+
+    /// Returns the keyframes of the produced segments found while remuxing them,
+    /// in the order of the media timeline.
+    pub fn keyframe_index(&self) -> Vec<IndexedSegment> {
+        self.keyframes()
+            .map_or_else(Vec::new, |x| x.segments().iter().cloned().collect())
+    }
+
+    /// Returns the keyframe to seek to for `position` on the media timeline
+    /// of the produced segments, i.e., the last one at or before it (or the first one).
+    pub fn nearest_keyframe(&self, position: Duration) -> Option<KeyframePosition> {
+        self.keyframes().and_then(|x| x.nearest(position))
+    }
+
+    /// Returns the total duration and the segment start times of the VOD media playlist
+    /// being played, available once it is loaded (see `Event::TimelineLoaded`).
+    ///
+    /// For master playlists, this is the timeline of the current variant.
+    pub fn timeline(&self) -> Option<&Timeline> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref x) => x.timeline(),
+            PlaylistHandler::MediaPlaylist(ref x) => x.timeline(),
+            _ => None,
+        }
+    }
+
+    /// Returns the tile of the image stream (see `ThumbnailConfig::image_streams`) previewing
+    /// `position`, measured from the start of the image playlist (as that of `Timeline`
+    /// for VOD playlists). The host fetches the image and crops the tile from it.
+    pub fn image_tile_at(&self, position: Duration) -> Option<ImageTile> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref x) => x.image_tile_at(position),
+            _ => None,
+        }
+    }
+
+    /// Returns the `EXT-X-DATERANGE`s of the played playlists which do not mark ad breaks
+    /// (e.g., chapters), in the order of their start dates.
+    ///
+    /// `Event::DateRangeEntered` and `Event::DateRangeExited` are emitted as the playback
+    /// position (see `set_playback_position`) crosses them, which requires
+    /// `EXT-X-PROGRAM-DATE-TIME`.
+    pub fn date_ranges(&self) -> &[DateRange] {
+        self.date_range_timeline().map_or(&[], |x| x.ranges())
+    }
+
+    /// Returns the date ranges containing `position` on the media timeline
+    /// of the produced segments.
+    pub fn date_ranges_at(&self, position: Duration) -> Vec<&DateRange> {
+        match (self.date_range_timeline(), self.program_date_time_at(position)) {
+            (Some(x), Some(date_time)) => x.ranges_at(date_time),
+            _ => Vec::new(),
+        }
+    }
+
+    fn date_range_timeline(&self) -> Option<&DateRangeTimeline> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref x) => Some(x.date_ranges()),
+            PlaylistHandler::MediaPlaylist(ref x) => Some(x.date_ranges()),
+            _ => None,
+        }
+    }
+
+    fn keyframes(&self) -> Option<&KeyframeIndex> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref x) => Some(x.keyframe_index()),
+            PlaylistHandler::MediaPlaylist(ref x) => Some(x.keyframe_index()),
+            _ => None,
+        }
+    }
+
+    fn latency_estimator(&self) -> Option<&LatencyEstimator> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref x) => Some(x.latency_estimator()),
+            PlaylistHandler::MediaPlaylist(ref x) => Some(x.latency_estimator()),
+            _ => None,
+        }
+    }
+
+    /// Returns the statistics of the current playback.
+    pub fn stats(&self) -> Stats {
+        let stats = match self.handler {
+            PlaylistHandler::NotStarted | PlaylistHandler::Loading(_) => Stats::default(),
+            PlaylistHandler::MasterPlaylist(ref x) => x.stats(),
+            PlaylistHandler::MediaPlaylist(ref x) => x.stats(),
+            PlaylistHandler::Download(ref x) => x.stats(),
+            PlaylistHandler::Thumbnails(ref x) => x.stats(),
+        };
+        Stats {
+            qoe: self.qoe.stats().clone(),
+            ..stats
+        }
+    }
+
+    /// Returns the timestamped snapshot of `stats()`.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            timestamp_ms: self.config.hooks.now().map(|t| t.as_secs_f64() * 1000.0),
+            stats: self.stats(),
+        }
+    }
+
+    /// Returns `metrics()` serialized as a JSON object.
+    pub fn metrics_json(&self) -> Result<String> {
+        track!(serde_json::to_string(&self.metrics()).map_err(Error::from))
+    }
+
+    /// Returns the log of the requested fetches of the current (or last) playback
+    /// as a HAR (HTTP Archive) like JSON object (see `RequestLog::to_har`).
+    pub fn request_log_har(&self) -> serde_json::Value {
+        self.request_log.to_har()
+    }
+
+    /// Returns the diagnostic trace of the current (or last) playback.
+    ///
+    /// Only the latest `TraceConfig::history_size` records are retained
+    /// unless `PlayerConfig::trace` is enabled.
+    pub fn trace_records(&self) -> Vec<TraceRecord> {
+        self.trace.records()
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace.clear();
+    }
+
+    /// Returns the inputs of the current (or last) playback, which can be given to
+    /// `replay::replay` for reproducing it (see `PlayerConfig::input_log`).
+    pub fn input_log(&self) -> &[InputRecord] {
+        self.input_log.records()
+    }
+
+    /// Returns `input_log()` serialized as a JSON array.
+    pub fn input_log_json(&self) -> Result<String> {
+        track!(serde_json::to_string(self.input_log.records()).map_err(Error::from))
+    }
+
+    /// Restarts the input log with the configuration and the start of a playback.
+    fn start_input_log<F: FnOnce() -> Input>(&mut self, f: F) {
+        self.input_log.clear();
+        if self.config.input_log.enabled {
+            let config = Box::new(self.config.clone());
+            self.input_log
+                .record(&self.config.hooks, Input::Configure { config });
+            self.input_log.record(&self.config.hooks, f());
+        }
+    }
+
+    fn log_input<F: FnOnce() -> Input>(&mut self, f: F) {
+        if self.config.input_log.enabled {
+            self.input_log.record(&self.config.hooks, f());
+        }
+    }
+
+    /// Stops the current playback.
+    ///
+    /// The fetches in flight and the pending timers are cancelled by `Action::Abort` actions,
+    /// so that they do not reach the next playback, whose action IDs may be the same.
+    pub fn stop(&mut self) {
+        match self.handler {
+            PlaylistHandler::NotStarted => return,
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.stop(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.stop(),
+            PlaylistHandler::Download(ref mut x) => x.stop(),
+            PlaylistHandler::Thumbnails(ref mut x) => x.stop(),
+            PlaylistHandler::Loading(ref mut x) => x.stop(),
+        }
+        self.log_input(|| Input::Stop);
+        while let Some(action) = self.next_handler_action() {
+            self.action_queue.push_back(action);
+        }
+        for action_id in mem::take(&mut self.pending_timers) {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+        self.handler = PlaylistHandler::NotStarted;
+        log!(self.config, Info, "Stopped the playback");
+    }
+
     pub fn next_action(&mut self) -> Option<Action> {
-        match *self {
-            HlsPlayer::NotStarted => None,
-            HlsPlayer::MasterPlaylist(ref mut x) => x.next_action(),
-            HlsPlayer::MediaPlayilst(ref mut x) => x.next_action(),
+        let action = self.action_queue
+            .pop_front()
+            .or_else(|| self.next_handler_action());
+        if let Some(ref action) = action {
+            if let Action::SetTimeout { action_id, .. } = *action {
+                self.pending_timers.insert(action_id);
+            }
+            self.trace.record(|| TraceEntry::ActionEmitted {
+                action: action.clone(),
+            });
+            self.request_log.on_action(action);
         }
+        action
     }
 
-    pub fn next_segment(&mut self) -> Option<Vec<u8>> {
-        match *self {
-            HlsPlayer::NotStarted => None,
-            HlsPlayer::MasterPlaylist(ref mut x) => x.next_segment(),
-            HlsPlayer::MediaPlayilst(ref mut x) => x.next_segment(),
+    /// Takes all the queued actions at once, for bindings in which each `next_action` call
+    /// crosses a costly boundary (e.g., when many fetches are scheduled at once).
+    pub fn drain_actions(&mut self) -> Vec<Action> {
+        iter::from_fn(|| self.next_action()).collect()
+    }
+
+    fn next_handler_action(&mut self) -> Option<Action> {
+        match self.handler {
+            PlaylistHandler::NotStarted => None,
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.next_action(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.next_action(),
+            PlaylistHandler::Download(ref mut x) => x.next_action(),
+            PlaylistHandler::Thumbnails(ref mut x) => x.next_action(),
+            PlaylistHandler::Loading(ref mut x) => x.next_action(),
+        }
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        if let Some(event) = self.event_queue.pop_front() {
+            return Some(event);
+        }
+        match self.handler {
+            PlaylistHandler::NotStarted => None,
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.next_event(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.next_event(),
+            PlaylistHandler::Download(ref mut x) => x.next_event(),
+            PlaylistHandler::Thumbnails(ref mut x) => x.next_event(),
+            PlaylistHandler::Loading(ref mut x) => x.next_event(),
+        }
+    }
+
+    /// Takes the next fMP4 segment to append to the media buffer, along with the playlist
+    /// entry it has been produced from.
+    ///
+    /// The first one is preceded by the initialization segment (see `SegmentInfo::init_bytes`).
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        let segment = match self.handler {
+            PlaylistHandler::NotStarted | PlaylistHandler::Loading(_) => None,
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.next_segment(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.next_segment(),
+            PlaylistHandler::Download(ref mut x) => x.next_segment(),
+            PlaylistHandler::Thumbnails(ref mut x) => x.next_segment(),
+        };
+        if let Some(ref segment) = segment {
+            self.log_input(|| Input::SegmentTaken);
+            self.qoe.on_segment_taken(&self.config.hooks);
+            self.last_segment_info = Some(segment.info.clone());
+        }
+        segment
+    }
+
+    /// Takes the bytes of the next segment, as `next_segment` did before 0.2.0.
+    ///
+    /// Its info is still available from `last_segment_info`.
+    #[deprecated(since = "0.2.0", note = "use `next_segment`, whose `data` are the bytes")]
+    pub fn next_segment_bytes(&mut self) -> Option<Vec<u8>> {
+        self.next_segment().map(|segment| segment.data)
+    }
+
+    /// Hands the next segment to `f`, and recycles its buffer as soon as `f` returns.
+    ///
+    /// This is for hosts which copy segments out of the memory of the player rather than
+    /// keeping references into it (e.g., into transferable buffers posted from a Web Worker).
+    /// Returns `false` if there is no segment.
+    pub fn take_segment_with<F>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&[u8], &SegmentInfo),
+    {
+        match self.next_segment() {
+            None => false,
+            Some(segment) => {
+                f(&segment.data, &segment.info);
+                self.recycle_segment(segment.data);
+                true
+            }
+        }
+    }
+
+    /// Returns the info of the segment last taken by `next_segment`, for bindings
+    /// which hand over its bytes alone.
+    pub fn last_segment_info(&self) -> Option<&SegmentInfo> {
+        self.last_segment_info.as_ref()
+    }
+
+    /// Returns the data of a segment taken by `next_segment` after the host has consumed it,
+    /// so that its allocation is reused for later segments.
+    pub fn recycle_segment(&mut self, segment: Vec<u8>) {
+        match self.handler {
+            PlaylistHandler::NotStarted | PlaylistHandler::Loading(_) => {}
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.recycle_segment(segment),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.recycle_segment(segment),
+            PlaylistHandler::Download(ref mut x) => x.recycle_segment(segment),
+            PlaylistHandler::Thumbnails(ref mut x) => x.recycle_segment(segment),
+        }
+    }
+
+    /// Takes the bytes recorded since the last call (see `PlayerConfig::recording`).
+    ///
+    /// Appending them in order makes a single fMP4 file of the playback, whose timestamps
+    /// continue across discontinuities (see `FragmentTimeline`).
+    pub fn take_recording(&mut self) -> Vec<u8> {
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => x.take_recording(),
+            PlaylistHandler::MediaPlaylist(ref mut x) => x.take_recording(),
+            PlaylistHandler::NotStarted
+            | PlaylistHandler::Download(_)
+            | PlaylistHandler::Thumbnails(_)
+            | PlaylistHandler::Loading(_) => Vec::new(),
         }
     }
 
@@ -58,22 +897,229 @@ impl HlsPlayer {
         data: &[u8],
         fetch_duration_ms: u32,
     ) -> Result<()> {
-        match *self {
-            HlsPlayer::NotStarted => Ok(()),
-            HlsPlayer::MasterPlaylist(ref mut x) => {
-                track!(x.handle_data(action_id, data, fetch_duration_ms))
+        track!(self.handle_data_with_url(action_id, data, fetch_duration_ms, None))
+    }
+
+    /// Same as `handle_data`, but also notifies the URL the response was served from.
+    ///
+    /// Hosts should pass the final URL when the request was redirected
+    /// (e.g., `Response.url` of the `fetch` API), so that relative URIs resolve against it.
+    pub fn handle_data_with_url(
+        &mut self,
+        action_id: ActionId,
+        data: &[u8],
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        self.log_input(|| Input::Data {
+            action_id,
+            data: data.to_owned(),
+            fetch_duration_ms,
+            final_url: final_url.map(|u| u.to_string()),
+        });
+        self.request_log
+            .on_completed(action_id, Some(data.len()), fetch_duration_ms);
+        match self.handler {
+            PlaylistHandler::NotStarted => {}
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.handle_data(action_id, data, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::MediaPlaylist(ref mut x) => {
+                track!(x.handle_data(action_id, data, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::Download(ref mut x) => {
+                track!(x.handle_data(action_id, data, fetch_duration_ms, final_url))?
             }
-            HlsPlayer::MediaPlayilst(ref mut x) => {
-                track!(x.handle_data(action_id, data, fetch_duration_ms))
+            PlaylistHandler::Thumbnails(ref mut x) => {
+                track!(x.handle_data(action_id, data, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::Loading(ref mut x) => {
+                if let Some(m3u8) = x.handle_data(action_id, data) {
+                    let url = final_url.unwrap_or_else(|| x.url()).clone();
+                    return track!(self.play_loaded_source(url, &m3u8));
+                }
             }
         }
+        self.track_downloads();
+        Ok(())
+    }
+
+    /// Passes the response headers of the fetch requested by `action_id`, before its body.
+    ///
+    /// Header names are case-insensitive. Hosts may pass only those read by the player:
+    /// `Server-Timing` and `CMSD-Dynamic` (see `ThroughputHintConfig`),
+    /// and `Date` and `Age` (see `LiveEdgeConfig::correct_clock_skew`).
+    pub fn handle_response_headers(
+        &mut self,
+        action_id: ActionId,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        self.log_input(|| Input::ResponseHeaders {
+            action_id,
+            headers: headers.clone(),
+        });
+        match self.handler {
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.handle_response_headers(action_id, headers))
+            }
+            PlaylistHandler::MediaPlaylist(ref mut x) => {
+                track!(x.handle_response_headers(action_id, headers))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Same as `handle_response_headers`, with the headers given as a JSON object.
+    pub fn handle_response_headers_json(&mut self, action_id: ActionId, json: &str) -> Result<()> {
+        let headers = track!(serde_json::from_str(json).map_err(Error::from))?;
+        track!(self.handle_response_headers(action_id, &headers))
+    }
+
+    /// Passes a chunk of the response body of the fetch requested by `action_id`.
+    ///
+    /// It allows hosts to feed bodies progressively (e.g., from a `ReadableStream`)
+    /// instead of calling `handle_data` with the whole body.
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) -> Result<()> {
+        self.log_input(|| Input::DataChunk {
+            action_id,
+            chunk: chunk.to_owned(),
+        });
+        self.request_log.on_data_chunk(action_id, chunk.len());
+        match self.handler {
+            PlaylistHandler::NotStarted => Ok(()),
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.handle_data_chunk(action_id, chunk))
+            }
+            PlaylistHandler::MediaPlaylist(ref mut x) => {
+                track!(x.handle_data_chunk(action_id, chunk))
+            }
+            PlaylistHandler::Download(ref mut x) => {
+                track!(x.handle_data_chunk(action_id, chunk))
+            }
+            PlaylistHandler::Thumbnails(ref mut x) => {
+                track!(x.handle_data_chunk(action_id, chunk))
+            }
+            PlaylistHandler::Loading(ref mut x) => {
+                x.handle_data_chunk(action_id, chunk);
+                Ok(())
+            }
+        }
+    }
+
+    /// Notifies the end of the response body given by `handle_data_chunk`.
+    ///
+    /// `final_url` is the same as `handle_data_with_url`.
+    pub fn handle_data_end(
+        &mut self,
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        self.log_input(|| Input::DataEnd {
+            action_id,
+            fetch_duration_ms,
+            final_url: final_url.map(|u| u.to_string()),
+        });
+        self.request_log
+            .on_completed(action_id, None, fetch_duration_ms);
+        match self.handler {
+            PlaylistHandler::NotStarted => {}
+            PlaylistHandler::MasterPlaylist(ref mut x) => {
+                track!(x.handle_data_end(action_id, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::MediaPlaylist(ref mut x) => {
+                track!(x.handle_data_end(action_id, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::Download(ref mut x) => {
+                track!(x.handle_data_end(action_id, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::Thumbnails(ref mut x) => {
+                track!(x.handle_data_end(action_id, fetch_duration_ms, final_url))?
+            }
+            PlaylistHandler::Loading(ref mut x) => {
+                if let Some(m3u8) = x.handle_data_end(action_id) {
+                    let url = final_url.unwrap_or_else(|| x.url()).clone();
+                    return track!(self.play_loaded_source(url, &m3u8));
+                }
+            }
+        }
+        self.track_downloads();
+        Ok(())
+    }
+
+    fn track_downloads(&mut self) {
+        if self.stats().segments_downloaded > 0 {
+            self.qoe.on_segment_downloaded(&self.config.hooks);
+        }
     }
 
     pub fn handle_timeout(&mut self, action_id: ActionId) -> Result<()> {
-        match *self {
-            HlsPlayer::NotStarted => Ok(()),
-            HlsPlayer::MasterPlaylist(ref mut x) => track!(x.handle_timeout(action_id)),
-            HlsPlayer::MediaPlayilst(ref mut x) => track!(x.handle_timeout(action_id)),
+        self.log_input(|| Input::Timeout { action_id });
+        self.pending_timers.remove(&action_id);
+        match self.handler {
+            PlaylistHandler::NotStarted => Ok(()),
+            PlaylistHandler::MasterPlaylist(ref mut x) => track!(x.handle_timeout(action_id)),
+            PlaylistHandler::MediaPlaylist(ref mut x) => track!(x.handle_timeout(action_id)),
+            PlaylistHandler::Download(ref mut x) => track!(x.handle_timeout(action_id)),
+            PlaylistHandler::Thumbnails(ref mut x) => track!(x.handle_timeout(action_id)),
+            PlaylistHandler::Loading(ref mut x) => {
+                x.handle_timeout(action_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Notifies that the fetch requested by `action_id` did not complete within its `timeout`.
+    pub fn handle_fetch_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        self.log_input(|| Input::FetchTimeout { action_id });
+        self.request_log.on_timeout(action_id);
+        match self.handler {
+            PlaylistHandler::NotStarted => Ok(()),
+            PlaylistHandler::MasterPlaylist(ref mut x) => track!(x.handle_fetch_timeout(action_id)),
+            PlaylistHandler::MediaPlaylist(ref mut x) => track!(x.handle_fetch_timeout(action_id)),
+            PlaylistHandler::Download(ref mut x) => track!(x.handle_fetch_timeout(action_id)),
+            PlaylistHandler::Thumbnails(ref mut x) => track!(x.handle_fetch_timeout(action_id)),
+            PlaylistHandler::Loading(ref mut x) => {
+                x.handle_failure(action_id, FailureClass::Transient, "timed out");
+                Ok(())
+            }
         }
     }
+
+    /// Notifies the failure of the fetch requested by `action_id`.
+    ///
+    /// `status` is the HTTP status code of the response, or `0` if no response was received.
+    pub fn handle_error(&mut self, action_id: ActionId, status: u16) -> Result<()> {
+        self.log_input(|| Input::Error { action_id, status });
+        self.request_log.on_error(action_id, status);
+        match self.handler {
+            PlaylistHandler::NotStarted => Ok(()),
+            PlaylistHandler::MasterPlaylist(ref mut x) => track!(x.handle_error(action_id, status)),
+            PlaylistHandler::MediaPlaylist(ref mut x) => track!(x.handle_error(action_id, status)),
+            PlaylistHandler::Download(ref mut x) => track!(x.handle_error(action_id, status)),
+            PlaylistHandler::Thumbnails(ref mut x) => track!(x.handle_error(action_id, status)),
+            PlaylistHandler::Loading(ref mut x) => {
+                let class = FailureClass::from_status(status);
+                x.handle_failure(action_id, class, &format!("status={}", status));
+                Ok(())
+            }
+        }
+    }
+}
+impl Default for HlsPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum PlaylistHandler {
+    NotStarted,
+    MasterPlaylist(MasterPlaylistHandler),
+    MediaPlaylist(MediaPlaylistHandler),
+    Download(DownloadHandler),
+    Thumbnails(ThumbnailHandler),
+
+    /// The playlist of `HlsPlayer::load_source` is being fetched.
+    Loading(SourceLoader),
 }
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use super::{MediaType, OutputQueueConfig, OverflowPolicy};
+
+/// Produced segment taken by `HlsPlayer::next_segment`.
+#[derive(Debug)]
+pub struct OutputSegment {
+    /// fMP4 bytes (raw H.264 access units for thumbnails) to append to the media buffer.
+    pub data: Vec<u8>,
+
+    pub info: SegmentInfo,
+}
+
+/// Playlist entry which an `OutputSegment` has been produced from.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentInfo {
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+
+    /// Discontinuity sequence number of the segment (see `Event::SegmentLoaded`).
+    pub discontinuity: u64,
+
+    /// Index of the variant in the master playlist, if started from one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<usize>,
+
+    /// `EXTINF` duration, or the duration shown during trick play.
+    pub duration_ms: f64,
+
+    /// `EXT-X-PROGRAM-DATE-TIME` of the segment (milliseconds since the UNIX epoch), if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_date_time_ms: Option<f64>,
+
+    /// Bytes of the initialization segment at the start of `data` (zero if there is none).
+    pub init_bytes: usize,
+
+    /// MIME type and codecs of the initialization segment, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<MediaType>,
+
+    /// Media sequence number of the first segment of the audio rendition merged into
+    /// the segment, if any (see `RenditionConfig::audio_playlists`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_sequence: Option<u64>,
+}
+
+/// Result of `OutputQueue::push`.
+#[derive(Debug)]
+pub enum PushOutcome {
+    Queued,
+
+    /// The oldest segments were dropped to make room (`OverflowPolicy::DropOldest`).
+    Dropped(Vec<OutputSegment>),
+
+    /// The queue has exceeded its limits (`OverflowPolicy::Error`).
+    Overflowed,
+}
+
+/// Queue of the produced fMP4 segments waiting to be taken by the host.
+#[derive(Debug)]
+pub struct OutputQueue {
+    config: OutputQueueConfig,
+    segments: VecDeque<OutputSegment>,
+    bytes: usize,
+
+    /// Whether the front segment holds the initialization segment, which is never dropped.
+    is_front_initial: bool,
+}
+impl OutputQueue {
+    pub fn new(config: &OutputQueueConfig) -> Self {
+        OutputQueue {
+            config: config.clone(),
+            segments: VecDeque::new(),
+            bytes: 0,
+            is_front_initial: false,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns `true` if no more segments should be fetched (`OverflowPolicy::Block`).
+    pub fn is_blocking(&self) -> bool {
+        self.config.overflow == OverflowPolicy::Block && self.is_full()
+    }
+
+    fn is_full(&self) -> bool {
+        self.config
+            .max_segments
+            .is_some_and(|max| self.segments.len() >= max)
+            || self.config.max_bytes.is_some_and(|max| self.bytes >= max)
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.config
+            .max_segments
+            .is_some_and(|max| self.segments.len() > max)
+            || self.config.max_bytes.is_some_and(|max| self.bytes > max)
+    }
+
+    pub fn push(&mut self, segment: OutputSegment) -> PushOutcome {
+        if self.segments.is_empty() {
+            self.is_front_initial = segment.info.init_bytes > 0;
+        }
+        self.bytes += segment.data.len();
+        self.segments.push_back(segment);
+        if !self.is_exceeded() {
+            return PushOutcome::Queued;
+        }
+        match self.config.overflow {
+            OverflowPolicy::Block => PushOutcome::Queued,
+            OverflowPolicy::Error => PushOutcome::Overflowed,
+            OverflowPolicy::DropOldest => {
+                let mut dropped = Vec::new();
+                let index = if self.is_front_initial { 1 } else { 0 };
+                // The latest segment is always kept.
+                while self.is_exceeded() && self.segments.len() > index + 1 {
+                    let segment = self.segments.remove(index).expect("Never fails");
+                    self.bytes -= segment.data.len();
+                    dropped.push(segment);
+                }
+                PushOutcome::Dropped(dropped)
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<OutputSegment> {
+        let segment = self.segments.pop_front()?;
+        self.bytes -= segment.data.len();
+        self.is_front_initial = false;
+        Some(segment)
+    }
+}
@@ -0,0 +1,213 @@
+//! Ring buffer of produced segments, for worker-based players which read them through
+//! a `SharedArrayBuffer` instead of taking them one by one with `HlsPlayer::next_segment`.
+//!
+//! When the memory of the player is shared (e.g., a `WebAssembly.Memory` created with
+//! `shared: true`), another thread reads the records in place, and the only copy of a segment
+//! is the one into the ring. The header holds four 32-bit words, accessed atomically:
+//!
+//! - `0`: write position, in bytes from the start of the data.
+//! - `1`: read position, which is advanced by the consumer.
+//! - `2`: capacity of the data in bytes.
+//! - `3`: number of the records written so far, on which the consumer waits
+//!   (with `Atomics.wait`) and which the producer notifies after `fill`.
+//!
+//! The memory is shared only by the build described in `README.md`
+//! (`.cargo/shared-memory.toml`), with the atomics and bulk memory features of WebAssembly;
+//! other builds can only read the ring from the thread of the player.
+//!
+//! Records are aligned to four bytes and made of the total length (`u32`, little endian),
+//! the length of the info, the `SegmentInfo` in JSON, and the segment. A zero length means
+//! that the next record is at the start of the data.
+use std::sync::atomic::{AtomicU32, Ordering};
+use serde_json;
+
+use {Error, ErrorKind, Result};
+use super::{HlsPlayer, OutputSegment};
+
+const WRITE_POSITION: usize = 0;
+const READ_POSITION: usize = 1;
+const CAPACITY: usize = 2;
+const RECORDS: usize = 3;
+
+/// Size of the record header (total length and info length).
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// Single-producer single-consumer ring of segment records.
+#[derive(Debug)]
+pub struct OutputRing {
+    header: Box<[AtomicU32]>,
+    data: Vec<u8>,
+
+    /// Segment taken from the player which does not fit into the ring yet.
+    pending: Option<OutputSegment>,
+}
+impl OutputRing {
+    /// Makes a ring of `capacity` bytes, rounded up to a multiple of four.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.div_ceil(4) * 4;
+        let header = (0..4).map(|_| AtomicU32::new(0)).collect::<Vec<_>>();
+        header[CAPACITY].store(capacity as u32, Ordering::Release);
+        OutputRing {
+            header: header.into_boxed_slice(),
+            data: vec![0; capacity],
+            pending: None,
+        }
+    }
+
+    /// Header words shared with the consumer.
+    pub fn header(&self) -> &[AtomicU32] {
+        &self.header
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Writes the segments of `player` into the ring until it is full, and returns
+    /// the number of the written ones.
+    ///
+    /// A segment which does not fit is kept until the consumer frees enough space, so that
+    /// the player stops producing segments (see `OutputQueueConfig`) while the ring is full.
+    /// A segment larger than the ring fails, and is kept as well.
+    pub fn fill(&mut self, player: &mut HlsPlayer) -> Result<usize> {
+        let mut written = 0;
+        while let Some(segment) = self.pending.take().or_else(|| player.next_segment()) {
+            let info = match track!(self.encode_info(&segment)) {
+                Ok(info) => info,
+                Err(e) => {
+                    self.pending = Some(segment);
+                    return Err(e);
+                }
+            };
+            if !self.write(&info, &segment.data) {
+                self.pending = Some(segment);
+                break;
+            }
+            player.recycle_segment(segment.data);
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Returns the `SegmentInfo` in JSON, after checking that the record of `segment` may fit.
+    fn encode_info(&self, segment: &OutputSegment) -> Result<Vec<u8>> {
+        let info = track!(serde_json::to_vec(&segment.info).map_err(Error::from))?;
+        let size = record_size(info.len(), segment.data.len());
+
+        // Leaves room for the wrap-around mark.
+        track_assert!(
+            size + 4 <= self.data.len(),
+            ErrorKind::InvalidInput,
+            "The segment {} ({} bytes) is larger than the output ring ({} bytes)",
+            segment.info.sequence,
+            segment.data.len(),
+            self.data.len()
+        );
+        Ok(info)
+    }
+
+    /// Writes a record if it fits.
+    fn write(&mut self, info: &[u8], segment: &[u8]) -> bool {
+        let capacity = self.data.len();
+        let write = self.header[WRITE_POSITION].load(Ordering::Acquire) as usize;
+        let read = self.header[READ_POSITION].load(Ordering::Acquire) as usize;
+        let size = record_size(info.len(), segment.len());
+        let position = if write >= read {
+            if write + size < capacity || (write + size == capacity && read > 0) {
+                write
+            } else if size < read {
+                // Marks the wrap-around.
+                self.data[write..write + 4].copy_from_slice(&0u32.to_le_bytes());
+                0
+            } else {
+                return false;
+            }
+        } else if write + size < read {
+            write
+        } else {
+            return false;
+        };
+
+        let total = (RECORD_HEADER_SIZE + info.len() + segment.len()) as u32;
+        let mut offset = position;
+        let info_len = (info.len() as u32).to_le_bytes();
+        for bytes in &[&total.to_le_bytes()[..], &info_len, info, segment] {
+            self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        }
+        let next = (position + size) % capacity;
+        self.header[WRITE_POSITION].store(next as u32, Ordering::Release);
+        self.header[RECORDS].fetch_add(1, Ordering::AcqRel);
+        true
+    }
+}
+
+/// Size of a record padded to four bytes.
+fn record_size(info_len: usize, segment_len: usize) -> usize {
+    (RECORD_HEADER_SIZE + info_len + segment_len).div_ceil(4) * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::SegmentInfo;
+
+    fn segment(sequence: u64, len: usize) -> OutputSegment {
+        OutputSegment {
+            data: vec![sequence as u8; len],
+            info: SegmentInfo {
+                sequence,
+                discontinuity: 0,
+                variant: None,
+                duration_ms: 4000.0,
+                program_date_time_ms: None,
+                init_bytes: 0,
+                media_type: None,
+                audio_sequence: None,
+            },
+        }
+    }
+
+    fn position(ring: &OutputRing, word: usize) -> usize {
+        ring.header()[word].load(Ordering::Acquire) as usize
+    }
+
+    #[test]
+    fn too_large_segments_are_kept() {
+        let mut player = HlsPlayer::new();
+        let mut ring = OutputRing::new(64);
+        ring.pending = Some(segment(7, 64));
+        assert!(ring.fill(&mut player).is_err());
+        assert_eq!(ring.pending.as_ref().map(|s| s.info.sequence), Some(7));
+        assert!(ring.fill(&mut player).is_err());
+        assert_eq!(position(&ring, RECORDS), 0);
+    }
+
+    #[test]
+    fn records_wrap_around_once_read() {
+        let mut player = HlsPlayer::new();
+        let info_len = serde_json::to_vec(&segment(0, 0).info).unwrap().len();
+        let size = record_size(info_len, 10);
+        let mut ring = OutputRing::new(size * 2 + 8);
+
+        ring.pending = Some(segment(1, 10));
+        assert_eq!(ring.fill(&mut player).unwrap(), 1);
+        ring.pending = Some(segment(2, 10));
+        assert_eq!(ring.fill(&mut player).unwrap(), 1);
+        assert_eq!(position(&ring, WRITE_POSITION), size * 2);
+
+        // The third record fits neither after the second one nor before the unread first one.
+        ring.pending = Some(segment(3, 10));
+        assert_eq!(ring.fill(&mut player).unwrap(), 0);
+        assert!(ring.pending.is_some());
+
+        ring.header()[READ_POSITION].store((size * 2) as u32, Ordering::Release);
+        assert_eq!(ring.fill(&mut player).unwrap(), 1);
+        assert_eq!(&ring.data()[size * 2..size * 2 + 4], &[0; 4]);
+        assert_eq!(position(&ring, WRITE_POSITION), size);
+        assert_eq!(position(&ring, RECORDS), 3);
+        let total = RECORD_HEADER_SIZE + info_len + 10;
+        assert_eq!(&ring.data()[..4], &(total as u32).to_le_bytes());
+        assert_eq!(ring.data()[total - 1], 3);
+    }
+}
@@ -0,0 +1,372 @@
+//! Incremental handling of reloaded media playlists.
+//!
+//! Live playlists with long DVR windows mostly repeat the segments handled on the previous
+//! reloads. The body of the last playlist is kept along with the offsets of its segments,
+//! so that a reload which repeats them (apart from those dropped from the start of the window)
+//! is recognized by comparing bytes. Only its header and the new segments are then decoded
+//! and passed to the parser.
+use std::ops::Range;
+
+use super::text;
+
+/// Tags that belong to media segments rather than to the playlist header.
+const SEGMENT_TAGS: &[&str] = &[
+    "#EXTINF",
+    "#EXT-X-BYTERANGE",
+    "#EXT-X-DISCONTINUITY",
+    "#EXT-X-KEY",
+    "#EXT-X-MAP",
+    "#EXT-X-PROGRAM-DATE-TIME",
+    "#EXT-X-DATERANGE",
+    "#EXT-X-GAP",
+    "#EXT-X-BITRATE",
+    "#EXT-X-CUE-OUT",
+    "#EXT-X-CUE-IN",
+];
+
+const MEDIA_SEQUENCE_TAG: &str = "#EXT-X-MEDIA-SEQUENCE:";
+const KEY_TAG: &[u8] = b"#EXT-X-KEY";
+const MAP_TAG: &[u8] = b"#EXT-X-MAP";
+
+/// Media playlist reduced to the segments following the already handled ones.
+#[derive(Debug)]
+pub struct PlaylistTail {
+    /// `EXT-X-MEDIA-SEQUENCE` of the original playlist.
+    pub media_sequence: u64,
+
+    /// Number of the segments listed in the original playlist.
+    pub segments: usize,
+
+    /// Playlist consisting of the header of the original one, the `EXT-X-MAP` and `EXT-X-KEY`
+    /// tags in effect (in their original order), and the segments after the handled ones.
+    ///
+    /// Its `EXT-X-MEDIA-SEQUENCE` is the sequence number of the first new segment.
+    pub m3u8: String,
+}
+
+/// Last reloaded playlist, against which the next one is compared.
+#[derive(Debug, Default)]
+pub struct PlaylistDiff {
+    /// Body of the playlist as fetched.
+    data: Vec<u8>,
+
+    media_sequence: u64,
+
+    /// Offset of the first segment (or its first tag).
+    header_end: usize,
+
+    /// Offsets just after the URI line of each segment.
+    segment_ends: Vec<usize>,
+
+    /// `EXT-X-KEY` and `EXT-X-MAP` tags in order.
+    resource_tags: Vec<ResourceTag>,
+}
+impl PlaylistDiff {
+    /// Keeps `data` as the last playlist, and reduces it to the segments whose sequence
+    /// numbers are greater than `last_seq`, the last one of the previous playlist.
+    ///
+    /// Returns `None` if the previous playlist does not end with `last_seq`, or `data`
+    /// does not list the same segments from its media sequence on (e.g., after a gap or
+    /// a media sequence reset); such playlists must be handled in full.
+    pub fn reload(&mut self, data: &[u8], last_seq: Option<u64>) -> Option<PlaylistTail> {
+        let tail = last_seq.and_then(|last_seq| self.tail(data, last_seq));
+        if tail.is_none() {
+            self.rebuild(data);
+        }
+        tail
+    }
+
+    /// Forgets the last playlist (e.g., when another one is played).
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.segment_ends.clear();
+        self.resource_tags.clear();
+    }
+
+    fn tail(&mut self, data: &[u8], last_seq: u64) -> Option<PlaylistTail> {
+        let listed = self.segment_ends.len() as u64;
+        if listed == 0 || last_seq + 1 != self.media_sequence + listed {
+            return None;
+        }
+        let header_end = header_end(data)?;
+        let header = text::decode(&data[..header_end]);
+        let media_sequence = parse_media_sequence(&header)?;
+        if media_sequence < self.media_sequence || media_sequence > last_seq {
+            return None;
+        }
+
+        // The segments dropped from the window, and the tags in effect for the first kept one
+        // which may be repeated before it.
+        let dropped = (media_sequence - self.media_sequence) as usize;
+        let kept_start = match dropped {
+            0 => self.header_end,
+            n => self.segment_ends[n - 1],
+        };
+        let kept = &self.data[kept_start..*self.segment_ends.last().expect("Never fails")];
+        let in_effect = self.tags_in_effect(dropped);
+        let mut kept_offset = header_end;
+        let mut repeated_tags = Vec::new();
+        while !data[kept_offset..].starts_with(kept) {
+            let (line, end) = lines(data, kept_offset).next()?;
+            let is_repeated = in_effect.iter().any(|t| trim(&self.data[t.line.clone()]) == line);
+            if !is_repeated || repeated_tags.len() == in_effect.len() {
+                return None;
+            }
+            repeated_tags.push(ResourceTag {
+                segment: 0,
+                is_map: is_tag(line, MAP_TAG),
+                line: kept_offset..end,
+            });
+            kept_offset = end;
+        }
+        let cut = kept_offset + kept.len();
+
+        // The parser rejects a leading `EXT-X-BYTERANGE` without offset,
+        // whose offset depends on the cut off segment.
+        let first_block = lines(data, cut).map(|(l, _)| l).take_while(|l| !is_uri(l));
+        if first_block
+            .filter(|l| l.starts_with(b"#EXT-X-BYTERANGE:"))
+            .any(|l| !l.contains(&b'@'))
+        {
+            return None;
+        }
+
+        let new_segments = lines(data, cut).filter(|&(l, _)| is_uri(l)).count();
+        let segments = (last_seq + 1 - media_sequence) as usize + new_segments;
+        let mut tail = String::with_capacity(data.len() - cut + header.len());
+        let mut has_media_sequence = false;
+        for line in header.lines().map(|l| l.trim()) {
+            if line.starts_with(MEDIA_SEQUENCE_TAG) {
+                has_media_sequence = true;
+                push_line(&mut tail, &format!("{}{}", MEDIA_SEQUENCE_TAG, last_seq + 1));
+            } else {
+                push_line(&mut tail, line);
+            }
+        }
+        if !has_media_sequence {
+            push_line(&mut tail, &format!("{}{}", MEDIA_SEQUENCE_TAG, last_seq + 1));
+        }
+        if new_segments > 0 {
+            for tag in self.tags_in_effect(self.segment_ends.len()) {
+                push_line(&mut tail, &String::from_utf8_lossy(trim(&self.data[tag.line])));
+            }
+        }
+        tail.push_str(&text::decode(&data[cut..]));
+
+        // Moves the offsets of the kept segments to the new body.
+        let shift = |offset: usize| offset - kept_start + kept_offset;
+        self.segment_ends.drain(..dropped);
+        for end in &mut self.segment_ends {
+            *end = shift(*end);
+        }
+        let kept_tags = self.resource_tags
+            .drain(..)
+            .filter(|t| t.segment >= dropped)
+            .map(|t| ResourceTag {
+                segment: t.segment - dropped,
+                is_map: t.is_map,
+                line: shift(t.line.start)..shift(t.line.end),
+            });
+        let tags = repeated_tags.into_iter().chain(kept_tags).collect();
+        self.resource_tags = tags;
+        self.media_sequence = media_sequence;
+        self.header_end = header_end;
+        self.data.clear();
+        self.data.extend_from_slice(data);
+        self.scan_segments(cut);
+        Some(PlaylistTail {
+            media_sequence,
+            segments,
+            m3u8: tail,
+        })
+    }
+
+    /// Scans all of `data` as the last playlist.
+    fn rebuild(&mut self, data: &[u8]) {
+        self.clear();
+        self.data.extend_from_slice(data);
+        let header = header_end(data).and_then(|end| {
+            let media_sequence = parse_media_sequence(&text::decode(&data[..end]))?;
+            Some((end, media_sequence))
+        });
+        if let Some((end, media_sequence)) = header {
+            self.header_end = end;
+            self.media_sequence = media_sequence;
+            self.scan_segments(end);
+        }
+    }
+
+    /// Records the segments and the tags of `data` from `start` on.
+    fn scan_segments(&mut self, start: usize) {
+        let mut line_start = start;
+        for (line, end) in lines(&self.data, start) {
+            if is_uri(line) {
+                self.segment_ends.push(end);
+            } else if is_tag(line, KEY_TAG) || is_tag(line, MAP_TAG) {
+                self.resource_tags.push(ResourceTag {
+                    segment: self.segment_ends.len(),
+                    is_map: is_tag(line, MAP_TAG),
+                    line: line_start..end,
+                });
+            }
+            line_start = end;
+        }
+    }
+
+    /// Returns the lines of the `EXT-X-KEY` and `EXT-X-MAP` tags in effect for the segment
+    /// at `index`, which precede it, in their order.
+    ///
+    /// Several `EXT-X-KEY` tags before the same segment apply together (e.g., of different
+    /// `KEYFORMAT`s).
+    fn tags_in_effect(&self, index: usize) -> Vec<ResourceTag> {
+        let preceding = self.resource_tags.iter().take_while(|t| t.segment < index).count();
+        let preceding = &self.resource_tags[..preceding];
+        let map = preceding.iter().rposition(|t| t.is_map);
+        let key_segment = preceding.iter().rev().find(|t| !t.is_map).map(|t| t.segment);
+        preceding
+            .iter()
+            .enumerate()
+            .filter(|&(i, t)| {
+                if t.is_map {
+                    Some(i) == map
+                } else {
+                    Some(t.segment) == key_segment
+                }
+            })
+            .map(|(_, t)| t.clone())
+            .collect()
+    }
+}
+
+/// `EXT-X-KEY` or `EXT-X-MAP` tag of a playlist.
+#[derive(Debug, Clone)]
+struct ResourceTag {
+    /// Index of the segment which the tag precedes.
+    segment: usize,
+
+    is_map: bool,
+
+    /// Offsets of the line.
+    line: Range<usize>,
+}
+
+/// Returns the offset of the first line of `data` which belongs to a segment.
+fn header_end(data: &[u8]) -> Option<usize> {
+    let mut line_start = 0;
+    for (line, end) in lines(data, 0) {
+        if is_uri(line) || is_segment_tag(line) {
+            return Some(line_start);
+        }
+        line_start = end;
+    }
+    None
+}
+
+fn parse_media_sequence(header: &str) -> Option<u64> {
+    match header.lines().map(|l| l.trim()).find(|l| l.starts_with(MEDIA_SEQUENCE_TAG)) {
+        None => Some(0),
+        Some(l) => l[MEDIA_SEQUENCE_TAG.len()..].trim().parse().ok(),
+    }
+}
+
+/// Returns the trimmed lines of `data` from `start` on, with the offsets just after them.
+fn lines(data: &[u8], start: usize) -> impl Iterator<Item = (&[u8], usize)> {
+    data[start..]
+        .split_inclusive(|&b| b == b'\n' || b == b'\r')
+        .scan(start, |end, line| {
+            *end += line.len();
+            Some((trim(line), *end))
+        })
+}
+
+/// Trims the whitespace, the BOM and the NUL padding around `line`.
+fn trim(line: &[u8]) -> &[u8] {
+    let line = line.strip_prefix("\u{feff}".as_bytes()).unwrap_or(line);
+    let start = line.iter().position(|&b| !is_padding(b)).unwrap_or(line.len());
+    let end = line.iter().rposition(|&b| !is_padding(b)).map_or(start, |i| i + 1);
+    &line[start..end]
+}
+
+fn is_padding(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == 0
+}
+
+fn is_uri(line: &[u8]) -> bool {
+    !line.is_empty() && !line.starts_with(b"#")
+}
+
+fn is_segment_tag(line: &[u8]) -> bool {
+    SEGMENT_TAGS.iter().any(|tag| is_tag(line, tag.as_bytes()))
+}
+
+fn is_tag(line: &[u8], tag: &[u8]) -> bool {
+    line.starts_with(tag) && line.get(tag.len()).is_none_or(|&c| c == b':')
+}
+
+fn push_line(buf: &mut String, line: &str) {
+    buf.push_str(line);
+    buf.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n";
+
+    fn playlist(media_sequence: u64, body: &str) -> String {
+        format!("{}#EXT-X-MEDIA-SEQUENCE:{}\n{}", HEADER, media_sequence, body)
+    }
+
+    fn segments(range: Range<u64>) -> String {
+        range.map(|i| format!("#EXTINF:4,\n{}.ts\n", i)).collect()
+    }
+
+    #[test]
+    fn only_new_segments_are_kept() {
+        let mut diff = PlaylistDiff::default();
+        let first = playlist(0, &("#EXT-X-MAP:URI=\"init.mp4\"\n".to_owned() + &segments(0..3)));
+        assert!(diff.reload(first.as_bytes(), None).is_none());
+
+        // Two segments dropped and two added, with the map repeated before the first one.
+        let second = playlist(2, &("#EXT-X-MAP:URI=\"init.mp4\"\n".to_owned() + &segments(2..5)));
+        let tail = diff.reload(second.as_bytes(), Some(2)).unwrap();
+        assert_eq!(tail.media_sequence, 2);
+        assert_eq!(tail.segments, 3);
+        let expected = playlist(3, &("#EXT-X-MAP:URI=\"init.mp4\"\n".to_owned() + &segments(3..5)));
+        assert_eq!(tail.m3u8, expected);
+
+        let third = playlist(4, &segments(4..6));
+        let tail = diff.reload(third.as_bytes(), Some(4)).unwrap();
+        let expected = playlist(5, &("#EXT-X-MAP:URI=\"init.mp4\"\n".to_owned() + &segments(5..6)));
+        assert_eq!(tail.m3u8, expected);
+
+        // Not the last handled segment.
+        assert!(diff.reload(third.as_bytes(), Some(4)).is_none());
+        assert!(diff.reload(third.as_bytes(), Some(5)).is_some());
+    }
+
+    #[test]
+    fn tags_keep_their_order() {
+        let key = "#EXT-X-KEY:METHOD=AES-128,URI=\"k\"\n";
+        let map = "#EXT-X-MAP:URI=\"init.ts\"\n";
+        let body = format!("{}{}{}", map, key, segments(0..2));
+        let mut diff = PlaylistDiff::default();
+        assert!(diff.reload(playlist(0, &body).as_bytes(), None).is_none());
+
+        let tail = diff.reload(playlist(0, &(body.clone() + &segments(2..3))).as_bytes(), Some(1));
+        let expected = playlist(2, &format!("{}{}{}", map, key, segments(2..3)));
+        assert_eq!(tail.unwrap().m3u8, expected);
+    }
+
+    #[test]
+    fn changed_segments_are_not_reduced() {
+        let mut diff = PlaylistDiff::default();
+        assert!(diff.reload(playlist(0, &segments(0..3)).as_bytes(), None).is_none());
+        let changed = playlist(1, &segments(0..3).replace("1.ts", "x.ts"));
+        assert!(diff.reload(changed.as_bytes(), Some(2)).is_none());
+
+        // A gap after the last handled segment.
+        assert!(diff.reload(playlist(5, &segments(5..7)).as_bytes(), Some(3)).is_none());
+    }
+}
@@ -0,0 +1,364 @@
+//! Background reloads of the media playlists of the variants which the next switch may take
+//! (see `PlaylistPrefetchConfig`).
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+use super::{Action, ActionFactory, ActionId, PlayerConfig, ResourceKey, ResourceKind, StreamId};
+use super::discontinuity;
+use super::target_duration;
+use super::text;
+
+/// Stream ID of the actions of `PlaylistPrefetcher`, distinct from those of media playlists.
+pub const PREFETCH_STREAM_ID: StreamId = 0xFB;
+
+/// Interval of the reloads until a target duration is known.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Fetcher of the media playlists of the variants which the playback may switch to:
+/// the substitute of a failover and the lowest variant of the low-power mode.
+///
+/// The playlists are reloaded every (shortest) target duration until one of them is taken
+/// by a switch; VOD playlists are fetched once.
+///
+/// While the buffer is comfortable (see `PlaylistPrefetchConfig::warm_up_buffer_ms`),
+/// the segment of the failover substitute following the played one is fetched as well.
+#[derive(Debug)]
+pub struct PlaylistPrefetcher {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    targets: Vec<Url>,
+    playlists: HashMap<Url, PrefetchedPlaylist>,
+    fetching: HashMap<ActionId, Url>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+    reload_action_id: Option<ActionId>,
+
+    /// Variant (the failover substitute) whose next segment is warmed up.
+    warm_up_url: Option<Url>,
+
+    warm_up: Option<WarmUpSegment>,
+    buffer_length: Option<Duration>,
+}
+impl PlaylistPrefetcher {
+    pub fn new(config: &PlayerConfig) -> Self {
+        PlaylistPrefetcher {
+            config: config.clone(),
+            action_factory: ActionFactory::new(PREFETCH_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            targets: Vec::new(),
+            playlists: HashMap::new(),
+            fetching: HashMap::new(),
+            partial_bodies: HashMap::new(),
+            reload_action_id: None,
+            warm_up_url: None,
+            warm_up: None,
+            buffer_length: None,
+        }
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == PREFETCH_STREAM_ID
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    /// Replaces the prefetched playlists by those of the `failover` substitute and the `lowest`
+    /// variant, fetching the new ones unless `is_paused`.
+    pub fn set_targets(&mut self, failover: Option<Url>, lowest: Option<Url>, is_paused: bool) {
+        if self.warm_up_url != failover {
+            self.cancel_warm_up();
+            self.warm_up_url = failover.clone();
+        }
+        let targets = failover.into_iter().chain(lowest).collect::<Vec<_>>();
+        if targets == self.targets {
+            return;
+        }
+        let fetching = self.fetching
+            .iter()
+            .filter(|&(_, url)| !targets.contains(url))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for action_id in fetching {
+            self.fetching.remove(&action_id);
+            self.partial_bodies.remove(&action_id);
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+        self.playlists.retain(|url, _| targets.contains(url));
+        self.targets = targets;
+        if !is_paused {
+            self.request();
+        }
+    }
+
+    /// Fetches the playlists of the targets which are neither being fetched nor complete.
+    pub fn request(&mut self) {
+        let urls = self.targets
+            .iter()
+            .filter(|url| !self.fetching.values().any(|u| u == *url))
+            .filter(|url| !self.playlists.get(*url).is_some_and(|p| p.is_vod))
+            .cloned()
+            .collect::<Vec<_>>();
+        for url in urls {
+            let action = self.action_factory
+                .fetch_data(url.clone(), ResourceKind::Playlist, None);
+            self.fetching.insert(action.id(), url);
+            self.action_queue.push_back(action);
+        }
+    }
+
+    /// Takes the playlist prefetched from `url`, unless it is older than
+    /// `PlaylistPrefetchConfig::max_age_ratio` target durations.
+    pub fn take(&mut self, url: &Url) -> Option<String> {
+        let playlist = self.playlists.remove(url)?;
+        let max_age = playlist
+            .target_duration
+            .mul_f64(self.config.playlist_prefetch.max_age_ratio);
+        let now = self.config.hooks.now();
+        let is_stale = !playlist.is_vod
+            && now.zip(playlist.fetched_at)
+                .is_some_and(|(now, at)| now.saturating_sub(at) > max_age);
+        if is_stale {
+            log!(self.config, Debug, "The prefetched playlist {} is stale", url);
+            return None;
+        }
+        Some(playlist.m3u8)
+    }
+
+    pub fn set_buffer_length(&mut self, buffer_length: Duration) {
+        self.buffer_length = Some(buffer_length);
+        self.action_factory.set_buffer_length(buffer_length);
+    }
+
+    /// Fetches the segment `next_sequence` of the failover substitute if the buffer is comfortable,
+    /// dropping the one warmed up before if it has been played past.
+    ///
+    /// Segments of byte ranges are not warmed up.
+    pub fn warm_up(&mut self, next_sequence: Option<u64>) {
+        if self.warm_up.as_ref().map(|w| w.sequence) == next_sequence {
+            return;
+        }
+        self.cancel_warm_up();
+        let threshold = match self.config.playlist_prefetch.warm_up_buffer_ms {
+            None => return,
+            Some(ms) => Duration::from_millis(u64::from(ms)),
+        };
+        if self.buffer_length.is_none_or(|b| b < threshold) {
+            return;
+        }
+        let (sequence, variant_url) = match (next_sequence, self.warm_up_url.clone()) {
+            (Some(sequence), Some(url)) => (sequence, url),
+            _ => return,
+        };
+        let url = self.playlists
+            .get(&variant_url)
+            .and_then(|p| sequence.checked_sub(p.media_sequence).map(|i| (p, i)))
+            .and_then(|(p, i)| p.segment_urls.get(i as usize).cloned().flatten());
+        let url = match url {
+            None => return,
+            Some(url) => url,
+        };
+        log!(self.config, Debug, "Warms up the segment #{} of {}", sequence, variant_url);
+        self.action_factory
+            .set_target_duration(self.playlists[&variant_url].target_duration);
+        let action = self.action_factory
+            .fetch_data(url.clone(), ResourceKind::Segment, None);
+        self.warm_up = Some(WarmUpSegment {
+            variant_url,
+            sequence,
+            resource: ResourceKey { url, range: None },
+            action_id: Some(action.id()),
+            data: Vec::new(),
+            fetch_duration_ms: 0,
+            is_failed: false,
+        });
+        self.action_queue.push_back(action);
+    }
+
+    /// Takes the segment warmed up for the variant `url` once it has been fetched,
+    /// along with its fetch duration in milliseconds.
+    pub fn take_segment(&mut self, url: &Url) -> Option<(ResourceKey, Vec<u8>, u32)> {
+        let is_ready = self.warm_up
+            .as_ref()
+            .is_some_and(|w| w.variant_url == *url && w.action_id.is_none() && !w.is_failed);
+        if !is_ready {
+            return None;
+        }
+        let warm_up = self.warm_up.take().expect("Never fails");
+        Some((warm_up.resource, warm_up.data, warm_up.fetch_duration_ms))
+    }
+
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8], fetch_duration_ms: u32) {
+        if let Some(warm_up) = self.warm_up
+            .as_mut()
+            .filter(|w| w.action_id == Some(action_id))
+        {
+            log!(self.config, Debug, "Warmed up the segment #{}", warm_up.sequence);
+            warm_up.action_id = None;
+            warm_up.data = data.to_owned();
+            warm_up.fetch_duration_ms = fetch_duration_ms;
+            return;
+        }
+        let url = match self.fetching.remove(&action_id) {
+            None => return,
+            Some(url) => url,
+        };
+        let m3u8 = text::decode(data).into_owned();
+        let (split, _) = discontinuity::split_sequence(&m3u8);
+        let (repaired, _) = target_duration::repair(&split);
+        let playlist: MediaPlaylist = match repaired.parse() {
+            Ok(playlist) => playlist,
+            Err(e) => {
+                log!(self.config, Warn, "Cannot parse the prefetched playlist {}: {}", url, e);
+                self.schedule_reload();
+                return;
+            }
+        };
+        log!(self.config, Debug, "Prefetched the playlist {}", url);
+        let segment_urls = playlist
+            .segments()
+            .iter()
+            .map(|s| {
+                let url = Url::options().base_url(Some(&url)).parse(s.uri()).ok();
+                url.filter(|_| s.byte_range_tag().is_none())
+            })
+            .collect();
+        let playlist = PrefetchedPlaylist {
+            m3u8,
+            target_duration: playlist.target_duration_tag().duration(),
+            is_vod: playlist.end_list_tag().is_some(),
+            fetched_at: self.config.hooks.now(),
+            media_sequence: playlist.media_sequence_tag().map_or(0, |t| t.seq_num()),
+            segment_urls,
+        };
+        self.playlists.insert(url, playlist);
+        self.schedule_reload();
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        let is_warm_up = self.warm_up
+            .as_ref()
+            .is_some_and(|w| w.action_id == Some(action_id));
+        if is_warm_up || self.fetching.contains_key(&action_id) {
+            self.partial_bodies
+                .entry(action_id)
+                .or_default()
+                .extend_from_slice(chunk);
+        }
+    }
+
+    pub fn handle_data_end(&mut self, action_id: ActionId, fetch_duration_ms: u32) {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        self.handle_data(action_id, &body, fetch_duration_ms);
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`, which is retried
+    /// by the next reload.
+    pub fn handle_failure(&mut self, action_id: ActionId, description: &str) {
+        self.partial_bodies.remove(&action_id);
+        if let Some(warm_up) = self.warm_up
+            .as_mut()
+            .filter(|w| w.action_id == Some(action_id))
+        {
+            // Not retried, since the playback may not need it.
+            log!(self.config, Debug, "Cannot warm up the segment: {}", description);
+            warm_up.action_id = None;
+            warm_up.is_failed = true;
+            return;
+        }
+        if let Some(url) = self.fetching.remove(&action_id) {
+            log!(self.config, Debug, "Cannot prefetch the playlist {}: {}", url, description);
+            self.playlists.remove(&url);
+            self.schedule_reload();
+        }
+    }
+
+    /// Returns `true` if `action_id` is the timer of the next reload,
+    /// after which `request` should be called.
+    pub fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+        if self.reload_action_id != Some(action_id) {
+            return false;
+        }
+        self.reload_action_id = None;
+        true
+    }
+
+    /// Returns `true` if neither a fetch nor a reload is pending, i.e., `request` is due.
+    pub fn is_idle(&self) -> bool {
+        self.fetching.is_empty() && self.reload_action_id.is_none()
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.reload_action_id = None;
+        self.partial_bodies.clear();
+        for (action_id, _) in self.fetching.drain() {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+        self.cancel_warm_up();
+    }
+
+    fn cancel_warm_up(&mut self) {
+        let action_id = self.warm_up.take().and_then(|w| w.action_id);
+        if let Some(action_id) = action_id {
+            self.partial_bodies.remove(&action_id);
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    /// Schedules the next reload once all the fetches have completed,
+    /// unless every target is complete.
+    fn schedule_reload(&mut self) {
+        if !self.fetching.is_empty() || self.reload_action_id.is_some() {
+            return;
+        }
+        let live = self.targets
+            .iter()
+            .map(|url| self.playlists.get(url))
+            .filter(|p| !p.is_some_and(|p| p.is_vod))
+            .collect::<Vec<_>>();
+        if live.is_empty() {
+            return;
+        }
+        let interval = live.iter()
+            .map(|p| p.map_or(DEFAULT_INTERVAL, |p| p.target_duration))
+            .min()
+            .unwrap_or(DEFAULT_INTERVAL);
+        let action = self.action_factory.set_timeout(interval);
+        self.reload_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
+
+#[derive(Debug)]
+struct PrefetchedPlaylist {
+    m3u8: String,
+    target_duration: Duration,
+    is_vod: bool,
+
+    /// Time by `Hooks::clock`.
+    fetched_at: Option<Duration>,
+
+    media_sequence: u64,
+
+    /// URLs of the segments, except for those of byte ranges.
+    segment_urls: Vec<Option<Url>>,
+}
+
+/// Segment of the failover substitute fetched ahead of a switch to it.
+#[derive(Debug)]
+struct WarmUpSegment {
+    variant_url: Url,
+    sequence: u64,
+    resource: ResourceKey,
+
+    /// Fetch in flight, if any.
+    action_id: Option<ActionId>,
+
+    data: Vec<u8>,
+    fetch_duration_ms: u32,
+    is_failed: bool,
+}
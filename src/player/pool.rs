@@ -0,0 +1,65 @@
+use std::cmp;
+
+/// Capacity of the smallest size class (4 KiB).
+const MIN_CLASS: usize = 12;
+
+/// Capacity of the largest size class (64 MiB); larger buffers are not pooled.
+const MAX_CLASS: usize = 26;
+
+/// Maximum number of the idle buffers per size class.
+const MAX_IDLE_PER_CLASS: usize = 4;
+
+/// Pool of byte buffers keyed by power-of-two capacity classes.
+///
+/// It reuses the allocations of the segment bodies and the produced fMP4 segments,
+/// which otherwise grow the wasm memory during long live sessions.
+#[derive(Debug)]
+pub struct BufferPool {
+    classes: Vec<Vec<Vec<u8>>>,
+}
+impl BufferPool {
+    pub fn new() -> Self {
+        BufferPool {
+            classes: (MIN_CLASS..=MAX_CLASS).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Returns an empty buffer whose capacity is at least `min_capacity`.
+    pub fn take(&mut self, min_capacity: usize) -> Vec<u8> {
+        let class = class_of(min_capacity.next_power_of_two());
+        if class > MAX_CLASS {
+            return Vec::with_capacity(min_capacity);
+        }
+        let reused = self.classes[class - MIN_CLASS..]
+            .iter_mut()
+            .find_map(|buffers| buffers.pop());
+        reused.unwrap_or_else(|| Vec::with_capacity(1 << class))
+    }
+
+    /// Returns `buffer` to the pool for reuse.
+    pub fn give(&mut self, mut buffer: Vec<u8>) {
+        let capacity = buffer.capacity();
+        if capacity < 1 << MIN_CLASS {
+            return;
+        }
+        // Rounds down, so that every buffer of a class can hold `1 << class` bytes.
+        let class = (usize::BITS - 1 - capacity.leading_zeros()) as usize;
+        if class > MAX_CLASS {
+            return;
+        }
+        let buffers = &mut self.classes[class - MIN_CLASS];
+        if buffers.len() < MAX_IDLE_PER_CLASS {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+}
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn class_of(power_of_two: usize) -> usize {
+    cmp::max(power_of_two.trailing_zeros() as usize, MIN_CLASS)
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use super::{Event, Hooks};
+
+/// Buffer length below which the playback is regarded as stalled.
+const REBUFFER_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Quality of experience metrics of a playback.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QoeStats {
+    /// Time from the play call to the completion of the first segment fetch.
+    pub time_to_first_segment_ms: Option<f64>,
+
+    /// Time from the play call to when the host took the first fMP4 segment.
+    pub time_to_first_append_ms: Option<f64>,
+
+    /// Number of completed rebuffering periods.
+    pub rebuffer_count: u32,
+
+    /// Total duration of the completed rebuffering periods.
+    pub rebuffer_duration_ms: f64,
+
+    pub is_rebuffering: bool,
+}
+
+/// Tracker of `QoeStats`.
+///
+/// Timings require `Hooks::clock`. Rebuffering is inferred from the buffer lengths reported by
+/// the host: it starts when the buffer runs dry after the first append,
+/// and ends when the buffer is refilled.
+#[derive(Debug, Default)]
+pub struct QoeTracker {
+    stats: QoeStats,
+    started_at: Option<Duration>,
+    has_appended: bool,
+    rebuffer_started_at: Option<Duration>,
+}
+impl QoeTracker {
+    pub fn new(hooks: &Hooks) -> Self {
+        QoeTracker {
+            started_at: hooks.now(),
+            ..QoeTracker::default()
+        }
+    }
+
+    pub fn stats(&self) -> &QoeStats {
+        &self.stats
+    }
+
+    pub fn on_segment_downloaded(&mut self, hooks: &Hooks) {
+        if self.stats.time_to_first_segment_ms.is_none() {
+            self.stats.time_to_first_segment_ms = self.elapsed_ms(hooks);
+        }
+    }
+
+    pub fn on_segment_taken(&mut self, hooks: &Hooks) {
+        if !self.has_appended {
+            self.has_appended = true;
+            self.stats.time_to_first_append_ms = self.elapsed_ms(hooks);
+        }
+    }
+
+    /// Returns `Event::Rebuffered` when a rebuffering period has ended.
+    pub fn on_buffer_length(&mut self, hooks: &Hooks, buffer_length: Duration) -> Option<Event> {
+        if !self.has_appended {
+            return None;
+        }
+        let is_stalled = buffer_length < REBUFFER_THRESHOLD;
+        if is_stalled == self.stats.is_rebuffering {
+            return None;
+        }
+        self.stats.is_rebuffering = is_stalled;
+        let now = hooks.now();
+        if is_stalled {
+            self.rebuffer_started_at = now;
+            return None;
+        }
+
+        let duration_ms = self.rebuffer_started_at
+            .take()
+            .and_then(|start| now.map(|now| to_ms(now.saturating_sub(start))));
+        self.stats.rebuffer_count += 1;
+        self.stats.rebuffer_duration_ms += duration_ms.unwrap_or(0.0);
+        Some(Event::Rebuffered { duration_ms })
+    }
+
+    fn elapsed_ms(&self, hooks: &Hooks) -> Option<f64> {
+        let started_at = self.started_at?;
+        hooks.now().map(|now| to_ms(now.saturating_sub(started_at)))
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
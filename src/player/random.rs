@@ -0,0 +1,26 @@
+/// Small non-cryptographic pseudo random number generator (xorshift64*).
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // Scrambles the seed by SplitMix64 so that small or zero seeds are usable.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Random {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    /// Returns a value uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let n = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (n >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,154 @@
+use std::mem;
+use mse_fmp4::fmp4::MediaSegment;
+
+use {ErrorKind, Result};
+
+/// Per track durations of `media_segment` in the timescales of the tracks,
+/// in the order of its `traf` boxes.
+pub fn track_durations(media_segment: &MediaSegment) -> Vec<u64> {
+    media_segment
+        .moof_box
+        .traf_boxes
+        .iter()
+        .map(|traf| {
+            let default = traf.tfhd_box.default_sample_duration.unwrap_or(0);
+            traf.trun_box
+                .samples
+                .iter()
+                .map(|s| u64::from(s.duration.unwrap_or(default)))
+                .sum()
+        })
+        .collect()
+}
+
+/// Timeline of fMP4 fragments concatenated into a single stream.
+///
+/// The remuxer starts the decode time of every fragment at zero, which suits MSE in the
+/// `sequence` mode but not files. The timeline rewrites the `mfhd` sequence numbers and
+/// the `tfdt` decode times so that each fragment follows the previous ones, also across
+/// discontinuities.
+#[derive(Debug, Default)]
+pub struct FragmentTimeline {
+    sequence_number: u32,
+    decode_times: Vec<u64>,
+}
+impl FragmentTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrites `fragment` (a `moof` box followed by `mdat` boxes) in place,
+    /// and advances the timeline by `durations` (see `track_durations`).
+    ///
+    /// The `tfdt` boxes are of version 0, so decode times wrap around after 2^32 ticks
+    /// (13 hours at 90 kHz).
+    pub fn apply(&mut self, fragment: &mut [u8], durations: &[u64]) -> Result<()> {
+        let (moof_start, moof_end) = track_assert_some!(
+            track!(children(fragment, 0, fragment.len()))?
+                .into_iter()
+                .find(|b| &b.0 == b"moof")
+                .map(|b| (b.1, b.2)),
+            ErrorKind::InvalidInput
+        );
+        if self.decode_times.len() < durations.len() {
+            self.decode_times.resize(durations.len(), 0);
+        }
+        self.sequence_number += 1;
+
+        let mut traf_index = 0;
+        for (box_type, start, end) in track!(children(fragment, moof_start + 8, moof_end))? {
+            if &box_type == b"mfhd" {
+                track_assert!(end - start >= 16, ErrorKind::InvalidInput);
+                write_u32(&mut fragment[start + 12..], self.sequence_number);
+            } else if &box_type == b"traf" {
+                track_assert!(traf_index < durations.len(), ErrorKind::InvalidInput);
+                let decode_time = self.decode_times[traf_index];
+                for (box_type, start, end) in track!(children(fragment, start + 8, end))? {
+                    if &box_type == b"tfdt" {
+                        track_assert!(end - start >= 16, ErrorKind::InvalidInput);
+                        write_u32(&mut fragment[start + 12..], decode_time as u32);
+                    }
+                }
+                traf_index += 1;
+            }
+        }
+        for (decode_time, duration) in self.decode_times.iter_mut().zip(durations) {
+            *decode_time += duration;
+        }
+        Ok(())
+    }
+}
+
+/// Sink that concatenates the produced fMP4 segments into a single progressively growing
+/// fMP4 stream (see `PlayerConfig::recording`).
+///
+/// Only the first initialization segment is recorded, so the stream assumes that the codec
+/// parameters do not change.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    timeline: FragmentTimeline,
+    buffer: Vec<u8>,
+    is_initialized: bool,
+    recorded_bytes: u64,
+}
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total size of the recorded stream, including the bytes already taken.
+    pub fn recorded_bytes(&self) -> u64 {
+        self.recorded_bytes
+    }
+
+    /// Appends `fragment` preceded by `init` (if this is the first one) to the stream.
+    pub fn record(
+        &mut self,
+        init: Option<&[u8]>,
+        fragment: &[u8],
+        durations: &[u64],
+    ) -> Result<()> {
+        let start = self.buffer.len();
+        if !self.is_initialized {
+            let init = track_assert_some!(init, ErrorKind::InvalidInput);
+            self.buffer.extend_from_slice(init);
+        }
+        let fragment_start = self.buffer.len();
+        self.buffer.extend_from_slice(fragment);
+        let result = track!(self.timeline.apply(&mut self.buffer[fragment_start..], durations));
+        if let Err(e) = result {
+            self.buffer.truncate(start);
+            return Err(e);
+        }
+        self.is_initialized = true;
+        self.recorded_bytes += (self.buffer.len() - start) as u64;
+        Ok(())
+    }
+
+    /// Takes the bytes recorded since the last call, to be appended to the output file.
+    pub fn take(&mut self) -> Vec<u8> {
+        mem::take(&mut self.buffer)
+    }
+}
+
+/// Returns the type and the range of the boxes in `data[start..end]`.
+fn children(data: &[u8], mut start: usize, end: usize) -> Result<Vec<([u8; 4], usize, usize)>> {
+    let mut boxes = Vec::new();
+    while start + 8 <= end {
+        let size = read_u32(&data[start..]) as usize;
+        track_assert!(size >= 8 && start + size <= end, ErrorKind::InvalidInput, "size={}", size);
+        let mut box_type = [0; 4];
+        box_type.copy_from_slice(&data[start + 4..start + 8]);
+        boxes.push((box_type, start, start + size));
+        start += size;
+    }
+    Ok(boxes)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn write_u32(data: &mut [u8], value: u32) {
+    data[..4].copy_from_slice(&value.to_be_bytes());
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use super::Hooks;
+
+/// Metrics of the media playlist reloads, for detecting misbehaving origins.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlaylistRefreshStats {
+    /// Number of the loaded (and parsed) playlists.
+    pub load_count: u64,
+
+    /// Time between the last two loads.
+    pub last_reload_interval_ms: Option<f64>,
+
+    /// Average time between consecutive loads.
+    pub average_reload_interval_ms: Option<f64>,
+
+    /// Number of the latest consecutive loads without new segments.
+    pub unchanged_reload_streak: u32,
+
+    /// Longest `unchanged_reload_streak` so far.
+    pub max_unchanged_reload_streak: u32,
+
+    /// Size of the last loaded playlist in bytes.
+    pub playlist_bytes: usize,
+
+    /// Number of the segments listed in the last loaded playlist.
+    pub playlist_segments: usize,
+
+    pub target_duration_ms: Option<u32>,
+
+    /// Average time it took the origin to publish a new segment,
+    /// observed between the loads that added segments.
+    pub average_publish_interval_ms: Option<f64>,
+
+    /// `average_publish_interval_ms` minus `target_duration_ms`.
+    ///
+    /// Large positive values mean that the origin lags behind the advertised cadence.
+    pub publish_drift_ms: Option<f64>,
+}
+
+/// Tracker of `PlaylistRefreshStats`.
+///
+/// The intervals require `Hooks::clock`.
+#[derive(Debug, Default)]
+pub struct PlaylistRefreshTracker {
+    stats: PlaylistRefreshStats,
+    first_loaded_at: Option<Duration>,
+    last_loaded_at: Option<Duration>,
+    first_updated_at: Option<Duration>,
+    published_segments: u64,
+}
+impl PlaylistRefreshTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> &PlaylistRefreshStats {
+        &self.stats
+    }
+
+    /// Records a loaded playlist of `bytes` bytes listing `segments` segments,
+    /// `new_segments` of which have not been seen before.
+    pub fn on_playlist_loaded(
+        &mut self,
+        hooks: &Hooks,
+        bytes: usize,
+        segments: usize,
+        new_segments: usize,
+        target_duration: Duration,
+    ) {
+        let now = hooks.now();
+        let stats = &mut self.stats;
+        stats.load_count += 1;
+        stats.playlist_bytes = bytes;
+        stats.playlist_segments = segments;
+        stats.target_duration_ms = Some(to_ms(target_duration) as u32);
+
+        if let Some(now) = now {
+            if let Some(last) = self.last_loaded_at {
+                stats.last_reload_interval_ms = Some(to_ms(now.saturating_sub(last)));
+            }
+            if let Some(first) = self.first_loaded_at {
+                let reloads = (stats.load_count - 1) as f64;
+                stats.average_reload_interval_ms = Some(to_ms(now.saturating_sub(first)) / reloads);
+            } else {
+                self.first_loaded_at = Some(now);
+            }
+            self.last_loaded_at = Some(now);
+        }
+
+        if new_segments == 0 {
+            stats.unchanged_reload_streak += 1;
+            if stats.unchanged_reload_streak > stats.max_unchanged_reload_streak {
+                stats.max_unchanged_reload_streak = stats.unchanged_reload_streak;
+            }
+            return;
+        }
+        stats.unchanged_reload_streak = 0;
+
+        let now = match now {
+            None => return,
+            Some(now) => now,
+        };
+        // The segments of the first load had been published before the playback started.
+        if let Some(first) = self.first_updated_at {
+            self.published_segments += new_segments as u64;
+            let interval = to_ms(now.saturating_sub(first)) / self.published_segments as f64;
+            stats.average_publish_interval_ms = Some(interval);
+            stats.publish_drift_ms = Some(interval - to_ms(target_duration));
+        } else {
+            self.first_updated_at = Some(now);
+        }
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,195 @@
+//! Remuxing of TS segments into fMP4, shared by playbacks and downloads.
+//!
+//! When several segments are ready at once (e.g., those of a download, or those fetched ahead
+//! while catching up, see `CatchUpConfig`) and the `parallel_remux` feature is enabled, they are
+//! remuxed by a pool of at most `thread::available_parallelism` threads, including the calling
+//! one. Where threads cannot be spawned, the segments are remuxed in the calling thread.
+//!
+//! This is the case of the wasm module of `wasm_api` (`wasm32-unknown-unknown`), whose `std`
+//! has no threads even with a shared memory: `available_parallelism` fails there, so that
+//! the segments are remuxed one after another. The pool is used by native hosts (see `ffi`).
+use std::borrow::Cow;
+use std::io::Read;
+use mpeg2ts::ts::TsPacketReader;
+use mse_fmp4::fmp4::{InitializationSegment, MediaSegment};
+use mse_fmp4::mpeg2_ts;
+
+use Result;
+use super::TrackConfig;
+use super::scte35::{SpliceCue, SpliceExtractor};
+use super::track_selection::{SelectedTracks, TrackFilter};
+use super::ts_merge;
+use super::ts_sync;
+
+/// Init section (if any) and body of a TS segment.
+pub type Job<'a> = (&'a [u8], &'a [u8]);
+
+/// fMP4 segments remuxed from a TS segment.
+#[derive(Debug)]
+pub struct Remuxed {
+    pub initialization_segment: InitializationSegment,
+    pub media_segment: MediaSegment,
+
+    /// Streams selected by `TrackConfig`.
+    pub tracks: Option<SelectedTracks>,
+}
+
+/// TS packets of a segment as they are remuxed.
+#[derive(Debug)]
+pub struct TsInput<'a> {
+    pub init: Cow<'a, [u8]>,
+    pub segment: Cow<'a, [u8]>,
+
+    /// Bytes skipped because they were not aligned to TS packets (see `ts_sync::resync`).
+    pub skipped: usize,
+
+    /// Cues of the SCTE-35 streams, whose packets are stripped.
+    pub cues: Vec<SpliceCue>,
+}
+impl<'a> TsInput<'a> {
+    /// Prepares `ts_segment` preceded by `init` (which holds its PAT and PMT) for remuxing,
+    /// merged with the TS packets `audio` of a demuxed audio rendition if any.
+    ///
+    /// They are read in sequence without being concatenated into a new buffer,
+    /// unless the audio is merged.
+    pub fn new(init: &'a [u8], ts_segment: &'a [u8], audio: Option<&[u8]>) -> Result<Self> {
+        let (init, init_skipped) = ts_sync::resync(init);
+        let (segment, skipped) = ts_sync::resync(ts_segment);
+        let mut splices = SpliceExtractor::new();
+        let init = strip(&mut splices, init);
+        let segment = strip(&mut splices, segment);
+        let (init, segment) = match audio {
+            None => (init, segment),
+            Some(audio) => {
+                let video = [&init[..], &segment[..]].concat();
+                let merged = track!(ts_merge::merge(&video, audio))?;
+                (Cow::Borrowed(&[][..]), Cow::Owned(merged))
+            }
+        };
+        Ok(TsInput {
+            init,
+            segment,
+            skipped: init_skipped + skipped,
+            cues: splices.into_cues(),
+        })
+    }
+
+    /// Returns a reader of the packets of the init section followed by those of the segment.
+    pub fn reader(&self) -> impl Read + '_ {
+        (&self.init[..]).chain(&self.segment[..])
+    }
+
+    pub fn remux(&self, tracks: &TrackConfig) -> Result<Remuxed> {
+        let mut selected = None;
+        let (initialization_segment, media_segment) = {
+            let reader = TsPacketReader::new(self.reader());
+            let reader = TrackFilter::new(reader, tracks, &mut selected);
+            track!(mpeg2_ts::to_fmp4(reader))?
+        };
+        Ok(Remuxed {
+            initialization_segment,
+            media_segment,
+            tracks: selected,
+        })
+    }
+}
+
+/// Strips the SCTE-35 packets of `data` without copying it again.
+fn strip<'a>(splices: &mut SpliceExtractor, data: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+    match data {
+        Cow::Borrowed(data) => splices.strip(data),
+        Cow::Owned(data) => match splices.strip(&data) {
+            Cow::Owned(stripped) => Cow::Owned(stripped),
+            Cow::Borrowed(_) => Cow::Owned(data),
+        },
+    }
+}
+
+/// Remuxes `ts_segment` preceded by `init`.
+pub fn remux(tracks: &TrackConfig, init: &[u8], ts_segment: &[u8]) -> Result<Remuxed> {
+    let input = track!(TsInput::new(init, ts_segment, None))?;
+    track!(input.remux(tracks))
+}
+
+/// Remuxes the segments of `jobs`, returning the results in the same order.
+#[cfg(not(feature = "parallel_remux"))]
+pub fn remux_all(tracks: &TrackConfig, jobs: &[Job]) -> Vec<Result<Remuxed>> {
+    jobs.iter()
+        .map(|&(init, ts_segment)| track!(remux(tracks, init, ts_segment)))
+        .collect()
+}
+
+/// Remuxes the segments of `jobs` by a pool of threads, returning the results in the same order.
+///
+/// Each thread takes the next job not taken yet, so that the number of threads is bounded
+/// by the available parallelism regardless of the number of jobs.
+#[cfg(feature = "parallel_remux")]
+pub fn remux_all(tracks: &TrackConfig, jobs: &[Job]) -> Vec<Result<Remuxed>> {
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = workers.min(jobs.len());
+    let next_job = AtomicUsize::new(0);
+    let results = jobs.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+    let work = || loop {
+        let i = next_job.fetch_add(1, Ordering::Relaxed);
+        let (init, ts_segment) = match jobs.get(i) {
+            None => break,
+            Some(&job) => job,
+        };
+        let result = track!(remux(tracks, init, ts_segment));
+        *results[i].lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+    };
+    thread::scope(|scope| {
+        for _ in 1..workers {
+            // The calling thread takes the jobs of the threads which cannot be spawned.
+            if thread::Builder::new().spawn_scoped(scope, work).is_err() {
+                break;
+            }
+        }
+        work();
+    });
+    results
+        .into_iter()
+        .map(|r| {
+            let result = r.into_inner().unwrap_or_else(|e| e.into_inner());
+            result.expect("Never fails")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(result: &Result<Remuxed>) -> String {
+        match *result {
+            Ok(ref remuxed) => format!("{:?}", remuxed.tracks),
+            // Without the history, which differs by the call sites.
+            Err(ref e) => e.to_string().lines().next().unwrap_or("").to_owned(),
+        }
+    }
+
+    #[test]
+    fn results_keep_the_order_of_the_jobs() {
+        // A PAT without programs, and bytes without any TS packet.
+        let mut pat = [0xff; 188];
+        pat[..13].copy_from_slice(&[
+            0x47, 0x40, 0x00, 0x10, 0x00, 0x00, 0xb0, 0x0d, 0x00, 0x01, 0xc1, 0x00, 0x00,
+        ]);
+        let garbage = [0; 10];
+        let jobs = (0..8)
+            .map(|i| (&[][..], if i % 3 == 0 { &pat[..] } else { &garbage[..] }))
+            .collect::<Vec<_>>();
+
+        let config = TrackConfig::default();
+        let expected = jobs.iter()
+            .map(|&(init, ts)| describe(&remux(&config, init, ts)))
+            .collect::<Vec<_>>();
+        assert_ne!(expected[0], expected[1]);
+        let results = remux_all(&config, &jobs);
+        assert_eq!(results.iter().map(describe).collect::<Vec<_>>(), expected);
+    }
+}
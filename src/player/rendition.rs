@@ -0,0 +1,194 @@
+//! Selection of the alternative renditions (`EXT-X-MEDIA`) of master playlists.
+use hls_m3u8::tags::ExtXMedia;
+use hls_m3u8::types::MediaType;
+use url::Url;
+
+use {Error, Result};
+
+/// Prefix of the `CHARACTERISTICS` of accessibility renditions
+/// (e.g., `public.accessibility.describes-video`).
+const ACCESSIBILITY_PREFIX: &str = "public.accessibility.";
+
+/// `TYPE` of the renditions handled by the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum RenditionType {
+    Audio,
+    Subtitles,
+}
+
+/// `EXT-X-MEDIA` entry of the `AUDIO` or `SUBTITLES` type.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rendition {
+    #[serde(rename = "type")]
+    pub media_type: RenditionType,
+
+    pub group_id: String,
+    pub name: String,
+
+    /// `LANGUAGE` (RFC 5646 tag).
+    pub language: Option<String>,
+
+    /// Absolute URL of the media playlist, or `None` if the rendition is in the variant stream.
+    pub uri: Option<String>,
+
+    pub default: bool,
+    pub autoselect: bool,
+    pub forced: bool,
+
+    /// `CHARACTERISTICS` (Uniform Type Identifiers) in the listed order.
+    pub characteristics: Vec<String>,
+}
+impl Rendition {
+    /// Returns the rendition of `tag`, or `None` if it is of another type.
+    pub fn from_tag(base_url: &Url, tag: &ExtXMedia) -> Result<Option<Self>> {
+        let media_type = match tag.media_type() {
+            MediaType::Audio => RenditionType::Audio,
+            MediaType::Subtitles => RenditionType::Subtitles,
+            MediaType::Video | MediaType::ClosedCaptions => return Ok(None),
+        };
+        let uri = match tag.uri() {
+            None => None,
+            Some(uri) => Some(track!(
+                Url::options()
+                    .base_url(Some(base_url))
+                    .parse(uri.as_ref())
+                    .map_err(Error::from)
+            )?),
+        };
+        Ok(Some(Rendition {
+            media_type,
+            group_id: tag.group_id().as_ref().to_owned(),
+            name: tag.name().as_ref().to_owned(),
+            language: tag.language().map(|l| l.as_ref().to_owned()),
+            uri: uri.map(|u| u.into_string()),
+            default: tag.default(),
+            autoselect: tag.autoselect(),
+            forced: tag.forced(),
+            characteristics: tag.characteristics().map_or_else(Vec::new, |c| {
+                c.split(',')
+                    .map(|c| c.trim().to_owned())
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            }),
+        }))
+    }
+}
+
+/// Returns the index of the rendition of the `media_type` group `group_id`
+/// to be played among `renditions`.
+///
+/// The first of `languages` (in the order of preference) with a matching rendition decides,
+/// where exact matches precede those of the primary subtag only (e.g., `en` for `en-US`).
+/// Otherwise, a rendition with `characteristics` is selected if requested, then
+/// the `DEFAULT=YES` rendition, and if there is none, the first one only if `fallback_to_first`.
+///
+/// Renditions with all of `characteristics` take precedence among the candidates,
+/// and without them, renditions without accessibility characteristics do.
+/// `FORCED=YES` renditions are not selected (see `select_forced`).
+pub fn select(
+    renditions: &[Rendition],
+    media_type: RenditionType,
+    group_id: &str,
+    languages: &[String],
+    characteristics: &[String],
+    fallback_to_first: bool,
+) -> Option<usize> {
+    let rank = |r: &Rendition| {
+        let is_matched = if characteristics.is_empty() {
+            !r.characteristics
+                .iter()
+                .any(|c| c.starts_with(ACCESSIBILITY_PREFIX))
+        } else {
+            characteristics.iter().all(|c| r.characteristics.contains(c))
+        };
+        (!is_matched, !r.default, !r.autoselect)
+    };
+    let group = renditions
+        .iter()
+        .enumerate()
+        .filter(|&(_, r)| r.media_type == media_type && r.group_id == group_id && !r.forced)
+        .collect::<Vec<_>>();
+    for language in languages {
+        for &is_exact in &[true, false] {
+            let matched = group
+                .iter()
+                .filter(|&&(_, r)| {
+                    r.language
+                        .as_ref()
+                        .is_some_and(|l| matches_language(l, language, is_exact))
+                })
+                .min_by_key(|&&(_, r)| rank(r));
+            if let Some(&(i, _)) = matched {
+                return Some(i);
+            }
+        }
+    }
+    if !characteristics.is_empty() {
+        let matched = group
+            .iter()
+            .filter(|&&(_, r)| characteristics.iter().all(|c| r.characteristics.contains(c)))
+            .min_by_key(|&&(_, r)| rank(r));
+        if let Some(&(i, _)) = matched {
+            return Some(i);
+        }
+    }
+    group
+        .iter()
+        .filter(|&&(_, r)| r.default || fallback_to_first)
+        .min_by_key(|&&(_, r)| rank(r))
+        .map(|&(i, _)| i)
+}
+
+/// Returns the index of the `FORCED=YES` subtitle rendition of the group `group_id`
+/// in the language of `audio`, if it differs from `original_language`.
+///
+/// Forced subtitles translate the parts of the content (e.g., signs or foreign dialogue)
+/// that are not in the language of the played audio.
+pub fn select_forced(
+    renditions: &[Rendition],
+    group_id: &str,
+    audio: &Rendition,
+    original_language: &str,
+) -> Option<usize> {
+    let language = audio.language.as_ref()?;
+    if matches_language(language, original_language, false) {
+        return None;
+    }
+    let candidates = renditions
+        .iter()
+        .enumerate()
+        .filter(|&(_, r)| {
+            r.media_type == RenditionType::Subtitles && r.group_id == group_id && r.forced
+        })
+        .filter(|&(_, r)| r.language.is_some())
+        .collect::<Vec<_>>();
+    [true, false]
+        .iter()
+        .find_map(|&is_exact| {
+            candidates.iter().find(|&&(_, r)| {
+                r.language
+                    .as_ref()
+                    .is_some_and(|l| matches_language(l, language, is_exact))
+            })
+        })
+        .map(|&(i, _)| i)
+}
+
+/// Returns the language of the `DEFAULT=YES` audio rendition of the group `group_id`,
+/// which is taken as the original language of the content.
+pub fn default_language<'a>(renditions: &'a [Rendition], group_id: &str) -> Option<&'a str> {
+    renditions
+        .iter()
+        .find(|r| r.media_type == RenditionType::Audio && r.group_id == group_id && r.default)
+        .and_then(|r| r.language.as_ref())
+        .map(|l| l.as_str())
+}
+
+fn matches_language(language: &str, preferred: &str, is_exact: bool) -> bool {
+    if is_exact {
+        language.eq_ignore_ascii_case(preferred)
+    } else {
+        let primary = |l: &str| l.split('-').next().unwrap_or("").to_ascii_lowercase();
+        primary(language) == primary(preferred)
+    }
+}
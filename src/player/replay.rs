@@ -0,0 +1,274 @@
+//! Recording of the inputs given to `HlsPlayer`, and their replay into a fresh player
+//! for reproducing field issues exactly.
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::Duration;
+use url::Url;
+
+use {Error, Result};
+use super::{ActionId, Event, HlsPlayer, Hooks, ManualClock, PlayerConfig};
+use super::simulation::SimulationRecord;
+
+/// Input given to `HlsPlayer` by the host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Input {
+    /// Configuration of the playback, recorded at its start (without `PlayerConfig::hooks`).
+    Configure { config: Box<PlayerConfig> },
+
+    PlayMasterPlaylist { url: String, m3u8: String },
+    PlayMediaPlaylist { url: String, m3u8: String },
+    Download { url: String, m3u8: String },
+    GenerateThumbnails { url: String, m3u8: String },
+    LoadSource { url: String },
+    QueueNext { url: String, m3u8: String },
+    Stop,
+    SetBufferLength { buffer_length_ms: u64 },
+    SetPlaybackPosition { position_ms: u64 },
+    SetBufferedRanges { ranges_ms: Vec<(u64, u64)> },
+    StartTrickPlay { rate: f64, position_ms: u64 },
+    StopTrickPlay { position_ms: u64 },
+    SetVariantLocked { locked: bool },
+    SetLowPower { enabled: bool },
+    SetAudioLanguages { languages: Vec<String> },
+    Suspend,
+    Resume,
+    Data {
+        action_id: ActionId,
+        data: Vec<u8>,
+        fetch_duration_ms: u32,
+        final_url: Option<String>,
+    },
+    ResponseHeaders {
+        action_id: ActionId,
+        headers: BTreeMap<String, String>,
+    },
+    DataChunk { action_id: ActionId, chunk: Vec<u8> },
+    DataEnd {
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<String>,
+    },
+    Timeout { action_id: ActionId },
+    FetchTimeout { action_id: ActionId },
+    Error { action_id: ActionId, status: u16 },
+
+    /// A segment has been taken by `HlsPlayer::next_segment`, which may release
+    /// the blocked output queue.
+    SegmentTaken,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputRecord {
+    /// Time of `Hooks::clock` when the input was given, if the clock is set.
+    pub now_ms: Option<f64>,
+
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+/// Log of the inputs of the current (or last) playback (see `PlayerConfig::input_log`).
+#[derive(Debug, Default)]
+pub struct InputLog {
+    records: Vec<InputRecord>,
+}
+impl InputLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> &[InputRecord] {
+        &self.records
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn record(&mut self, hooks: &Hooks, input: Input) {
+        let now_ms = hooks.now().map(|t| t.as_secs_f64() * 1000.0);
+        self.records.push(InputRecord { now_ms, input });
+    }
+}
+
+/// Outcome of `replay`.
+#[derive(Debug)]
+pub struct Replay {
+    /// Player after all the inputs have been given.
+    pub player: HlsPlayer,
+
+    /// Actions, events and segments emitted by the player.
+    pub records: Vec<SimulationRecord>,
+
+    pub segments: Vec<Vec<u8>>,
+
+    /// Errors returned by the player, with the indices of the inputs causing them.
+    pub errors: Vec<(usize, Error)>,
+}
+impl Replay {
+    pub fn events(&self) -> Vec<&Event> {
+        self.records
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Event { ref event, .. } => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Gives the recorded `inputs` to a fresh player in order.
+///
+/// The clock of the player is set to the recorded time before each input, and the actions
+/// and events are taken after each one. Hooks other than the clock (e.g., `UrlRewriter`) are
+/// not replayed, so they should be given by `hooks` if the original playback used them.
+pub fn replay(inputs: &[InputRecord], mut hooks: Hooks) -> Replay {
+    let clock = ManualClock::new();
+    hooks.clock = Some(Rc::new(clock.clone()));
+    let config = PlayerConfig {
+        hooks: hooks.clone(),
+        ..PlayerConfig::default()
+    };
+    let mut replay = Replay {
+        player: HlsPlayer::with_config(config),
+        records: Vec::new(),
+        segments: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (i, record) in inputs.iter().enumerate() {
+        let now = Duration::from_secs_f64(record.now_ms.unwrap_or(0.0).max(0.0) / 1000.0);
+        clock.set_now(now);
+        let at_ms = now.as_secs() * 1000 + u64::from(now.subsec_millis());
+        let player = &mut replay.player;
+        let result = match record.input.clone() {
+            Input::Configure { mut config } => {
+                config.hooks = hooks.clone();
+                config.input_log.enabled = false;
+                player.set_config(*config);
+                Ok(())
+            }
+            Input::PlayMasterPlaylist { url, m3u8 } => {
+                parse_url(&url).and_then(|url| track!(player.play_master_playlist(url, &m3u8)))
+            }
+            Input::PlayMediaPlaylist { url, m3u8 } => {
+                parse_url(&url).and_then(|url| track!(player.play_media_playlist(url, &m3u8)))
+            }
+            Input::Download { url, m3u8 } => {
+                parse_url(&url).and_then(|url| track!(player.download(url, &m3u8)))
+            }
+            Input::GenerateThumbnails { url, m3u8 } => {
+                parse_url(&url).and_then(|url| track!(player.generate_thumbnails(url, &m3u8)))
+            }
+            Input::LoadSource { url } => {
+                parse_url(&url).and_then(|url| track!(player.load_source(url)))
+            }
+            Input::QueueNext { url, m3u8 } => {
+                parse_url(&url).and_then(|url| track!(player.queue_next(url, &m3u8)))
+            }
+            Input::Stop => {
+                player.stop();
+                Ok(())
+            }
+            Input::SetBufferLength { buffer_length_ms } => {
+                player.set_buffer_length(Duration::from_millis(buffer_length_ms));
+                Ok(())
+            }
+            Input::SetPlaybackPosition { position_ms } => {
+                player.set_playback_position(Duration::from_millis(position_ms));
+                Ok(())
+            }
+            Input::SetBufferedRanges { ranges_ms } => {
+                let ranges = ranges_ms
+                    .into_iter()
+                    .map(|(start, end)| (Duration::from_millis(start), Duration::from_millis(end)))
+                    .collect::<Vec<_>>();
+                player.set_buffered_ranges(&ranges);
+                Ok(())
+            }
+            Input::StartTrickPlay { rate, position_ms } => {
+                track!(player.start_trick_play(rate, Duration::from_millis(position_ms)))
+            }
+            Input::StopTrickPlay { position_ms } => {
+                track!(player.stop_trick_play(Duration::from_millis(position_ms)))
+            }
+            Input::SetVariantLocked { locked } => track!(player.set_variant_locked(locked)),
+            Input::SetLowPower { enabled } => {
+                player.set_low_power(enabled);
+                Ok(())
+            }
+            Input::SetAudioLanguages { languages } => {
+                player.set_audio_languages(languages);
+                Ok(())
+            }
+            Input::Suspend => track!(player.suspend()),
+            Input::Resume => track!(player.resume()),
+            Input::Data {
+                action_id,
+                data,
+                fetch_duration_ms,
+                final_url,
+            } => parse_final_url(final_url).and_then(|url| {
+                track!(player.handle_data_with_url(
+                    action_id,
+                    &data,
+                    fetch_duration_ms,
+                    url.as_ref()
+                ))
+            }),
+            Input::ResponseHeaders { action_id, headers } => {
+                track!(player.handle_response_headers(action_id, &headers))
+            }
+            Input::DataChunk { action_id, chunk } => {
+                track!(player.handle_data_chunk(action_id, &chunk))
+            }
+            Input::DataEnd {
+                action_id,
+                fetch_duration_ms,
+                final_url,
+            } => parse_final_url(final_url).and_then(|url| {
+                track!(player.handle_data_end(action_id, fetch_duration_ms, url.as_ref()))
+            }),
+            Input::Timeout { action_id } => track!(player.handle_timeout(action_id)),
+            Input::FetchTimeout { action_id } => track!(player.handle_fetch_timeout(action_id)),
+            Input::Error { action_id, status } => track!(player.handle_error(action_id, status)),
+            Input::SegmentTaken => {
+                if let Some(segment) = player.next_segment() {
+                    replay.records.push(SimulationRecord::Segment {
+                        at_ms,
+                        bytes: segment.data.len(),
+                        info: segment.info,
+                    });
+                    replay.segments.push(segment.data);
+                }
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            replay.errors.push((i, e));
+        }
+        while let Some(action) = replay.player.next_action() {
+            replay.records.push(SimulationRecord::Action { at_ms, action });
+        }
+        while let Some(event) = replay.player.next_event() {
+            replay.records.push(SimulationRecord::Event { at_ms, event });
+        }
+    }
+    replay
+}
+
+/// Parses `json` given by `HlsPlayer::input_log_json`, and replays it (see `replay`).
+pub fn replay_json(json: &str, hooks: Hooks) -> Result<Replay> {
+    let inputs: Vec<InputRecord> = track!(::serde_json::from_str(json).map_err(Error::from))?;
+    Ok(replay(&inputs, hooks))
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    track!(Url::parse(url).map_err(Error::from))
+}
+
+fn parse_final_url(url: Option<String>) -> Result<Option<Url>> {
+    match url {
+        None => Ok(None),
+        Some(url) => parse_url(&url).map(Some),
+    }
+}
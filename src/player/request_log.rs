@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use serde_json::Value;
+use url::Url;
+
+use super::{Action, ActionId, ByteRange, FetchPriority, Hooks, ResourceKind};
+use super::date_time;
+
+/// Outcome of a logged request, as reported by the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestOutcome {
+    Pending,
+    Completed { bytes: u64 },
+    Failed { status: u16 },
+    TimedOut,
+    Aborted,
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestLogEntry {
+    pub action_id: ActionId,
+    pub url: Url,
+    pub kind: ResourceKind,
+    pub headers: BTreeMap<String, String>,
+    pub range: Option<ByteRange>,
+    pub priority: FetchPriority,
+
+    /// Time since the UNIX epoch when the request was handed to the host
+    /// (`None` without `Hooks::clock`).
+    pub started_at: Option<Duration>,
+
+    /// Fetch duration reported by the host, or measured by `Hooks::clock` otherwise.
+    pub duration_ms: Option<f64>,
+
+    pub outcome: RequestOutcome,
+    received_bytes: u64,
+}
+
+/// Bounded log of the requested fetches (see `PlayerConfig::request_log`).
+///
+/// When it is full, the oldest entries are dropped.
+#[derive(Debug)]
+pub struct RequestLog {
+    max_entries: usize,
+    hooks: Hooks,
+    entries: VecDeque<RequestLogEntry>,
+}
+impl RequestLog {
+    pub fn new(max_entries: usize, hooks: &Hooks) -> Self {
+        RequestLog {
+            max_entries,
+            hooks: hooks.clone(),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &VecDeque<RequestLogEntry> {
+        &self.entries
+    }
+
+    /// Records `action` handed to the host.
+    pub fn on_action(&mut self, action: &Action) {
+        match *action {
+            Action::FetchData {
+                action_id,
+                ref url,
+                kind,
+                ref headers,
+                range,
+                priority,
+                ..
+            } => {
+                if self.max_entries == 0 {
+                    return;
+                }
+                while self.entries.len() >= self.max_entries {
+                    self.entries.pop_front();
+                }
+                self.entries.push_back(RequestLogEntry {
+                    action_id,
+                    url: url.clone(),
+                    kind,
+                    headers: headers.clone(),
+                    range,
+                    priority,
+                    started_at: self.hooks.now(),
+                    duration_ms: None,
+                    outcome: RequestOutcome::Pending,
+                    received_bytes: 0,
+                });
+            }
+            Action::Abort { action_id } => self.finish(action_id, None, RequestOutcome::Aborted),
+            Action::SetTimeout { .. } | Action::Preconnect { .. } => {}
+        }
+    }
+
+    pub fn on_data_chunk(&mut self, action_id: ActionId, bytes: usize) {
+        if let Some(entry) = self.pending_entry_mut(action_id) {
+            entry.received_bytes += bytes as u64;
+        }
+    }
+
+    /// Records the completion of the fetch; `bytes` is `None` for bodies given in chunks.
+    pub fn on_completed(&mut self, action_id: ActionId, bytes: Option<usize>, duration_ms: u32) {
+        let received = self.pending_entry_mut(action_id).map_or(0, |e| e.received_bytes);
+        let bytes = bytes.map_or(received, |b| b as u64);
+        let outcome = RequestOutcome::Completed { bytes };
+        self.finish(action_id, Some(f64::from(duration_ms)), outcome);
+    }
+
+    pub fn on_error(&mut self, action_id: ActionId, status: u16) {
+        self.finish(action_id, None, RequestOutcome::Failed { status });
+    }
+
+    pub fn on_timeout(&mut self, action_id: ActionId) {
+        self.finish(action_id, None, RequestOutcome::TimedOut);
+    }
+
+    fn finish(&mut self, action_id: ActionId, duration_ms: Option<f64>, outcome: RequestOutcome) {
+        let now = self.hooks.now();
+        if let Some(entry) = self.pending_entry_mut(action_id) {
+            entry.duration_ms = duration_ms.or_else(|| {
+                let started_at = entry.started_at?;
+                now.map(|now| now.saturating_sub(started_at).as_secs_f64() * 1000.0)
+            });
+            entry.outcome = outcome;
+        }
+    }
+
+    fn pending_entry_mut(&mut self, action_id: ActionId) -> Option<&mut RequestLogEntry> {
+        self.entries
+            .iter_mut()
+            .rev()
+            .find(|e| e.action_id == action_id && e.outcome == RequestOutcome::Pending)
+    }
+
+    /// Exports the entries as a HAR (HTTP Archive) 1.2 like JSON object.
+    ///
+    /// Fields that the player cannot know (e.g., response headers) are omitted, the status of
+    /// successful responses is assumed to be `200` (or `206` for ranges), and failures without
+    /// responses have the status `0`. Player specific fields are prefixed with `_`.
+    pub fn to_har(&self) -> Value {
+        let entries = self.entries.iter().map(|e| e.to_har()).collect::<Vec<_>>();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": entries,
+            }
+        })
+    }
+}
+
+impl RequestLogEntry {
+    fn to_har(&self) -> Value {
+        let mut headers = self.headers
+            .iter()
+            .map(|(name, value)| json!({"name": name, "value": value}))
+            .collect::<Vec<_>>();
+        if let Some(range) = self.range {
+            let value = format!("bytes={}-{}", range.offset, range.end() - 1);
+            headers.push(json!({"name": "Range", "value": value}));
+        }
+        let query = self.url
+            .query_pairs()
+            .map(|(name, value)| json!({"name": name, "value": value}))
+            .collect::<Vec<_>>();
+        let (status, body_size, error) = match self.outcome {
+            RequestOutcome::Pending => (0, -1, Some("pending")),
+            RequestOutcome::Completed { bytes } => {
+                let status = if self.range.is_some() { 206 } else { 200 };
+                (status, bytes as i64, None)
+            }
+            RequestOutcome::Failed { status } => (status, -1, None),
+            RequestOutcome::TimedOut => (0, -1, Some("timeout")),
+            RequestOutcome::Aborted => (0, -1, Some("aborted")),
+        };
+        let time = self.duration_ms.unwrap_or(-1.0);
+        json!({
+            "startedDateTime": self.started_at.map(date_time::format),
+            "time": time,
+            "request": {
+                "method": "GET",
+                "url": self.url.as_str(),
+                "httpVersion": "",
+                "headers": headers,
+                "queryString": query,
+                "headersSize": -1,
+                "bodySize": 0,
+            },
+            "response": {
+                "status": status,
+                "statusText": "",
+                "httpVersion": "",
+                "headers": [],
+                "content": {"size": body_size},
+                "headersSize": -1,
+                "bodySize": body_size,
+                "_error": error,
+            },
+            "timings": {"send": 0, "wait": time, "receive": 0},
+            "_actionId": self.action_id,
+            "_kind": self.kind,
+            "_priority": self.priority,
+        })
+    }
+}
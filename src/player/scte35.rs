@@ -0,0 +1,635 @@
+//! SCTE-35 splice information carried in TS segments, for streams which signal ad breaks
+//! in-band rather than in their playlists (see `Event::Scte35Cue`).
+//!
+//! The reader of TS packets takes every stream of the PMT for a PES stream, and fails on the
+//! sections of SCTE-35 streams, so their packets are taken out of the segments before
+//! remuxing, and the `splice_insert` and `time_signal` commands of their sections are parsed.
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::timestamps::{self, PTS_TIMESCALE};
+
+const PACKET_SIZE: usize = 188;
+
+/// `stream_type` of SCTE-35 streams.
+const SCTE35_STREAM_TYPE: u8 = 0x86;
+
+/// `table_id` of `splice_info_section`.
+const SPLICE_INFO_TABLE_ID: u8 = 0xFC;
+
+const SPLICE_INSERT: u8 = 0x05;
+const TIME_SIGNAL: u8 = 0x06;
+
+/// `splice_descriptor_tag` of `segmentation_descriptor`.
+const SEGMENTATION_DESCRIPTOR_TAG: u8 = 0x02;
+
+/// `identifier` of SCTE-35 splice descriptors ("CUEI").
+const CUEI: u32 = 0x4355_4549;
+
+/// Cue of a splice command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpliceCue {
+    pub kind: CueKind,
+
+    /// `splice_event_id` of `splice_insert`, or `segmentation_event_id` of `time_signal`.
+    pub event_id: u32,
+
+    /// Splice time on the 90 kHz PTS timeline with `pts_adjustment` applied,
+    /// or `None` if the splice is immediate.
+    pub pts: Option<u64>,
+
+    /// Offset of `pts` from the start of the media of the segment, if known.
+    pub offset_ms: Option<f64>,
+
+    /// Duration of the break signaled by a cue-out.
+    pub duration_ms: Option<f64>,
+
+    /// `true` if the break ends after `duration_ms` without a cue-in (`auto_return`).
+    pub auto_return: bool,
+
+    /// `segmentation_type_id` of the segmentation descriptor of `time_signal` cues.
+    pub segmentation_type: Option<u8>,
+}
+
+/// Kind of `SpliceCue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CueKind {
+    /// Start of a break (out of the network).
+    Out,
+
+    /// End of a break (back to the network).
+    In,
+}
+
+/// Extractor of the SCTE-35 cues of the TS packets given to `strip` in sequence.
+#[derive(Debug, Default)]
+pub struct SpliceExtractor {
+    pmt_pids: Vec<u16>,
+    splice_pids: Vec<u16>,
+
+    /// Partially received sections by PID.
+    sections: HashMap<u16, Vec<u8>>,
+
+    cues: Vec<SpliceCue>,
+}
+impl SpliceExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `data` without the packets of the SCTE-35 streams, whose cues are extracted.
+    ///
+    /// `data` must be aligned to TS packets (see `ts_sync::resync`).
+    pub fn strip<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let stripped = data.chunks(PACKET_SIZE)
+            .map(|p| p.len() == PACKET_SIZE && self.handle_packet(p))
+            .collect::<Vec<_>>();
+        if !stripped.contains(&true) {
+            return Cow::Borrowed(data);
+        }
+        let packets = data.chunks(PACKET_SIZE)
+            .zip(stripped)
+            .filter(|&(_, is_stripped)| !is_stripped)
+            .flat_map(|(p, _)| p.iter().cloned())
+            .collect();
+        Cow::Owned(packets)
+    }
+
+    /// Returns the cues extracted so far.
+    pub fn into_cues(self) -> Vec<SpliceCue> {
+        self.cues
+    }
+
+    /// Handles `packet` and returns `true` if it belongs to a SCTE-35 stream.
+    fn handle_packet(&mut self, packet: &[u8]) -> bool {
+        let pid = pid(packet);
+        let is_unit_start = packet[1] & 0x40 != 0;
+        let payload = match payload(packet) {
+            None => return self.splice_pids.contains(&pid),
+            Some(payload) => payload,
+        };
+        if pid == 0 && is_unit_start {
+            self.pmt_pids = psi_section(payload)
+                .filter(|s| s[0] == 0x00)
+                .map(pat_pmt_pids)
+                .unwrap_or_default();
+        } else if self.pmt_pids.contains(&pid) && is_unit_start {
+            if let Some(section) = psi_section(payload).filter(|s| s[0] == 0x02) {
+                self.splice_pids = pmt_splice_pids(section);
+                let splice_pids = &self.splice_pids;
+                self.sections.retain(|pid, _| splice_pids.contains(pid));
+            }
+        } else if self.splice_pids.contains(&pid) {
+            self.handle_splice_payload(pid, is_unit_start, payload);
+            return true;
+        }
+        false
+    }
+
+    fn handle_splice_payload(&mut self, pid: u16, is_unit_start: bool, payload: &[u8]) {
+        let mut payload = payload;
+        if is_unit_start {
+            let pointer = payload[0] as usize;
+            if payload.len() <= pointer {
+                self.sections.remove(&pid);
+                return;
+            }
+            if let Some(mut section) = self.sections.remove(&pid) {
+                section.extend_from_slice(&payload[1..=pointer]);
+                self.complete_sections(pid, section);
+            }
+            payload = &payload[1 + pointer..];
+            self.sections.insert(pid, Vec::new());
+        }
+        if let Some(mut section) = self.sections.remove(&pid) {
+            section.extend_from_slice(payload);
+            self.complete_sections(pid, section);
+        }
+    }
+
+    /// Parses the complete sections at the start of `data`, and keeps the rest for `pid`.
+    fn complete_sections(&mut self, pid: u16, mut data: Vec<u8>) {
+        loop {
+            if data.first() == Some(&0xFF) {
+                // Stuffing up to the end of the packet.
+                return;
+            }
+            if data.len() < 3 {
+                break;
+            }
+            let len = 3 + (((data[1] as usize) & 0x0F) << 8 | data[2] as usize);
+            if data.len() < len {
+                break;
+            }
+            if data[0] == SPLICE_INFO_TABLE_ID && crc32(&data[..len]) == 0 {
+                self.cues.extend(parse_splice_info(&data[..len]));
+            }
+            data.drain(..len);
+        }
+        self.sections.insert(pid, data);
+    }
+}
+
+/// Returns the time of `cue` from `media_start` (a PTS).
+pub fn offset_ms(cue: &SpliceCue, media_start: u64) -> Option<f64> {
+    let pts = cue.pts?;
+    let ticks = timestamps::elapsed_signed(media_start, pts);
+    Some(ticks as f64 * 1000.0 / PTS_TIMESCALE as f64)
+}
+
+pub fn pid(packet: &[u8]) -> u16 {
+    u16::from(packet[1] & 0x1F) << 8 | u16::from(packet[2])
+}
+
+/// Returns the payload of `packet`, after its adaptation field if any.
+pub fn payload(packet: &[u8]) -> Option<&[u8]> {
+    let control = (packet[3] >> 4) & 0b11;
+    if control & 0b01 == 0 {
+        return None;
+    }
+    let start = if control & 0b10 != 0 {
+        5 + packet[4] as usize
+    } else {
+        4
+    };
+    packet.get(start..).filter(|p| !p.is_empty())
+}
+
+/// Returns the PSI section starting in `payload` if it is entirely in that packet.
+pub fn psi_section(payload: &[u8]) -> Option<&[u8]> {
+    let start = 1 + *payload.first()? as usize;
+    let header = payload.get(start..start + 3)?;
+    let len = 3 + (((header[1] as usize) & 0x0F) << 8 | header[2] as usize);
+    payload.get(start..start + len).filter(|s| s.len() >= 12)
+}
+
+/// PMT PIDs of the programs of the PAT `section`.
+pub fn pat_pmt_pids(section: &[u8]) -> Vec<u16> {
+    section[8..section.len() - 4]
+        .chunks(4)
+        .filter(|e| e.len() == 4 && (e[0], e[1]) != (0, 0))
+        .map(|e| u16::from(e[2] & 0x1F) << 8 | u16::from(e[3]))
+        .collect()
+}
+
+/// PIDs of the SCTE-35 streams of the PMT `section`.
+fn pmt_splice_pids(section: &[u8]) -> Vec<u16> {
+    let end = section.len() - 4;
+    let program_info_length = ((section[10] as usize) & 0x0F) << 8 | section[11] as usize;
+    let mut i = 12 + program_info_length;
+    let mut pids = Vec::new();
+    while i + 5 <= end {
+        let entry = &section[i..i + 5];
+        if entry[0] == SCTE35_STREAM_TYPE {
+            pids.push(u16::from(entry[1] & 0x1F) << 8 | u16::from(entry[2]));
+        }
+        i += 5 + (((entry[3] as usize) & 0x0F) << 8 | entry[4] as usize);
+    }
+    pids
+}
+
+/// Parses the cues of `splice_info_section`, which are ignored if encrypted or malformed.
+fn parse_splice_info(section: &[u8]) -> Vec<SpliceCue> {
+    let mut bits = Bits::new(&section[3..]);
+    let header = (|| {
+        let _protocol_version = bits.read(8)?;
+        let is_encrypted = bits.flag()?;
+        let _encryption_algorithm = bits.read(6)?;
+        let pts_adjustment = bits.read(33)?;
+        let _cw_index = bits.read(8)?;
+        let _tier = bits.read(12)?;
+        let command_length = bits.read(12)? as usize;
+        let command_type = bits.read(8)? as u8;
+        Some((pts_adjustment, command_length, command_type)).filter(|_| !is_encrypted)
+    })();
+    let (pts_adjustment, command_length, command_type) = match header {
+        None => return Vec::new(),
+        Some(header) => header,
+    };
+    let command_end = bits.position() + command_length * 8;
+    let adjust = |pts: u64| (pts + pts_adjustment) & ((1 << 33) - 1);
+    match command_type {
+        SPLICE_INSERT => parse_splice_insert(&mut bits)
+            .map(|mut cue| {
+                cue.pts = cue.pts.map(adjust);
+                vec![cue]
+            })
+            .unwrap_or_default(),
+        TIME_SIGNAL => {
+            let pts = match bits.splice_time() {
+                None => return Vec::new(),
+                Some(pts) => pts.map(adjust),
+            };
+            // Legacy encoders set `splice_command_length` to 0xFFF.
+            if command_length != 0xFFF && !bits.seek(command_end) {
+                return Vec::new();
+            }
+            parse_segmentation_descriptors(&mut bits)
+                .into_iter()
+                .map(|mut cue| {
+                    cue.pts = pts;
+                    cue
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn parse_splice_insert(bits: &mut Bits) -> Option<SpliceCue> {
+    let event_id = bits.read(32)? as u32;
+    let cancel = bits.flag()?;
+    bits.skip(7)?;
+    if cancel {
+        return None;
+    }
+    let out_of_network = bits.flag()?;
+    let program_splice = bits.flag()?;
+    let has_duration = bits.flag()?;
+    let immediate = bits.flag()?;
+    bits.skip(4)?;
+    let mut pts = None;
+    if program_splice && !immediate {
+        pts = bits.splice_time()?;
+    }
+    if !program_splice {
+        let components = bits.read(8)?;
+        for _ in 0..components {
+            let _tag = bits.read(8)?;
+            if !immediate {
+                // Cues of the first component are taken for those of the program.
+                pts = pts.or(bits.splice_time()?);
+            }
+        }
+    }
+    let mut duration_ms = None;
+    let mut auto_return = false;
+    if has_duration {
+        auto_return = bits.flag()?;
+        bits.skip(6)?;
+        duration_ms = Some(to_millis(bits.read(33)?));
+    }
+    Some(SpliceCue {
+        kind: if out_of_network {
+            CueKind::Out
+        } else {
+            CueKind::In
+        },
+        event_id,
+        pts,
+        offset_ms: None,
+        duration_ms,
+        auto_return,
+        segmentation_type: None,
+    })
+}
+
+/// Parses the segmentation descriptors which start or end breaks.
+fn parse_segmentation_descriptors(bits: &mut Bits) -> Vec<SpliceCue> {
+    let mut cues = Vec::new();
+    let loop_length = match bits.read(16) {
+        None => return cues,
+        Some(n) => n as usize,
+    };
+    let end = bits.position() + loop_length * 8;
+    while bits.position() + 16 <= end {
+        let (tag, length) = match (bits.read(8), bits.read(8)) {
+            (Some(tag), Some(length)) => (tag as u8, length as usize),
+            _ => break,
+        };
+        let next = bits.position() + length * 8;
+        if tag == SEGMENTATION_DESCRIPTOR_TAG {
+            cues.extend(parse_segmentation_descriptor(bits));
+        }
+        if !bits.seek(next) {
+            break;
+        }
+    }
+    cues
+}
+
+fn parse_segmentation_descriptor(bits: &mut Bits) -> Option<SpliceCue> {
+    if bits.read(32)? != u64::from(CUEI) {
+        return None;
+    }
+    let event_id = bits.read(32)? as u32;
+    let cancel = bits.flag()?;
+    bits.skip(7)?;
+    if cancel {
+        return None;
+    }
+    let program_segmentation = bits.flag()?;
+    let has_duration = bits.flag()?;
+    bits.skip(6)?;
+    if !program_segmentation {
+        let components = bits.read(8)?;
+        bits.skip(components as usize * 48)?;
+    }
+    let duration_ms = if has_duration {
+        Some(to_millis(bits.read(40)?))
+    } else {
+        None
+    };
+    let _upid_type = bits.read(8)?;
+    let upid_length = bits.read(8)?;
+    bits.skip(upid_length as usize * 8)?;
+    let segmentation_type = bits.read(8)? as u8;
+    let kind = match segmentation_type {
+        // Break, provider/distributor advertisement and placement opportunity starts.
+        0x22 | 0x30 | 0x32 | 0x34 | 0x36 | 0x38 | 0x3A | 0x3C | 0x3E | 0x44 | 0x46 => CueKind::Out,
+        0x23 | 0x31 | 0x33 | 0x35 | 0x37 | 0x39 | 0x3B | 0x3D | 0x3F | 0x45 | 0x47 => CueKind::In,
+        _ => return None,
+    };
+    Some(SpliceCue {
+        kind,
+        event_id,
+        pts: None,
+        offset_ms: None,
+        duration_ms,
+        auto_return: false,
+        segmentation_type: Some(segmentation_type),
+    })
+}
+
+fn to_millis(ticks: u64) -> f64 {
+    ticks as f64 * 1000.0 / PTS_TIMESCALE as f64
+}
+
+/// CRC-32/MPEG-2, which is zero over a section ending with its `CRC_32`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &b in data {
+        crc ^= u32::from(b) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Big-endian bit reader.
+#[derive(Debug)]
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits { data, position: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn read(&mut self, bits: usize) -> Option<u64> {
+        if self.position + bits > self.data.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            let bit = self.data[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = value << 1 | u64::from(bit);
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.read(1).map(|b| b == 1)
+    }
+
+    fn skip(&mut self, bits: usize) -> Option<()> {
+        if self.seek(self.position + bits) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn seek(&mut self, position: usize) -> bool {
+        let is_valid = position <= self.data.len() * 8;
+        if is_valid {
+            self.position = position;
+        }
+        is_valid
+    }
+
+    /// Reads `splice_time()`, whose PTS is `None` unless `time_specified_flag` is set.
+    fn splice_time(&mut self) -> Option<Option<u64>> {
+        if self.flag()? {
+            self.skip(6)?;
+            Some(Some(self.read(33)?))
+        } else {
+            self.skip(7)?;
+            Some(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPLICE_PID: u16 = 0x1F0;
+
+    /// `splice_insert` sample of SCTE 35 (14.2): a cue-out of 60.3 s with `auto_return`.
+    const SPLICE_INSERT_SECTION: [u8; 50] = [
+        0xFC, 0x30, 0x2F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xF0, 0x14, 0x05, 0x48,
+        0x00, 0x00, 0x8F, 0x7F, 0xEF, 0xFE, 0x73, 0x69, 0xC0, 0x2E, 0xFE, 0x00, 0x52, 0xCC, 0xF5,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x08, 0x43, 0x55, 0x45, 0x49, 0x00, 0x00, 0x01,
+        0x35, 0x62, 0xDB, 0xA3, 0x0A,
+    ];
+
+    /// `time_signal` sample of SCTE 35 (14.1): a provider placement opportunity of 307 s.
+    const TIME_SIGNAL_SECTION: [u8; 55] = [
+        0xFC, 0x30, 0x34, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xF0, 0x05, 0x06, 0xFE,
+        0x72, 0xBD, 0x00, 0x50, 0x00, 0x1E, 0x02, 0x1C, 0x43, 0x55, 0x45, 0x49, 0x48, 0x00, 0x00,
+        0x8E, 0x7F, 0xCF, 0x00, 0x01, 0xA5, 0x99, 0xB0, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x2C,
+        0xA0, 0xA1, 0x8A, 0x34, 0x02, 0x00, 0x9A, 0xC9, 0xD1, 0x7E,
+    ];
+
+    fn packet(pid: u16, is_unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let flags = if is_unit_start { 0x40 } else { 0 };
+        let mut packet = vec![0x47, flags | (pid >> 8) as u8, pid as u8, 0x10];
+        packet.extend_from_slice(payload);
+        packet.resize(PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    /// Returns a packet stuffed by its adaptation field, so that `payload` ends at its end.
+    fn stuffed_packet(pid: u16, is_unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let flags = if is_unit_start { 0x40 } else { 0 };
+        let stuffing = PACKET_SIZE - 4 - payload.len();
+        let mut packet = vec![0x47, flags | (pid >> 8) as u8, pid as u8, 0x30];
+        packet.extend_from_slice(&[stuffing as u8 - 1, 0]);
+        packet.resize(4 + stuffing, 0xFF);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Returns the PAT and the PMT of a program with a SCTE-35 stream of `SPLICE_PID`.
+    fn program() -> Vec<u8> {
+        let pat = [0, 0x00, 0xB0, 0x0D, 0, 1, 0xC1, 0, 0, 0, 1, 0xE0, 0x20, 0, 0, 0, 0];
+        let pmt = [
+            0, 0x02, 0xB0, 0x17, 0, 1, 0xC1, 0, 0, 0xE1, 0x00, 0xF0, 0x00,
+            0x1B, 0xE1, 0x00, 0xF0, 0x00,
+            SCTE35_STREAM_TYPE, 0xE0 | (SPLICE_PID >> 8) as u8, SPLICE_PID as u8, 0xF0, 0x00,
+            0, 0, 0, 0,
+        ];
+        [packet(0, true, &pat), packet(0x20, true, &pmt)].concat()
+    }
+
+    /// Replaces the `CRC_32` of `section` with the one of its other bytes.
+    fn fix_crc(mut section: Vec<u8>) -> Vec<u8> {
+        let len = section.len() - 4;
+        let crc = crc32(&section[..len]);
+        section[len..].copy_from_slice(&crc.to_be_bytes());
+        section
+    }
+
+    fn extract(packets: &[Vec<u8>]) -> Vec<SpliceCue> {
+        let ts = [program(), packets.concat()].concat();
+        let mut extractor = SpliceExtractor::new();
+        assert_eq!(extractor.strip(&ts).len(), 2 * PACKET_SIZE);
+        extractor.into_cues()
+    }
+
+    fn splice_insert_cue() -> SpliceCue {
+        SpliceCue {
+            kind: CueKind::Out,
+            event_id: 0x4800_008F,
+            pts: Some(0x0_7369_C02E),
+            offset_ms: None,
+            duration_ms: Some(0x52_CCF5 as f64 / 90.0),
+            auto_return: true,
+            segmentation_type: None,
+        }
+    }
+
+    #[test]
+    fn splice_insert_is_parsed() {
+        let payload = [&[0][..], &SPLICE_INSERT_SECTION].concat();
+        let cues = extract(&[packet(SPLICE_PID, true, &payload)]);
+        assert_eq!(cues, [splice_insert_cue()]);
+    }
+
+    #[test]
+    fn time_signal_is_parsed() {
+        let payload = [&[0][..], &TIME_SIGNAL_SECTION].concat();
+        let cues = extract(&[packet(SPLICE_PID, true, &payload)]);
+        assert_eq!(
+            cues,
+            [SpliceCue {
+                kind: CueKind::Out,
+                event_id: 0x4800_008E,
+                pts: Some(0x0_72BD_0050),
+                offset_ms: None,
+                duration_ms: Some(307_000.0),
+                auto_return: false,
+                segmentation_type: Some(0x34),
+            }]
+        );
+    }
+
+    #[test]
+    fn sections_split_across_packets_are_joined() {
+        let (head, tail) = SPLICE_INSERT_SECTION.split_at(20);
+        let cues = extract(&[
+            stuffed_packet(SPLICE_PID, true, &[&[0][..], head].concat()),
+            packet(SPLICE_PID, false, tail),
+        ]);
+        assert_eq!(cues, [splice_insert_cue()]);
+    }
+
+    #[test]
+    fn sections_with_invalid_crcs_are_ignored() {
+        let mut section = SPLICE_INSERT_SECTION.to_vec();
+        section[17] ^= 1;
+        let payload = [&[0][..], &section].concat();
+        assert_eq!(extract(&[packet(SPLICE_PID, true, &payload)]), []);
+
+        // The same change with the CRC updated.
+        let payload = [&[0][..], &fix_crc(section)].concat();
+        let cues = extract(&[packet(SPLICE_PID, true, &payload)]);
+        assert_eq!(cues.iter().map(|c| c.event_id).collect::<Vec<_>>(), [0x4800_008E]);
+    }
+
+    #[test]
+    fn pointer_fields_end_the_previous_section() {
+        // The tail of the `splice_insert` precedes the `time_signal` in the second packet.
+        let (head, tail) = SPLICE_INSERT_SECTION.split_at(40);
+        let second = [&[tail.len() as u8][..], tail, &TIME_SIGNAL_SECTION].concat();
+        let cues = extract(&[
+            stuffed_packet(SPLICE_PID, true, &[&[0][..], head].concat()),
+            packet(SPLICE_PID, true, &second),
+        ]);
+        let event_ids = cues.iter().map(|c| c.event_id).collect::<Vec<_>>();
+        assert_eq!(event_ids, [0x4800_008F, 0x4800_008E]);
+
+        // A pointer beyond the payload drops the partial section.
+        let mut invalid = vec![0xFF; PACKET_SIZE - 4];
+        invalid[0] = PACKET_SIZE as u8;
+        let cues = extract(&[
+            stuffed_packet(SPLICE_PID, true, &[&[0][..], head].concat()),
+            packet(SPLICE_PID, true, &invalid),
+            packet(SPLICE_PID, false, tail),
+        ]);
+        assert_eq!(cues, []);
+    }
+
+    #[test]
+    fn pts_adjustments_wrap_around_33_bits() {
+        // `pts_adjustment` of 2^33 - 0x100, whose top bit follows `encryption_algorithm`.
+        let mut section = SPLICE_INSERT_SECTION.to_vec();
+        section[4] |= 1;
+        section[5..9].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0x00]);
+        let payload = [&[0][..], &fix_crc(section)].concat();
+        let cues = extract(&[packet(SPLICE_PID, true, &payload)]);
+        assert_eq!(cues[0].pts, Some(0x0_7369_C02E - 0x100));
+    }
+}
@@ -0,0 +1,184 @@
+//! Resolution of the resources referenced by the segments of media playlists,
+//! shared by the handlers which read them.
+use std::collections::HashMap;
+use hls_m3u8::{MediaPlaylist, MediaSegment};
+use hls_m3u8::tags::{ExtXKey, ExtXMap};
+use hls_m3u8::types::EncryptionMethod;
+use url::Url;
+
+use {Error, Result};
+use super::{ByteRange, PlayerConfig, ResourceKey};
+use super::aes;
+
+/// Resolves the URI `uri` of a playlist against `base_url`.
+pub fn resolve_url(base_url: &Url, uri: &str) -> Result<Url> {
+    track!(
+        Url::options()
+            .base_url(Some(base_url))
+            .parse(uri)
+            .map_err(Error::from)
+    )
+}
+
+/// Returns the `EXT-X-BYTERANGE` of `segment`, and updates `byte_range_end` to its end
+/// (or zero without one).
+///
+/// A `EXT-X-BYTERANGE` without offset starts at `byte_range_end`,
+/// just after the previous sub-range.
+pub fn resolve_byte_range(segment: &MediaSegment, byte_range_end: &mut u64) -> Option<ByteRange> {
+    let range = segment.byte_range_tag().map(|tag| {
+        let range = tag.range();
+        ByteRange {
+            offset: range.start.map_or(*byte_range_end, |start| start as u64),
+            length: range.length as u64,
+        }
+    });
+    *byte_range_end = range.map_or(0, |r| r.end());
+    range
+}
+
+/// Returns the init section of the `EXT-X-MAP` `tag`, whose `BYTERANGE` starts at zero
+/// without offset.
+pub fn map_key(base_url: &Url, tag: &ExtXMap) -> Result<ResourceKey> {
+    Ok(ResourceKey {
+        url: track!(resolve_url(base_url, tag.uri()))?,
+        range: tag.range().map(|r| ByteRange {
+            offset: r.start.unwrap_or(0) as u64,
+            length: r.length as u64,
+        }),
+    })
+}
+
+/// `EXT-X-KEY:METHOD=AES-128` applied to a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    pub key: ResourceKey,
+
+    /// The media sequence number of the segment is used instead if `None`.
+    pub iv: Option<[u8; aes::KEY_LEN]>,
+}
+impl Encryption {
+    /// Decrypts `data` of (or preceding) the segment `seq` with the fetched `key`.
+    pub fn decrypt(&self, key: &[u8], seq: u64, data: &[u8]) -> Result<Vec<u8>> {
+        // Without `IV`, the media sequence number is the IV (RFC 8216, section 5.2).
+        let iv = self.iv.unwrap_or_else(|| aes::sequence_iv(seq));
+        track!(aes::decrypt(key, &iv, data))
+    }
+}
+
+/// Returns the AES-128 encryption in effect after the `EXT-X-KEY` tags of a segment,
+/// or `None` after `METHOD=NONE` or if only unsupported methods and key formats are listed.
+///
+/// Unsupported tags following a supported one are taken for alternatives to it
+/// (e.g., `KEYFORMAT`s of DRM systems).
+pub fn parse_key(
+    config: &PlayerConfig,
+    base_url: &Url,
+    tags: &[ExtXKey],
+) -> Result<Option<Encryption>> {
+    for tag in tags.iter().rev() {
+        let k = match tag.key() {
+            None => return Ok(None),
+            Some(k) => k,
+        };
+        let is_supported = k.method == EncryptionMethod::Aes128
+            && k.key_format.as_deref().is_none_or(|f| f == "identity");
+        if is_supported {
+            return Ok(Some(Encryption {
+                key: ResourceKey {
+                    url: track!(resolve_url(base_url, &k.uri))?,
+                    range: None,
+                },
+                iv: k.iv.as_ref().map(|iv| iv.0),
+            }));
+        }
+    }
+    if let Some(k) = tags.last().and_then(|t| t.key()) {
+        log!(
+            config,
+            Warn,
+            "Unsupported EXT-X-KEY in {}: method={}, keyformat={:?}",
+            base_url,
+            k.method,
+            k.key_format.as_deref()
+        );
+    }
+    Ok(None)
+}
+
+/// Returns whether any segment of `playlist` is encrypted (by a supported method or not).
+pub fn is_encrypted(playlist: &MediaPlaylist) -> bool {
+    playlist
+        .segments()
+        .iter()
+        .flat_map(|s| s.key_tags())
+        .any(|t| t.key().is_some())
+}
+
+/// Returns the number of the `EXT-X-KEY` tags preceding the `EXT-X-MAP` of each segment
+/// by its index, since `MediaSegment` does not keep the order of its tags.
+pub fn keys_before_maps(m3u8: &str) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    let mut index = 0;
+    let mut keys = 0;
+    for line in m3u8.lines().map(|l| l.trim()) {
+        if !line.is_empty() && !line.starts_with('#') {
+            index += 1;
+            keys = 0;
+        } else if line.starts_with("#EXT-X-KEY:") {
+            keys += 1;
+        } else if line.starts_with("#EXT-X-MAP:") {
+            counts.insert(index, keys);
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_ranges_without_offset_follow_the_previous_one() {
+        let playlist: MediaPlaylist = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n\
+                                       #EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"100\"\n\
+                                       #EXTINF:4,\n#EXT-X-BYTERANGE:500@100\nmedia.mp4\n\
+                                       #EXTINF:4,\n#EXT-X-BYTERANGE:300\nmedia.mp4\n\
+                                       #EXTINF:4,\nnext.mp4\n#EXT-X-ENDLIST\n"
+            .parse()
+            .unwrap();
+        let mut end = 0;
+        let ranges = playlist
+            .segments()
+            .iter()
+            .map(|s| resolve_byte_range(s, &mut end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [
+                Some(ByteRange {
+                    offset: 100,
+                    length: 500,
+                }),
+                Some(ByteRange {
+                    offset: 600,
+                    length: 300,
+                }),
+                None,
+            ]
+        );
+        assert_eq!(end, 0);
+
+        let base_url = Url::parse("https://example.com/a/media.m3u8").unwrap();
+        let tag = playlist.segments()[0].map_tag().unwrap();
+        let key = map_key(&base_url, tag).unwrap();
+        assert_eq!(key.url.as_str(), "https://example.com/a/init.mp4");
+        assert_eq!(
+            key.range,
+            Some(ByteRange {
+                offset: 0,
+                length: 100,
+            })
+        );
+    }
+}
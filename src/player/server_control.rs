@@ -0,0 +1,45 @@
+//! `EXT-X-SERVER-CONTROL` of media playlists, which the parser does not keep.
+use std::time::Duration;
+
+use super::text::{attribute, parse_seconds};
+
+/// Number of target durations held back from the live edge if `HOLD-BACK` is absent.
+const DEFAULT_HOLD_BACK_TARGET_DURATIONS: u32 = 3;
+
+/// Delivery directives of a media playlist given by `EXT-X-SERVER-CONTROL`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ServerControl {
+    /// Whether the server holds playlist requests with `_HLS_msn` until the segment is listed.
+    pub can_block_reload: bool,
+
+    /// Minimum distance from the end of the playlist to the start of the playback.
+    pub hold_back: Option<Duration>,
+
+    /// Minimum distance from the live edge for playbacks by partial segments.
+    ///
+    /// The player fetches whole segments only, so it is just reported.
+    pub part_hold_back: Option<Duration>,
+}
+impl ServerControl {
+    /// Returns the directives of the media playlist `m3u8` (the defaults without the tag).
+    pub fn parse(m3u8: &str) -> Self {
+        let attributes = match m3u8
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("#EXT-X-SERVER-CONTROL:"))
+        {
+            None => return ServerControl::default(),
+            Some(x) => x,
+        };
+        ServerControl {
+            can_block_reload: attribute(attributes, "CAN-BLOCK-RELOAD") == Some("YES"),
+            hold_back: attribute(attributes, "HOLD-BACK").and_then(parse_seconds),
+            part_hold_back: attribute(attributes, "PART-HOLD-BACK").and_then(parse_seconds),
+        }
+    }
+
+    /// Returns `hold_back`, or three times `target_duration` as specified if absent.
+    pub fn hold_back(&self, target_duration: Duration) -> Duration {
+        self.hold_back
+            .unwrap_or(target_duration * DEFAULT_HOLD_BACK_TARGET_DURATIONS)
+    }
+}
@@ -0,0 +1,432 @@
+//! Deterministic simulation of a host driving `HlsPlayer`, for testing the scheduling,
+//! retry and reload logic without a network or real time.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::Duration;
+use url::Url;
+
+use {Error, ErrorKind, Result};
+use super::{Action, ActionId, ByteRange, Event, HlsPlayer, ManualClock, PlayerConfig,
+            SegmentInfo};
+
+/// Scripted response to a fetch.
+#[derive(Debug, Clone)]
+pub enum Response {
+    /// Responds with `body` after `latency`.
+    ///
+    /// Byte range requests are given the requested range of `body`.
+    /// `headers` are passed by `HlsPlayer::handle_response_headers` if any.
+    Data {
+        body: Rc<[u8]>,
+        latency: Duration,
+        headers: Rc<BTreeMap<String, String>>,
+    },
+
+    /// Fails with the HTTP `status` (`0` for network errors) after `latency`.
+    Error { status: u16, latency: Duration },
+
+    /// Never responds, so the fetch times out after the `timeout` of the action.
+    Stall,
+}
+impl Response {
+    pub fn data<T: Into<Vec<u8>>>(body: T) -> Self {
+        Response::Data {
+            body: Rc::from(body.into()),
+            latency: Duration::from_millis(0),
+            headers: Rc::default(),
+        }
+    }
+
+    pub fn error(status: u16) -> Self {
+        Response::Error {
+            status,
+            latency: Duration::from_millis(0),
+        }
+    }
+
+    /// Returns the same response delayed by `latency`.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        match self {
+            Response::Data { body, headers, .. } => Response::Data {
+                body,
+                latency,
+                headers,
+            },
+            Response::Error { status, .. } => Response::Error { status, latency },
+            Response::Stall => Response::Stall,
+        }
+    }
+
+    /// Returns the same response with the header `name: value` (ignored by errors and stalls).
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        if let Response::Data {
+            ref mut headers, ..
+        } = self
+        {
+            Rc::make_mut(headers).insert(name.to_owned(), value.to_owned());
+        }
+        self
+    }
+}
+
+/// Input given to the player by the simulated host, or output taken from it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum SimulationRecord {
+    Action { at_ms: u64, action: Action },
+    Event { at_ms: u64, event: Event },
+    Segment {
+        at_ms: u64,
+        bytes: usize,
+        info: SegmentInfo,
+    },
+}
+
+/// Host that answers the fetches of an `HlsPlayer` from scripted responses
+/// and fires its timers on a virtual clock.
+///
+/// Responses are registered per URL; each fetch takes the next one, and the last one is
+/// repeated (e.g., the final state of a live playlist). Unregistered URLs fail with 404.
+/// Simultaneous inputs are delivered in the order of their scheduling,
+/// so runs with the same script always produce the same records.
+#[derive(Debug)]
+pub struct Simulation {
+    player: HlsPlayer,
+    clock: ManualClock,
+    now: Duration,
+    responses: HashMap<String, VecDeque<Response>>,
+
+    /// Scheduled inputs keyed by their due time and scheduling order.
+    inputs: BTreeMap<(Duration, u64), Input>,
+
+    next_input_seq: u64,
+    records: Vec<SimulationRecord>,
+    segments: Vec<Vec<u8>>,
+}
+impl Simulation {
+    /// Makes a simulation of a player with `config`, whose clock is replaced by the virtual one.
+    pub fn new(mut config: PlayerConfig) -> Self {
+        let clock = ManualClock::new();
+        config.hooks.clock = Some(Rc::new(clock.clone()));
+        Simulation {
+            player: HlsPlayer::with_config(config),
+            clock,
+            now: Duration::from_millis(0),
+            responses: HashMap::new(),
+            inputs: BTreeMap::new(),
+            next_input_seq: 0,
+            records: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Adds `response` to the responses to the fetches of `url`.
+    pub fn respond(&mut self, url: &str, response: Response) -> &mut Self {
+        self.responses
+            .entry(url.to_owned())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    /// Starts playing the playlist taken from the responses to `url`.
+    pub fn play(&mut self, url: &str) -> Result<()> {
+        let parsed_url = track!(Url::parse(url).map_err(Error::from))?;
+        let m3u8 = match self.next_response(url) {
+            Response::Data { body, .. } => String::from_utf8_lossy(&body).into_owned(),
+            _ => track_panic!(ErrorKind::InvalidInput, "No playlist for {}", url),
+        };
+        track!(self.player.play(parsed_url, &m3u8))?;
+        self.poll();
+        Ok(())
+    }
+
+    pub fn player(&self) -> &HlsPlayer {
+        &self.player
+    }
+
+    /// Returns the player, e.g., for calling `HlsPlayer::set_buffer_length`.
+    ///
+    /// The outputs of such calls are taken at the next `run_*` call.
+    pub fn player_mut(&mut self) -> &mut HlsPlayer {
+        &mut self.player
+    }
+
+    /// Virtual time elapsed since the start of the simulation.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn records(&self) -> &[SimulationRecord] {
+        &self.records
+    }
+
+    pub fn events(&self) -> Vec<&Event> {
+        self.records
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Event { ref event, .. } => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Segments taken from the player so far.
+    pub fn segments(&self) -> &[Vec<u8>] {
+        &self.segments
+    }
+
+    /// Delivers the inputs due within `duration`, and advances the clock by `duration`.
+    pub fn run_for(&mut self, duration: Duration) -> Result<()> {
+        let end = self.now + duration;
+        track!(self.run_until(end))
+    }
+
+    /// Delivers the inputs due until `end`, and advances the clock to `end`.
+    pub fn run_until(&mut self, end: Duration) -> Result<()> {
+        self.poll();
+        while let Some((&(due, seq), _)) = self.inputs.iter().next() {
+            if due > end {
+                break;
+            }
+            let input = self.inputs.remove(&(due, seq)).expect("Never fails");
+            self.set_now(due);
+            track!(self.deliver(input))?;
+            self.poll();
+        }
+        self.set_now(end);
+        Ok(())
+    }
+
+    /// Delivers the inputs until none is left, or `limit` of virtual time has elapsed.
+    ///
+    /// Returns `true` if the player has become idle (e.g., at the end of a VOD playlist).
+    pub fn run_until_idle(&mut self, limit: Duration) -> Result<bool> {
+        let end = self.now + limit;
+        self.poll();
+        while let Some(&(due, _)) = self.inputs.keys().next() {
+            if due > end {
+                self.set_now(end);
+                return Ok(false);
+            }
+            track!(self.run_until(due))?;
+        }
+        Ok(true)
+    }
+
+    fn set_now(&mut self, now: Duration) {
+        self.now = now;
+        self.clock.set_now(now);
+    }
+
+    fn at_ms(&self) -> u64 {
+        self.now.as_secs() * 1000 + u64::from(self.now.subsec_millis())
+    }
+
+    fn next_response(&mut self, url: &str) -> Response {
+        match self.responses.get_mut(url) {
+            None => Response::error(404),
+            Some(responses) => {
+                if responses.len() > 1 {
+                    responses.pop_front().expect("Never fails")
+                } else {
+                    responses.front().cloned().unwrap_or_else(|| Response::error(404))
+                }
+            }
+        }
+    }
+
+    fn schedule(&mut self, delay: Duration, input: Input) {
+        let key = (self.now + delay, self.next_input_seq);
+        self.next_input_seq += 1;
+        self.inputs.insert(key, input);
+    }
+
+    /// Takes all the outputs of the player, and schedules the inputs answering its actions.
+    fn poll(&mut self) {
+        loop {
+            let at_ms = self.at_ms();
+            if let Some(action) = self.player.next_action() {
+                self.records.push(SimulationRecord::Action {
+                    at_ms,
+                    action: action.clone(),
+                });
+                self.handle_action(action);
+            } else if let Some(event) = self.player.next_event() {
+                self.records.push(SimulationRecord::Event { at_ms, event });
+            } else if let Some(segment) = self.player.next_segment() {
+                self.records.push(SimulationRecord::Segment {
+                    at_ms,
+                    bytes: segment.data.len(),
+                    info: segment.info,
+                });
+                self.segments.push(segment.data);
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::FetchData {
+                action_id,
+                url,
+                timeout,
+                range,
+                ..
+            } => {
+                let timeout = Duration::from_millis(u64::from(timeout));
+                match self.next_response(url.as_str()) {
+                    Response::Data { ref latency, .. } | Response::Error { ref latency, .. }
+                        if timeout.as_millis() > 0 && *latency >= timeout =>
+                    {
+                        self.schedule(timeout, Input::FetchTimeout(action_id));
+                    }
+                    Response::Data {
+                        body,
+                        latency,
+                        headers,
+                    } => {
+                        let body = slice(&body, range).to_vec();
+                        self.schedule(latency, Input::Data(action_id, body, latency, headers));
+                    }
+                    Response::Error { status, latency } => {
+                        self.schedule(latency, Input::Error(action_id, status));
+                    }
+                    Response::Stall => {
+                        if timeout.as_millis() > 0 {
+                            self.schedule(timeout, Input::FetchTimeout(action_id));
+                        }
+                    }
+                }
+            }
+            Action::SetTimeout {
+                action_id,
+                duration,
+            } => {
+                let duration = Duration::from_millis(u64::from(duration));
+                self.schedule(duration, Input::Timeout(action_id));
+            }
+            Action::Abort { action_id } => {
+                self.inputs.retain(|_, input| input.action_id() != action_id);
+            }
+            Action::Preconnect { .. } => {}
+        }
+    }
+
+    fn deliver(&mut self, input: Input) -> Result<()> {
+        match input {
+            Input::Data(action_id, body, latency, headers) => {
+                let fetch_duration_ms = latency.as_millis() as u32;
+                if !headers.is_empty() {
+                    track!(self.player.handle_response_headers(action_id, &headers))?;
+                }
+                track!(self.player.handle_data(action_id, &body, fetch_duration_ms))
+            }
+            Input::Error(action_id, status) => track!(self.player.handle_error(action_id, status)),
+            Input::Timeout(action_id) => track!(self.player.handle_timeout(action_id)),
+            Input::FetchTimeout(action_id) => track!(self.player.handle_fetch_timeout(action_id)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Input {
+    Data(ActionId, Vec<u8>, Duration, Rc<BTreeMap<String, String>>),
+    Error(ActionId, u16),
+    Timeout(ActionId),
+    FetchTimeout(ActionId),
+}
+impl Input {
+    fn action_id(&self) -> ActionId {
+        match *self {
+            Input::Data(action_id, _, _, _)
+            | Input::Error(action_id, _)
+            | Input::Timeout(action_id)
+            | Input::FetchTimeout(action_id) => action_id,
+        }
+    }
+}
+
+fn slice(body: &[u8], range: Option<ByteRange>) -> &[u8] {
+    match range {
+        None => body,
+        Some(range) => {
+            let start = (range.offset as usize).min(body.len());
+            let end = (range.end() as usize).min(body.len());
+            &body[start..end]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    const VOD: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n\
+                       #EXTINF:4,\n#EXT-X-BYTERANGE:100@0\nall.ts\n\
+                       #EXTINF:4,\n#EXT-X-BYTERANGE:88\nall.ts\n#EXT-X-ENDLIST\n";
+
+    /// Returns the time, timeout and range of the segment fetches.
+    fn fetches(sim: &Simulation) -> Vec<(u64, u32, Option<ByteRange>)> {
+        sim.records()
+            .iter()
+            .filter_map(|r| match *r {
+                SimulationRecord::Action {
+                    at_ms,
+                    action:
+                        Action::FetchData {
+                            ref url,
+                            timeout,
+                            range,
+                            ..
+                        },
+                } if url.path() == "/all.ts" => Some((at_ms, timeout, range)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn simulate() -> Simulation {
+        let mut sim = Simulation::new(PlayerConfig::default());
+        sim.respond("http://x/a.m3u8", Response::data(VOD))
+            .respond("http://x/all.ts", Response::Stall)
+            .respond("http://x/all.ts", Response::data(vec![0x47; 188]));
+        sim.play("http://x/a.m3u8").unwrap();
+        sim.run_for(Duration::from_secs(30)).unwrap();
+        sim
+    }
+
+    #[test]
+    fn stalled_fetches_time_out() {
+        let sim = simulate();
+        let fetches = fetches(&sim);
+        let (_, timeout, range) = fetches[0];
+        assert!(timeout > 0);
+        assert_eq!(range.map(|r| r.offset), Some(0));
+
+        // Retried `RetryConfig::initial_delay_ms` after the timeout.
+        assert_eq!(fetches[1].0, u64::from(timeout) + 500);
+        assert_eq!(fetches[1].2, range);
+    }
+
+    #[test]
+    fn same_scripts_produce_same_records() {
+        let records = serde_json::to_string(simulate().records()).unwrap();
+        assert_eq!(serde_json::to_string(simulate().records()).unwrap(), records);
+    }
+
+    #[test]
+    fn byte_ranges_are_sliced() {
+        let body = (0..=255).collect::<Vec<u8>>();
+        let range = ByteRange {
+            offset: 250,
+            length: 10,
+        };
+        assert_eq!(slice(&body, Some(range)), &body[250..]);
+        assert_eq!(slice(&body, None).len(), 256);
+    }
+}
@@ -0,0 +1,125 @@
+//! Fetching of the playlist given by `HlsPlayer::load_source`.
+use std::collections::VecDeque;
+use url::Url;
+
+use super::{Action, ActionFactory, ActionId, Event, FailureClass, PlayerConfig, ResourceKind,
+            StreamId};
+use super::text;
+
+/// Stream ID of the actions of `SourceLoader`, distinct from those of media playlists.
+pub const SOURCE_STREAM_ID: StreamId = 0xFC;
+
+/// Fetcher of the master or media playlist of a new source.
+///
+/// Failed fetches are retried according to `PlayerConfig::playlist_retry`,
+/// after which a fatal `Event::Error` is emitted.
+#[derive(Debug)]
+pub struct SourceLoader {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    url: Url,
+    fetch_action_id: Option<ActionId>,
+    retry_action_id: Option<ActionId>,
+    failures: u32,
+    partial_body: Vec<u8>,
+}
+impl SourceLoader {
+    pub fn new(config: &PlayerConfig, url: Url) -> Self {
+        let mut loader = SourceLoader {
+            config: config.clone(),
+            action_factory: ActionFactory::new(SOURCE_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            url,
+            fetch_action_id: None,
+            retry_action_id: None,
+            failures: 0,
+            partial_body: Vec::new(),
+        };
+        loader.request_playlist();
+        loader
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    /// Returns the fetched playlist if `action_id` is the fetch of it.
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8]) -> Option<String> {
+        if self.fetch_action_id != Some(action_id) {
+            return None;
+        }
+        self.fetch_action_id = None;
+        self.partial_body.clear();
+        Some(text::decode(data).into_owned())
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        if self.fetch_action_id == Some(action_id) {
+            self.partial_body.extend_from_slice(chunk);
+        }
+    }
+
+    /// Same as `handle_data` for the body given by `handle_data_chunk`.
+    pub fn handle_data_end(&mut self, action_id: ActionId) -> Option<String> {
+        let body = std::mem::take(&mut self.partial_body);
+        self.handle_data(action_id, &body)
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) {
+        if self.retry_action_id == Some(action_id) {
+            self.retry_action_id = None;
+            self.request_playlist();
+        }
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    pub fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        if self.fetch_action_id != Some(action_id) {
+            return;
+        }
+        self.fetch_action_id = None;
+        self.partial_body.clear();
+        self.failures += 1;
+        if !class.is_permanent() && self.failures < self.config.playlist_retry.max_attempts {
+            let delay = self.config.playlist_retry.delay(self.failures);
+            let action = self.action_factory.set_timeout(delay);
+            self.retry_action_id = Some(action.id());
+            self.action_queue.push_back(action);
+            return;
+        }
+        let reason = format!("Cannot fetch the playlist {}: {}", self.url, description);
+        log!(self.config, Error, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: true,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.retry_action_id = None;
+        self.partial_body.clear();
+        if let Some(action_id) = self.fetch_action_id.take() {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn request_playlist(&mut self) {
+        let action = self.action_factory
+            .fetch_data(self.url.clone(), ResourceKind::Playlist, None);
+        self.fetch_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
@@ -0,0 +1,92 @@
+use super::{ImageStream, PlaylistRefreshStats, QoeStats, Rendition, ServerControl, VariantSwitch};
+
+/// Aggregate playback statistics.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// `BANDWIDTH` of the played variant in bits per second, if started from a master playlist.
+    pub bitrate: Option<u64>,
+
+    /// Index of the played `EXT-X-STREAM-INF` entry, if started from a master playlist.
+    pub variant_index: Option<usize>,
+
+    /// `CODECS` of the played variant, in which the video codec is replaced by
+    /// the `SUPPLEMENTAL-CODECS` supported by `DeviceConfig::supplemental_codecs`.
+    pub codecs: Option<String>,
+
+    /// Indices of the variants excluded by `PlayerConfig::device`.
+    pub excluded_variants: Vec<usize>,
+
+    /// Indices of the variants blacklisted by `PlayerConfig::variant_blacklist`.
+    pub blacklisted_variants: Vec<usize>,
+
+    /// History of the variant selections in chronological order.
+    pub variant_switches: Vec<VariantSwitch>,
+
+    /// `PATHWAY-ID` of the played variant, if the master playlist uses content steering.
+    pub pathway: Option<String>,
+
+    /// Audio rendition of the played variant selected by `PlayerConfig::renditions`,
+    /// if the variant refers to an `AUDIO` group.
+    pub audio_rendition: Option<Rendition>,
+
+    /// Subtitle rendition of the played variant selected by `PlayerConfig::renditions`.
+    pub subtitle_rendition: Option<Rendition>,
+
+    /// Audio and subtitle renditions of the master playlist, with which hosts can choose
+    /// the `RenditionConfig` preferences (e.g., by `characteristics`).
+    pub renditions: Vec<Rendition>,
+
+    /// Forced subtitle rendition loaded by the player (see `RenditionConfig::forced_subtitles`).
+    pub forced_subtitle_rendition: Option<Rendition>,
+
+    /// Image stream loaded by the player (see `ThumbnailConfig::image_streams`).
+    pub image_stream: Option<ImageStream>,
+
+    /// Estimated network throughput in kbps.
+    pub throughput_kbps: Option<u32>,
+
+    /// Buffer length reported by the host in milliseconds.
+    pub buffer_length_ms: Option<u32>,
+
+    /// Total size of the fetched playlists and segments.
+    pub bytes_downloaded: u64,
+
+    /// Number of the fetched media segments.
+    pub segments_downloaded: u64,
+
+    /// Total size of the produced segments not yet taken by the host.
+    pub queued_output_bytes: usize,
+
+    /// Estimated end-to-end live latency in milliseconds (`None` for VOD).
+    ///
+    /// It is measured by `LatencyEstimator` if `Hooks::clock` and the playback position are
+    /// available, and otherwise approximated by the buffer length plus the duration of
+    /// the segments yet to be fetched.
+    pub live_latency_ms: Option<u32>,
+
+    /// `EXT-X-PROGRAM-DATE-TIME` presented at the notified playback position,
+    /// in milliseconds since the UNIX epoch.
+    pub program_date_time_ms: Option<f64>,
+
+    /// Offset of the client clock from that of the origin in milliseconds (positive if
+    /// the client is ahead), measured by the `Date` of the playlist responses.
+    pub clock_offset_ms: Option<i64>,
+
+    pub qoe: QoeStats,
+
+    /// Media playlist reload metrics.
+    pub playlist_refresh: PlaylistRefreshStats,
+
+    /// `EXT-X-SERVER-CONTROL` of the played media playlist.
+    pub server_control: ServerControl,
+}
+
+/// Timestamped snapshot of `Stats`, for hosts sending periodic beacons to analytics backends.
+#[derive(Debug, Clone, Serialize)]
+pub struct Metrics {
+    /// Wall-clock time in milliseconds since the UNIX epoch (`None` without `Hooks::clock`).
+    pub timestamp_ms: Option<f64>,
+
+    #[serde(flatten)]
+    pub stats: Stats,
+}
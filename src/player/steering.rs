@@ -0,0 +1,368 @@
+//! Content steering (`EXT-X-CONTENT-STEERING`), by which a steering server decides
+//! the pathway (CDN) serving the variants of a master playlist.
+//!
+//! The parser does not keep the tag nor the `PATHWAY-ID` attributes of the variants,
+//! so they are taken from the playlist text.
+use std::cmp;
+use std::collections::{BTreeSet, VecDeque};
+use std::mem;
+use std::time::Duration;
+use serde_json;
+use url::Url;
+
+use {Error, ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, PlayerConfig, ResourceKind, StreamId};
+use super::text::attribute;
+
+/// Stream ID of the actions of `ContentSteering`, distinct from those of media playlists.
+pub const STEERING_STREAM_ID: StreamId = 0xFF;
+
+/// `PATHWAY-ID` of the variants without the attribute.
+pub const DEFAULT_PATHWAY_ID: &str = ".";
+
+/// Reload interval used if the manifest omits `TTL` (or cannot be fetched).
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// Lower bound of the reload interval, which keeps misconfigured servers from being flooded.
+const MIN_TTL: Duration = Duration::from_secs(1);
+
+/// `EXT-X-CONTENT-STEERING` tag of a master playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SteeringTag {
+    pub server_uri: Url,
+
+    /// Pathway used until the steering manifest is obtained.
+    pub pathway_id: Option<String>,
+}
+impl SteeringTag {
+    /// Returns the tag of the master playlist `m3u8` at `url`, if any.
+    pub fn parse(url: &Url, m3u8: &str) -> Result<Option<Self>> {
+        let attributes = match m3u8
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("#EXT-X-CONTENT-STEERING:"))
+        {
+            None => return Ok(None),
+            Some(x) => x,
+        };
+        let server_uri = track_assert_some!(
+            attribute(attributes, "SERVER-URI"),
+            ErrorKind::InvalidInput,
+            "No SERVER-URI: {}",
+            url
+        );
+        let server_uri = track!(
+            Url::options()
+                .base_url(Some(url))
+                .parse(server_uri)
+                .map_err(Error::from)
+        )?;
+        let pathway_id = attribute(attributes, "PATHWAY-ID").map(|id| id.to_owned());
+        Ok(Some(SteeringTag {
+            server_uri,
+            pathway_id,
+        }))
+    }
+}
+
+/// Returns the `PATHWAY-ID` of each `EXT-X-STREAM-INF` entry of `m3u8` in order.
+pub fn variant_pathways(m3u8: &str) -> Vec<String> {
+    m3u8.lines()
+        .filter_map(|l| l.trim().strip_prefix("#EXT-X-STREAM-INF:"))
+        .map(|attributes| {
+            attribute(attributes, "PATHWAY-ID")
+                .unwrap_or(DEFAULT_PATHWAY_ID)
+                .to_owned()
+        })
+        .collect()
+}
+
+/// Returns the index of the variant of `pathway` equivalent to the `from`-th variant,
+/// i.e., the one at the same position among the variants of its pathway.
+///
+/// Pathways are expected to list the same renditions in the same order.
+pub fn equivalent_variant(pathways: &[String], from: usize, pathway: &str) -> Option<usize> {
+    let from_pathway = pathways.get(from)?;
+    let position = pathways[..from]
+        .iter()
+        .filter(|p| *p == from_pathway)
+        .count();
+    let candidates = pathways
+        .iter()
+        .enumerate()
+        .filter(|&(_, p)| p == pathway)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let last = candidates.len().checked_sub(1)?;
+    Some(candidates[cmp::min(position, last)])
+}
+
+/// Steering manifest returned by the steering server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SteeringManifest {
+    #[serde(rename = "VERSION")]
+    pub version: u32,
+
+    /// Seconds until the manifest should be reloaded.
+    #[serde(rename = "TTL")]
+    pub ttl: Option<u64>,
+
+    #[serde(rename = "RELOAD-URI")]
+    pub reload_uri: Option<String>,
+
+    /// Pathways in the order of preference.
+    #[serde(rename = "PATHWAY-PRIORITY", default)]
+    pub pathway_priority: Vec<String>,
+}
+impl SteeringManifest {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let manifest: Self = track!(serde_json::from_slice(data).map_err(Error::from))?;
+        track_assert!(
+            manifest.version == 1,
+            ErrorKind::InvalidInput,
+            "Unsupported steering manifest version: {}",
+            manifest.version
+        );
+        Ok(manifest)
+    }
+}
+
+/// Client of the steering server, which keeps the pathway priorities up to date.
+///
+/// Failed fetches keep the last priorities and are retried after the TTL.
+#[derive(Debug)]
+pub struct ContentSteering {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    server_uri: Url,
+    priority: Vec<String>,
+
+    /// Pathways given up due to failures, until the next manifest is obtained.
+    penalized: BTreeSet<String>,
+
+    ttl: Duration,
+    fetch_action_id: Option<ActionId>,
+    partial_body: Vec<u8>,
+    reload_action_id: Option<ActionId>,
+}
+impl ContentSteering {
+    pub fn new(config: &PlayerConfig, tag: &SteeringTag) -> Self {
+        ContentSteering {
+            config: config.clone(),
+            action_factory: ActionFactory::new(STEERING_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            server_uri: tag.server_uri.clone(),
+            priority: Vec::new(),
+            penalized: BTreeSet::new(),
+            ttl: DEFAULT_TTL,
+            fetch_action_id: None,
+            partial_body: Vec::new(),
+            reload_action_id: None,
+        }
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == STEERING_STREAM_ID
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    /// Fetches the manifest, reporting the current `pathway` and `throughput_kbps`
+    /// to the server by the `_HLS_pathway` and `_HLS_throughput` query parameters.
+    pub fn request(&mut self, pathway: &str, throughput_kbps: Option<u32>) {
+        let mut url = self.server_uri.clone();
+        url.query_pairs_mut().append_pair("_HLS_pathway", pathway);
+        if let Some(kbps) = throughput_kbps {
+            let bps = u64::from(kbps) * 1000;
+            url.query_pairs_mut()
+                .append_pair("_HLS_throughput", &bps.to_string());
+        }
+        let action = self.action_factory
+            .fetch_data(url, ResourceKind::SteeringManifest, None);
+        self.fetch_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+
+    /// Handles the manifest fetched by `action_id`.
+    ///
+    /// Returns `true` if the priorities have been updated.
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8]) -> bool {
+        if self.fetch_action_id != Some(action_id) {
+            return false;
+        }
+        self.fetch_action_id = None;
+        let result = SteeringManifest::parse(data).and_then(|manifest| {
+            let reload_uri = match manifest.reload_uri {
+                None => None,
+                Some(ref uri) => Some(track!(
+                    Url::options()
+                        .base_url(Some(&self.server_uri))
+                        .parse(uri)
+                        .map_err(Error::from)
+                )?),
+            };
+            Ok((manifest, reload_uri))
+        });
+        let (manifest, reload_uri) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                log!(self.config, Warn, "Cannot handle the steering manifest: {}", e);
+                self.schedule_reload();
+                return false;
+            }
+        };
+        log!(
+            self.config,
+            Debug,
+            "Steering manifest: ttl={:?}, pathway_priority={:?}",
+            manifest.ttl,
+            manifest.pathway_priority
+        );
+        if let Some(uri) = reload_uri {
+            self.server_uri = uri;
+        }
+        self.ttl = manifest
+            .ttl
+            .map_or(DEFAULT_TTL, Duration::from_secs)
+            .max(MIN_TTL);
+        self.priority = manifest.pathway_priority;
+        self.penalized.clear();
+        self.schedule_reload();
+        true
+    }
+
+    /// Handles a chunk of the manifest, which is handled by `handle_data_end`.
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        if self.fetch_action_id == Some(action_id) {
+            self.partial_body.extend_from_slice(chunk);
+        }
+    }
+
+    /// Handles the end of the manifest given by `handle_data_chunk` (see `handle_data`).
+    pub fn handle_data_end(&mut self, action_id: ActionId) -> bool {
+        let body = mem::take(&mut self.partial_body);
+        self.handle_data(action_id, &body)
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    pub fn handle_failure(&mut self, action_id: ActionId, description: &str) {
+        if self.fetch_action_id != Some(action_id) {
+            return;
+        }
+        self.fetch_action_id = None;
+        self.partial_body.clear();
+        log!(self.config, Warn, "Cannot fetch the steering manifest: {}", description);
+        self.schedule_reload();
+    }
+
+    /// Returns `true` if `action_id` is the timer of the next reload,
+    /// after which `request` should be called.
+    pub fn handle_timeout(&mut self, action_id: ActionId) -> bool {
+        if self.reload_action_id != Some(action_id) {
+            return false;
+        }
+        self.reload_action_id = None;
+        true
+    }
+
+    /// Returns `true` if neither a fetch nor a reload is pending, i.e., `request` is due.
+    pub fn is_idle(&self) -> bool {
+        self.fetch_action_id.is_none() && self.reload_action_id.is_none()
+    }
+
+    /// Excludes `pathway` from the selection until the next manifest is obtained.
+    pub fn penalize(&mut self, pathway: &str) {
+        self.penalized.insert(pathway.to_owned());
+    }
+
+    /// Returns the most preferred of the `available` pathways that are not penalized.
+    ///
+    /// Pathways not listed by the server follow the listed ones in the order of `available`,
+    /// so that the playback can fail over even before the manifest is obtained.
+    pub fn select<'a>(&self, available: &'a [String]) -> Option<&'a str> {
+        let is_usable = |p: &&String| !self.penalized.contains(*p);
+        self.priority
+            .iter()
+            .filter_map(|p| available.iter().find(|a| *a == p))
+            .chain(available.iter())
+            .find(is_usable)
+            .map(|p| p.as_str())
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.reload_action_id = None;
+        self.partial_body.clear();
+        if let Some(action_id) = self.fetch_action_id.take() {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn schedule_reload(&mut self) {
+        let action = self.action_factory.set_timeout(self.ttl);
+        self.reload_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pathways(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| (*id).to_owned()).collect()
+    }
+
+    /// Returns a client which has obtained the manifest `json`.
+    fn steering(json: &str) -> ContentSteering {
+        let tag = SteeringTag {
+            server_uri: Url::parse("http://x/steering.json").unwrap(),
+            pathway_id: None,
+        };
+        let mut steering = ContentSteering::new(&PlayerConfig::default(), &tag);
+        steering.request("A", None);
+        let action_id = match steering.next_action() {
+            Some(Action::FetchData { action_id, .. }) => action_id,
+            action => panic!("{:?}", action),
+        };
+        assert!(steering.handle_data(action_id, json.as_bytes()));
+        steering
+    }
+
+    #[test]
+    fn pathways_are_selected_by_priority() {
+        let steering = steering(r#"{"VERSION": 1, "TTL": 10, "PATHWAY-PRIORITY": ["C", "B"]}"#);
+        assert_eq!(steering.select(&pathways(&["A", "B", "C"])), Some("C"));
+        assert_eq!(steering.select(&pathways(&["A", "B"])), Some("B"));
+    }
+
+    #[test]
+    fn pathways_missing_from_the_manifest_follow_the_listed_ones() {
+        let mut steering = steering(r#"{"VERSION": 1, "PATHWAY-PRIORITY": ["X", "B"]}"#);
+        let available = pathways(&["A", "B", "C"]);
+        assert_eq!(steering.select(&available), Some("B"));
+
+        steering.penalize("B");
+        assert_eq!(steering.select(&available), Some("A"));
+        steering.penalize("A");
+        steering.penalize("C");
+        assert_eq!(steering.select(&available), None);
+    }
+
+    #[test]
+    fn manifests_of_other_versions_are_rejected() {
+        assert!(SteeringManifest::parse(br#"{"VERSION": 2}"#).is_err());
+        let manifest = SteeringManifest::parse(br#"{"VERSION": 1}"#).unwrap();
+        assert!(manifest.pathway_priority.is_empty());
+    }
+
+    #[test]
+    fn equivalent_variants_keep_their_positions() {
+        let pathways = pathways(&["A", "A", "B", "B", "C"]);
+        assert_eq!(equivalent_variant(&pathways, 1, "B"), Some(3));
+        assert_eq!(equivalent_variant(&pathways, 1, "C"), Some(4));
+        assert_eq!(equivalent_variant(&pathways, 1, "D"), None);
+    }
+}
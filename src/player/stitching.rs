@@ -0,0 +1,126 @@
+//! Client-side ad insertion by splicing ad pods into the segments of the played playlist.
+//!
+//! Ad breaks are marked by `EXT-X-CUE-OUT`/`EXT-X-CUE-IN` or by `EXT-X-DATERANGE` tags with
+//! `SCTE35-OUT`/`SCTE35-IN` attributes. Since the parser does not keep them, they are taken
+//! from the playlist text and located by the segment they precede.
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+use {ErrorKind, Result};
+use super::{ByteRange, ResourceKey};
+use super::discontinuity;
+use super::segment_resources;
+use super::target_duration;
+use super::text::{attribute, parse_seconds};
+
+/// Boundary of an ad break.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cue {
+    /// The break starts at the segment.
+    Out {
+        /// `ID` of the `EXT-X-DATERANGE`.
+        id: Option<String>,
+
+        /// Planned duration of the break, if given.
+        duration: Option<Duration>,
+    },
+
+    /// The main content resumes at the segment.
+    In,
+}
+
+/// Returns the cues of `m3u8` with the indices of the segments they precede.
+pub fn parse_cues(m3u8: &str) -> Vec<(usize, Cue)> {
+    let mut cues = Vec::new();
+    let mut index = 0;
+    for line in m3u8.lines().map(|l| l.trim()) {
+        if !line.is_empty() && !line.starts_with('#') {
+            index += 1;
+        } else if line == "#EXT-X-CUE-IN" {
+            cues.push((index, Cue::In));
+        } else if line == "#EXT-X-CUE-OUT" || line.starts_with("#EXT-X-CUE-OUT:") {
+            // Both `#EXT-X-CUE-OUT:30` and `#EXT-X-CUE-OUT:DURATION=30` are in use.
+            let value = line.split_once(':').map_or("", |x| x.1);
+            let value = attribute(value, "DURATION").unwrap_or(value);
+            let duration = parse_seconds(value);
+            cues.push((index, Cue::Out { id: None, duration }));
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-DATERANGE:") {
+            if attribute(attributes, "SCTE35-OUT").is_some() {
+                let duration = attribute(attributes, "DURATION")
+                    .or_else(|| attribute(attributes, "PLANNED-DURATION"))
+                    .and_then(parse_seconds);
+                let id = attribute(attributes, "ID").map(|id| id.to_owned());
+                cues.push((index, Cue::Out { id, duration }));
+            } else if attribute(attributes, "SCTE35-IN").is_some() {
+                cues.push((index, Cue::In));
+            }
+        }
+    }
+    cues
+}
+
+/// Ad pod given by `HlsPlayer::insert_ad_pod`, played at the next ad break.
+#[derive(Debug, Clone)]
+pub struct AdPod {
+    pub url: Url,
+    pub segments: Vec<AdSegment>,
+    pub duration: Duration,
+}
+impl AdPod {
+    /// Parses the VOD media playlist `m3u8` of the pod.
+    pub fn parse(url: Url, m3u8: &str) -> Result<Self> {
+        let (m3u8, _) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        track_assert!(
+            playlist.end_list_tag().is_some(),
+            ErrorKind::InvalidInput,
+            "Ad pods must be VOD playlists: {}",
+            url
+        );
+        track_assert!(
+            !playlist.segments().is_empty(),
+            ErrorKind::InvalidInput,
+            "Empty ad pod: {}",
+            url
+        );
+
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        let mut segments = Vec::with_capacity(playlist.segments().len());
+        let mut byte_range_end = 0;
+        let mut init = None;
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+
+            // An `EXT-X-MAP` applies to the following segments until the next one.
+            if let Some(tag) = segment.map_tag() {
+                init = Some(track!(segment_resources::map_key(&url, tag))?);
+            }
+            segments.push(AdSegment {
+                seq: first_seq + i as u64,
+                url: track!(segment_resources::resolve_url(&url, segment.uri()))?,
+                range,
+                init: init.clone(),
+                duration: segment.inf_tag().duration(),
+            });
+        }
+        let duration = segments.iter().map(|s| s.duration).sum();
+        Ok(AdPod {
+            url,
+            segments,
+            duration,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdSegment {
+    /// Sequence number in the pod playlist.
+    pub seq: u64,
+
+    pub url: Url,
+    pub range: Option<ByteRange>,
+    pub init: Option<ResourceKey>,
+    pub duration: Duration,
+}
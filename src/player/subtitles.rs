@@ -0,0 +1,223 @@
+//! Loading of the forced subtitle rendition, whose cues are reported to the host.
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+use {Error, Result};
+use super::{Action, ActionFactory, ActionId, Event, FailureClass, PlayerConfig, ResourceKind,
+            StreamId};
+use super::discontinuity;
+use super::target_duration;
+use super::text;
+use super::webvtt;
+
+/// Stream ID of the actions of `SubtitleLoader`, distinct from those of media playlists.
+pub const SUBTITLE_STREAM_ID: StreamId = 0xFE;
+
+/// Fetcher of the WebVTT segments of a subtitle rendition.
+///
+/// Live playlists are reloaded every target duration. Failed segments are skipped,
+/// since subtitles should not interrupt the playback.
+#[derive(Debug)]
+pub struct SubtitleLoader {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    url: Url,
+    language: Option<String>,
+    fetch_playlist_action_id: Option<ActionId>,
+    playlist_failures: u32,
+    reload_action_id: Option<ActionId>,
+
+    /// Sequence numbers of the fetched segments.
+    fetching_segments: HashMap<ActionId, u64>,
+
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+    last_sequence: Option<u64>,
+}
+impl SubtitleLoader {
+    pub fn new(config: &PlayerConfig, url: Url, language: Option<String>) -> Self {
+        let mut loader = SubtitleLoader {
+            config: config.clone(),
+            action_factory: ActionFactory::new(SUBTITLE_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            url,
+            language,
+            fetch_playlist_action_id: None,
+            playlist_failures: 0,
+            reload_action_id: None,
+            fetching_segments: HashMap::new(),
+            partial_bodies: HashMap::new(),
+            last_sequence: None,
+        };
+        loader.request_playlist();
+        loader
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == SUBTITLE_STREAM_ID
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Skips the segments up to `seq` (e.g., those requested from another pathway).
+    pub fn resume_after(&mut self, seq: Option<u64>) {
+        self.last_sequence = seq;
+    }
+
+    pub fn last_sequence(&self) -> Option<u64> {
+        self.last_sequence
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8]) {
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let result = self.handle_playlist(&text::decode(data));
+            if let Err(e) = result {
+                self.handle_playlist_failure(FailureClass::Permanent, e.to_string());
+            }
+        } else if let Some(sequence) = self.fetching_segments.remove(&action_id) {
+            let cues = webvtt::parse_cues(&text::decode(data));
+            log!(
+                self.config,
+                Debug,
+                "Loaded the forced subtitles #{}: {} cues",
+                sequence,
+                cues.len()
+            );
+            self.event_queue.push_back(Event::ForcedSubtitlesLoaded {
+                language: self.language.clone(),
+                sequence,
+                cues,
+            });
+        }
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        self.partial_bodies
+            .entry(action_id)
+            .or_default()
+            .extend_from_slice(chunk);
+    }
+
+    pub fn handle_data_end(&mut self, action_id: ActionId) {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        self.handle_data(action_id, &body);
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) {
+        if self.reload_action_id == Some(action_id) {
+            self.reload_action_id = None;
+            self.request_playlist();
+        }
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    pub fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        self.partial_bodies.remove(&action_id);
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let reason = format!(
+                "Cannot fetch the subtitle playlist {}: {}",
+                self.url, description
+            );
+            self.handle_playlist_failure(class, reason);
+        } else if let Some(sequence) = self.fetching_segments.remove(&action_id) {
+            log!(
+                self.config,
+                Warn,
+                "Skipped the forced subtitles #{}: {}",
+                sequence,
+                description
+            );
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.action_queue.clear();
+        self.reload_action_id = None;
+        self.partial_bodies.clear();
+        let action_ids = self.fetch_playlist_action_id
+            .take()
+            .into_iter()
+            .chain(self.fetching_segments.drain().map(|(id, _)| id))
+            .collect::<Vec<_>>();
+        for action_id in action_ids {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn request_playlist(&mut self) {
+        let action = self.action_factory
+            .fetch_data(self.url.clone(), ResourceKind::Playlist, None);
+        self.fetch_playlist_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+
+    fn handle_playlist(&mut self, m3u8: &str) -> Result<()> {
+        let (m3u8, _) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        self.playlist_failures = 0;
+        let target_duration = playlist.target_duration_tag().duration();
+        self.action_factory.set_target_duration(target_duration);
+
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            let seq = first_seq + i as u64;
+            if self.last_sequence.is_some_and(|last| seq <= last) {
+                continue;
+            }
+            let url = track!(
+                Url::options()
+                    .base_url(Some(&self.url))
+                    .parse(segment.uri())
+                    .map_err(Error::from)
+            )?;
+            let action = self.action_factory
+                .fetch_data(url, ResourceKind::Subtitles, None);
+            self.fetching_segments.insert(action.id(), seq);
+            self.action_queue.push_back(action);
+            self.last_sequence = Some(seq);
+        }
+        if playlist.end_list_tag().is_none() {
+            self.schedule_reload(target_duration);
+        }
+        Ok(())
+    }
+
+    fn handle_playlist_failure(&mut self, class: FailureClass, reason: String) {
+        self.playlist_failures += 1;
+        if !class.is_permanent() && self.playlist_failures < self.config.playlist_retry.max_attempts
+        {
+            let delay = self.config.playlist_retry.delay(self.playlist_failures);
+            self.schedule_reload(delay);
+            return;
+        }
+        log!(self.config, Warn, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+    }
+
+    fn schedule_reload(&mut self, delay: Duration) {
+        let action = self.action_factory.set_timeout(delay);
+        self.reload_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+}
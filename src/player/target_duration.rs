@@ -0,0 +1,50 @@
+//! `EXT-X-TARGETDURATION`, which some packagers omit or set to zero although the parser
+//! requires a positive value. It is inferred from the `EXTINF` durations in that case,
+//! so that reloads are still scheduled at about the segment duration.
+use std::borrow::Cow;
+
+const TAG: &str = "#EXT-X-TARGETDURATION";
+const EXTINF: &str = "#EXTINF:";
+const EXTM3U: &str = "#EXTM3U";
+
+/// Target duration (in seconds) assumed for playlists without segments.
+const DEFAULT_TARGET_DURATION: u64 = 6;
+
+/// Returns `m3u8` with a valid tag, and the target duration (in seconds) inferred
+/// if the tag was missing, malformed or zero.
+///
+/// The inferred value is the longest `EXTINF` duration rounded up to whole seconds.
+pub fn repair(m3u8: &str) -> (Cow<'_, str>, Option<u64>) {
+    if is_valid(m3u8) {
+        return (Cow::Borrowed(m3u8), None);
+    }
+
+    let inferred = m3u8
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix(EXTINF))
+        .filter_map(|v| v.split(',').next()?.trim().parse::<f64>().ok())
+        .filter(|d| d.is_finite() && *d >= 0.0)
+        .map(|d| (d.ceil() as u64).max(1))
+        .max()
+        .unwrap_or(DEFAULT_TARGET_DURATION);
+    let tag = format!("{}:{}\n", TAG, inferred);
+    let mut repaired = String::with_capacity(m3u8.len() + tag.len());
+    let mut lines = m3u8.lines().peekable();
+    if lines.peek().is_some_and(|l| l.trim().starts_with(EXTM3U)) {
+        repaired.extend(lines.next());
+        repaired.push('\n');
+    }
+    repaired.push_str(&tag);
+    for line in lines.filter(|l| !l.trim().starts_with(TAG)) {
+        repaired.push_str(line);
+        repaired.push('\n');
+    }
+    (Cow::Owned(repaired), Some(inferred))
+}
+
+/// Returns `true` if `m3u8` has a valid tag.
+pub fn is_valid(m3u8: &str) -> bool {
+    m3u8.lines()
+        .filter_map(|l| l.trim().strip_prefix(TAG)?.strip_prefix(':'))
+        .any(|v| v.trim().parse::<u64>().is_ok_and(|d| d > 0))
+}
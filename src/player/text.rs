@@ -0,0 +1,86 @@
+//! Tolerant decoding of playlist text.
+//!
+//! Origins occasionally serve playlists with a UTF-8 BOM, CRLF line endings, trailing NUL
+//! padding, or stray non-UTF-8 bytes (e.g., Latin-1 in comments), which are accepted
+//! instead of failing the whole playlist.
+use std::borrow::Cow;
+use std::time::Duration;
+
+const BOM: char = '\u{feff}';
+
+/// Decodes `data` as UTF-8, replacing invalid sequences with `U+FFFD`,
+/// and normalizes the result (see `normalize`).
+pub fn decode(data: &[u8]) -> Cow<'_, str> {
+    match String::from_utf8_lossy(data) {
+        Cow::Borrowed(text) => normalize(text),
+        Cow::Owned(text) => Cow::Owned(normalize(&text).into_owned()),
+    }
+}
+
+/// Strips a leading BOM and trailing NULs, and converts CRLF and CR line endings to LF.
+///
+/// Already normalized text is returned without being copied.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    let text = text.trim_start_matches(BOM).trim_end_matches('\0');
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// Returns `true` if `m3u8` is a media playlist rather than a master playlist,
+/// including one without `EXT-X-TARGETDURATION`.
+pub fn is_media_playlist(m3u8: &str) -> bool {
+    m3u8.contains("#EXT-X-TARGETDURATION") || m3u8.contains("#EXTINF")
+}
+
+/// Returns the value of the attribute `name` in the attribute list `attributes`,
+/// without the quotes of quoted strings.
+pub fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    self::attributes(attributes)
+        .find(|&(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+/// Returns the names and values of the attribute list `attributes` in order,
+/// without the quotes of quoted strings.
+pub fn attributes(attributes: &str) -> Attributes<'_> {
+    Attributes { rest: attributes }
+}
+
+/// Iterator returned by `attributes`.
+#[derive(Debug, Clone)]
+pub struct Attributes<'a> {
+    rest: &'a str,
+}
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let eq = self.rest.find('=')?;
+        let key = self.rest[..eq].trim();
+        let value_start = &self.rest[eq + 1..];
+        let (value, next) = if let Some(quoted) = value_start.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = value_start.find(',').unwrap_or(value_start.len());
+            (&value_start[..end], &value_start[end..])
+        };
+        self.rest = next.trim_start_matches(',');
+        Some((key, value))
+    }
+}
+
+/// Parses the decimal seconds of an attribute value (e.g., `DURATION`).
+pub fn parse_seconds(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|s| *s >= 0.0 && *s < f64::from(u32::MAX))
+        .map(Duration::from_secs_f64)
+}
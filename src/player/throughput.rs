@@ -0,0 +1,52 @@
+//! Throughput estimate of the fetches, which is reported as `Stats::throughput_kbps` and
+//! CMCD `mtp`; variants are not switched by it.
+
+/// Estimator of the network throughput by the exponentially weighted moving average of fetches.
+#[derive(Debug, Clone)]
+pub struct ThroughputEstimator {
+    estimate_kbps: Option<f64>,
+}
+impl ThroughputEstimator {
+    /// Weight of the latest sample.
+    const ALPHA: f64 = 0.3;
+
+    pub fn new() -> Self {
+        ThroughputEstimator {
+            estimate_kbps: None,
+        }
+    }
+
+    /// Records that `bytes` bytes were fetched in `fetch_duration_ms`.
+    pub fn record(&mut self, bytes: usize, fetch_duration_ms: u32) {
+        if fetch_duration_ms == 0 {
+            return;
+        }
+        self.add_sample(bytes as f64 * 8.0 / f64::from(fetch_duration_ms));
+    }
+
+    /// Records the throughput `kbps` reported by a server, as a sample of its own.
+    pub fn record_hint(&mut self, kbps: u32) {
+        if kbps == 0 {
+            return;
+        }
+        self.add_sample(f64::from(kbps));
+    }
+
+    fn add_sample(&mut self, sample: f64) {
+        self.estimate_kbps = Some(match self.estimate_kbps {
+            None => sample,
+            Some(estimate) => Self::ALPHA * sample + (1.0 - Self::ALPHA) * estimate,
+        });
+    }
+
+    /// Returns the estimated throughput in kbps, if any fetch has been recorded.
+    pub fn estimate_kbps(&self) -> Option<u32> {
+        self.estimate_kbps
+            .map(|kbps| kbps.min(f64::from(u32::MAX)) as u32)
+    }
+}
+impl Default for ThroughputEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,77 @@
+//! Throughput hints of the response headers reported by the host
+//! (see `HlsPlayer::handle_response_headers`).
+//!
+//! - `CMSD-Dynamic` (CTA-5006): the `etp` (estimated throughput in kbps) of the last entry,
+//!   i.e., that of the server closest to the client.
+//! - `Server-Timing`: the durations (`dur` in milliseconds) of the metrics listed by
+//!   `ThroughputHintConfig::server_timing_metrics`, spent by the servers before responding
+//!   (e.g., on cache misses), which do not reflect the throughput of the network.
+use std::collections::BTreeMap;
+
+use super::ThroughputHintConfig;
+
+/// Hints of the response of a fetch.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThroughputHint {
+    pub etp_kbps: Option<u32>,
+
+    /// Time spent by the servers before responding, in milliseconds.
+    pub server_duration_ms: Option<f64>,
+}
+impl ThroughputHint {
+    /// Reads the hints of `headers`, whose names are case-insensitive.
+    pub fn parse(config: &ThroughputHintConfig, headers: &BTreeMap<String, String>) -> Self {
+        let mut hint = ThroughputHint::default();
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("CMSD-Dynamic") {
+                hint.etp_kbps = list_members(value)
+                    .filter_map(|m| parameter(m, "etp"))
+                    .filter_map(|v| v.parse::<f64>().ok())
+                    .filter(|&kbps| kbps > 0.0)
+                    .last()
+                    .map(|kbps| kbps.min(f64::from(u32::MAX)) as u32)
+                    .or(hint.etp_kbps);
+            } else if name.eq_ignore_ascii_case("Server-Timing") {
+                let duration = list_members(value)
+                    .filter(|m| {
+                        let metric = m.split(';').next().unwrap_or("").trim();
+                        config.server_timing_metrics.iter().any(|n| n == metric)
+                    })
+                    .filter_map(|m| parameter(m, "dur"))
+                    .filter_map(|v| v.parse::<f64>().ok())
+                    .filter(|&ms| ms > 0.0)
+                    .sum::<f64>();
+                if duration > 0.0 {
+                    let total = hint.server_duration_ms.unwrap_or(0.0) + duration;
+                    hint.server_duration_ms = Some(total);
+                }
+            }
+        }
+        hint
+    }
+
+    /// Returns `fetch_duration_ms` without the time spent by the servers
+    /// (only if that leaves some time for the transfer).
+    pub fn transfer_duration_ms(&self, fetch_duration_ms: u32) -> u32 {
+        match self.server_duration_ms {
+            Some(ms) if ms < f64::from(fetch_duration_ms) => {
+                (f64::from(fetch_duration_ms) - ms).ceil() as u32
+            }
+            _ => fetch_duration_ms,
+        }
+    }
+}
+
+/// Splits the list `value` of a structured header into its members.
+fn list_members(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(|m| m.trim()).filter(|m| !m.is_empty())
+}
+
+/// Returns the value of the parameter `name` of a list member, without quotes.
+fn parameter<'a>(member: &'a str, name: &str) -> Option<&'a str> {
+    member
+        .split(';')
+        .filter_map(|p| p.split_once('='))
+        .find(|&(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
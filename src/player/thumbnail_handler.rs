@@ -0,0 +1,430 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use url::Url;
+
+use {ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, BufferPool, ByteRange, Event, FailureClass,
+            OutputSegment, PlayerConfig, ResourceKind, SegmentInfo, Stats};
+use super::discontinuity;
+use super::keyframe;
+use super::segment_resources;
+use super::target_duration;
+
+/// Handler that fetches the keyframes of an I-frame playlist (`EXT-X-I-FRAMES-ONLY`)
+/// for scrubbing preview thumbnails (see `HlsPlayer::generate_thumbnails`).
+///
+/// Each keyframe is output as a raw H.264 access unit in the Annex B format,
+/// announced by a preceding `Event::ThumbnailLoaded` giving its time.
+/// Keyframes that cannot be fetched or parsed are skipped.
+/// Encrypted I-frame playlists are not supported.
+#[derive(Debug)]
+pub struct ThumbnailHandler {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    output: VecDeque<OutputSegment>,
+    frames: Vec<Frame>,
+    pending_fetches: VecDeque<Fetch>,
+    in_flight_fetches: HashMap<ActionId, Fetch>,
+    retry_timers: HashMap<ActionId, Fetch>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+    pool: BufferPool,
+    bytes_downloaded: u64,
+    thumbnails: usize,
+    is_finished: bool,
+}
+impl ThumbnailHandler {
+    pub fn new(config: &PlayerConfig, url: Url, m3u8: &str) -> Result<Self> {
+        let (m3u8, mut discontinuity) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        track_assert!(
+            playlist.i_frames_only_tag().is_some(),
+            ErrorKind::InvalidInput,
+            "Not an I-frame playlist: {}",
+            url
+        );
+        // Keyframes are byte ranges within segments that AES-128-CBC encrypts as a whole,
+        // so they cannot be decrypted on their own.
+        track_assert!(
+            !segment_resources::is_encrypted(&playlist),
+            ErrorKind::InvalidInput,
+            "Encrypted I-frame playlists are not supported: {}",
+            url
+        );
+
+        let min_interval = Duration::from_millis(config.thumbnails.min_interval_ms);
+        let mut frames = Vec::new();
+        let mut time = Duration::from_secs(0);
+        let mut last_time = None;
+        let mut byte_range_end = 0;
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            if segment.discontinuity_tag().is_some() {
+                discontinuity += 1;
+            }
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+
+            let duration = segment.inf_tag().duration();
+            let is_selected = last_time.is_none_or(|last| time >= last + min_interval);
+            if is_selected {
+                last_time = Some(time);
+                frames.push(Frame {
+                    url: track!(segment_resources::resolve_url(&url, segment.uri()))?,
+                    range,
+                    seq: first_seq + i as u64,
+                    discontinuity,
+                    time,
+                    duration,
+                });
+            }
+            time += duration;
+        }
+
+        let mut action_factory = ActionFactory::new(0, config);
+        action_factory.set_target_duration(playlist.target_duration_tag().duration());
+        log!(
+            config,
+            Info,
+            "Starts generating thumbnails from {}: frames={}",
+            url,
+            frames.len()
+        );
+        let pending_fetches = (0..frames.len()).map(Fetch::new).collect();
+        let mut this = ThumbnailHandler {
+            config: config.clone(),
+            action_factory,
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            output: VecDeque::new(),
+            frames,
+            pending_fetches,
+            in_flight_fetches: HashMap::new(),
+            retry_timers: HashMap::new(),
+            partial_bodies: HashMap::new(),
+            pool: BufferPool::new(),
+            bytes_downloaded: 0,
+            thumbnails: 0,
+            is_finished: false,
+        };
+        this.request_fetches();
+        Ok(this)
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        self.output.pop_front()
+    }
+
+    pub fn recycle_segment(&mut self, segment: Vec<u8>) {
+        self.pool.give(segment);
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        if let Some(fetch) = self.retry_timers.remove(&action_id) {
+            self.request(fetch);
+        }
+        Ok(())
+    }
+
+    /// Handles the response body of the fetch requested by `action_id`.
+    ///
+    /// The URIs of the playlist are resolved against its URL, so `_final_url` is not used.
+    pub fn handle_data(
+        &mut self,
+        action_id: ActionId,
+        data: &[u8],
+        fetch_duration_ms: u32,
+        _final_url: Option<&Url>,
+    ) -> Result<()> {
+        let fetch = match self.in_flight_fetches.remove(&action_id) {
+            None => return Ok(()),
+            Some(fetch) => fetch,
+        };
+        self.action_factory.record_fetch_success();
+        self.action_factory
+            .record_throughput(data.len(), fetch_duration_ms);
+        self.bytes_downloaded += data.len() as u64;
+
+        let frame = &self.frames[fetch.index];
+        match track!(keyframe::extract(data)) {
+            Err(e) => {
+                log!(self.config, Warn, "Skips the keyframe {}: {}", frame.url, e);
+            }
+            Ok(keyframe) => {
+                self.thumbnails += 1;
+                self.event_queue.push_back(Event::ThumbnailLoaded {
+                    index: fetch.index,
+                    time_ms: frame.time.as_secs_f64() * 1000.0,
+                    duration_ms: frame.duration.as_secs_f64() * 1000.0,
+                    pts_ms: keyframe.pts.map(|pts| pts as f64 / 90.0),
+                    codec: keyframe.codec,
+                    bytes: keyframe.data.len(),
+                });
+                let info = SegmentInfo {
+                    sequence: frame.seq,
+                    discontinuity: frame.discontinuity,
+                    variant: None,
+                    duration_ms: frame.duration.as_secs_f64() * 1000.0,
+                    program_date_time_ms: None,
+                    init_bytes: 0,
+                    media_type: None,
+                    audio_sequence: None,
+                };
+                self.output.push_back(OutputSegment {
+                    data: keyframe.data,
+                    info,
+                });
+            }
+        }
+        self.request_fetches();
+        Ok(())
+    }
+
+    /// Handles a chunk of the response body of the fetch requested by `action_id`.
+    ///
+    /// The body is handled once `handle_data_end` is called.
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) -> Result<()> {
+        if self.in_flight_fetches.contains_key(&action_id) {
+            let pool = &mut self.pool;
+            self.partial_bodies
+                .entry(action_id)
+                .or_insert_with(|| pool.take(chunk.len()))
+                .extend_from_slice(chunk);
+        }
+        Ok(())
+    }
+
+    /// Handles the end of the response body given by `handle_data_chunk`.
+    pub fn handle_data_end(
+        &mut self,
+        action_id: ActionId,
+        fetch_duration_ms: u32,
+        final_url: Option<&Url>,
+    ) -> Result<()> {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        let result = track!(self.handle_data(action_id, &body, fetch_duration_ms, final_url));
+        self.pool.give(body);
+        result
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    ///
+    /// Transient failures are retried according to `PlayerConfig::segment_retry`.
+    pub fn handle_error(&mut self, action_id: ActionId, status: u16) -> Result<()> {
+        let class = FailureClass::from_status(status);
+        self.handle_failure(action_id, class, format!("status={}", status));
+        Ok(())
+    }
+
+    pub fn handle_fetch_timeout(&mut self, action_id: ActionId) -> Result<()> {
+        let description = "timed out".to_owned();
+        self.handle_failure(action_id, FailureClass::Transient, description);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            throughput_kbps: self.action_factory.throughput_kbps(),
+            bytes_downloaded: self.bytes_downloaded,
+            segments_downloaded: self.thumbnails as u64,
+            ..Stats::default()
+        }
+    }
+
+    /// Cancels all the pending fetches and timers.
+    ///
+    /// Fetches that have already been handed to the host are aborted by `Action::Abort`.
+    pub fn stop(&mut self) {
+        self.is_finished = true;
+        self.action_queue.clear();
+        self.pending_fetches.clear();
+        self.retry_timers.clear();
+        self.partial_bodies.clear();
+        let in_flight_fetches: Vec<_> = self.in_flight_fetches.drain().collect();
+        for (action_id, _) in in_flight_fetches {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: String) {
+        self.partial_bodies.remove(&action_id);
+        let mut fetch = match self.in_flight_fetches.remove(&action_id) {
+            None => return,
+            Some(fetch) => fetch,
+        };
+        log!(self.config, Warn, "Fetch failed: action_id={:?}, {}", action_id, description);
+        let failover = self.action_factory.record_fetch_failure();
+        self.event_queue.extend(failover);
+
+        fetch.attempts += 1;
+        if !class.is_permanent() && fetch.attempts < self.config.segment_retry.max_attempts {
+            let delay = self.config.segment_retry.delay(fetch.attempts);
+            let action = self.action_factory.set_timeout(delay);
+            self.retry_timers.insert(action.id(), fetch);
+            self.action_queue.push_back(action);
+        } else {
+            let reason = format!(
+                "Skips the keyframe {}: {}, class={:?}, attempts={}",
+                self.frames[fetch.index].url, description, class, fetch.attempts
+            );
+            log!(self.config, Warn, "{}", reason);
+            self.event_queue.push_back(Event::Error {
+                fatal: false,
+                reason,
+                history: Vec::new(),
+            });
+        }
+        self.request_fetches();
+    }
+
+    /// Requests the pending fetches up to `ThumbnailConfig::max_concurrent_fetches`.
+    fn request_fetches(&mut self) {
+        let max = cmp::max(self.config.thumbnails.max_concurrent_fetches, 1);
+        while !self.is_finished {
+            if self.in_flight_fetches.len() + self.retry_timers.len() >= max {
+                break;
+            }
+            match self.pending_fetches.pop_front() {
+                None => break,
+                Some(fetch) => self.request(fetch),
+            }
+        }
+        let is_idle = self.in_flight_fetches.is_empty() && self.retry_timers.is_empty();
+        if !self.is_finished && is_idle && self.pending_fetches.is_empty() {
+            self.is_finished = true;
+            log!(
+                self.config,
+                Info,
+                "Generated {} of {} thumbnails",
+                self.thumbnails,
+                self.frames.len()
+            );
+            self.event_queue.push_back(Event::ThumbnailsCompleted {
+                thumbnails: self.thumbnails,
+            });
+        }
+    }
+
+    fn request(&mut self, fetch: Fetch) {
+        let frame = &self.frames[fetch.index];
+        let action = self.action_factory
+            .fetch_data(frame.url.clone(), ResourceKind::Segment, frame.range);
+        self.in_flight_fetches.insert(action.id(), fetch);
+        self.action_queue.push_back(action);
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    url: Url,
+    range: Option<ByteRange>,
+    seq: u64,
+    discontinuity: u64,
+
+    /// Start of the frame on the media timeline (the sum of the preceding durations).
+    time: Duration,
+
+    duration: Duration,
+}
+
+#[derive(Debug)]
+struct Fetch {
+    /// Index of the frame.
+    index: usize,
+
+    attempts: u32,
+}
+impl Fetch {
+    fn new(index: usize) -> Self {
+        Fetch {
+            index,
+            attempts: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ts_fixture;
+
+    /// I-frame playlist of a keyframe every second.
+    const I_FRAMES: &str = "#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-TARGETDURATION:1\n\
+                            #EXT-X-I-FRAMES-ONLY\n\
+                            #EXTINF:1,\nk0.ts\n#EXTINF:1,\nk1.ts\n#EXTINF:1,\nk2.ts\n\
+                            #EXTINF:1,\nk3.ts\n#EXTINF:1,\nk4.ts\n#EXT-X-ENDLIST\n";
+
+    /// Responds to the fetches of `handler` with the keyframes until it has finished,
+    /// and returns the fetched paths.
+    fn run(handler: &mut ThumbnailHandler) -> Vec<String> {
+        let mut paths = Vec::new();
+        while let Some(action) = handler.next_action() {
+            if let Action::FetchData { action_id, url, .. } = action {
+                let seq = url.path()[2..].trim_end_matches(".ts").parse::<u64>().unwrap();
+                let ts = ts_fixture::segment(seq * 90_000, 1);
+                handler.handle_data(action_id, &ts, 10, None).unwrap();
+                paths.push(url.path().to_owned());
+            }
+        }
+        paths
+    }
+
+    #[test]
+    fn keyframes_are_thinned_by_the_min_interval() {
+        let mut config = PlayerConfig::default();
+        config.thumbnails.min_interval_ms = 1500;
+        let url = Url::parse("http://x/i.m3u8").unwrap();
+        let mut handler = ThumbnailHandler::new(&config, url, I_FRAMES).unwrap();
+        assert_eq!(run(&mut handler), ["/k0.ts", "/k2.ts", "/k4.ts"]);
+
+        let mut loaded = Vec::new();
+        while let Some(event) = handler.next_event() {
+            match event {
+                Event::ThumbnailLoaded {
+                    index,
+                    time_ms,
+                    pts_ms,
+                    ..
+                } => loaded.push((index, time_ms, pts_ms)),
+                Event::ThumbnailsCompleted { thumbnails } => assert_eq!(thumbnails, 3),
+                event => panic!("{:?}", event),
+            }
+        }
+        assert_eq!(
+            loaded,
+            [
+                (0, 0.0, Some(0.0)),
+                (1, 2000.0, Some(2000.0)),
+                (2, 4000.0, Some(4000.0)),
+            ]
+        );
+        let segment = handler.next_segment().unwrap();
+        assert_eq!(segment.data, ts_fixture::access_unit(true));
+        assert_eq!(segment.info.sequence, 0);
+    }
+
+    #[test]
+    fn unparsable_keyframes_are_skipped() {
+        let url = Url::parse("http://x/i.m3u8").unwrap();
+        let mut handler = ThumbnailHandler::new(&PlayerConfig::default(), url, I_FRAMES).unwrap();
+        let action_id = match handler.next_action() {
+            Some(Action::FetchData { action_id, .. }) => action_id,
+            action => panic!("{:?}", action),
+        };
+        handler.handle_data(action_id, &[0x47; 188], 10, None).unwrap();
+        assert!(handler.next_segment().is_none());
+        run(&mut handler);
+        assert_eq!(handler.stats().segments_downloaded, 4);
+    }
+}
@@ -0,0 +1,69 @@
+//! Timeline of VOD media playlists, for laying out seek bars (see `HlsPlayer::timeline`).
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+
+/// Segments of a VOD media playlist laid out from its start.
+#[derive(Debug, Clone, Serialize)]
+pub struct Timeline {
+    /// Sum of the `EXTINF` durations of the segments.
+    pub duration_ms: f64,
+
+    pub segments: Vec<TimelineSegment>,
+}
+impl Timeline {
+    /// Makes the timeline of `playlist`, whose first segment has `media_sequence`.
+    pub fn new(playlist: &MediaPlaylist, media_sequence: u64) -> Self {
+        let mut start = Duration::from_secs(0);
+        let segments = playlist
+            .segments()
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let duration = s.inf_tag().duration();
+                let segment = TimelineSegment {
+                    sequence: media_sequence + i as u64,
+                    start_ms: to_millis(start),
+                    duration_ms: to_millis(duration),
+                };
+                start += duration;
+                segment
+            })
+            .collect();
+        Timeline {
+            duration_ms: to_millis(start),
+            segments,
+        }
+    }
+
+    /// Replaces the duration of the segment `sequence` by `duration`, shifting the following
+    /// segments, and returns `false` if it is not in the timeline.
+    pub fn adjust(&mut self, sequence: u64, duration: Duration) -> bool {
+        let index = match self.segments.iter().position(|s| s.sequence == sequence) {
+            None => return false,
+            Some(i) => i,
+        };
+        let delta = to_millis(duration) - self.segments[index].duration_ms;
+        self.segments[index].duration_ms += delta;
+        for segment in &mut self.segments[index + 1..] {
+            segment.start_ms += delta;
+        }
+        self.duration_ms += delta;
+        true
+    }
+}
+
+/// Segment of `Timeline`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineSegment {
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+
+    /// Total duration of the preceding segments.
+    pub start_ms: f64,
+
+    pub duration_ms: f64,
+}
+
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
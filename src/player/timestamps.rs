@@ -0,0 +1,131 @@
+//! Timestamps of the PES packets of TS segments, which the remuxer does not keep
+//! (see `av_sync`) and from which the actual durations of segments are measured,
+//! and the timed metadata of the segments, which the remuxer does not take (see `emsg`).
+use std::cmp;
+use std::time::Duration;
+use mpeg2ts::pes::{PesPacketReader, ReadPesPacket};
+use mpeg2ts::ts::ReadTsPacket;
+
+use Result;
+
+pub const PTS_TIMESCALE: u64 = 90_000;
+
+/// Number of the samples of an AAC frame.
+pub const SAMPLES_PER_FRAME: u64 = 1024;
+
+/// PTS wrap-around period (33 bits).
+const PTS_PERIOD: u64 = 1 << 33;
+
+/// Header of ID3v2 tags.
+const ID3_MAGIC: &[u8] = b"ID3";
+
+/// Timestamps of the PES packets of a TS segment.
+#[derive(Debug, Default)]
+pub struct StreamTimestamps {
+    /// PTSs of the video packets in the order of their packets.
+    video: Vec<u64>,
+
+    /// PTS and number of the ADTS frames of each audio PES packet.
+    audio: Vec<(u64, usize)>,
+
+    /// PTS and ID3 tags of the timed metadata packets.
+    metadata: Vec<(u64, Vec<u8>)>,
+}
+impl StreamTimestamps {
+    /// Reads the timestamps of the packets of `reader`, which are remuxed if given to the remuxer
+    /// (except for the timed metadata).
+    pub fn read<R: ReadTsPacket>(reader: R) -> Result<Self> {
+        let mut timestamps = StreamTimestamps::default();
+        let mut reader = PesPacketReader::new(reader);
+        while let Some(pes) = track!(reader.read_pes_packet())? {
+            let pts = match pes.header.pts {
+                None => continue,
+                Some(pts) => pts.as_u64(),
+            };
+            if pes.header.stream_id.is_video() {
+                timestamps.video.push(pts);
+            } else if pes.header.stream_id.is_audio() {
+                timestamps.audio.push((pts, adts_frames(&pes.data)));
+            } else if pes.data.starts_with(ID3_MAGIC) {
+                timestamps.metadata.push((pts, pes.data));
+            }
+        }
+        Ok(timestamps)
+    }
+
+    /// Earliest video PTS.
+    pub fn video_start(&self) -> Option<u64> {
+        let first = *self.video.first()?;
+        self.video.iter().min_by_key(|&&pts| elapsed_signed(first, pts)).cloned()
+    }
+
+    /// PTS and number of the frames of each audio packet.
+    pub fn audio(&self) -> &[(u64, usize)] {
+        &self.audio
+    }
+
+    /// PTS and ID3 tags of the timed metadata packets.
+    pub fn metadata(&self) -> &[(u64, Vec<u8>)] {
+        &self.metadata
+    }
+
+    /// Earliest PTS of the media, by its video if any.
+    pub fn media_start(&self) -> Option<u64> {
+        self.video_start().or_else(|| self.audio.first().map(|&(pts, _)| pts))
+    }
+
+    /// Returns the presentation duration of the segment: from its first frame to the end of
+    /// its last one, by its video if any, and otherwise by its audio sampled at `sample_rate`.
+    ///
+    /// The duration of the last video frame is taken to be the shortest PTS interval.
+    pub fn duration(&self, sample_rate: Option<u32>) -> Option<Duration> {
+        let ticks = if let Some(start) = self.video_start() {
+            let mut times = self.video
+                .iter()
+                .map(|&pts| elapsed(start, pts))
+                .collect::<Vec<_>>();
+            times.sort();
+            let frame = times.windows(2).map(|w| w[1] - w[0]).filter(|&d| d > 0).min()?;
+            times[times.len() - 1] + frame
+        } else {
+            let sample_rate = u64::from(sample_rate.filter(|&r| r > 0)?);
+            let &(start, _) = self.audio.first()?;
+            let &(last, frames) = self.audio.last()?;
+            elapsed(start, last) + frames as u64 * SAMPLES_PER_FRAME * PTS_TIMESCALE / sample_rate
+        };
+        let micros = ticks * 1_000_000 / PTS_TIMESCALE;
+        Some(Duration::from_micros(micros))
+    }
+}
+
+/// Returns the signed distance from `from` to `to`, taking the shorter way around
+/// the wrap-around of the PTS.
+pub fn elapsed_signed(from: u64, to: u64) -> i64 {
+    let d = to.wrapping_sub(from) % PTS_PERIOD;
+    if d > PTS_PERIOD / 2 {
+        d as i64 - PTS_PERIOD as i64
+    } else {
+        d as i64
+    }
+}
+
+/// Returns the distance from `from` to `to`, or zero if `to` precedes `from`.
+fn elapsed(from: u64, to: u64) -> u64 {
+    cmp::max(elapsed_signed(from, to), 0) as u64
+}
+
+/// Returns the number of the ADTS frames in the payload of a PES packet.
+fn adts_frames(mut data: &[u8]) -> usize {
+    let mut frames = 0;
+    while data.len() >= 7 && data[0] == 0xFF && data[1] & 0xF0 == 0xF0 {
+        let length = (usize::from(data[3] & 0x03) << 11)
+            | (usize::from(data[4]) << 3)
+            | (usize::from(data[5]) >> 5);
+        if length < 7 || length > data.len() {
+            break;
+        }
+        frames += 1;
+        data = &data[length..];
+    }
+    frames
+}
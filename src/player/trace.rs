@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use url::Url;
+use url_serde;
+
+use super::{Action, ActionId, Hooks, PlayerConfig, TraceConfig, VariantSwitch};
+
+/// Record of a state transition of the player.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRecord {
+    /// Wall-clock time in milliseconds since the UNIX epoch (`None` without `Hooks::clock`).
+    pub timestamp_ms: Option<f64>,
+
+    #[serde(flatten)]
+    pub entry: TraceEntry,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum TraceEntry {
+    PlaylistParsed {
+        #[serde(with = "url_serde")] url: Url,
+        media_sequence: u64,
+        segments: usize,
+        is_live: bool,
+    },
+    SegmentScheduled {
+        sequence: u64,
+        #[serde(with = "url_serde")] url: Url,
+        is_init: bool,
+    },
+    ActionEmitted { action: Action },
+    FragmentProduced {
+        sequence: u64,
+        is_init: bool,
+        bytes: usize,
+    },
+    FetchFailed {
+        action_id: ActionId,
+        reason: String,
+    },
+    VariantSwitched { switch: VariantSwitch },
+}
+
+/// Bounded log of `TraceRecord`s (see `PlayerConfig::trace`).
+///
+/// Clones of a trace refer to the same records. When it is full, the oldest records are dropped,
+/// so it works as a ring buffer of the recent history while the full trace is disabled.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    config: TraceConfig,
+    hooks: Hooks,
+    records: Rc<RefCell<VecDeque<TraceRecord>>>,
+}
+impl Trace {
+    pub fn new(config: &PlayerConfig) -> Self {
+        Trace {
+            config: config.trace.clone(),
+            hooks: config.hooks.clone(),
+            records: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Records `entry`.
+    ///
+    /// `entry` is evaluated lazily, so that it costs nothing if no records are retained.
+    pub fn record<F>(&self, entry: F)
+    where
+        F: FnOnce() -> TraceEntry,
+    {
+        let capacity = self.config.capacity();
+        if capacity == 0 {
+            return;
+        }
+        let timestamp_ms = self.hooks.now().map(|t| t.as_secs_f64() * 1000.0);
+        let mut records = self.records.borrow_mut();
+        while records.len() >= capacity {
+            records.pop_front();
+        }
+        records.push_back(TraceRecord {
+            timestamp_ms,
+            entry: entry(),
+        });
+    }
+
+    /// Returns the records in chronological order.
+    pub fn records(&self) -> Vec<TraceRecord> {
+        self.records.borrow().iter().cloned().collect()
+    }
+
+    /// Returns up to `TraceConfig::history_size` latest records.
+    pub fn history(&self) -> Vec<TraceRecord> {
+        let records = self.records.borrow();
+        let skip = records.len().saturating_sub(self.config.history_size);
+        records.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.records.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,152 @@
+//! Selection of the elementary streams of TS segments which are remuxed.
+//!
+//! The remuxer takes one H.264 and one AAC stream, but interleaves the packets of every audio
+//! stream of the program (e.g., one per language) into its single audio track, and rejects
+//! other codecs. The packets of the audio and video streams which are not selected are
+//! dropped before remuxing, and so are those of timed metadata (ID3) streams, which
+//! the remuxer rejects (see `timestamps::StreamTimestamps::metadata`).
+use mpeg2ts;
+use mpeg2ts::es::StreamType;
+use mpeg2ts::ts::{ReadTsPacket, TsPacket, TsPayload};
+use mpeg2ts::ts::payload::Pmt;
+
+use super::TrackConfig;
+
+/// Streams selected from the PMT of a segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectedTracks {
+    pub video_pid: Option<u16>,
+    pub audio_pid: Option<u16>,
+
+    /// PIDs of the audio and video streams which are dropped.
+    pub dropped_pids: Vec<u16>,
+
+    /// PIDs of the timed metadata streams.
+    pub metadata_pids: Vec<u16>,
+
+    /// `true` if a stream requested by `TrackConfig` is not in the program,
+    /// in which case the first one of its kind is selected instead.
+    pub is_fallback: bool,
+}
+
+/// TS packet reader which drops the packets of the unselected streams.
+///
+/// The selection made from the last PMT is stored into `selected`.
+#[derive(Debug)]
+pub struct TrackFilter<'a, R> {
+    inner: R,
+    config: &'a TrackConfig,
+    selected: &'a mut Option<SelectedTracks>,
+    keeps_metadata: bool,
+}
+impl<'a, R: ReadTsPacket> TrackFilter<'a, R> {
+    pub fn new(
+        inner: R,
+        config: &'a TrackConfig,
+        selected: &'a mut Option<SelectedTracks>,
+    ) -> Self {
+        TrackFilter {
+            inner,
+            config,
+            selected,
+            keeps_metadata: false,
+        }
+    }
+
+    /// Keeps the packets of the timed metadata streams, which are not given to the remuxer.
+    pub fn keep_metadata(mut self) -> Self {
+        self.keeps_metadata = true;
+        self
+    }
+}
+impl<'a, R: ReadTsPacket> ReadTsPacket for TrackFilter<'a, R> {
+    fn read_ts_packet(&mut self) -> mpeg2ts::Result<Option<TsPacket>> {
+        while let Some(packet) = track!(self.inner.read_ts_packet())? {
+            if let Some(TsPayload::Pmt(ref pmt)) = packet.payload {
+                *self.selected = Some(select(self.config, pmt));
+            }
+            let pid = packet.header.pid.as_u16();
+            let is_dropped = self.selected.as_ref().is_some_and(|s| {
+                s.dropped_pids.contains(&pid)
+                    || (!self.keeps_metadata && s.metadata_pids.contains(&pid))
+            });
+            if !is_dropped {
+                return Ok(Some(packet));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn select(config: &TrackConfig, pmt: &Pmt) -> SelectedTracks {
+    let pids = |stream_type| {
+        pmt.table
+            .iter()
+            .filter(|es| es.stream_type == stream_type)
+            .map(|es| es.elementary_pid.as_u16())
+            .collect::<Vec<_>>()
+    };
+    let videos = pids(StreamType::H264);
+    let audios = pids(StreamType::AdtsAac);
+
+    let requested_video = config
+        .video_pid
+        .map(|pid| Some(pid).filter(|pid| videos.contains(pid)));
+    let requested_audio = match (config.audio_pid, config.audio_index) {
+        (Some(pid), _) => Some(Some(pid).filter(|pid| audios.contains(pid))),
+        (None, Some(index)) => Some(audios.get(index).cloned()),
+        (None, None) => None,
+    };
+    let is_fallback = requested_video == Some(None) || requested_audio == Some(None);
+    let video_pid = requested_video
+        .and_then(|pid| pid)
+        .or_else(|| videos.first().cloned());
+    let audio_pid = requested_audio
+        .and_then(|pid| pid)
+        .or_else(|| audios.first().cloned());
+
+    let dropped_pids = pmt.table
+        .iter()
+        .filter(|es| is_audio_or_video(es.stream_type))
+        .map(|es| es.elementary_pid.as_u16())
+        .filter(|&pid| Some(pid) != video_pid && Some(pid) != audio_pid)
+        .collect();
+    SelectedTracks {
+        video_pid,
+        audio_pid,
+        dropped_pids,
+        metadata_pids: pids(StreamType::PacketizedMetadata),
+        is_fallback,
+    }
+}
+
+fn is_audio_or_video(stream_type: StreamType) -> bool {
+    use self::StreamType::*;
+    matches!(
+        stream_type,
+        Mpeg1Video
+            | Mpeg2Video
+            | Mpeg1Audio
+            | Mpeg2HalvedSampleRateAudio
+            | AdtsAac
+            | Mpeg4H263BasedVideo
+            | Mpeg4LoasMultiFormatFramedAudio
+            | H264
+            | H265
+            | ChineseVideoStandard
+            | PcmAudio
+            | DolbyDigitalUpToSixChannelAudio
+            | Dts6ChannelAudio
+            | DolbyTrueHdLosslessAudio
+            | DolbyDigitalPlusUpTo16ChannelAudio
+            | Dts8ChannelAudio
+            | Dts8ChannelLosslessAudio
+            | DolbyDigitalPlusUpTo16ChannelAudioForAtsc
+            | DolbyDigitalUpToSixChannelAudioWithAes128Cbc
+            | DolbyDigitalPlusUpToSixChannelAudioWithAes128Cbc
+            | AdtsAacWithAes128Cbc
+            | UltraHdVideo
+            | H264WithAes128Cbc
+            | MicrosoftWindowsMediaVideo9
+    )
+}
@@ -0,0 +1,635 @@
+//! Fast-forward and rewind previews made of the keyframes of I-frame playlists.
+//!
+//! Encrypted I-frame playlists are not supported.
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+use hls_m3u8::MediaPlaylist;
+use mse_fmp4::fmp4::{AvcConfigurationBox, AvcSampleEntry, InitializationSegment, MediaDataBox,
+                     MediaSegment, Mp4Box, Sample, SampleEntry, SampleFlags, TrackBox,
+                     TrackExtendsBox, TrackFragmentBox};
+use mse_fmp4::avc::AvcDecoderConfigurationRecord;
+use mse_fmp4::io::WriteTo;
+use url::Url;
+
+use {Error, ErrorKind, Result};
+use super::{Action, ActionFactory, ActionId, BufferPool, ByteRange, Event, FailureClass,
+            OutputSegment, PlayerConfig, ResourceKind, SegmentInfo, StreamId};
+use super::discontinuity;
+use super::keyframe::{self, Keyframe};
+use super::media_type::{self, MediaType};
+use super::segment_resources;
+use super::target_duration;
+use super::text;
+
+/// Stream ID of the actions of `TrickPlayHandler`, distinct from those of media playlists.
+pub const TRICK_PLAY_STREAM_ID: StreamId = 0xFD;
+
+/// Timescale of the produced fragments (the MPEG-2 TS clock).
+const TIMESCALE: u32 = 90_000;
+
+/// Handler that fetches the keyframes of an I-frame playlist (`EXT-X-I-FRAMES-ONLY`)
+/// for trick play (see `HlsPlayer::start_trick_play`).
+///
+/// Starting at a position of the media timeline, every shown keyframe advances it by
+/// `rate / TrickPlayConfig::frames_per_second` seconds (backwards if `rate` is negative).
+/// Keyframes are requested at `frames_per_second`, with up to `max_concurrent_fetches` in flight,
+/// and output in order as video-only fMP4 fragments of a single sample lasting one frame
+/// period. Like the remuxed segments, their decode times start at zero, so they are meant for
+/// MSE in the `sequence` mode. Keyframes that cannot be fetched or parsed are skipped.
+#[derive(Debug)]
+pub struct TrickPlayHandler {
+    config: PlayerConfig,
+    action_factory: ActionFactory,
+    action_queue: VecDeque<Action>,
+    event_queue: VecDeque<Event>,
+    output: VecDeque<OutputSegment>,
+    url: Url,
+    rate: f64,
+    position: Duration,
+    fetch_playlist_action_id: Option<ActionId>,
+    playlist_failures: u32,
+    reload_action_id: Option<ActionId>,
+    frames: Vec<Frame>,
+
+    /// Index of the next frame to be requested.
+    next_frame: usize,
+
+    tick_action_id: Option<ActionId>,
+    in_flight_fetches: HashMap<ActionId, usize>,
+    partial_bodies: HashMap<ActionId, Vec<u8>>,
+
+    /// Fetched (or skipped) frames waiting for the preceding ones.
+    fetched_frames: BTreeMap<usize, Option<Keyframe>>,
+
+    /// Index of the next frame to be output.
+    next_output: usize,
+
+    /// SPS of the last initialization segment.
+    sps: Option<Vec<u8>>,
+
+    sequence_number: u32,
+    frames_output: usize,
+    pool: BufferPool,
+    is_finished: bool,
+}
+impl TrickPlayHandler {
+    /// Makes a handler that starts by fetching the I-frame playlist `url`.
+    pub fn new(config: &PlayerConfig, url: Url, rate: f64, position: Duration) -> Self {
+        log!(
+            config,
+            Info,
+            "Starts the trick play from {}: rate={}, position={:?}",
+            url,
+            rate,
+            position
+        );
+        let mut handler = TrickPlayHandler {
+            config: config.clone(),
+            action_factory: ActionFactory::new(TRICK_PLAY_STREAM_ID, config),
+            action_queue: VecDeque::new(),
+            event_queue: VecDeque::new(),
+            output: VecDeque::new(),
+            url,
+            rate,
+            position,
+            fetch_playlist_action_id: None,
+            playlist_failures: 0,
+            reload_action_id: None,
+            frames: Vec::new(),
+            next_frame: 0,
+            tick_action_id: None,
+            in_flight_fetches: HashMap::new(),
+            partial_bodies: HashMap::new(),
+            fetched_frames: BTreeMap::new(),
+            next_output: 0,
+            sps: None,
+            sequence_number: 0,
+            frames_output: 0,
+            pool: BufferPool::new(),
+            is_finished: false,
+        };
+        handler.request_playlist();
+        handler
+    }
+
+    pub fn is_own_action(action_id: ActionId) -> bool {
+        action_id.media_playlist_id() == TRICK_PLAY_STREAM_ID
+    }
+
+    pub fn next_action(&mut self) -> Option<Action> {
+        self.action_queue.pop_front()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event_queue.pop_front()
+    }
+
+    pub fn next_segment(&mut self) -> Option<OutputSegment> {
+        self.output.pop_front()
+    }
+
+    pub fn handle_timeout(&mut self, action_id: ActionId) {
+        if self.reload_action_id == Some(action_id) {
+            self.reload_action_id = None;
+            self.request_playlist();
+        } else if self.tick_action_id == Some(action_id) {
+            self.tick_action_id = None;
+            self.request_frame();
+        }
+    }
+
+    pub fn handle_data(&mut self, action_id: ActionId, data: &[u8], fetch_duration_ms: u32) {
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let result = self.handle_playlist(&text::decode(data));
+            if let Err(e) = result {
+                self.handle_playlist_failure(FailureClass::Permanent, e.to_string());
+            }
+            return;
+        }
+        let index = match self.in_flight_fetches.remove(&action_id) {
+            None => return,
+            Some(index) => index,
+        };
+        self.action_factory.record_fetch_success();
+        self.action_factory
+            .record_throughput(data.len(), fetch_duration_ms);
+        let keyframe = match track!(keyframe::extract(data)) {
+            Err(e) => {
+                log!(self.config, Warn, "Skips the keyframe {}: {}", self.frames[index].url, e);
+                None
+            }
+            Ok(keyframe) => Some(keyframe),
+        };
+        self.fetched_frames.insert(index, keyframe);
+        self.flush();
+    }
+
+    pub fn handle_data_chunk(&mut self, action_id: ActionId, chunk: &[u8]) {
+        let pool = &mut self.pool;
+        self.partial_bodies
+            .entry(action_id)
+            .or_insert_with(|| pool.take(chunk.len()))
+            .extend_from_slice(chunk);
+    }
+
+    pub fn handle_data_end(&mut self, action_id: ActionId, fetch_duration_ms: u32) {
+        let body = self.partial_bodies.remove(&action_id).unwrap_or_default();
+        self.handle_data(action_id, &body, fetch_duration_ms);
+        self.pool.give(body);
+    }
+
+    /// Handles the failure of the fetch requested by `action_id`.
+    ///
+    /// Keyframes are not retried, since the preview moves on in the meantime.
+    pub fn handle_failure(&mut self, action_id: ActionId, class: FailureClass, description: &str) {
+        self.partial_bodies.remove(&action_id);
+        if self.fetch_playlist_action_id == Some(action_id) {
+            self.fetch_playlist_action_id = None;
+            let reason = format!(
+                "Cannot fetch the I-frame playlist {}: {}",
+                self.url, description
+            );
+            self.handle_playlist_failure(class, reason);
+        } else if let Some(index) = self.in_flight_fetches.remove(&action_id) {
+            log!(
+                self.config,
+                Warn,
+                "Skips the keyframe {}: {}, class={:?}",
+                self.frames[index].url,
+                description,
+                class
+            );
+            let failover = self.action_factory.record_fetch_failure();
+            self.event_queue.extend(failover);
+            self.fetched_frames.insert(index, None);
+            self.flush();
+        }
+    }
+
+    /// Cancels all the pending fetches and timers, and discards the fragments not yet taken.
+    pub fn stop(&mut self) {
+        self.is_finished = true;
+        self.action_queue.clear();
+        self.output.clear();
+        self.fetched_frames.clear();
+        self.partial_bodies.clear();
+        self.reload_action_id = None;
+        self.tick_action_id = None;
+        let action_ids = self.fetch_playlist_action_id
+            .take()
+            .into_iter()
+            .chain(self.in_flight_fetches.drain().map(|(id, _)| id))
+            .collect::<Vec<_>>();
+        for action_id in action_ids {
+            self.action_queue.push_back(Action::Abort { action_id });
+        }
+    }
+
+    fn request_playlist(&mut self) {
+        let action = self.action_factory
+            .fetch_data(self.url.clone(), ResourceKind::Playlist, None);
+        self.fetch_playlist_action_id = Some(action.id());
+        self.action_queue.push_back(action);
+    }
+
+    fn handle_playlist(&mut self, m3u8: &str) -> Result<()> {
+        let (m3u8, mut discontinuity) = discontinuity::split_sequence(m3u8);
+        let (m3u8, _) = target_duration::repair(&m3u8);
+        let playlist: MediaPlaylist = track!(m3u8.parse())?;
+        track_assert!(
+            playlist.i_frames_only_tag().is_some(),
+            ErrorKind::InvalidInput,
+            "Not an I-frame playlist: {}",
+            self.url
+        );
+        // Keyframes are byte ranges within segments that AES-128-CBC encrypts as a whole,
+        // so they cannot be decrypted on their own.
+        track_assert!(
+            !segment_resources::is_encrypted(&playlist),
+            ErrorKind::InvalidInput,
+            "Encrypted I-frame playlists are not supported: {}",
+            self.url
+        );
+        self.action_factory
+            .set_target_duration(playlist.target_duration_tag().duration());
+
+        let mut frames = Vec::new();
+        let mut time = Duration::from_secs(0);
+        let mut byte_range_end = 0;
+        let first_seq = playlist.media_sequence_tag().map_or(0, |t| t.seq_num());
+        for (i, segment) in playlist.segments().iter().enumerate() {
+            if segment.discontinuity_tag().is_some() {
+                discontinuity += 1;
+            }
+            let range = segment_resources::resolve_byte_range(segment, &mut byte_range_end);
+            frames.push(Frame {
+                url: track!(segment_resources::resolve_url(&self.url, segment.uri()))?,
+                range,
+                seq: first_seq + i as u64,
+                discontinuity,
+                time,
+            });
+            time += segment.inf_tag().duration();
+        }
+        let step = self.rate / self.frames_per_second();
+        self.frames = select_frames(frames, time, self.position, step);
+        log!(
+            self.config,
+            Info,
+            "Selected {} keyframes of the I-frame playlist {}",
+            self.frames.len(),
+            self.url
+        );
+        if self.frames.is_empty() {
+            self.finish();
+        }
+        self.request_frame();
+        Ok(())
+    }
+
+    fn handle_playlist_failure(&mut self, class: FailureClass, reason: String) {
+        self.playlist_failures += 1;
+        if !class.is_permanent() && self.playlist_failures < self.config.playlist_retry.max_attempts
+        {
+            let delay = self.config.playlist_retry.delay(self.playlist_failures);
+            let action = self.action_factory.set_timeout(delay);
+            self.reload_action_id = Some(action.id());
+            self.action_queue.push_back(action);
+            return;
+        }
+        log!(self.config, Warn, "{}", reason);
+        self.event_queue.push_back(Event::Error {
+            fatal: false,
+            reason,
+            history: Vec::new(),
+        });
+        self.finish();
+    }
+
+    fn frames_per_second(&self) -> f64 {
+        self.config.trick_play.frames_per_second.max(0.1)
+    }
+
+    /// Requests the next frame unless `TrickPlayConfig::max_concurrent_fetches` are in flight,
+    /// and schedules the next request after a frame period.
+    fn request_frame(&mut self) {
+        if self.is_finished || self.next_frame >= self.frames.len() {
+            return;
+        }
+        let max = cmp::max(self.config.trick_play.max_concurrent_fetches, 1);
+        if self.in_flight_fetches.len() < max {
+            let frame = &self.frames[self.next_frame];
+            let action = self.action_factory
+                .fetch_data(frame.url.clone(), ResourceKind::Segment, frame.range);
+            self.in_flight_fetches.insert(action.id(), self.next_frame);
+            self.action_queue.push_back(action);
+            self.next_frame += 1;
+        }
+        if self.next_frame < self.frames.len() {
+            let period = Duration::from_secs_f64(1.0 / self.frames_per_second());
+            let action = self.action_factory.set_timeout(period);
+            self.tick_action_id = Some(action.id());
+            self.action_queue.push_back(action);
+        }
+    }
+
+    /// Outputs the fetched frames that follow the already output ones.
+    fn flush(&mut self) {
+        while let Some(keyframe) = self.fetched_frames.remove(&self.next_output) {
+            let index = self.next_output;
+            self.next_output += 1;
+            let keyframe = match keyframe {
+                None => continue,
+                Some(keyframe) => keyframe,
+            };
+            match track!(self.make_fragment(&keyframe)) {
+                Err(e) => {
+                    log!(self.config, Warn, "Skips the keyframe {}: {}", self.frames[index].url, e);
+                }
+                Ok((fragment, init_bytes, media_type)) => {
+                    self.frames_output += 1;
+                    let frame = &self.frames[index];
+                    self.event_queue.push_back(Event::TrickPlayFrameLoaded {
+                        time_ms: frame.time.as_secs_f64() * 1000.0,
+                        pts_ms: keyframe.pts.map(|pts| pts as f64 / 90.0),
+                        bytes: fragment.len(),
+                    });
+                    let info = SegmentInfo {
+                        sequence: frame.seq,
+                        discontinuity: frame.discontinuity,
+                        variant: None,
+                        duration_ms: 1000.0 / self.frames_per_second(),
+                        program_date_time_ms: None,
+                        init_bytes,
+                        media_type,
+                        audio_sequence: None,
+                    };
+                    self.output.push_back(OutputSegment {
+                        data: fragment,
+                        info,
+                    });
+                }
+            }
+        }
+        if self.next_output >= self.frames.len() {
+            self.finish();
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.is_finished {
+            return;
+        }
+        self.is_finished = true;
+        log!(
+            self.config,
+            Info,
+            "Completed the trick play: {} of {} keyframes",
+            self.frames_output,
+            self.frames.len()
+        );
+        self.event_queue.push_back(Event::TrickPlayCompleted {
+            frames: self.frames_output,
+        });
+    }
+
+    /// Makes the fragment of `keyframe`, preceded by an initialization segment
+    /// if it is the first one or its SPS has changed, and returns it along with
+    /// the size and type of the initialization segment.
+    fn make_fragment(
+        &mut self,
+        keyframe: &Keyframe,
+    ) -> Result<(Vec<u8>, usize, Option<MediaType>)> {
+        let nal_units = keyframe::nal_units(&keyframe.data);
+        let find = |nal_unit_type| nal_units.iter().find(|n| n[0] & 0x1F == nal_unit_type);
+        let mut fragment = self.pool.take(keyframe.data.len() + 1024);
+        let mut init_bytes = 0;
+        let mut init_type = None;
+        if let (Some(sps), Some(pps)) = (find(7), find(8)) {
+            if self.sps.as_ref().is_none_or(|s| s[..] != sps[..]) {
+                let summary = track!(keyframe::parse_sps(sps))?;
+                let init = initialization_segment(sps, pps, &summary);
+                track!(init.write_to(&mut fragment).map_err(Error::from))?;
+                self.sps = Some(sps.to_vec());
+                init_bytes = fragment.len();
+                init_type = media_type::of(&init);
+            }
+        }
+        track_assert!(
+            self.sps.is_some(),
+            ErrorKind::InvalidInput,
+            "No SPS and PPS preceding the keyframe"
+        );
+
+        // Parameter sets are given by the `avcC` box, and delimiters are not needed.
+        let mut data = Vec::with_capacity(keyframe.data.len());
+        for nal in nal_units.iter().filter(|n| !matches!(n[0] & 0x1F, 7..=9)) {
+            data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            data.extend_from_slice(nal);
+        }
+        self.sequence_number += 1;
+        let duration = (f64::from(TIMESCALE) / self.frames_per_second()) as u32;
+        let segment = track!(media_segment(self.sequence_number, data, duration))?;
+        track!(segment.write_to(&mut fragment).map_err(Error::from))?;
+        Ok((fragment, init_bytes, init_type))
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    url: Url,
+    range: Option<ByteRange>,
+    seq: u64,
+    discontinuity: u64,
+
+    /// Start of the frame on the media timeline (the sum of the preceding durations).
+    time: Duration,
+}
+
+/// Returns the frames shown from `position` by steps of `step` seconds until either end
+/// of the playlist lasting `total`, each frame at most once.
+fn select_frames(frames: Vec<Frame>, total: Duration, position: Duration, step: f64) -> Vec<Frame> {
+    let mut indices = Vec::new();
+    let mut target = position.as_secs_f64();
+    while step != 0.0 && target >= 0.0 && target < total.as_secs_f64() {
+        let index = frames
+            .iter()
+            .rposition(|f| f.time.as_secs_f64() <= target)
+            .unwrap_or(0);
+        if indices.last() != Some(&index) {
+            indices.push(index);
+        }
+        target += step;
+    }
+    let mut frames = frames.into_iter().map(Some).collect::<Vec<_>>();
+    indices
+        .into_iter()
+        .filter_map(|i| frames[i].take())
+        .collect()
+}
+
+fn initialization_segment(
+    sps: &[u8],
+    pps: &[u8],
+    summary: &keyframe::SpsSummary,
+) -> InitializationSegment {
+    let mut segment = InitializationSegment::default();
+    segment.moov_box.mvhd_box.timescale = TIMESCALE;
+
+    let mut track = TrackBox::new(true);
+    track.tkhd_box.width = u32::from(summary.width) << 16;
+    track.tkhd_box.height = u32::from(summary.height) << 16;
+    track.mdia_box.mdhd_box.timescale = TIMESCALE;
+    let sample_entry = AvcSampleEntry {
+        width: summary.width,
+        height: summary.height,
+        avcc_box: AvcConfigurationBox {
+            configuration: AvcDecoderConfigurationRecord {
+                profile_idc: summary.profile_idc,
+                constraint_set_flag: summary.constraint_set_flag,
+                level_idc: summary.level_idc,
+                sequence_parameter_set: sps.to_vec(),
+                picture_parameter_set: pps.to_vec(),
+            },
+        },
+    };
+    track
+        .mdia_box
+        .minf_box
+        .stbl_box
+        .stsd_box
+        .sample_entries
+        .push(SampleEntry::Avc(sample_entry));
+    segment.moov_box.trak_boxes.push(track);
+    segment
+        .moov_box
+        .mvex_box
+        .trex_boxes
+        .push(TrackExtendsBox::new(true));
+    segment
+}
+
+/// Makes a fragment of the single sync sample `data` (length prefixed NAL units).
+fn media_segment(sequence_number: u32, data: Vec<u8>, duration: u32) -> Result<MediaSegment> {
+    let mut segment = MediaSegment::default();
+    segment.moof_box.mfhd_box.sequence_number = sequence_number;
+    let mut traf = TrackFragmentBox::new(true);
+    traf.trun_box.data_offset = Some(0); // Updated below.
+    traf.trun_box.samples.push(Sample {
+        duration: Some(duration),
+        size: Some(data.len() as u32),
+        flags: Some(SampleFlags {
+            is_leading: 0,
+            sample_depends_on: 2,
+            sample_is_depdended_on: 0,
+            sample_has_redundancy: 0,
+            sample_padding_value: 0,
+            sample_is_non_sync_sample: false,
+            sample_degradation_priority: 0,
+        }),
+        composition_time_offset: None,
+    });
+    segment.moof_box.traf_boxes.push(traf);
+
+    // The data follows the `moof` box and the header of the `mdat` box.
+    let moof_size = track!(segment.moof_box.box_size().map_err(Error::from))?;
+    segment.moof_box.traf_boxes[0].trun_box.data_offset = Some(moof_size as i32 + 8);
+    segment.mdat_boxes.push(MediaDataBox { data });
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+    use super::super::ts_fixture;
+
+    /// I-frame playlist of a keyframe every second for ten seconds.
+    fn i_frames() -> String {
+        let mut m3u8 = "#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-TARGETDURATION:1\n\
+                        #EXT-X-I-FRAMES-ONLY\n"
+            .to_owned();
+        for i in 0..10 {
+            m3u8 += &format!("#EXTINF:1,\nk{}.ts\n", i);
+        }
+        m3u8 + "#EXT-X-ENDLIST\n"
+    }
+
+    /// Returns the indices of the frames of the playlist selected with `position` and `step`.
+    fn selected(position_secs: u64, step: f64) -> Vec<u64> {
+        let frames = (0..10)
+            .map(|i| Frame {
+                url: Url::parse(&format!("http://x/k{}.ts", i)).unwrap(),
+                range: None,
+                seq: i,
+                discontinuity: 0,
+                time: Duration::from_secs(i),
+            })
+            .collect();
+        let total = Duration::from_secs(10);
+        select_frames(frames, total, Duration::from_secs(position_secs), step)
+            .into_iter()
+            .map(|f| f.seq)
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_stepped_by_the_rate() {
+        assert_eq!(selected(2, 2.0), [2, 4, 6, 8]);
+        assert_eq!(selected(9, -3.0), [9, 6, 3, 0]);
+
+        // Slower steps show each frame once.
+        assert_eq!(selected(7, 0.5), [7, 8, 9]);
+        assert!(selected(0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn keyframes_are_output_as_fragments() {
+        let config = PlayerConfig::default();
+        let url = Url::parse("http://x/i.m3u8").unwrap();
+        // Two frames per second of media at the default four frames per second.
+        let mut handler = TrickPlayHandler::new(&config, url, 2.0, Duration::from_secs(6));
+        let mut paths = Vec::new();
+        while let Some(action) = handler.next_action() {
+            match action {
+                Action::FetchData { action_id, url, .. } => {
+                    let data = match url.path()[2..].trim_end_matches(".ts").parse::<u64>() {
+                        Ok(seq) => ts_fixture::segment(seq * 90_000, 1),
+                        Err(_) => i_frames().into_bytes(),
+                    };
+                    handler.handle_data(action_id, &data, 10);
+                    paths.push(url.path().to_owned());
+                }
+                Action::SetTimeout { action_id, .. } => handler.handle_timeout(action_id),
+                action => panic!("{:?}", action),
+            }
+        }
+        assert_eq!(paths, ["/i.m3u8", "/k6.ts", "/k7.ts", "/k8.ts", "/k9.ts"]);
+
+        let segments = iter::from_fn(|| handler.next_segment()).collect::<Vec<_>>();
+        assert_eq!(segments.len(), 4);
+        let first = &segments[0].info;
+        assert!(first.init_bytes > 0);
+        assert_eq!(
+            first.media_type.as_ref().map(|t| t.codecs.as_str()),
+            Some("avc1.42C01E")
+        );
+        // The SPS is unchanged, so the other fragments have no initialization segment.
+        assert!(segments[1..].iter().all(|s| s.info.init_bytes == 0));
+        assert_eq!(segments[3].info.sequence, 9);
+        assert_eq!(segments[3].info.duration_ms, 250.0);
+
+        let mut times = Vec::new();
+        while let Some(event) = handler.next_event() {
+            match event {
+                Event::TrickPlayFrameLoaded { time_ms, .. } => times.push(time_ms),
+                Event::TrickPlayCompleted { frames } => assert_eq!(frames, 4),
+                event => panic!("{:?}", event),
+            }
+        }
+        assert_eq!(times, [6000.0, 7000.0, 8000.0, 9000.0]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Synthetic MPEG-2 TS segments for the tests.
+//!
+//! A segment carries a PAT, a PMT, a 64x64 Baseline H.264 stream at 30 fps (whose first
+//! access unit is an IDR picture preceded by the SPS and PPS) and an ADTS AAC stream.
+//! The pictures are not decodable, but the streams are remuxable.
+use super::scte35;
+
+const PACKET_SIZE: usize = 188;
+const PMT_PID: u16 = 0x1000;
+
+/// PID of the H.264 stream.
+pub const VIDEO_PID: u16 = 0x100;
+
+/// PID of the ADTS AAC stream.
+pub const AUDIO_PID: u16 = 0x101;
+
+/// Duration of a video frame in the 90 kHz clock.
+pub const FRAME_DURATION: u64 = 3000;
+
+/// SPS of a 64x64 picture (`profile_idc` 66, `level_idc` 30).
+pub const SPS: [u8; 7] = [0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x10, 0x99];
+
+/// PPS referring to `SPS`.
+pub const PPS: [u8; 4] = [0x68, 0xCE, 0x3C, 0x80];
+
+/// Returns a segment of `frames` video frames starting at `pts`, and as much audio.
+pub fn segment(pts: u64, frames: usize) -> Vec<u8> {
+    let mut ts = program();
+    let mut counters = [0; 2];
+    for i in 0..frames {
+        let pts = pts + i as u64 * FRAME_DURATION;
+        let pes = pes(0xE0, pts, &access_unit(i == 0));
+        ts.extend(packets(VIDEO_PID, &pes, &mut counters[0]));
+    }
+
+    // 1024 samples per AAC frame of 44.1 kHz, rounded up.
+    let audio_frames = (frames as u64 * FRAME_DURATION * 44_100).div_ceil(90_000 * 1024);
+    let mut adts = Vec::new();
+    for _ in 0..audio_frames {
+        adts.extend_from_slice(&adts_frame());
+    }
+    let pes = pes(0xC0, pts, &adts);
+    ts.extend(packets(AUDIO_PID, &pes, &mut counters[1]));
+    ts
+}
+
+/// Returns the NAL units of a video frame in the Annex B byte stream format.
+pub fn access_unit(is_idr: bool) -> Vec<u8> {
+    let mut data = vec![0, 0, 0, 1, 0x09, 0xF0];
+    if is_idr {
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(&SPS);
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(&PPS);
+        data.extend_from_slice(&[0, 0, 1, 0x65, 0x88, 0x84, 0x00, 0x33, 0xFF]);
+    } else {
+        data.extend_from_slice(&[0, 0, 1, 0x41, 0x9A, 0x02, 0x04, 0xFF]);
+    }
+    data
+}
+
+fn adts_frame() -> [u8; 13] {
+    // AAC LC, 44.1 kHz, stereo, and a frame length of 13 bytes.
+    let len = 13;
+    [
+        0xFF, 0xF1, 0x50, 0x80, (len >> 3) as u8, ((len & 7) << 5) as u8 | 0x1F, 0xFC,
+        0x21, 0x10, 0x04, 0x60, 0x8C, 0x1C,
+    ]
+}
+
+/// Returns the PAT and the PMT.
+fn program() -> Vec<u8> {
+    let pat = section(&[
+        0x00, 0xB0, 0x0D, 0, 1, 0xC1, 0, 0, 0, 1, 0xE0 | (PMT_PID >> 8) as u8, PMT_PID as u8,
+    ]);
+    let pmt = section(&[
+        0x02, 0xB0, 0x17, 0, 1, 0xC1, 0, 0, 0xE0 | (VIDEO_PID >> 8) as u8, VIDEO_PID as u8,
+        0xF0, 0x00,
+        0x1B, 0xE0 | (VIDEO_PID >> 8) as u8, VIDEO_PID as u8, 0xF0, 0x00,
+        0x0F, 0xE0 | (AUDIO_PID >> 8) as u8, AUDIO_PID as u8, 0xF0, 0x00,
+    ]);
+    [packet(0, true, 0, &pat), packet(PMT_PID, true, 0, &pmt)].concat()
+}
+
+/// Prefixes the pointer field to, and appends the CRC to, the PSI section `section`.
+fn section(section: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0];
+    bytes.extend_from_slice(section);
+    let crc = scte35::crc32(section);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+fn pes(stream_id: u8, pts: u64, data: &[u8]) -> Vec<u8> {
+    // The video PES packets are unbounded as usual.
+    let len = if stream_id == 0xE0 { 0 } else { 8 + data.len() };
+    let mut pes = vec![0, 0, 1, stream_id, (len >> 8) as u8, len as u8, 0x80, 0x80, 5];
+    pes.extend_from_slice(&[
+        0x21 | ((pts >> 29) & 0x0E) as u8,
+        (pts >> 22) as u8,
+        ((pts >> 14) & 0xFE) as u8 | 1,
+        (pts >> 7) as u8,
+        ((pts << 1) & 0xFE) as u8 | 1,
+    ]);
+    pes.extend_from_slice(data);
+    pes
+}
+
+/// Splits `pes` into the TS packets of `pid`.
+fn packets(pid: u16, pes: &[u8], counter: &mut u8) -> Vec<u8> {
+    let mut ts = Vec::new();
+    for (i, chunk) in pes.chunks(PACKET_SIZE - 4).enumerate() {
+        ts.extend(packet(pid, i == 0, *counter, chunk));
+        *counter = (*counter + 1) & 0x0F;
+    }
+    ts
+}
+
+/// Returns a TS packet, stuffed by its adaptation field if `payload` is short.
+fn packet(pid: u16, is_unit_start: bool, counter: u8, payload: &[u8]) -> Vec<u8> {
+    let flags = if is_unit_start { 0x40 } else { 0 };
+    let mut packet = vec![0x47, flags | (pid >> 8) as u8, pid as u8, counter];
+    let stuffing = PACKET_SIZE - 4 - payload.len();
+    if pid == 0 || pid == PMT_PID || stuffing == 0 {
+        packet[3] |= 0x10;
+    } else {
+        packet[3] |= 0x30;
+        packet.push(stuffing as u8 - 1);
+        if stuffing > 1 {
+            packet.push(0);
+            packet.resize(4 + stuffing, 0xFF);
+        }
+    }
+    packet.extend_from_slice(payload);
+    if packet.len() < PACKET_SIZE {
+        // Tables are followed by stuffing bytes instead of an adaptation field.
+        packet.resize(PACKET_SIZE, 0xFF);
+    }
+    packet
+}
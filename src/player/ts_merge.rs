@@ -0,0 +1,337 @@
+//! Merging of the TS segments of a demuxed audio rendition into those of the video,
+//! since the remuxer takes one program with both an H.264 and an AAC stream
+//! (see `RenditionConfig::audio_playlists`).
+//!
+//! The audio streams of the video segment (if any) are replaced by those of the audio segment,
+//! with their PIDs remapped if they collide. The PMT of the video segment is rewritten to
+//! list them.
+//!
+//! The PES packets of the audio are interleaved with the video ones by their timestamps
+//! (DTS, or else PTS), as in a muxed segment, so that the remuxed samples of both tracks
+//! cover the same time span as they are read. Those without timestamps follow the previous
+//! packet of the same stream.
+use std::cmp;
+use std::collections::{BTreeSet, HashMap};
+
+use {ErrorKind, Result};
+use super::keyframe;
+use super::scte35;
+use super::timestamps;
+use super::ts_sync;
+
+const PACKET_SIZE: usize = 188;
+
+/// `table_id` of `TS_program_map_section`.
+const PMT_TABLE_ID: u8 = 0x02;
+
+/// First PID assigned to the audio streams which collide with those of the video segment.
+const FIRST_REMAPPED_PID: u16 = 0x0100;
+
+/// Last PID available to elementary streams.
+const LAST_PID: u16 = 0x1FFE;
+
+/// Returns `video` (aligned to TS packets) with the audio streams of `audio`.
+pub fn merge(video: &[u8], audio: &[u8]) -> Result<Vec<u8>> {
+    let (audio, _) = ts_sync::resync(audio);
+    let audio_streams: Vec<_> = Program::read(&audio)
+        .map(|p| p.streams.into_iter().filter(|s| is_audio(s.stream_type)).collect())
+        .unwrap_or_default();
+    track_assert!(
+        !audio_streams.is_empty(),
+        ErrorKind::InvalidInput,
+        "No audio streams in the audio segment"
+    );
+    let video_program = track_assert_some!(
+        Program::read(video),
+        ErrorKind::InvalidInput,
+        "No PMT in the video segment"
+    );
+    let (dropped, kept): (Vec<_>, Vec<_>) = video_program
+        .streams
+        .into_iter()
+        .partition(|s| is_audio(s.stream_type));
+    let dropped_pids = dropped.iter().map(|s| s.pid).collect::<Vec<_>>();
+
+    let mut used_pids = kept.iter().map(|s| s.pid).collect::<BTreeSet<_>>();
+    used_pids.insert(0);
+    used_pids.insert(video_program.pmt_pid);
+    let mut remapped_pids = HashMap::new();
+    for stream in &audio_streams {
+        let pid = if used_pids.contains(&stream.pid) {
+            let free = (FIRST_REMAPPED_PID..=LAST_PID).find(|pid| !used_pids.contains(pid));
+            track_assert_some!(free, ErrorKind::InvalidInput, "No free PIDs")
+        } else {
+            stream.pid
+        };
+        used_pids.insert(pid);
+        remapped_pids.insert(stream.pid, pid);
+    }
+    let entries = kept.iter()
+        .map(|s| s.entry(s.pid))
+        .chain(audio_streams.iter().map(|s| s.entry(remapped_pids[&s.pid])))
+        .collect::<Vec<_>>();
+
+    // The audio packets by PES packet, each with its timestamp.
+    let mut units: Vec<(Option<u64>, Vec<u8>)> = Vec::new();
+    let mut last_units = HashMap::new();
+    for packet in audio.chunks(PACKET_SIZE).filter(|p| p.len() == PACKET_SIZE) {
+        let original_pid = scte35::pid(packet);
+        let pid = match remapped_pids.get(&original_pid) {
+            None => continue,
+            Some(&pid) => pid,
+        };
+        let unit = match last_units.get(&original_pid) {
+            Some(&unit) if !is_unit_start(packet) => unit,
+            _ => {
+                units.push((pes_timestamp(packet), Vec::new()));
+                last_units.insert(original_pid, units.len() - 1);
+                units.len() - 1
+            }
+        };
+        let data = &mut units[unit].1;
+        let start = data.len();
+        data.extend_from_slice(packet);
+        data[start + 1] = (packet[1] & 0xE0) | (pid >> 8) as u8;
+        data[start + 2] = pid as u8;
+    }
+
+    let mut units = units.into_iter().peekable();
+    let mut merged = Vec::with_capacity(video.len() + audio.len());
+    for packet in video.chunks(PACKET_SIZE).filter(|p| p.len() == PACKET_SIZE) {
+        let pid = scte35::pid(packet);
+        if dropped_pids.contains(&pid) {
+            continue;
+        }
+        if pid == video_program.pmt_pid && is_unit_start(packet) {
+            merged.extend_from_slice(&track!(rewrite_pmt(packet, &entries))?);
+            continue;
+        }
+        if let Some(t) = pes_timestamp(packet) {
+            let precedes = |&(a, _): &(Option<u64>, Vec<u8>)| {
+                a.is_none_or(|a| timestamps::elapsed_signed(a, t) >= 0)
+            };
+            while let Some((_, data)) = units.next_if(precedes) {
+                merged.extend_from_slice(&data);
+            }
+        }
+        merged.extend_from_slice(packet);
+    }
+    for (_, data) in units {
+        merged.extend_from_slice(&data);
+    }
+    Ok(merged)
+}
+
+/// Elementary stream listed by a PMT.
+#[derive(Debug)]
+struct Stream {
+    stream_type: u8,
+    pid: u16,
+
+    /// `descriptor()`s of the entry.
+    es_info: Vec<u8>,
+}
+impl Stream {
+    /// Returns the PMT entry of the stream with the PID `pid`.
+    fn entry(&self, pid: u16) -> Vec<u8> {
+        let len = self.es_info.len();
+        let mut entry = vec![
+            self.stream_type,
+            0xE0 | (pid >> 8) as u8,
+            pid as u8,
+            0xF0 | (len >> 8) as u8,
+            len as u8,
+        ];
+        entry.extend_from_slice(&self.es_info);
+        entry
+    }
+}
+
+/// First program of a segment.
+#[derive(Debug)]
+struct Program {
+    pmt_pid: u16,
+    streams: Vec<Stream>,
+}
+impl Program {
+    /// Reads the first PAT of `data` and the first PMT following it.
+    fn read(data: &[u8]) -> Option<Self> {
+        let mut pmt_pid = None;
+        for packet in data.chunks(PACKET_SIZE).filter(|p| p.len() == PACKET_SIZE) {
+            if !is_unit_start(packet) {
+                continue;
+            }
+            let pid = scte35::pid(packet);
+            let section = match scte35::payload(packet).and_then(scte35::psi_section) {
+                None => continue,
+                Some(section) => section,
+            };
+            if pid == 0 && section[0] == 0x00 && pmt_pid.is_none() {
+                pmt_pid = scte35::pat_pmt_pids(section).first().cloned();
+            } else if Some(pid) == pmt_pid && section[0] == PMT_TABLE_ID {
+                return Some(Program {
+                    pmt_pid: pid,
+                    streams: pmt_streams(section),
+                });
+            }
+        }
+        None
+    }
+}
+
+fn is_unit_start(packet: &[u8]) -> bool {
+    packet[1] & 0x40 != 0
+}
+
+/// Returns the DTS (or else the PTS) of the PES packet starting in `packet`, if any.
+fn pes_timestamp(packet: &[u8]) -> Option<u64> {
+    let pes = scte35::payload(packet).filter(|_| is_unit_start(packet))?;
+    if pes.len() < 14 || pes[..3] != [0, 0, 1] {
+        return None;
+    }
+    match pes[7] >> 6 {
+        0b11 if pes.len() >= 19 => Some(keyframe::read_timestamp(&pes[14..19])),
+        0b10 | 0b11 => Some(keyframe::read_timestamp(&pes[9..14])),
+        _ => None,
+    }
+}
+
+/// Returns the streams of the PMT `section`.
+fn pmt_streams(section: &[u8]) -> Vec<Stream> {
+    let end = section.len() - 4;
+    let mut i = 12 + program_info_length(section);
+    let mut streams = Vec::new();
+    while i + 5 <= end {
+        let entry = &section[i..i + 5];
+        let es_info_length = ((entry[3] as usize) & 0x0F) << 8 | entry[4] as usize;
+        streams.push(Stream {
+            stream_type: entry[0],
+            pid: u16::from(entry[1] & 0x1F) << 8 | u16::from(entry[2]),
+            es_info: section[i + 5..cmp::min(i + 5 + es_info_length, end)].to_vec(),
+        });
+        i += 5 + es_info_length;
+    }
+    streams
+}
+
+fn program_info_length(section: &[u8]) -> usize {
+    ((section[10] as usize) & 0x0F) << 8 | section[11] as usize
+}
+
+/// Returns the PMT `packet` with the stream `entries` instead of its own.
+fn rewrite_pmt(packet: &[u8], entries: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let payload = track_assert_some!(scte35::payload(packet), ErrorKind::InvalidInput);
+    let section = track_assert_some!(
+        scte35::psi_section(payload),
+        ErrorKind::InvalidInput,
+        "The PMT of the video segment spans several packets"
+    );
+    let header_end = 12 + program_info_length(section);
+    let mut rewritten = section[..header_end].to_vec();
+    for entry in entries {
+        rewritten.extend_from_slice(entry);
+    }
+    let section_length = rewritten.len() + 4 - 3;
+    rewritten[1] = (rewritten[1] & 0xF0) | (section_length >> 8) as u8;
+    rewritten[2] = section_length as u8;
+    let crc = scte35::crc32(&rewritten);
+    rewritten.extend_from_slice(&crc.to_be_bytes());
+
+    let payload_start = PACKET_SIZE - payload.len();
+    track_assert!(
+        payload_start + 1 + rewritten.len() <= PACKET_SIZE,
+        ErrorKind::InvalidInput,
+        "The merged PMT does not fit into a packet"
+    );
+    let mut rewritten_packet = packet[..payload_start].to_vec();
+    rewritten_packet.push(0x00);
+    rewritten_packet.extend_from_slice(&rewritten);
+    rewritten_packet.resize(PACKET_SIZE, 0xFF);
+    Ok(rewritten_packet)
+}
+
+/// Returns `true` if `stream_type` is that of an audio stream (ISO/IEC 13818-1, ATSC A/52).
+fn is_audio(stream_type: u8) -> bool {
+    matches!(
+        stream_type,
+        0x03 | 0x04 | 0x0F | 0x11 | 0x80..=0x85 | 0x87 | 0xC1 | 0xC2 | 0xCF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pid: u16, is_unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let flags = if is_unit_start { 0x40 } else { 0 };
+        let mut packet = vec![0x47, flags | (pid >> 8) as u8, pid as u8, 0x10];
+        packet.extend_from_slice(payload);
+        packet.resize(PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    fn program(stream_type: u8, pid: u16) -> Vec<u8> {
+        let pat = [0, 0x00, 0xB0, 0x0D, 0, 1, 0xC1, 0, 0, 0, 1, 0xE0, 0x20, 0, 0, 0, 0];
+        let pmt = [
+            0, 0x02, 0xB0, 0x12, 0, 1, 0xC1, 0, 0, 0xE1, 0x00, 0xF0, 0x00,
+            stream_type, 0xE0 | (pid >> 8) as u8, pid as u8, 0xF0, 0x00, 0, 0, 0, 0,
+        ];
+        [packet(0, true, &pat), packet(0x20, true, &pmt)].concat()
+    }
+
+    fn timestamp(prefix: u8, t: u64) -> [u8; 5] {
+        [
+            prefix | ((t >> 29) & 0x0E) as u8 | 1,
+            (t >> 22) as u8,
+            ((t >> 14) & 0xFE) as u8 | 1,
+            (t >> 7) as u8,
+            ((t << 1) & 0xFE) as u8 | 1,
+        ]
+    }
+
+    fn pes(pid: u16, stream_id: u8, pts: u64, dts: Option<u64>) -> Vec<u8> {
+        let mut payload = vec![0, 0, 1, stream_id, 0, 0, 0x80];
+        match dts {
+            None => {
+                payload.extend_from_slice(&[0x80, 5]);
+                payload.extend_from_slice(&timestamp(0x20, pts));
+            }
+            Some(dts) => {
+                payload.extend_from_slice(&[0xC0, 10]);
+                payload.extend_from_slice(&timestamp(0x30, pts));
+                payload.extend_from_slice(&timestamp(0x10, dts));
+            }
+        }
+        packet(pid, true, &payload)
+    }
+
+    #[test]
+    fn audio_is_interleaved_by_timestamps() {
+        let video = [
+            program(0x1B, 0x100),
+            pes(0x100, 0xE0, 3000, Some(0)),
+            pes(0x100, 0xE0, 9000, Some(3000)),
+            pes(0x100, 0xE0, 6000, Some(6000)),
+        ].concat();
+        let audio = [
+            program(0x0F, 0x100),
+            pes(0x100, 0xC0, 1000, None),
+            packet(0x100, false, &[]),
+            pes(0x100, 0xC0, 2000, None),
+            pes(0x100, 0xC0, 7000, None),
+        ].concat();
+        let merged = merge(&video, &audio).unwrap();
+
+        let packets = merged.chunks(PACKET_SIZE).collect::<Vec<_>>();
+        let pids = packets.iter().map(|p| scte35::pid(p)).collect::<Vec<_>>();
+        assert_eq!(pids, [0, 0x20, 0x100, 0x101, 0x101, 0x101, 0x100, 0x100, 0x101]);
+        let timestamps = packets.iter().map(|p| pes_timestamp(p)).collect::<Vec<_>>();
+        assert_eq!(
+            timestamps[2..],
+            [Some(0), Some(1000), None, Some(2000), Some(3000), Some(6000), Some(7000)]
+        );
+        let program = Program::read(&merged).unwrap();
+        let streams = program.streams.iter().map(|s| (s.stream_type, s.pid)).collect::<Vec<_>>();
+        assert_eq!(streams, [(0x1B, 0x100), (0x0F, 0x101)]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Resynchronization of MPEG-2 TS packets.
+//!
+//! Some origins serve segments with leading garbage, truncated packets or corrupt bytes,
+//! on which the TS reader aborts. Such segments are reduced to the packets that are
+//! aligned to sync bytes before being remuxed.
+use std::borrow::Cow;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+/// Returns the packets of `data` that start with a confirmed sync byte,
+/// and the number of the skipped bytes.
+///
+/// A sync byte is confirmed if it is followed by another one a packet later,
+/// or if its packet ends the data. A trailing partial packet is skipped.
+///
+/// Well-formed data is returned without being copied.
+pub fn resync(data: &[u8]) -> (Cow<'_, [u8]>, usize) {
+    if is_aligned(data) {
+        return (Cow::Borrowed(data), 0);
+    }
+
+    let mut packets = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i + PACKET_SIZE <= data.len() {
+        if is_packet_start(data, i) {
+            packets.extend_from_slice(&data[i..i + PACKET_SIZE]);
+            i += PACKET_SIZE;
+        } else {
+            i += 1;
+        }
+    }
+    let skipped = data.len() - packets.len();
+    (Cow::Owned(packets), skipped)
+}
+
+fn is_aligned(data: &[u8]) -> bool {
+    data.len().is_multiple_of(PACKET_SIZE) && data.chunks(PACKET_SIZE).all(|p| p[0] == SYNC_BYTE)
+}
+
+fn is_packet_start(data: &[u8], i: usize) -> bool {
+    let next = i + PACKET_SIZE;
+    data[i] == SYNC_BYTE && (next == data.len() || data.get(next) == Some(&SYNC_BYTE))
+}
@@ -0,0 +1,480 @@
+//! Conformance checks of playlists against RFC 8216.
+//!
+//! The player tolerates some deviations of origins (see `text` and `discontinuity`)
+//! and rejects others. `validate` reports all of them at once, so that operators can
+//! tell why a stream misbehaves without reading the playlist line by line.
+use hls_m3u8::{MasterPlaylist, MediaPlaylist};
+
+use super::{discontinuity, text};
+
+/// Tags that only appear in master playlists.
+const MASTER_TAGS: &[&str] = &[
+    "#EXT-X-STREAM-INF",
+    "#EXT-X-I-FRAME-STREAM-INF",
+    "#EXT-X-MEDIA",
+    "#EXT-X-SESSION-DATA",
+    "#EXT-X-SESSION-KEY",
+    "#EXT-X-CONTENT-STEERING",
+];
+
+/// Tags that only appear in media playlists.
+const MEDIA_TAGS: &[&str] = &[
+    "#EXTINF",
+    "#EXT-X-TARGETDURATION",
+    "#EXT-X-MEDIA-SEQUENCE",
+    "#EXT-X-DISCONTINUITY-SEQUENCE",
+    "#EXT-X-ENDLIST",
+    "#EXT-X-PLAYLIST-TYPE",
+    "#EXT-X-I-FRAMES-ONLY",
+    "#EXT-X-BYTERANGE",
+    "#EXT-X-DISCONTINUITY",
+    "#EXT-X-KEY",
+    "#EXT-X-MAP",
+    "#EXT-X-PROGRAM-DATE-TIME",
+];
+
+/// Tags that must not appear more than once in a playlist.
+const SINGLE_TAGS: &[&str] = &[
+    "#EXT-X-VERSION",
+    "#EXT-X-TARGETDURATION",
+    "#EXT-X-MEDIA-SEQUENCE",
+    "#EXT-X-DISCONTINUITY-SEQUENCE",
+    "#EXT-X-PLAYLIST-TYPE",
+    "#EXT-X-ENDLIST",
+    "#EXT-X-I-FRAMES-ONLY",
+    "#EXT-X-INDEPENDENT-SEGMENTS",
+    "#EXT-X-START",
+];
+
+/// Severity of a `ValidationIssue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// Violation of the specification, which clients may reject.
+    Error,
+
+    /// Deviation that is allowed, but breaks older or stricter clients.
+    Warning,
+}
+
+/// Rule broken by a `ValidationIssue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum IssueKind {
+    /// The first line is not `#EXTM3U`.
+    MissingHeader,
+
+    /// Master playlist tags and media playlist tags are used in the same playlist.
+    MixedTags,
+
+    /// A tag that is allowed once appears again.
+    DuplicateTag,
+
+    MissingTargetDuration,
+
+    /// `EXTINF` (rounded to the nearest integer) exceeds `EXT-X-TARGETDURATION`.
+    SegmentTooLong,
+
+    /// `EXTINF` or `EXT-X-STREAM-INF` is not followed by a URI line.
+    MissingUri,
+
+    /// A required (or recommended, for warnings) attribute of a tag is absent.
+    MissingAttribute,
+
+    /// `EXT-X-VERSION` is lower than the one required by the used tags and attributes.
+    VersionMismatch,
+
+    /// The player cannot parse the playlist.
+    Unparsable,
+}
+
+/// Spec violation or compatibility problem found in a playlist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+
+    /// 1-based number of the offending line, or `None` if it concerns the whole playlist.
+    pub line: Option<usize>,
+
+    pub message: String,
+}
+
+/// Returns the issues found in the master or media playlist `m3u8`, in the order of lines.
+pub fn validate(m3u8: &str) -> Vec<ValidationIssue> {
+    let m3u8 = text::normalize(m3u8);
+    let lines = m3u8
+        .lines()
+        .map(|l| l.trim())
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .filter(|&(_, l)| !l.is_empty())
+        .collect::<Vec<_>>();
+    let mut validator = Validator::default();
+    if lines.first().is_none_or(|&(_, l)| l != "#EXTM3U") {
+        validator.error(IssueKind::MissingHeader, Some(1), "The first line is not #EXTM3U");
+    }
+
+    let first_line_of = |tags: &[&str]| {
+        lines
+            .iter()
+            .find(|&&(_, l)| tags.contains(&tag_name(l)))
+            .map(|&(n, l)| (n, tag_name(l)))
+    };
+    if let (Some((master, master_tag)), Some((media, media_tag))) =
+        (first_line_of(MASTER_TAGS), first_line_of(MEDIA_TAGS))
+    {
+        let message = format!(
+            "Both a master playlist tag ({} at line {}) and a media playlist tag \
+             ({} at line {}) are used",
+            master_tag, master, media_tag, media
+        );
+        validator.error(IssueKind::MixedTags, Some(master.max(media)), &message);
+    }
+
+    // Same as `HlsPlayer::play`.
+    let is_master = !m3u8.contains("#EXT-X-TARGETDURATION");
+    validator.check_single_tags(&lines);
+    if is_master {
+        validator.check_master(&lines);
+    } else {
+        validator.check_media(&lines);
+    }
+    validator.check_version(&lines);
+
+    let result = if is_master {
+        m3u8.parse::<MasterPlaylist>().map(|_| ())
+    } else {
+        let (m3u8, _) = discontinuity::split_sequence(&m3u8);
+        m3u8.parse::<MediaPlaylist>().map(|_| ())
+    };
+    if let Err(e) = result {
+        let message = format!(
+            "The player cannot parse the playlist: {}",
+            e.to_string().lines().next().unwrap_or("")
+        );
+        validator.error(IssueKind::Unparsable, None, &message);
+    }
+
+    let mut issues = validator.issues;
+    issues.sort_by_key(|i| i.line.unwrap_or(usize::MAX));
+    issues
+}
+
+#[derive(Debug, Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+
+    /// Highest `EXT-X-VERSION` required by the used tags, with the reason and its line.
+    required_version: Option<(u32, String, usize)>,
+}
+impl Validator {
+    fn check_single_tags(&mut self, lines: &[(usize, &str)]) {
+        for tag in SINGLE_TAGS {
+            let mut occurrences = lines.iter().filter(|&&(_, l)| tag_name(l) == *tag);
+            let first = match occurrences.next() {
+                None => continue,
+                Some(&(n, _)) => n,
+            };
+            for &(n, _) in occurrences {
+                let message = format!("{} appears again (first at line {})", tag, first);
+                self.error(IssueKind::DuplicateTag, Some(n), &message);
+            }
+        }
+    }
+
+    fn check_master(&mut self, lines: &[(usize, &str)]) {
+        let mut pending_variant = None;
+        for &(n, line) in lines {
+            if !line.starts_with('#') {
+                pending_variant = None;
+                continue;
+            }
+            let attributes = attributes(line);
+            match tag_name(line) {
+                "#EXT-X-STREAM-INF" => {
+                    self.check_variant_uri(pending_variant.replace(n));
+                    self.check_variant_attributes(n, "#EXT-X-STREAM-INF", attributes);
+                }
+                "#EXT-X-I-FRAME-STREAM-INF" => {
+                    self.check_variant_attributes(n, "#EXT-X-I-FRAME-STREAM-INF", attributes);
+                    if text::attribute(attributes, "URI").is_none() {
+                        let message = "#EXT-X-I-FRAME-STREAM-INF has no URI";
+                        self.error(IssueKind::MissingAttribute, Some(n), message);
+                    }
+                }
+                "#EXT-X-MEDIA" => {
+                    let is_service = text::attribute(attributes, "INSTREAM-ID")
+                        .is_some_and(|id| id.starts_with("SERVICE"));
+                    if is_service {
+                        self.require_version(7, "INSTREAM-ID=SERVICEn", n);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.check_variant_uri(pending_variant);
+    }
+
+    fn check_variant_uri(&mut self, line: Option<usize>) {
+        if let Some(n) = line {
+            let message = "#EXT-X-STREAM-INF is not followed by a URI";
+            self.error(IssueKind::MissingUri, Some(n), message);
+        }
+    }
+
+    fn check_variant_attributes(&mut self, n: usize, tag: &str, attributes: &str) {
+        if text::attribute(attributes, "BANDWIDTH").is_none() {
+            let message = format!("{} has no BANDWIDTH", tag);
+            self.error(IssueKind::MissingAttribute, Some(n), &message);
+        }
+        if text::attribute(attributes, "CODECS").is_none() {
+            let message = format!(
+                "{} has no CODECS, so clients cannot skip unsupported variants",
+                tag
+            );
+            self.warning(IssueKind::MissingAttribute, Some(n), &message);
+        }
+    }
+
+    fn check_media(&mut self, lines: &[(usize, &str)]) {
+        let target_duration = lines
+            .iter()
+            .find_map(|&(_, l)| l.strip_prefix("#EXT-X-TARGETDURATION:"))
+            .and_then(|s| s.trim().parse::<u64>().ok());
+        if target_duration.is_none() {
+            let message = "#EXT-X-TARGETDURATION is missing or malformed";
+            self.error(IssueKind::MissingTargetDuration, None, message);
+        }
+        let is_i_frames_only = lines
+            .iter()
+            .any(|&(_, l)| tag_name(l) == "#EXT-X-I-FRAMES-ONLY");
+
+        let mut pending_segment = None;
+        for &(n, line) in lines {
+            if !line.starts_with('#') {
+                pending_segment = None;
+                continue;
+            }
+            let attributes = attributes(line);
+            match tag_name(line) {
+                "#EXTINF" => {
+                    if let Some(previous) = pending_segment.replace(n) {
+                        let message = "#EXTINF is not followed by a URI";
+                        self.error(IssueKind::MissingUri, Some(previous), message);
+                    }
+                    let duration = attributes.split(',').next().unwrap_or("").trim();
+                    if duration.contains('.') {
+                        self.require_version(3, "floating-point EXTINF durations", n);
+                    }
+                    let seconds = text::parse_seconds(duration).map(|d| d.as_secs_f64().round());
+                    let limit = target_duration.map(|t| t as f64);
+                    if let (Some(seconds), Some(limit)) = (seconds, limit) {
+                        if seconds > limit {
+                            let message = format!(
+                                "#EXTINF:{} exceeds #EXT-X-TARGETDURATION:{}",
+                                duration, limit
+                            );
+                            self.error(IssueKind::SegmentTooLong, Some(n), &message);
+                        }
+                    }
+                }
+                "#EXT-X-BYTERANGE" => self.require_version(4, "#EXT-X-BYTERANGE", n),
+                "#EXT-X-I-FRAMES-ONLY" => self.require_version(4, "#EXT-X-I-FRAMES-ONLY", n),
+                "#EXT-X-MAP" if is_i_frames_only => self.require_version(5, "#EXT-X-MAP", n),
+                "#EXT-X-MAP" => {
+                    self.require_version(6, "#EXT-X-MAP (without #EXT-X-I-FRAMES-ONLY)", n)
+                }
+                "#EXT-X-KEY" => {
+                    for &name in &["KEYFORMAT", "KEYFORMATVERSIONS"] {
+                        if text::attribute(attributes, name).is_some() {
+                            self.require_version(5, name, n);
+                        }
+                    }
+                    if text::attribute(attributes, "IV").is_some() {
+                        self.require_version(2, "IV", n);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(n) = pending_segment {
+            let message = "#EXTINF is not followed by a URI";
+            self.error(IssueKind::MissingUri, Some(n), message);
+        }
+    }
+
+    fn check_version(&mut self, lines: &[(usize, &str)]) {
+        let (required, reason, n) = match self.required_version.take() {
+            None => return,
+            Some(x) => x,
+        };
+        let declared = lines
+            .iter()
+            .find_map(|&(_, l)| l.strip_prefix("#EXT-X-VERSION:"))
+            .map(|v| v.trim().parse::<u32>().unwrap_or(1));
+        if declared.unwrap_or(1) >= required {
+            return;
+        }
+        let message = match declared {
+            None => format!("#EXT-X-VERSION is missing, but {} requires {}", reason, required),
+            Some(declared) => format!(
+                "#EXT-X-VERSION:{} is lower than {} required by {}",
+                declared, required, reason
+            ),
+        };
+        self.warning(IssueKind::VersionMismatch, Some(n), &message);
+    }
+
+    fn require_version(&mut self, version: u32, reason: &str, line: usize) {
+        if self.required_version.as_ref().is_none_or(|r| r.0 < version) {
+            self.required_version = Some((version, reason.to_owned(), line));
+        }
+    }
+
+    fn error(&mut self, kind: IssueKind, line: Option<usize>, message: &str) {
+        self.push(Severity::Error, kind, line, message);
+    }
+
+    fn warning(&mut self, kind: IssueKind, line: Option<usize>, message: &str) {
+        self.push(Severity::Warning, kind, line, message);
+    }
+
+    fn push(&mut self, severity: Severity, kind: IssueKind, line: Option<usize>, message: &str) {
+        self.issues.push(ValidationIssue {
+            severity,
+            kind,
+            line,
+            message: message.to_owned(),
+        });
+    }
+}
+
+/// Returns the name of the tag `line` (e.g., `#EXTINF`), or `""` if it is not a tag.
+fn tag_name(line: &str) -> &str {
+    if !line.starts_with("#EXT") {
+        return "";
+    }
+    line.split(':').next().unwrap_or(line)
+}
+
+/// Returns the value of the tag `line` following the colon.
+fn attributes(line: &str) -> &str {
+    line.split_once(':').map_or("", |(_, v)| v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEDIA: &str = "#EXTM3U\n\
+                         #EXT-X-VERSION:3\n\
+                         #EXT-X-TARGETDURATION:4\n\
+                         #EXTINF:4.4,\n\
+                         seg0.ts\n\
+                         #EXTINF:3.0,\n\
+                         seg1.ts\n\
+                         #EXT-X-ENDLIST\n";
+
+    const MASTER: &str = "#EXTM3U\n\
+                          #EXT-X-STREAM-INF:BANDWIDTH=1000000,CODECS=\"avc1.42c01e,mp4a.40.2\"\n\
+                          low.m3u8\n\
+                          #EXT-X-STREAM-INF:BANDWIDTH=2000000,CODECS=\"avc1.42c01e,mp4a.40.2\"\n\
+                          high.m3u8\n";
+
+    /// Returns the severities and lines of the issues of `kind` found in `m3u8`.
+    fn issues(m3u8: &str, kind: IssueKind) -> Vec<(Severity, Option<usize>)> {
+        validate(m3u8)
+            .into_iter()
+            .filter(|i| i.kind == kind)
+            .map(|i| (i.severity, i.line))
+            .collect()
+    }
+
+    #[test]
+    fn valid_playlists_have_no_issues() {
+        assert_eq!(validate(MEDIA), []);
+        assert_eq!(validate(MASTER), []);
+    }
+
+    #[test]
+    fn playlists_start_with_the_header() {
+        assert_eq!(issues(&format!("\n\n{}", MEDIA), IssueKind::MissingHeader), []);
+        let m3u8 = MEDIA.replacen("#EXTM3U\n", "", 1);
+        assert_eq!(issues(&m3u8, IssueKind::MissingHeader), [(Severity::Error, Some(1))]);
+    }
+
+    #[test]
+    fn master_and_media_tags_are_not_mixed() {
+        let m3u8 = format!("{}#EXT-X-INDEPENDENT-SEGMENTS\n", MASTER);
+        assert_eq!(issues(&m3u8, IssueKind::MixedTags), []);
+        let m3u8 = format!("{}#EXT-X-ENDLIST\n", MASTER);
+        assert_eq!(issues(&m3u8, IssueKind::MixedTags), [(Severity::Error, Some(6))]);
+    }
+
+    #[test]
+    fn single_tags_appear_once() {
+        let m3u8 = MEDIA.replace("#EXT-X-ENDLIST\n", "#EXT-X-DISCONTINUITY\n#EXTINF:4,\nseg2.ts\n");
+        assert_eq!(issues(&m3u8, IssueKind::DuplicateTag), []);
+        let m3u8 = format!("{}#EXT-X-ENDLIST\n", MEDIA);
+        assert_eq!(issues(&m3u8, IssueKind::DuplicateTag), [(Severity::Error, Some(9))]);
+    }
+
+    #[test]
+    fn target_durations_are_integers() {
+        let m3u8 = MEDIA.replace("TARGETDURATION:4", "TARGETDURATION: 4");
+        assert_eq!(issues(&m3u8, IssueKind::MissingTargetDuration), []);
+        let m3u8 = MEDIA.replace("TARGETDURATION:4", "TARGETDURATION:4.5");
+        assert_eq!(
+            issues(&m3u8, IssueKind::MissingTargetDuration),
+            [(Severity::Error, None)]
+        );
+    }
+
+    #[test]
+    fn rounded_segment_durations_are_within_the_target_duration() {
+        // 4.4 s rounds down to the target duration.
+        assert_eq!(issues(MEDIA, IssueKind::SegmentTooLong), []);
+        let m3u8 = MEDIA.replace("#EXTINF:4.4,", "#EXTINF:4.5,");
+        assert_eq!(issues(&m3u8, IssueKind::SegmentTooLong), [(Severity::Error, Some(4))]);
+    }
+
+    #[test]
+    fn segments_and_variants_are_followed_by_uris() {
+        let m3u8 = MEDIA.replace("seg0.ts\n", "# A comment\nseg0.ts\n");
+        assert_eq!(issues(&m3u8, IssueKind::MissingUri), []);
+        let m3u8 = MEDIA.replace("seg0.ts\n", "");
+        assert_eq!(issues(&m3u8, IssueKind::MissingUri), [(Severity::Error, Some(4))]);
+        let m3u8 = MASTER.replace("high.m3u8\n", "");
+        assert_eq!(issues(&m3u8, IssueKind::MissingUri), [(Severity::Error, Some(4))]);
+    }
+
+    #[test]
+    fn variants_have_bandwidths_and_codecs() {
+        let m3u8 = format!(
+            "{}#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,CODECS=\"avc1.42c01e\",URI=\"i.m3u8\"\n",
+            MASTER
+        );
+        assert_eq!(issues(&m3u8, IssueKind::MissingAttribute), []);
+        let m3u8 = MASTER
+            .replacen("BANDWIDTH=1000000,", "", 1)
+            .replacen(",CODECS=\"avc1.42c01e,mp4a.40.2\"", "", 1);
+        assert_eq!(
+            issues(&m3u8, IssueKind::MissingAttribute),
+            [(Severity::Error, Some(2)), (Severity::Warning, Some(4))]
+        );
+    }
+
+    #[test]
+    fn versions_cover_the_used_features() {
+        // Floating-point durations require the version 3.
+        assert_eq!(issues(MEDIA, IssueKind::VersionMismatch), []);
+        let m3u8 = MEDIA.replace("#EXT-X-VERSION:3\n", "#EXT-X-VERSION:2\n");
+        assert_eq!(issues(&m3u8, IssueKind::VersionMismatch), [(Severity::Warning, Some(4))]);
+    }
+
+    #[test]
+    fn playlists_are_parsable_by_the_player() {
+        assert_eq!(issues(MEDIA, IssueKind::Unparsable), []);
+        let m3u8 = MEDIA.replace("#EXTINF:3.0,", "#EXTINF:three,");
+        assert_eq!(issues(&m3u8, IssueKind::Unparsable), [(Severity::Error, None)]);
+    }
+}
@@ -0,0 +1,39 @@
+/// Switch of the played variant of a master playlist.
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantSwitch {
+    /// Wall-clock time in milliseconds since the UNIX epoch (`None` without `Hooks::clock`).
+    pub timestamp_ms: Option<f64>,
+
+    /// Index of the previous variant (`None` for the initial selection).
+    pub from: Option<usize>,
+
+    /// Index of the new variant in the `EXT-X-STREAM-INF` entries.
+    pub to: usize,
+
+    /// `BANDWIDTH` of the new variant.
+    pub bandwidth: u64,
+
+    pub reason: SwitchReason,
+}
+
+/// Reason of `VariantSwitch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum SwitchReason {
+    /// Selected by `PlayerConfig::variant_index` when starting the playback.
+    Initial,
+
+    /// The steering server has given precedence to another pathway.
+    Steering,
+
+    /// The media playlist of the previous pathway has failed repeatedly.
+    PathwayFailover,
+
+    /// The low-power mode has been entered or left (see `PlayerConfig::low_power`).
+    LowPower,
+
+    /// The previous variant has been blacklisted (see `PlayerConfig::variant_blacklist`).
+    VariantFailover,
+
+    /// The cooldown of the blacklisted variant selected before has expired.
+    BlacklistExpired,
+}
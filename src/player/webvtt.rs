@@ -0,0 +1,133 @@
+//! Cues of WebVTT subtitle segments.
+use super::text;
+
+/// Cue of a WebVTT segment, timed on the timeline of the media segments.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubtitleCue {
+    pub start_ms: f64,
+    pub end_ms: f64,
+
+    /// Cue payload (possibly with WebVTT markup), whose lines are joined by `\n`.
+    pub text: String,
+}
+
+/// Returns the cues of the WebVTT segment `vtt`.
+///
+/// The cue times are converted by `X-TIMESTAMP-MAP` to the MPEG-2 TS timeline
+/// of the media segments, if given. Cues with malformed timings are skipped.
+pub fn parse_cues(vtt: &str) -> Vec<SubtitleCue> {
+    let vtt = text::normalize(vtt);
+    let mut blocks = vtt.split("\n\n").map(|b| b.trim_matches('\n'));
+    let offset_ms = blocks.next().map_or(0.0, timestamp_map_offset_ms);
+
+    let mut cues = Vec::new();
+    for block in blocks {
+        let mut lines = block.lines();
+        let timing = match lines.by_ref().take(2).find(|l| l.contains("-->")) {
+            None => continue,
+            Some(x) => x,
+        };
+        let (start, rest) = match timing.split_once("-->") {
+            None => continue,
+            Some(x) => x,
+        };
+        let end = rest.split_whitespace().next().unwrap_or("");
+        if let (Some(start), Some(end)) = (parse_timestamp(start.trim()), parse_timestamp(end)) {
+            cues.push(SubtitleCue {
+                start_ms: start + offset_ms,
+                end_ms: end + offset_ms,
+                text: lines.collect::<Vec<_>>().join("\n"),
+            });
+        }
+    }
+    cues
+}
+
+/// Returns the offset given by `X-TIMESTAMP-MAP=MPEGTS:<pts>,LOCAL:<time>` of the `header`.
+fn timestamp_map_offset_ms(header: &str) -> f64 {
+    let map = match header
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("X-TIMESTAMP-MAP="))
+    {
+        None => return 0.0,
+        Some(x) => x,
+    };
+    let mut mpegts = 0.0;
+    let mut local = 0.0;
+    for pair in map.split(',').map(|p| p.trim()) {
+        match pair.split_once(':') {
+            Some(("MPEGTS", v)) => mpegts = v.trim().parse::<u64>().map_or(0.0, |v| v as f64),
+            Some(("LOCAL", v)) => local = parse_timestamp(v.trim()).unwrap_or(0.0),
+            _ => {}
+        }
+    }
+    mpegts / 90.0 - local
+}
+
+/// Parses `[hh:]mm:ss.ttt` into milliseconds.
+fn parse_timestamp(s: &str) -> Option<f64> {
+    let (rest, millis) = s.split_once('.')?;
+    let mut fields = rest.rsplit(':');
+    let seconds: u64 = fields.next()?.parse().ok()?;
+    let minutes: u64 = fields.next()?.parse().ok()?;
+    let hours: u64 = match fields.next() {
+        None => 0,
+        Some(h) => h.parse().ok()?,
+    };
+    if fields.next().is_some() || millis.len() != 3 {
+        return None;
+    }
+    let millis: u64 = millis.parse().ok()?;
+    Some((((hours * 60 + minutes) * 60 + seconds) * 1000 + millis) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(vtt: &str) -> Vec<(f64, f64)> {
+        parse_cues(vtt).iter().map(|c| (c.start_ms, c.end_ms)).collect()
+    }
+
+    #[test]
+    fn cues_are_offset_by_the_timestamp_map() {
+        let cue = "\n\n00:00:01.000 --> 00:00:02.500\nHello\n";
+        assert_eq!(times(&format!("WEBVTT{}", cue)), [(1000.0, 2500.0)]);
+
+        // 900000 / 90 kHz = 10 s.
+        let header = "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000";
+        assert_eq!(times(&format!("{}{}", header, cue)), [(11_000.0, 12_500.0)]);
+
+        // The cue at LOCAL is presented at MPEGTS, whichever order they are listed in.
+        let header = "WEBVTT\nX-TIMESTAMP-MAP=LOCAL:00:00:01.000, MPEGTS:900000";
+        assert_eq!(times(&format!("{}{}", header, cue)), [(10_000.0, 11_500.0)]);
+
+        // Timestamps beyond 32 bits.
+        let header = "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:8589924592,LOCAL:01:00:00.000";
+        let start = 8_589_924_592.0 / 90.0 - 3_600_000.0 + 1000.0;
+        assert_eq!(times(&format!("{}{}", header, cue)), [(start, start + 1500.0)]);
+    }
+
+    #[test]
+    fn cues_are_parsed() {
+        let vtt = "\u{FEFF}WEBVTT\r\n\r\nNOTE a comment\r\n\r\n1\r\n\
+                   01:02:03.004 --> 01:02:04.000 align:start\r\nTwo\r\n<i>lines</i>\r\n\r\n\
+                   00:05.000 --> 00:06.000\r\nShort\r\n\r\n\
+                   00:07.00 --> 00:08.000\r\nMalformed\r\n";
+        assert_eq!(
+            parse_cues(vtt),
+            [
+                SubtitleCue {
+                    start_ms: 3_723_004.0,
+                    end_ms: 3_724_000.0,
+                    text: "Two\n<i>lines</i>".to_owned(),
+                },
+                SubtitleCue {
+                    start_ms: 5000.0,
+                    end_ms: 6000.0,
+                    text: "Short".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -53,14 +53,21 @@ pub mod wasm_bytes {
 
 }
 pub mod hls_player {
+    use std::time::Duration;
+    use serde_json::{self, Value};
     use url::Url;
 
     use {Error, HlsPlayer, MaybeError, MaybeJson, Ptr, WasmBytes, WasmStr};
-    use player::{Action, ActionId};
+    use player::{validate, Action, ActionId, DateRange, DownloadManifest, Event, ImageTile,
+                 IndexedSegment, InputRecord, KeyframePosition, Metrics, OutputRing, SegmentInfo,
+                 Stats, SyncHint, Timeline, TraceRecord, ValidationIssue};
 
     #[no_mangle]
     pub fn hls_player_new() -> Ptr<HlsPlayer> {
-        Ptr::new(HlsPlayer::new())
+        let mut player = HlsPlayer::new();
+        host_clock::install(&mut player);
+        host_logger::install(&mut player);
+        Ptr::new(player)
     }
 
     #[no_mangle]
@@ -71,12 +78,16 @@ pub mod hls_player {
     }
 
     #[no_mangle]
-    pub fn hls_player_play(player: Ptr<HlsPlayer>, url: WasmStr, m3u8: WasmStr) -> MaybeError {
-        if m3u8.contains("#EXT-X-TARGETDURATION") {
-            hls_player_play_media_playlist(player, url, m3u8)
-        } else {
-            hls_player_play_master_playlist(player, url, m3u8)
-        }
+    pub fn hls_player_set_config(mut player: Ptr<HlsPlayer>, config_json: WasmStr) -> MaybeError {
+        maybe_error!(player.set_config_json(&config_json));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_play(mut player: Ptr<HlsPlayer>, url: WasmStr, m3u8: WasmStr) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.play(url, &m3u8));
+        ok!()
     }
 
     #[no_mangle]
@@ -101,6 +112,228 @@ pub mod hls_player {
         ok!()
     }
 
+    #[no_mangle]
+    pub fn hls_player_load_source(mut player: Ptr<HlsPlayer>, url: WasmStr) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.load_source(url));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_play_data_url(mut player: Ptr<HlsPlayer>, url: WasmStr) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.play_data_url(url));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_download(
+        mut player: Ptr<HlsPlayer>,
+        url: WasmStr,
+        m3u8: WasmStr,
+    ) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.download(url, &m3u8));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_download_manifest(player: Ptr<HlsPlayer>) -> MaybeJson<DownloadManifest> {
+        match player.download_manifest() {
+            Some(manifest) => MaybeJson::new(manifest),
+            None => MaybeJson::null(),
+        }
+    }
+
+    #[no_mangle]
+    pub fn hls_player_sync_to(player: Ptr<HlsPlayer>, date_time_ms: f64) -> MaybeJson<SyncHint> {
+        let date_time = Duration::from_secs_f64(date_time_ms.max(0.0) / 1000.0);
+        match player.sync_to(date_time) {
+            Some(hint) => MaybeJson::new(&hint),
+            None => MaybeJson::null(),
+        }
+    }
+
+    #[no_mangle]
+    pub fn hls_player_keyframe_index(player: Ptr<HlsPlayer>) -> MaybeJson<Vec<IndexedSegment>> {
+        MaybeJson::new(&player.keyframe_index())
+    }
+
+    #[no_mangle]
+    pub fn hls_player_nearest_keyframe(
+        player: Ptr<HlsPlayer>,
+        position_ms: f64,
+    ) -> MaybeJson<KeyframePosition> {
+        let position = Duration::from_secs_f64(position_ms.max(0.0) / 1000.0);
+        match player.nearest_keyframe(position) {
+            Some(keyframe) => MaybeJson::new(&keyframe),
+            None => MaybeJson::null(),
+        }
+    }
+
+    #[no_mangle]
+    pub fn hls_player_timeline(player: Ptr<HlsPlayer>) -> MaybeJson<Timeline> {
+        match player.timeline() {
+            Some(timeline) => MaybeJson::new(timeline),
+            None => MaybeJson::null(),
+        }
+    }
+
+    /// Returns the tile of the image stream previewing `position_ms` (see `ImageTile`).
+    #[no_mangle]
+    pub fn hls_player_image_tile_at(
+        player: Ptr<HlsPlayer>,
+        position_ms: f64,
+    ) -> MaybeJson<ImageTile> {
+        let position = Duration::from_secs_f64(position_ms.max(0.0) / 1000.0);
+        match player.image_tile_at(position) {
+            Some(tile) => MaybeJson::new(&tile),
+            None => MaybeJson::null(),
+        }
+    }
+
+    /// Returns the `EXT-X-DATERANGE`s which do not mark ad breaks (e.g., chapters).
+    #[no_mangle]
+    pub fn hls_player_date_ranges(player: Ptr<HlsPlayer>) -> MaybeJson<Vec<DateRange>> {
+        MaybeJson::new(&player.date_ranges().to_vec())
+    }
+
+    /// Returns the spec violations and compatibility problems of the playlist `m3u8`.
+    #[no_mangle]
+    pub fn hls_validate_playlist(m3u8: WasmStr) -> MaybeJson<Vec<ValidationIssue>> {
+        MaybeJson::new(&validate(&m3u8))
+    }
+
+    #[no_mangle]
+    pub fn hls_player_generate_thumbnails(
+        mut player: Ptr<HlsPlayer>,
+        url: WasmStr,
+        m3u8: WasmStr,
+    ) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.generate_thumbnails(url, &m3u8));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_insert_ad_pod(
+        mut player: Ptr<HlsPlayer>,
+        url: WasmStr,
+        m3u8: WasmStr,
+    ) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.insert_ad_pod(url, &m3u8));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_queue_next(
+        mut player: Ptr<HlsPlayer>,
+        url: WasmStr,
+        m3u8: WasmStr,
+    ) -> MaybeError {
+        let url = maybe_error!(Url::parse(&url).map_err(Error::from));
+        maybe_error!(player.queue_next(url, &m3u8));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_start_trick_play(
+        mut player: Ptr<HlsPlayer>,
+        rate: f64,
+        position_ms: u32,
+    ) -> MaybeError {
+        let position = Duration::from_millis(u64::from(position_ms));
+        maybe_error!(player.start_trick_play(rate, position));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_stop_trick_play(mut player: Ptr<HlsPlayer>, position_ms: u32) -> MaybeError {
+        let position = Duration::from_millis(u64::from(position_ms));
+        maybe_error!(player.stop_trick_play(position));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_set_variant_locked(mut player: Ptr<HlsPlayer>, locked: i32) -> MaybeError {
+        maybe_error!(player.set_variant_locked(locked != 0));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_set_low_power(mut player: Ptr<HlsPlayer>, enabled: i32) {
+        player.set_low_power(enabled != 0);
+    }
+
+    /// Replaces the preferred audio languages given as a JSON array of RFC 5646 tags.
+    #[no_mangle]
+    pub fn hls_player_set_audio_languages(
+        mut player: Ptr<HlsPlayer>,
+        languages_json: WasmStr,
+    ) -> MaybeError {
+        let languages: Vec<String> =
+            maybe_error!(serde_json::from_str(&languages_json).map_err(Error::from));
+        player.set_audio_languages(languages);
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_suspend(mut player: Ptr<HlsPlayer>) -> MaybeError {
+        maybe_error!(player.suspend());
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_resume(mut player: Ptr<HlsPlayer>) -> MaybeError {
+        maybe_error!(player.resume());
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_stop(mut player: Ptr<HlsPlayer>) {
+        player.stop();
+    }
+
+    #[no_mangle]
+    pub fn hls_player_set_buffer_length(mut player: Ptr<HlsPlayer>, buffer_length_ms: u32) {
+        player.set_buffer_length(Duration::from_millis(u64::from(buffer_length_ms)));
+    }
+
+    #[no_mangle]
+    pub fn hls_player_set_playback_position(mut player: Ptr<HlsPlayer>, position_ms: u32) {
+        player.set_playback_position(Duration::from_millis(u64::from(position_ms)));
+    }
+
+    /// Notifies the buffered time ranges given as a JSON array of `[start_ms, end_ms]` pairs.
+    #[no_mangle]
+    pub fn hls_player_set_buffered_ranges(
+        mut player: Ptr<HlsPlayer>,
+        ranges_json: WasmStr,
+    ) -> MaybeError {
+        let ranges: Vec<(u32, u32)> =
+            maybe_error!(serde_json::from_str(&ranges_json).map_err(Error::from));
+        let ranges = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    Duration::from_millis(u64::from(start)),
+                    Duration::from_millis(u64::from(end)),
+                )
+            })
+            .collect::<Vec<_>>();
+        player.set_buffered_ranges(&ranges);
+        ok!()
+    }
+
+    /// Frees `segment` taken by `hls_player_next_segment`, reusing its allocation
+    /// for later segments (instead of `wasm_bytes_free`).
+    #[no_mangle]
+    pub fn hls_player_recycle_segment(mut player: Ptr<HlsPlayer>, segment: WasmBytes) {
+        let segment = unsafe { segment.into_vec() };
+        player.recycle_segment(segment);
+    }
+
     #[no_mangle]
     pub fn hls_player_handle_data(
         mut player: Ptr<HlsPlayer>,
@@ -113,6 +346,58 @@ pub mod hls_player {
         ok!()
     }
 
+    #[no_mangle]
+    pub fn hls_player_handle_data_with_url(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+        data: WasmBytes,
+        fetch_duration_ms: u32,
+        final_url: WasmStr,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        let final_url = maybe_error!(Url::parse(&final_url).map_err(Error::from));
+        maybe_error!(player.handle_data_with_url(
+            action_id,
+            &data,
+            fetch_duration_ms,
+            Some(&final_url)
+        ));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_handle_response_headers(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+        headers_json: WasmStr,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        maybe_error!(player.handle_response_headers_json(action_id, &headers_json));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_handle_data_chunk(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+        chunk: WasmBytes,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        maybe_error!(player.handle_data_chunk(action_id, &chunk));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_handle_data_end(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+        fetch_duration_ms: u32,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        maybe_error!(player.handle_data_end(action_id, fetch_duration_ms, None));
+        ok!()
+    }
+
     #[no_mangle]
     pub fn hls_player_handle_timeout(mut player: Ptr<HlsPlayer>, action_id: u32) -> MaybeError {
         let action_id = ActionId::from(action_id);
@@ -120,6 +405,27 @@ pub mod hls_player {
         ok!()
     }
 
+    #[no_mangle]
+    pub fn hls_player_handle_fetch_timeout(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        maybe_error!(player.handle_fetch_timeout(action_id));
+        ok!()
+    }
+
+    #[no_mangle]
+    pub fn hls_player_handle_error(
+        mut player: Ptr<HlsPlayer>,
+        action_id: u32,
+        status: u16,
+    ) -> MaybeError {
+        let action_id = ActionId::from(action_id);
+        maybe_error!(player.handle_error(action_id, status));
+        ok!()
+    }
+
     #[no_mangle]
     pub fn hls_player_next_action(mut player: Ptr<HlsPlayer>) -> MaybeJson<Action> {
         if let Some(action) = player.next_action() {
@@ -129,12 +435,238 @@ pub mod hls_player {
         }
     }
 
+    /// Takes all the queued actions as a JSON array, or returns null if there are none.
+    #[no_mangle]
+    pub fn hls_player_drain_actions(mut player: Ptr<HlsPlayer>) -> MaybeJson<Vec<Action>> {
+        let actions = player.drain_actions();
+        if actions.is_empty() {
+            MaybeJson::null()
+        } else {
+            MaybeJson::new(&actions)
+        }
+    }
+
+    #[no_mangle]
+    pub fn hls_player_next_event(mut player: Ptr<HlsPlayer>) -> MaybeJson<Event> {
+        if let Some(event) = player.next_event() {
+            MaybeJson::new(&event)
+        } else {
+            MaybeJson::null()
+        }
+    }
+
+    #[no_mangle]
+    pub fn hls_player_stats(player: Ptr<HlsPlayer>) -> MaybeJson<Stats> {
+        MaybeJson::new(&player.stats())
+    }
+
+    #[no_mangle]
+    pub fn hls_player_metrics(player: Ptr<HlsPlayer>) -> MaybeJson<Metrics> {
+        MaybeJson::new(&player.metrics())
+    }
+
+    #[no_mangle]
+    pub fn hls_player_request_log(player: Ptr<HlsPlayer>) -> MaybeJson<Value> {
+        MaybeJson::new(&player.request_log_har())
+    }
+
+    #[no_mangle]
+    pub fn hls_player_trace(player: Ptr<HlsPlayer>) -> MaybeJson<Vec<TraceRecord>> {
+        MaybeJson::new(&player.trace_records())
+    }
+
+    #[no_mangle]
+    pub fn hls_player_clear_trace(mut player: Ptr<HlsPlayer>) {
+        player.clear_trace();
+    }
+
+    /// Returns the inputs of the playback when `input_log.enabled` is configured.
+    #[no_mangle]
+    pub fn hls_player_input_log(player: Ptr<HlsPlayer>) -> MaybeJson<Vec<InputRecord>> {
+        MaybeJson::new(&player.input_log().to_vec())
+    }
+
     #[no_mangle]
     pub fn hls_player_next_segment(mut player: Ptr<HlsPlayer>) -> WasmBytes {
         if let Some(segment) = player.next_segment() {
-            WasmBytes::from(segment)
+            WasmBytes::from(segment.data)
         } else {
             WasmBytes::null()
         }
     }
+
+    /// Hands the next segment, along with its info (see `hls_player_segment_info`), to the host
+    /// function imported as `env.hls_host_transfer_segment(data_ptr, data_len, info_ptr,
+    /// info_len)` and returns `1`, or returns `0` if there is no segment.
+    ///
+    /// Both are valid only during the call, in which the host copies them out
+    /// (e.g., into a transferable `ArrayBuffer` for the main thread), and the buffer of
+    /// the segment is recycled right after.
+    #[no_mangle]
+    pub fn hls_player_transfer_segment(mut player: Ptr<HlsPlayer>) -> i32 {
+        let is_taken = player.take_segment_with(|data, info| {
+            let info = serde_json::to_vec(info).unwrap_or_default();
+            host_output::transfer(data, &info);
+        });
+        is_taken as i32
+    }
+
+    /// Makes a ring of `capacity` bytes into which `hls_player_fill_output_ring` writes segments,
+    /// for consumers in other threads when the memory is shared (see `OutputRing` and
+    /// the shared memory build in `README.md`).
+    #[no_mangle]
+    pub fn hls_output_ring_new(capacity: u32) -> Ptr<OutputRing> {
+        Ptr::new(OutputRing::new(capacity as usize))
+    }
+
+    #[no_mangle]
+    pub fn hls_output_ring_free(mut ring: Ptr<OutputRing>) {
+        unsafe {
+            ring.free();
+        }
+    }
+
+    /// Address of the four header words of `ring`.
+    #[no_mangle]
+    pub fn hls_output_ring_header_ptr(ring: Ptr<OutputRing>) -> i32 {
+        ring.header().as_ptr() as i32
+    }
+
+    /// Address of the data of `ring`, whose size is the third header word.
+    #[no_mangle]
+    pub fn hls_output_ring_data_ptr(ring: Ptr<OutputRing>) -> i32 {
+        ring.data().as_ptr() as i32
+    }
+
+    /// Writes the available segments into `ring` until it is full.
+    ///
+    /// The host then notifies the consumer on the fourth header word (`Atomics.notify`).
+    #[no_mangle]
+    pub fn hls_player_fill_output_ring(
+        mut player: Ptr<HlsPlayer>,
+        mut ring: Ptr<OutputRing>,
+    ) -> MaybeError {
+        maybe_error!(track!(ring.fill(&mut player)));
+        ok!()
+    }
+
+    /// Returns the sequence number, duration, variant, discontinuity sequence number
+    /// and program date time of the segment last taken by `hls_player_next_segment`.
+    #[no_mangle]
+    pub fn hls_player_segment_info(player: Ptr<HlsPlayer>) -> MaybeJson<SegmentInfo> {
+        if let Some(info) = player.last_segment_info() {
+            MaybeJson::new(info)
+        } else {
+            MaybeJson::null()
+        }
+    }
+
+    /// Takes the bytes recorded since the last call (empty unless `recording.enabled`
+    /// is configured), to be appended to the recorded fMP4 file.
+    #[no_mangle]
+    pub fn hls_player_take_recording(mut player: Ptr<HlsPlayer>) -> WasmBytes {
+        let recording = player.take_recording();
+        if recording.is_empty() {
+            WasmBytes::null()
+        } else {
+            WasmBytes::from(recording)
+        }
+    }
+
+    /// The clock of the host, imported as `env.hls_host_now_ms` (milliseconds since the UNIX epoch,
+    /// e.g., `performance.timeOrigin + performance.now()`).
+    #[cfg(target_arch = "wasm32")]
+    mod host_clock {
+        use std::rc::Rc;
+        use std::time::Duration;
+
+        use HlsPlayer;
+        use player::Clock;
+
+        extern "C" {
+            fn hls_host_now_ms() -> f64;
+        }
+
+        struct HostClock;
+        impl Clock for HostClock {
+            fn now(&self) -> Duration {
+                let now_ms = unsafe { hls_host_now_ms() };
+                Duration::from_secs_f64(now_ms.max(0.0) / 1000.0)
+            }
+        }
+
+        pub fn install(player: &mut HlsPlayer) {
+            let mut hooks = player.config().hooks.clone();
+            hooks.clock = Some(Rc::new(HostClock));
+            player.set_hooks(hooks);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    mod host_clock {
+        use HlsPlayer;
+
+        pub fn install(_player: &mut HlsPlayer) {}
+    }
+
+    /// The receiver of the segments of `hls_player_transfer_segment`, imported as
+    /// `env.hls_host_transfer_segment`.
+    #[cfg(target_arch = "wasm32")]
+    mod host_output {
+        extern "C" {
+            fn hls_host_transfer_segment(
+                data: *const u8,
+                data_len: usize,
+                info: *const u8,
+                info_len: usize,
+            );
+        }
+
+        pub fn transfer(data: &[u8], info: &[u8]) {
+            unsafe {
+                hls_host_transfer_segment(data.as_ptr(), data.len(), info.as_ptr(), info.len())
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    mod host_output {
+        pub fn transfer(_data: &[u8], _info: &[u8]) {}
+    }
+
+    /// The logger of the host, imported as `env.hls_host_log(level, message_ptr, message_len)`.
+    ///
+    /// `level` is `1` (error) to `5` (trace).
+    #[cfg(target_arch = "wasm32")]
+    mod host_logger {
+        use std::rc::Rc;
+
+        use HlsPlayer;
+        use player::{LogLevel, Logger};
+
+        extern "C" {
+            fn hls_host_log(level: u32, message: *const u8, message_len: usize);
+        }
+
+        struct HostLogger;
+        impl Logger for HostLogger {
+            fn log(&self, level: LogLevel, message: &str) {
+                let level = level as u32 + 1;
+                unsafe { hls_host_log(level, message.as_ptr(), message.len()) }
+            }
+        }
+
+        pub fn install(player: &mut HlsPlayer) {
+            let mut hooks = player.config().hooks.clone();
+            hooks.logger = Some(Rc::new(HostLogger));
+            player.set_hooks(hooks);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    mod host_logger {
+        use HlsPlayer;
+
+        pub fn install(_player: &mut HlsPlayer) {}
+    }
 }